@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use etcd_txn_parser::batch::parse_batch;
+use std::hint::black_box;
+
+fn inputs() -> Vec<Vec<u8>> {
+    (0..10_000)
+        .map(|index| {
+            format!("mod(\"key{index}\") > 0\n\nput key{index} \"overwrote-key{index}\"\n\nput key{index} \"created-key{index}\"")
+                .into_bytes()
+        })
+        .collect()
+}
+
+fn bench_parse_batch(c: &mut Criterion) {
+    let inputs = inputs();
+    let inputs: Vec<&[u8]> = inputs.iter().map(Vec::as_slice).collect();
+
+    c.bench_function("parse_batch 10k", |b| {
+        b.iter(|| parse_batch(black_box(inputs.iter().copied())))
+    });
+
+    #[cfg(feature = "parallel")]
+    c.bench_function("parse_batch_parallel 10k", |b| {
+        b.iter(|| etcd_txn_parser::batch::parse_batch_parallel(black_box(&inputs)))
+    });
+}
+
+criterion_group!(benches, bench_parse_batch);
+criterion_main!(benches);
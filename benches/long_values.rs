@@ -0,0 +1,28 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use etcd_txn_parser::parse;
+use std::hint::black_box;
+
+/// 1,000 puts each carrying a 32KiB quoted value, like certificates.
+fn input() -> Vec<u8> {
+    let value = "a".repeat(32 << 10);
+    let mut input = b"\n\n".to_vec();
+    for index in 0..1_000 {
+        input.extend_from_slice(format!("put key{index} \"{value}\"\n").as_bytes());
+    }
+    input.extend_from_slice(b"\n");
+    input
+}
+
+fn bench_long_values(c: &mut Criterion) {
+    let input = input();
+
+    let mut group = c.benchmark_group("long_values");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("parse 1k puts of 32KiB", |b| {
+        b.iter(|| parse(black_box(&input)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_long_values);
+criterion_main!(benches);
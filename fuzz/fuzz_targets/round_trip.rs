@@ -0,0 +1,29 @@
+#![no_main]
+
+use etcd_txn_parser::options::ParseOptions;
+use etcd_txn_parser::owned::TxnDataOwned;
+use etcd_txn_parser::parse_with_options;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|txn: TxnDataOwned| {
+    let mut rendered = vec![];
+    txn.as_txn()
+        .write_to(&mut rendered)
+        .expect("failed to write");
+
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        allow_file_refs: true,
+        allow_extensions: true,
+        ..Default::default()
+    };
+    let parsed = parse_with_options(&rendered, &options)
+        .unwrap_or_else(|err| panic!("failed to parse {rendered:?}: {err:?}"));
+    assert_eq!(parsed.to_owned(), txn);
+
+    let mut rewritten = vec![];
+    parsed
+        .write_to(&mut rewritten)
+        .expect("failed to write again");
+    assert_eq!(rewritten, rendered);
+});
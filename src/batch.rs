@@ -0,0 +1,80 @@
+//! Parsing of many independent transactions at once
+
+use crate::error::Error;
+use crate::{parse, TxnData};
+
+/// Parse many independent transactions.
+///
+/// The results are returned in the order of the inputs, so the index of a
+/// failed result is the index of the faulty input.
+pub fn parse_batch<'a>(
+    inputs: impl IntoIterator<Item = &'a [u8]>,
+) -> Vec<Result<TxnData<'a>, Error>> {
+    inputs.into_iter().map(parse).collect()
+}
+
+/// Parse many independent transactions in parallel.
+///
+/// The results are returned in the order of the inputs, so the index of a
+/// failed result is the index of the faulty input.
+#[cfg(feature = "parallel")]
+pub fn parse_batch_parallel<'a>(inputs: &[&'a [u8]]) -> Vec<Result<TxnData<'a>, Error>> {
+    use rayon::prelude::*;
+
+    inputs.par_iter().map(|input| parse(input)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::parse_batch;
+    use crate::error::Error;
+
+    fn inputs() -> Vec<Vec<u8>> {
+        (0..100)
+            .map(|index| {
+                if index % 10 == 7 {
+                    b"not a transaction".to_vec()
+                } else {
+                    format!("mod(key{index}) > 0\n\nput key{index} \"value\"\n\n").into_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let inputs = inputs();
+        let results = parse_batch(inputs.iter().map(Vec::as_slice));
+        assert_eq!(results.len(), 100);
+        for (index, result) in results.iter().enumerate() {
+            if index % 10 == 7 {
+                assert!(matches!(result, Err(Error::Parse(_))));
+            } else {
+                let txn = result.as_ref().expect("Failed to parse");
+                assert_eq!(txn.success.len(), 1);
+                assert!(txn
+                    .explain_compares()
+                    .all(|explanation| explanation.starts_with(&format!("key{index}'s"))));
+            }
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parse_batch_parallel() {
+        use crate::batch::parse_batch_parallel;
+
+        let inputs = inputs();
+        let inputs: Vec<&[u8]> = inputs.iter().map(Vec::as_slice).collect();
+        let sequential = parse_batch(inputs.iter().copied());
+        let parallel = parse_batch_parallel(&inputs);
+        assert_eq!(sequential.len(), parallel.len());
+        for (sequential, parallel) in sequential.iter().zip(&parallel) {
+            match (sequential, parallel) {
+                (Ok(sequential), Ok(parallel)) => assert_eq!(sequential, parallel),
+                (Err(_), Err(_)) => {}
+                _ => panic!("Sequential and parallel results differ"),
+            }
+        }
+    }
+}
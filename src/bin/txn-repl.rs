@@ -0,0 +1,149 @@
+//! Build a transaction line by line, like `etcdctl txn --interactive`.
+//!
+//! The compares, then the success and the failure operations are read from
+//! the standard input, a blank line ending each section. Each line is checked
+//! as soon as it is entered: a rejected line is reported and left out, so that
+//! it can be typed again, and `:undo` drops the last line of the section. The
+//! transaction is then printed in its canonical format.
+
+use etcd_txn_parser::compare::Compare;
+use etcd_txn_parser::error::render_error;
+use etcd_txn_parser::operation::Operation;
+use etcd_txn_parser::{parse, Section};
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+/// The sections in order, along with the prompt `etcdctl` shows for them.
+const SECTIONS: [(Section, &str); 3] = [
+    (Section::Compare, "compares:"),
+    (Section::Success, "success requests (get, put, del):"),
+    (Section::Failure, "failure requests (get, put, del):"),
+];
+
+/// Write the accepted lines as a transaction, sections separated by a blank
+/// line.
+fn transaction(sections: &[Vec<Vec<u8>>]) -> Vec<u8> {
+    let mut text = vec![];
+    for (index, lines) in sections.iter().enumerate() {
+        if index > 0 {
+            text.extend_from_slice(b"\n\n");
+        }
+        text.extend_from_slice(&lines.join(&b'\n'));
+    }
+    text
+}
+
+/// Check a line of `section`, returning the diagnostic to show if it's
+/// rejected. `before` is the transaction entered so far, up to the line, so
+/// that the line is numbered as in the final transaction.
+fn check(section: Section, line: &[u8], before: &[u8]) -> Option<String> {
+    let (err, help) = match section {
+        Section::Compare => match Compare::parse(line) {
+            Ok(_) => return None,
+            Err(err) => (
+                err,
+                Operation::parse(line).is_ok().then_some(
+                    "operations go in the success and failure sections, \
+                     end the compares with a blank line",
+                ),
+            ),
+        },
+        Section::Success | Section::Failure => match Operation::parse(line) {
+            Ok(_) => return None,
+            Err(err) => (
+                err,
+                Compare::parse(line)
+                    .is_ok()
+                    .then_some("compares go in the compare section, before the operations"),
+            ),
+        },
+    };
+    let input = [before, line].concat();
+
+    let mut diagnostic = render_error(&input, &(section, before.len(), err));
+    if let Some(help) = help {
+        diagnostic.push_str(&format!("  = help: {help}\n"));
+    }
+    Some(diagnostic)
+}
+
+fn main() -> ExitCode {
+    let mut input = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut stderr = io::stderr().lock();
+
+    let mut sections: Vec<Vec<Vec<u8>>> = vec![];
+    let mut eof = false;
+    for (section, prompt) in SECTIONS {
+        sections.push(vec![]);
+        if eof {
+            continue;
+        }
+        let _ = writeln!(stdout, "{prompt}");
+        let _ = stdout.flush();
+
+        loop {
+            let mut line = vec![];
+            match input.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    eof = true;
+                    break;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    let _ = writeln!(stderr, "error: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+            let line = line.strip_suffix(b"\n").unwrap_or(&line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+            if line.iter().all(u8::is_ascii_whitespace) {
+                break;
+            }
+            let mut before = transaction(&sections);
+            let Some(lines) = sections.last_mut() else {
+                break;
+            };
+            if !lines.is_empty() {
+                before.push(b'\n');
+            }
+            if line.trim_ascii() == b":undo" {
+                match lines.pop() {
+                    Some(undone) => {
+                        let _ = writeln!(stderr, "undone: {}", String::from_utf8_lossy(&undone));
+                    }
+                    None => {
+                        let _ = writeln!(stderr, "nothing to undo in the {section} section");
+                    }
+                }
+                continue;
+            }
+            match check(section, line, &before) {
+                Some(diagnostic) => {
+                    let _ = write!(stderr, "{diagnostic}");
+                }
+                None => lines.push(line.to_vec()),
+            }
+        }
+    }
+
+    let text = transaction(&sections);
+    let rendered = parse(&text).map_err(|err| err.to_string()).and_then(|txn| {
+        let mut rendered = vec![];
+        txn.write_to(&mut rendered)
+            .map(|_| rendered)
+            .map_err(|err| err.to_string())
+    });
+    match rendered {
+        Ok(rendered) => {
+            let _ = writeln!(stdout);
+            let _ = stdout.write_all(&rendered);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            let _ = writeln!(stderr, "error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Trailing checksum lines
+//!
+//! Some pipelines append the SHA-256 of a transaction as a last comment line,
+//! `# sha256:<64 hexadecimal digits>`, computed over everything preceding the
+//! line. See [`ParseOptions::trailing_checksum`](crate::options::ParseOptions::trailing_checksum).
+
+use crate::error::Error;
+
+/// The prefix of a checksum line.
+const PREFIX: &[u8] = b"# sha256:";
+
+/// The round constants of SHA-256.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Compute the SHA-256 digest of the data.
+// The indices are bounded by the 64 rounds, the size of the schedule
+#[allow(clippy::indexing_slicing)]
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // The data followed by a one bit, zeros and its length in bits
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in padded.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, added) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(added);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Parse the 64 hexadecimal digits of a digest.
+fn parse_digest(hex: &[u8]) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (byte, pair) in digest.iter_mut().zip(hex.chunks_exact(2)) {
+        let pair = std::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// Split the trailing checksum line off the data, returning the data it
+/// covers and the checksum, if the last non-blank line is a checksum line.
+///
+/// With `verify`, the checksum is required and must match the data.
+pub(crate) fn split_checksum(
+    data: &[u8],
+    verify: bool,
+) -> Result<(&[u8], Option<[u8; 32]>), Error> {
+    let trimmed = data.trim_ascii_end();
+    let start = trimmed
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |index| index + 1);
+    let (content, line) = trimmed.split_at(start);
+    let checksum = line
+        .strip_prefix(PREFIX)
+        .and_then(|hex| parse_digest(hex.trim_ascii()));
+
+    match checksum {
+        Some(expected) if verify => {
+            let actual = sha256(content);
+            if actual != expected {
+                return Err(Error::ChecksumMismatch { expected, actual });
+            }
+            Ok((content, checksum))
+        }
+        Some(_) => Ok((content, checksum)),
+        None if verify => Err(Error::MissingChecksum),
+        None => Ok((data, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{parse_digest, sha256, split_checksum};
+    use crate::error::Error;
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Two blocks once padded
+        assert_eq!(
+            hex(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let digest = sha256(b"abc");
+        assert_eq!(parse_digest(hex(&digest).as_bytes()), Some(digest));
+        assert_eq!(
+            parse_digest(hex(&digest).to_uppercase().as_bytes()),
+            Some(digest)
+        );
+        assert_eq!(parse_digest(b"ba78"), None);
+        assert_eq!(parse_digest(&[b'g'; 64]), None);
+    }
+
+    #[test]
+    fn test_split_checksum() {
+        let data = b"put k v\n";
+        assert!(matches!(
+            split_checksum(data, false),
+            Ok((b"put k v\n", None))
+        ));
+        assert!(matches!(
+            split_checksum(data, true),
+            Err(Error::MissingChecksum)
+        ));
+
+        let signed = format!("put k v\n# sha256:{}\n\n", hex(&sha256(data)));
+        assert!(matches!(
+            split_checksum(signed.as_bytes(), true),
+            Ok((b"put k v\n", Some(_)))
+        ));
+    }
+}
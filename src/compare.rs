@@ -2,34 +2,64 @@
 //!
 //! See the [Compare API](https://github.com/etcd-io/etcd/blob/main/etcdctl/README.md#txn-options) for
 //! more information.
-
-use crate::operation::Data;
-use elyze::acceptor::Acceptor;
-use elyze::bytes::components::groups::GroupKind;
+// Malformed input must be reported, never panic
+#![deny(clippy::arithmetic_side_effects)]
+
+use crate::data::{self, Key};
+use crate::escape::{decode, same_data};
+use crate::operation::{attempt, Data, DataKind};
+use crate::options::{ParseOptions, DEFAULT_QUOTE_CHARS};
+use crate::OptionalBlanks;
 use elyze::bytes::primitives::number::Number;
-use elyze::bytes::primitives::whitespace::OptionalWhitespaces;
 use elyze::bytes::token::Token;
 use elyze::errors::{ParseError, ParseResult};
-use elyze::peek::peek;
+use elyze::peek::{peek, UntilEnd};
 use elyze::recognizer::Recognizer;
 use elyze::scanner::Scanner;
 use elyze::visitor::Visitor;
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
 
 //----------------------------------------------------------------------------
 // Key
 //----------------------------------------------------------------------------
 
-struct Key<'a>(&'a [u8]);
-
-impl<'a> Visitor<'a, u8> for Key<'a> {
-    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        let key_slice =
-            peek(GroupKind::Parenthesis, scanner)?.ok_or(ParseError::UnexpectedToken)?;
-        let mut inner_scanner = Scanner::new(key_slice.peeked_slice());
-        let key = Data::accept(&mut inner_scanner)?.data;
-        scanner.bump_by(key_slice.end_slice);
+struct ParenthesizedKey<'a>(Data<'a>);
+
+impl<'a> ParenthesizedKey<'a> {
+    /// Parse a parenthesized key, which may be quoted by `quotes`. The
+    /// parentheses nest, an unbalanced group isn't a key, nor is a group
+    /// holding more than one word.
+    fn accept_with(scanner: &mut Scanner<'a, u8>, quotes: &[u8]) -> ParseResult<Self> {
+        let rest = peek(UntilEnd::default(), scanner)?
+            .map(|rest| rest.peeked_slice())
+            .unwrap_or_default();
+        let inner = rest.strip_prefix(b"(").ok_or(ParseError::UnexpectedToken)?;
+        let mut depth = 1usize;
+        let close = inner
+            .iter()
+            .position(|&byte| {
+                match byte {
+                    b'(' => depth = depth.saturating_add(1),
+                    b')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                depth == 0
+            })
+            .ok_or(ParseError::UnexpectedToken)?;
+        let mut inner_scanner =
+            Scanner::new(inner.get(..close).ok_or(ParseError::UnexpectedToken)?);
+        let key = Data::accept_with(&mut inner_scanner, quotes)?;
+        // Nothing but blanks follows the key, e.g. `mod("a" b)` isn't a key
+        OptionalBlanks::accept(&mut inner_scanner)?;
+        if !inner_scanner.remaining().is_empty() {
+            return Err(ParseError::UnexpectedToken);
+        }
+        // Both parentheses are consumed
+        scanner.bump_by(close.checked_add(2).ok_or(ParseError::UnexpectedToken)?);
 
-        Ok(Key(key))
+        Ok(ParenthesizedKey(key))
     }
 }
 
@@ -38,7 +68,8 @@ impl<'a> Visitor<'a, u8> for Key<'a> {
 // ----------------------------------------------------------------------------
 
 /// A comparison operator.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum OpType {
     /// Equal
     Equal,
@@ -46,10 +77,22 @@ pub enum OpType {
     GreaterThan,
     /// Less than
     LessThan,
+    /// Not equal, written `!=`, see
+    /// [`ParseOptions::etcd_version`](crate::options::ParseOptions::etcd_version).
+    NotEqual,
 }
 
 impl<'a> Visitor<'a, u8> for OpType {
+    /// Consume exactly one operator byte, two for `!=`, so that in
+    /// `val(k)=a=b` the value is `a=b`.
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        let rest = peek(UntilEnd::default(), scanner)?
+            .map(|rest| rest.peeked_slice())
+            .unwrap_or_default();
+        if rest.starts_with(b"!=") {
+            scanner.bump_by(2);
+            return Ok(OpType::NotEqual);
+        }
         let operator = Recognizer::new(scanner)
             .try_or(Token::Equal)?
             .try_or(Token::GreaterThan)?
@@ -60,47 +103,182 @@ impl<'a> Visitor<'a, u8> for OpType {
             Token::Equal => Ok(OpType::Equal),
             Token::GreaterThan => Ok(OpType::GreaterThan),
             Token::LessThan => Ok(OpType::LessThan),
-            _ => unreachable!("Recognizer should have caught this"),
+            _ => Err(ParseError::UnexpectedToken),
         }
     }
 }
 
+// ----------------------------------------------------------------------------
+// Numbers
+// ----------------------------------------------------------------------------
+
+/// A number compared with a numeric target.
+trait NumberLiteral<'a>: Visitor<'a, u8> {
+    fn value(self) -> u64;
+}
+
+impl NumberLiteral<'_> for Number<u64> {
+    fn value(self) -> u64 {
+        self.0
+    }
+}
+
+/// A number with an optional `0x`, `0o` or `0b` radix prefix, see
+/// [`ParseOptions::numeric_prefixes`](crate::options::ParseOptions::numeric_prefixes).
+struct RadixNumber(u64);
+
+impl<'a> Visitor<'a, u8> for RadixNumber {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        let rest = peek(UntilEnd::default(), scanner)?
+            .ok_or(ParseError::UnexpectedToken)?
+            .peeked_slice();
+        let (radix, digits) = match rest {
+            [b'0', b'x', digits @ ..] => (16, digits),
+            [b'0', b'o', digits @ ..] => (8, digits),
+            [b'0', b'b', digits @ ..] => (2, digits),
+            _ => return Ok(RadixNumber(Number::accept(scanner)?.0)),
+        };
+
+        let len = digits
+            .iter()
+            .take_while(|byte| byte.is_ascii_alphanumeric())
+            .count();
+        let (digits, _) = digits
+            .split_at_checked(len)
+            .ok_or(ParseError::UnexpectedToken)?;
+        let value = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u64::from_str_radix(digits, radix).ok())
+            .ok_or(ParseError::UnexpectedToken)?;
+
+        // The prefix is consumed along with the digits
+        scanner.bump_by(
+            digits
+                .len()
+                .checked_add(2)
+                .ok_or(ParseError::UnexpectedToken)?,
+        );
+        Ok(RadixNumber(value))
+    }
+}
+
+impl NumberLiteral<'_> for RadixNumber {
+    fn value(self) -> u64 {
+        self.0
+    }
+}
+
+/// A compare operation against a numeric target: `<name>(<key>) <op> <number>`.
+pub(crate) trait NumericCompare<'a>: Sized {
+    /// The uppercase word naming this target in etcd's compare dumps, see
+    /// [`ParseOptions::allow_target_words`].
+    const WORD: &'static [u8];
+
+    /// Whether the trimmed prefix names this target.
+    fn is_named(name: &[u8]) -> bool;
+
+    fn new(key: &'a [u8], key_kind: DataKind, op: OpType, value: u64) -> Self;
+}
+
+/// Parse a numeric compare operation, `N` being the accepted number syntax.
+/// The target may be written as its uppercase word if `target_words` is set.
+fn accept_numeric<'a, T: NumericCompare<'a>, N: NumberLiteral<'a>>(
+    scanner: &mut Scanner<'a, u8>,
+    quotes: &[u8],
+    target_words: bool,
+) -> ParseResult<T> {
+    OptionalBlanks::accept(scanner)?;
+    let prefix = peek(Token::OpenParen, scanner)?
+        .ok_or(ParseError::UnexpectedToken)?
+        .peeked_slice();
+    let name = prefix.trim_ascii_end();
+    if !(T::is_named(name) || (target_words && name == T::WORD)) {
+        return Err(ParseError::UnexpectedToken);
+    }
+
+    // Advance the scanner by the size of the prefix
+    scanner.bump_by(prefix.len());
+
+    let key = ParenthesizedKey::accept_with(scanner, quotes)?.0;
+
+    OptionalBlanks::accept(scanner)?;
+    let op = OpType::accept(scanner)?;
+    OptionalBlanks::accept(scanner)?;
+    let value = N::accept(scanner)?.value();
+
+    Ok(T::new(key.data, key.kind, op, value))
+}
+
+/// Compare a numeric compare on the bytes its key stands for, and decode its
+/// key.
+macro_rules! impl_numeric {
+    ($name:ident) => {
+        impl PartialEq for $name<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                same_data(
+                    (self.key.as_bytes(), self.key_kind),
+                    (other.key.as_bytes(), other.key_kind),
+                ) && self.value == other.value
+                    && self.op == other.op
+            }
+        }
+
+        impl<'a> $name<'a> {
+            /// The key with its escape sequences decoded when quoted, see
+            /// [`decode`](crate::escape::decode).
+            ///
+            /// # Errors
+            ///
+            /// An invalid escape sequence is an `UnexpectedToken` error.
+            pub fn decoded_key(&self) -> ParseResult<Cow<'a, [u8]>> {
+                decode(self.key.as_bytes(), self.key_kind)
+            }
+        }
+    };
+}
+
 // ----------------------------------------------------------------------------
 // Compare create revision
 // ----------------------------------------------------------------------------
 
 /// A create revision compare operation.
-#[derive(Debug, PartialEq)]
+///
+/// How the key is written doesn't take part in equality, the bytes it stands
+/// for do.
+#[derive(Debug)]
 pub struct CreateRevision<'a> {
     /// The key to compare.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
     /// The value to compare with.
     pub value: u64,
     /// The comparison operator.
     pub op: OpType,
 }
 
-impl<'a> Visitor<'a, u8> for CreateRevision<'a> {
-    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
-        let prefix = peek(Token::OpenParen, scanner)?
-            .ok_or(ParseError::UnexpectedToken)?
-            .peeked_slice();
-        if prefix.trim_ascii_end() != b"c" && prefix != b"create".trim_ascii_end() {
-            return Err(ParseError::UnexpectedToken);
-        }
+impl<'a> NumericCompare<'a> for CreateRevision<'a> {
+    const WORD: &'static [u8] = b"CREATE";
 
-        // Advance the scanner by the size of the prefix
-        scanner.bump_by(prefix.len());
+    fn is_named(name: &[u8]) -> bool {
+        name == b"c" || name == b"create"
+    }
 
-        let key = Key::accept(scanner)?.0;
+    fn new(key: &'a [u8], key_kind: DataKind, op: OpType, value: u64) -> Self {
+        CreateRevision {
+            key: Key(key),
+            key_kind,
+            value,
+            op,
+        }
+    }
+}
 
-        OptionalWhitespaces::accept(scanner)?;
-        let op = OpType::accept(scanner)?;
-        OptionalWhitespaces::accept(scanner)?;
-        let value = Number::accept(scanner)?.0;
+impl_numeric!(CreateRevision);
 
-        Ok(CreateRevision { key, value, op })
+impl<'a> Visitor<'a, u8> for CreateRevision<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        accept_numeric::<Self, Number<u64>>(scanner, DEFAULT_QUOTE_CHARS, false)
     }
 }
 
@@ -109,37 +287,43 @@ impl<'a> Visitor<'a, u8> for CreateRevision<'a> {
 // ----------------------------------------------------------------------------
 
 /// A modify revision compare operation.
-#[derive(Debug, PartialEq)]
+///
+/// How the key is written doesn't take part in equality, the bytes it stands
+/// for do.
+#[derive(Debug)]
 pub struct ModRevision<'a> {
     /// The key to compare.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
     /// The value to compare with.
     pub value: u64,
     /// The comparison operator.
     pub op: OpType,
 }
 
-impl<'a> Visitor<'a, u8> for ModRevision<'a> {
-    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
-        let prefix = peek(Token::OpenParen, scanner)?
-            .ok_or(ParseError::UnexpectedToken)?
-            .peeked_slice();
-        if prefix.trim_ascii_end() != b"m" && prefix != b"mod".trim_ascii_end() {
-            return Err(ParseError::UnexpectedToken);
-        }
+impl<'a> NumericCompare<'a> for ModRevision<'a> {
+    const WORD: &'static [u8] = b"MOD";
 
-        // Advance the scanner by the size of the prefix
-        scanner.bump_by(prefix.len());
+    fn is_named(name: &[u8]) -> bool {
+        name == b"m" || name == b"mod"
+    }
 
-        let key = Key::accept(scanner)?.0;
+    fn new(key: &'a [u8], key_kind: DataKind, op: OpType, value: u64) -> Self {
+        ModRevision {
+            key: Key(key),
+            key_kind,
+            value,
+            op,
+        }
+    }
+}
 
-        OptionalWhitespaces::accept(scanner)?;
-        let op = OpType::accept(scanner)?;
-        OptionalWhitespaces::accept(scanner)?;
-        let value = Number::accept(scanner)?.0;
+impl_numeric!(ModRevision);
 
-        Ok(ModRevision { key, value, op })
+impl<'a> Visitor<'a, u8> for ModRevision<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        accept_numeric::<Self, Number<u64>>(scanner, DEFAULT_QUOTE_CHARS, false)
     }
 }
 
@@ -148,40 +332,125 @@ impl<'a> Visitor<'a, u8> for ModRevision<'a> {
 // ----------------------------------------------------------------------------
 
 /// A value compare operation.
-#[derive(Debug, PartialEq)]
+///
+/// How the key and the value are written doesn't take part in equality, the
+/// bytes they stand for do.
+#[derive(Debug)]
 pub struct Value<'a> {
     /// The key to compare.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
     /// The value to compare with.
-    pub value: &'a [u8],
+    pub value: data::Value<'a>,
+    /// How the value is written.
+    pub value_kind: DataKind,
     /// The comparison operator.
     pub op: OpType,
 }
 
-impl<'a> Visitor<'a, u8> for Value<'a> {
-    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
+impl PartialEq for Value<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        same_data(
+            (self.key.as_bytes(), self.key_kind),
+            (other.key.as_bytes(), other.key_kind),
+        ) && same_data(
+            (self.value.as_bytes(), self.value_kind),
+            (other.value.as_bytes(), other.value_kind),
+        ) && self.op == other.op
+    }
+}
+
+impl<'a> Value<'a> {
+    /// The uppercase word naming the target in etcd's compare dumps.
+    pub(crate) const WORD: &'static [u8] = b"VALUE";
+
+    /// Whether the trimmed prefix names the target.
+    pub(crate) fn is_named(name: &[u8]) -> bool {
+        name == b"val" || name == b"value"
+    }
+
+    /// The key with its escape sequences decoded when quoted, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_key(&self) -> ParseResult<Cow<'a, [u8]>> {
+        decode(self.key.as_bytes(), self.key_kind)
+    }
+
+    /// The compared value with its escape sequences decoded when quoted.
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_value(&self) -> ParseResult<Cow<'a, [u8]>> {
+        decode(self.value.as_bytes(), self.value_kind)
+    }
+
+    /// Parse a value compare operation whose key and value may be quoted by
+    /// `quotes`. The target may be written as its uppercase word if
+    /// `target_words` is set.
+    fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        quotes: &[u8],
+        target_words: bool,
+    ) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
         let prefix = peek(Token::OpenParen, scanner)?
             .ok_or(ParseError::UnexpectedToken)?
             .peeked_slice();
-        if prefix.trim_ascii_end() != b"val" && prefix != b"value".trim_ascii_end() {
+        let name = prefix.trim_ascii_end();
+        if !(Value::is_named(name) || (target_words && name == Value::WORD)) {
             return Err(ParseError::UnexpectedToken);
         }
 
         // Advance the scanner by the size of the prefix
         scanner.bump_by(prefix.len());
 
-        let key = Key::accept(scanner)?.0;
+        let key = ParenthesizedKey::accept_with(scanner, quotes)?.0;
 
-        OptionalWhitespaces::accept(scanner)?;
+        OptionalBlanks::accept(scanner)?;
         let op = OpType::accept(scanner)?;
-        OptionalWhitespaces::accept(scanner)?;
-
-        let value = Data::accept(scanner)?.data;
-
-        OptionalWhitespaces::accept(scanner)?;
+        OptionalBlanks::accept(scanner)?;
+
+        // An unquoted value runs until the end of the line, spaces included,
+        // without its trailing whitespaces
+        let rest = peek(UntilEnd::default(), scanner)?
+            .map(|rest| rest.peeked_slice().trim_ascii_end())
+            .unwrap_or_default();
+        let start = scanner.current_position();
+        let value = Data::accept_with(scanner, quotes)?;
+        let value_kind = value.kind();
+        let value = match value_kind {
+            DataKind::Quoted | DataKind::SingleQuoted | DataKind::Bytes => value.data(),
+            DataKind::Unquoted => {
+                // The word may have taken some of the trailing whitespaces
+                scanner.jump_to(
+                    start
+                        .checked_add(rest.len())
+                        .ok_or(ParseError::UnexpectedToken)?,
+                );
+                rest
+            }
+        };
+
+        OptionalBlanks::accept(scanner)?;
+
+        Ok(Value {
+            key: Key(key.data),
+            key_kind: key.kind,
+            value: data::Value(value),
+            value_kind,
+            op,
+        })
+    }
+}
 
-        Ok(Value { key, value, op })
+impl<'a> Visitor<'a, u8> for Value<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        Value::accept_with(scanner, DEFAULT_QUOTE_CHARS, false)
     }
 }
 
@@ -190,37 +459,43 @@ impl<'a> Visitor<'a, u8> for Value<'a> {
 // ----------------------------------------------------------------------------
 
 /// A version compare operation.
-#[derive(Debug, PartialEq)]
+///
+/// How the key is written doesn't take part in equality, the bytes it stands
+/// for do.
+#[derive(Debug)]
 pub struct Version<'a> {
     /// The key to compare.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
     /// The value to compare with.
     pub value: u64,
     /// The comparison operator.
     pub op: OpType,
 }
 
-impl<'a> Visitor<'a, u8> for Version<'a> {
-    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
-        let prefix = peek(Token::OpenParen, scanner)?
-            .ok_or(ParseError::UnexpectedToken)?
-            .peeked_slice();
-        if prefix.trim_ascii_end() != b"ver" && prefix != b"version".trim_ascii_end() {
-            return Err(ParseError::UnexpectedToken);
-        }
+impl<'a> NumericCompare<'a> for Version<'a> {
+    const WORD: &'static [u8] = b"VERSION";
 
-        // Advance the scanner by the size of the prefix
-        scanner.bump_by(prefix.len());
+    fn is_named(name: &[u8]) -> bool {
+        name == b"ver" || name == b"version"
+    }
 
-        let key = Key::accept(scanner)?.0;
+    fn new(key: &'a [u8], key_kind: DataKind, op: OpType, value: u64) -> Self {
+        Version {
+            key: Key(key),
+            key_kind,
+            value,
+            op,
+        }
+    }
+}
 
-        OptionalWhitespaces::accept(scanner)?;
-        let op = OpType::accept(scanner)?;
-        OptionalWhitespaces::accept(scanner)?;
-        let value = Number::accept(scanner)?.0;
+impl_numeric!(Version);
 
-        Ok(Version { key, value, op })
+impl<'a> Visitor<'a, u8> for Version<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        accept_numeric::<Self, Number<u64>>(scanner, DEFAULT_QUOTE_CHARS, false)
     }
 }
 
@@ -229,37 +504,122 @@ impl<'a> Visitor<'a, u8> for Version<'a> {
 // ----------------------------------------------------------------------------
 
 /// A lease compare operation.
-#[derive(Debug, PartialEq)]
+///
+/// How the key is written doesn't take part in equality, the bytes it stands
+/// for do.
+#[derive(Debug)]
 pub struct Lease<'a> {
     /// The key to compare.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
     /// The value to compare with.
     pub value: u64,
     /// The comparison operator.
     pub op: OpType,
 }
 
+impl<'a> NumericCompare<'a> for Lease<'a> {
+    const WORD: &'static [u8] = b"LEASE";
+
+    fn is_named(name: &[u8]) -> bool {
+        name == b"lease"
+    }
+
+    fn new(key: &'a [u8], key_kind: DataKind, op: OpType, value: u64) -> Self {
+        Lease {
+            key: Key(key),
+            key_kind,
+            value,
+            op,
+        }
+    }
+}
+
+impl_numeric!(Lease);
+
 impl<'a> Visitor<'a, u8> for Lease<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
-        let prefix = peek(Token::OpenParen, scanner)?
-            .ok_or(ParseError::UnexpectedToken)?
-            .peeked_slice();
-        if prefix.trim_ascii_end() != b"lease" {
-            return Err(ParseError::UnexpectedToken);
-        }
+        accept_numeric::<Self, Number<u64>>(scanner, DEFAULT_QUOTE_CHARS, false)
+    }
+}
 
-        // Advance the scanner by the size of the prefix
-        scanner.bump_by(prefix.len());
+// ----------------------------------------------------------------------------
+// Custom compares
+// ----------------------------------------------------------------------------
 
-        let key = Key::accept(scanner)?.0;
+/// A parser of a compare target etcd doesn't know, registered in
+/// [`ParseOptions::compare_parsers`].
+///
+/// ```
+/// use elyze::errors::{ParseError, ParseResult};
+/// use etcd_txn_parser::compare::CompareParser;
+///
+/// /// `locked(<name>)`
+/// #[derive(Debug)]
+/// struct Locked;
+///
+/// impl CompareParser for Locked {
+///     fn target(&self) -> &[u8] {
+///         b"locked"
+///     }
+///
+///     fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+///         let name = args
+///             .strip_prefix(b"(")
+///             .and_then(|args| args.strip_suffix(b")"))
+///             .ok_or(ParseError::UnexpectedToken)?;
+///         Ok(vec![name])
+///     }
+/// }
+/// ```
+pub trait CompareParser: Debug + Send + Sync {
+    /// The name of the target, ending the first word of the lines parsed
+    /// before a whitespace or an opening parenthesis, e.g. `locked`.
+    fn target(&self) -> &[u8];
+
+    /// Split the rest of the line, leading whitespaces excluded, into the
+    /// arguments of the compare.
+    ///
+    /// # Errors
+    ///
+    /// An error rejects the line.
+    fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>>;
+}
 
-        OptionalWhitespaces::accept(scanner)?;
-        let op = OpType::accept(scanner)?;
-        OptionalWhitespaces::accept(scanner)?;
-        let value = Number::accept(scanner)?.0;
+/// A compare parsed by a registered [`CompareParser`].
+#[derive(Debug, PartialEq)]
+pub struct CustomCompare<'a> {
+    /// The target of the compare, e.g. `locked`.
+    pub target: &'a [u8],
+    /// The arguments returned by the parser.
+    pub args: Vec<&'a [u8]>,
+}
 
-        Ok(Lease { key, value, op })
+impl<'a> CustomCompare<'a> {
+    /// Parse a line with the first of the parsers handling its target.
+    fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        parsers: &[Arc<dyn CompareParser>],
+    ) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
+        let rest = peek(UntilEnd::default(), scanner)?
+            .ok_or(ParseError::UnexpectedToken)?
+            .peeked_slice();
+        let end = rest
+            .iter()
+            .position(|&byte| byte.is_ascii_whitespace() || byte == b'(')
+            .unwrap_or(rest.len());
+        let (target, args) = rest
+            .split_at_checked(end)
+            .ok_or(ParseError::UnexpectedToken)?;
+        let parser = parsers
+            .iter()
+            .find(|parser| parser.target() == target)
+            .ok_or(ParseError::UnexpectedToken)?;
+        let args = parser.parse_args(args.trim_ascii_start())?;
+        scanner.bump_by(rest.len());
+        Ok(CustomCompare { target, args })
     }
 }
 
@@ -269,6 +629,7 @@ impl<'a> Visitor<'a, u8> for Lease<'a> {
 
 /// A compare operation.
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Compare<'a> {
     /// A create revision compare operation.
     CreateRevision(CreateRevision<'a>),
@@ -280,28 +641,484 @@ pub enum Compare<'a> {
     Version(Version<'a>),
     /// A lease compare operation.
     Lease(Lease<'a>),
+    /// A compare parsed by one of the
+    /// [`ParseOptions::compare_parsers`](crate::options::ParseOptions::compare_parsers).
+    Custom(CustomCompare<'a>),
+    /// A line kept as is because it can't be parsed, see
+    /// [`ParseOptions::keep_unknown_lines`](crate::options::ParseOptions::keep_unknown_lines).
+    Unknown(&'a [u8]),
+}
+
+/// A parser of one kind of compare operation, taking the quote characters and
+/// whether the uppercase target words are accepted.
+type Alternative<'a> = fn(&mut Scanner<'a, u8>, &[u8], bool) -> ParseResult<Compare<'a>>;
+
+/// Parse a compare operation, `N` being the accepted number syntax, `quotes`
+/// the characters quoting keys and values and `target_words` whether the
+/// uppercase target words are accepted.
+fn accept_compare<'a, N: NumberLiteral<'a>>(
+    scanner: &mut Scanner<'a, u8>,
+    quotes: &[u8],
+    target_words: bool,
+) -> ParseResult<Compare<'a>> {
+    let alternatives: [Alternative<'a>; 5] = [
+        |scanner, quotes, words| {
+            accept_numeric::<_, N>(scanner, quotes, words).map(Compare::ModRevision)
+        },
+        |scanner, quotes, words| {
+            accept_numeric::<_, N>(scanner, quotes, words).map(Compare::CreateRevision)
+        },
+        |scanner, quotes, words| Value::accept_with(scanner, quotes, words).map(Compare::Value),
+        |scanner, quotes, words| {
+            accept_numeric::<_, N>(scanner, quotes, words).map(Compare::Version)
+        },
+        |scanner, quotes, words| accept_numeric::<_, N>(scanner, quotes, words).map(Compare::Lease),
+    ];
+    for accept in alternatives {
+        if let Some(compare) = attempt(scanner, |scanner| accept(scanner, quotes, target_words))? {
+            return Ok(compare);
+        }
+    }
+    Err(ParseError::UnexpectedToken)
 }
 
 impl<'a> Visitor<'a, u8> for Compare<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        let compare = Acceptor::new(scanner)
-            .try_or(Compare::ModRevision)?
-            .try_or(Compare::CreateRevision)?
-            .try_or(Compare::Value)?
-            .try_or(Compare::Version)?
-            .try_or(Compare::Lease)?
-            .finish()
-            .ok_or(ParseError::UnexpectedToken)?;
+        accept_compare::<Number<u64>>(scanner, DEFAULT_QUOTE_CHARS, false)
+    }
+}
+
+/// The compare as a line of the text format, as written by
+/// [`Compare::to_text`], e.g. `mod("key1") > 0`, so that the output parses
+/// back to the compare.
+///
+/// The target is written in its long form, the key and a compared value
+/// between quotes. They parse back to the same bytes, like
+/// [`Operation`](crate::operation::Operation)'s `Display` does: the bytes
+/// which aren't valid UTF-8, and the parentheses unbalancing a key, are
+/// written as `\xNN` escape sequences. Quoted data which can't be written as
+/// is and holds an invalid escape sequence is marked as
+/// `"..." <invalid escape>`, which doesn't parse.
+impl Display for Compare<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        crate::writer::display_with(f, |text, inexpressible| {
+            self.write_with(text, inexpressible)
+        })
+    }
+}
+
+/// Display a numeric compare like the [`Compare`] wrapping it.
+macro_rules! impl_display {
+    ($name:ident) => {
+        impl Display for $name<'_> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                let compare = Compare::$name($name {
+                    key: self.key,
+                    key_kind: self.key_kind,
+                    value: self.value,
+                    op: self.op,
+                });
+                Display::fmt(&compare, f)
+            }
+        }
+    };
+}
+
+impl_display!(CreateRevision);
+impl_display!(ModRevision);
+impl_display!(Version);
+impl_display!(Lease);
+
+/// Displayed like the [`Compare`] wrapping it.
+impl Display for Value<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let compare = Compare::Value(Value {
+            key: self.key,
+            key_kind: self.key_kind,
+            value: self.value,
+            value_kind: self.value_kind,
+            op: self.op,
+        });
+        Display::fmt(&compare, f)
+    }
+}
+
+impl<'a> Compare<'a> {
+    /// Parse a whole line as a compare operation, e.g. `mod("key1") > 0`.
+    ///
+    /// A trailing line feed is allowed. Errors can be rendered with
+    /// [`render_error`](crate::error::render_error).
+    pub fn parse(line: &'a [u8]) -> ParseResult<Self> {
+        crate::parse_line(line, &ParseOptions::default())
+    }
+
+    /// Parse a compare operation with the syntax selected by the options.
+    pub(crate) fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        options: &ParseOptions,
+    ) -> ParseResult<Self> {
+        let (quotes, words) = (options.quote_chars, options.allow_target_words);
+        let compare = if options.numeric_prefixes {
+            accept_compare::<RadixNumber>(scanner, quotes, words)
+        } else {
+            accept_compare::<Number<u64>>(scanner, quotes, words)
+        };
+        match compare {
+            // The built-in targets can't be overridden
+            Err(_) if !options.compare_parsers.is_empty() => {
+                CustomCompare::accept_with(scanner, &options.compare_parsers).map(Compare::Custom)
+            }
+            compare => compare,
+        }
+    }
+}
+
+/// The etcd version from which `!=` is supported, see
+/// [`ParseOptions::etcd_version`].
+const NOT_EQUAL_SINCE: (u32, u32) = (3, 3);
+
+/// Whether the trimmed prefix names a built-in target, or its uppercase word
+/// if `target_words` is set.
+fn is_target(name: &[u8], target_words: bool) -> bool {
+    let named: [fn(&[u8]) -> bool; 5] = [
+        CreateRevision::is_named,
+        ModRevision::is_named,
+        Value::is_named,
+        Version::is_named,
+        Lease::is_named,
+    ];
+    let words = [
+        CreateRevision::WORD,
+        ModRevision::WORD,
+        Value::WORD,
+        Version::WORD,
+        Lease::WORD,
+    ];
+    named.iter().any(|is_named| is_named(name)) || (target_words && words.contains(&name))
+}
+
+/// The position in `line` and the text of the operator of its compare, if
+/// the etcd version targeted by the options doesn't support it, e.g. the
+/// `!=` of `mod(key) != 5` for etcd 3.2.
+pub(crate) fn unsupported_operator(
+    line: &[u8],
+    options: &ParseOptions,
+) -> Option<(usize, &'static str)> {
+    let version = options.etcd_version?;
+    let mut scanner = Scanner::new(line);
+    OptionalBlanks::accept(&mut scanner).ok()?;
+    let prefix = peek(Token::OpenParen, &scanner).ok()??.peeked_slice();
+    if !is_target(prefix.trim_ascii_end(), options.allow_target_words) {
+        return None;
+    }
+    scanner.bump_by(prefix.len());
+    ParenthesizedKey::accept_with(&mut scanner, options.quote_chars).ok()?;
+    OptionalBlanks::accept(&mut scanner).ok()?;
+
+    let position = scanner.current_position();
+    let operator = match line.get(position..)? {
+        [b'!', b'=', ..] if version < NOT_EQUAL_SINCE => "!=",
+        [b'>', b'=', ..] => ">=",
+        [b'<', b'=', ..] => "<=",
+        _ => return None,
+    };
+    Some((position, operator))
+}
+
+/// The kind of a compare operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CompareKind {
+    /// A create revision compare operation.
+    CreateRevision,
+    /// A modify revision compare operation.
+    ModRevision,
+    /// A value compare operation.
+    Value,
+    /// A version compare operation.
+    Version,
+    /// A lease compare operation.
+    Lease,
+    /// A compare parsed by a registered parser.
+    Custom,
+    /// A line kept as is because it can't be parsed.
+    Unknown,
+}
+
+impl<'a> Compare<'a> {
+    /// Create a create revision compare operation.
+    pub fn create_revision(key: &'a [u8], op: OpType, value: u64) -> Self {
+        Compare::CreateRevision(CreateRevision {
+            key: Key(key),
+            key_kind: DataKind::default(),
+            value,
+            op,
+        })
+    }
+
+    /// Create a modify revision compare operation.
+    pub fn mod_revision(key: &'a [u8], op: OpType, value: u64) -> Self {
+        Compare::ModRevision(ModRevision {
+            key: Key(key),
+            key_kind: DataKind::default(),
+            value,
+            op,
+        })
+    }
+
+    /// Create a value compare operation.
+    pub fn value(key: &'a [u8], op: OpType, value: &'a [u8]) -> Self {
+        Compare::Value(Value {
+            key: Key(key),
+            key_kind: DataKind::default(),
+            value: data::Value(value),
+            value_kind: DataKind::default(),
+            op,
+        })
+    }
+
+    /// Create a version compare operation.
+    pub fn version(key: &'a [u8], op: OpType, value: u64) -> Self {
+        Compare::Version(Version {
+            key: Key(key),
+            key_kind: DataKind::default(),
+            value,
+            op,
+        })
+    }
+
+    /// Create a lease compare operation.
+    pub fn lease(key: &'a [u8], op: OpType, value: u64) -> Self {
+        Compare::Lease(Lease {
+            key: Key(key),
+            key_kind: DataKind::default(),
+            value,
+            op,
+        })
+    }
+
+    /// The kind of the compare operation.
+    pub fn kind(&self) -> CompareKind {
+        match self {
+            Compare::CreateRevision(_) => CompareKind::CreateRevision,
+            Compare::ModRevision(_) => CompareKind::ModRevision,
+            Compare::Value(_) => CompareKind::Value,
+            Compare::Version(_) => CompareKind::Version,
+            Compare::Lease(_) => CompareKind::Lease,
+            Compare::Custom(_) => CompareKind::Custom,
+            Compare::Unknown(_) => CompareKind::Unknown,
+        }
+    }
+
+    /// The compared key, `None` for a custom or an unknown compare.
+    pub fn key(&self) -> Option<Key<'a>> {
+        match self {
+            Compare::CreateRevision(compare) => Some(compare.key),
+            Compare::ModRevision(compare) => Some(compare.key),
+            Compare::Value(compare) => Some(compare.key),
+            Compare::Version(compare) => Some(compare.key),
+            Compare::Lease(compare) => Some(compare.key),
+            Compare::Custom(_) | Compare::Unknown(_) => None,
+        }
+    }
 
-        Ok(compare)
+    /// The compared key with its escape sequences decoded when quoted, `None`
+    /// for a custom or an unknown compare, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_key(&self) -> ParseResult<Option<Cow<'a, [u8]>>> {
+        match self {
+            Compare::CreateRevision(compare) => compare.decoded_key().map(Some),
+            Compare::ModRevision(compare) => compare.decoded_key().map(Some),
+            Compare::Value(compare) => compare.decoded_key().map(Some),
+            Compare::Version(compare) => compare.decoded_key().map(Some),
+            Compare::Lease(compare) => compare.decoded_key().map(Some),
+            Compare::Custom(_) | Compare::Unknown(_) => Ok(None),
+        }
+    }
+
+    /// The comparison operator, `None` for a custom or an unknown compare.
+    pub fn op(&self) -> Option<OpType> {
+        match self {
+            Compare::CreateRevision(compare) => Some(compare.op),
+            Compare::ModRevision(compare) => Some(compare.op),
+            Compare::Value(compare) => Some(compare.op),
+            Compare::Version(compare) => Some(compare.op),
+            Compare::Lease(compare) => Some(compare.op),
+            Compare::Custom(_) | Compare::Unknown(_) => None,
+        }
+    }
+
+    /// The number compared with, `None` for a value, a custom or an unknown
+    /// compare.
+    pub fn number(&self) -> Option<u64> {
+        match self {
+            Compare::CreateRevision(compare) => Some(compare.value),
+            Compare::ModRevision(compare) => Some(compare.value),
+            Compare::Version(compare) => Some(compare.value),
+            Compare::Lease(compare) => Some(compare.value),
+            Compare::Value(_) | Compare::Custom(_) | Compare::Unknown(_) => None,
+        }
     }
+
+    /// The bytes compared with, `None` unless this is a value compare.
+    pub fn expected_value(&self) -> Option<data::Value<'a>> {
+        match self {
+            Compare::Value(compare) => Some(compare.value),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a create revision compare operation.
+    pub fn is_create_revision(&self) -> bool {
+        matches!(self, Compare::CreateRevision(_))
+    }
+
+    /// Whether this is a modify revision compare operation.
+    pub fn is_mod_revision(&self) -> bool {
+        matches!(self, Compare::ModRevision(_))
+    }
+
+    /// Whether this is a value compare operation.
+    pub fn is_value(&self) -> bool {
+        matches!(self, Compare::Value(_))
+    }
+
+    /// Whether this is a version compare operation.
+    pub fn is_version(&self) -> bool {
+        matches!(self, Compare::Version(_))
+    }
+
+    /// Whether this is a lease compare operation.
+    pub fn is_lease(&self) -> bool {
+        matches!(self, Compare::Lease(_))
+    }
+
+    /// Whether this is a compare parsed by a registered parser.
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Compare::Custom(_))
+    }
+
+    /// Whether this is a line kept as is because it can't be parsed.
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, Compare::Unknown(_))
+    }
+
+    /// Whether the compare only succeeds if the key doesn't exist, e.g.
+    /// `create(k) = 0` or `version(k) < 1`.
+    ///
+    /// etcd reports a create revision and a version of zero for a missing key.
+    pub fn checks_absence(&self) -> bool {
+        matches!(
+            self,
+            Compare::CreateRevision(CreateRevision {
+                value: 0,
+                op: OpType::Equal,
+                ..
+            }) | Compare::CreateRevision(CreateRevision {
+                value: 1,
+                op: OpType::LessThan,
+                ..
+            }) | Compare::Version(Version {
+                value: 0,
+                op: OpType::Equal,
+                ..
+            }) | Compare::Version(Version {
+                value: 1,
+                op: OpType::LessThan,
+                ..
+            })
+        )
+    }
+
+    /// Whether the compare only succeeds if the key exists, e.g.
+    /// `create(k) > 0` or `version(k) > 0`.
+    pub fn checks_presence(&self) -> bool {
+        matches!(
+            self,
+            Compare::CreateRevision(CreateRevision {
+                value: 0,
+                op: OpType::GreaterThan,
+                ..
+            }) | Compare::Version(Version {
+                value: 0,
+                op: OpType::GreaterThan,
+                ..
+            })
+        )
+    }
+
+    /// Whether the compare only tests the existence of its key, see
+    /// [`Compare::checks_absence`] and [`Compare::checks_presence`].
+    pub fn is_existence_check(&self) -> bool {
+        self.checks_absence() || self.checks_presence()
+    }
+
+    /// Whether the compare can never succeed, whatever the key holds, e.g.
+    /// `version(k) < 0`.
+    ///
+    /// Revisions, versions and lease IDs are never negative, and no value
+    /// sorts before the empty one. Custom and unknown compares are never
+    /// reported.
+    pub fn is_unsatisfiable(&self) -> bool {
+        match self {
+            Compare::Value(compare) => compare.op == OpType::LessThan && compare.value.is_empty(),
+            _ => self.op() == Some(OpType::LessThan) && self.number() == Some(0),
+        }
+    }
+}
+
+/// Build the optimistic concurrency guards of keys read at a snapshot, given
+/// as pairs of a key and its mod revision.
+///
+/// A key read with a mod revision must still have it, `mod(key) = revision`.
+/// A key read as absent, with a mod revision of 0, must still be absent,
+/// `create(key) = 0`: etcd reports a create revision of 0 for a missing key.
+/// The guards keep the order of the snapshot.
+pub fn guards_from_snapshot<'a>(snapshot: &[(&'a [u8], u64)]) -> Vec<Compare<'a>> {
+    snapshot
+        .iter()
+        .map(|&(key, revision)| match revision {
+            0 => Compare::create_revision(key, OpType::Equal, 0),
+            revision => Compare::mod_revision(key, OpType::Equal, revision),
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::compare::{Compare, CreateRevision, Lease, ModRevision, OpType, Value, Version};
+    use crate::compare::{
+        guards_from_snapshot, unsupported_operator, Compare, CompareParser, CreateRevision,
+        CustomCompare, Lease, ModRevision, OpType, Value, Version,
+    };
+    use crate::data::{self, Key};
+    use crate::options::ParseOptions;
+    use elyze::errors::{ParseError, ParseResult};
     use elyze::scanner::Scanner;
     use elyze::visitor::Visitor;
+    use std::sync::Arc;
+
+    /// `<target>(<name>)`
+    #[derive(Debug)]
+    struct Named(&'static [u8]);
+
+    impl CompareParser for Named {
+        fn target(&self) -> &[u8] {
+            self.0
+        }
+
+        fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+            let name = args
+                .trim_ascii_end()
+                .strip_prefix(b"(")
+                .and_then(|args| args.strip_suffix(b")"))
+                .ok_or(ParseError::UnexpectedToken)?;
+            Ok(vec![name])
+        }
+    }
 
     #[test]
     fn test_create_revision() {
@@ -311,9 +1128,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::CreateRevision(CreateRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -323,9 +1141,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::CreateRevision(CreateRevision {
-                key: b"key with spaces",
+                key: Key(b"key with spaces"),
                 value: 51515221,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -335,9 +1154,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::CreateRevision(CreateRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -347,9 +1167,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::CreateRevision(CreateRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::GreaterThan
+                op: OpType::GreaterThan,
+                ..
             }))
         ));
 
@@ -359,9 +1180,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::CreateRevision(CreateRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::LessThan
+                op: OpType::LessThan,
+                ..
             }))
         ));
     }
@@ -374,9 +1196,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::ModRevision(ModRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -386,9 +1209,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::ModRevision(ModRevision {
-                key: b"key with spaces",
+                key: Key(b"key with spaces"),
                 value: 51515221,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -398,9 +1222,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::ModRevision(ModRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -410,9 +1235,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::ModRevision(ModRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::GreaterThan
+                op: OpType::GreaterThan,
+                ..
             }))
         ));
 
@@ -422,9 +1248,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::ModRevision(ModRevision {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::LessThan
+                op: OpType::LessThan,
+                ..
             }))
         ));
     }
@@ -437,9 +1264,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Value(Value {
-                key: b"key",
-                value: b"data",
-                op: OpType::Equal
+                key: Key(b"key"),
+                value: data::Value(b"data"),
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -449,9 +1277,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Value(Value {
-                key: b"key with spaces",
-                value: b"data",
-                op: OpType::Equal
+                key: Key(b"key with spaces"),
+                value: data::Value(b"data"),
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -461,9 +1290,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Value(Value {
-                key: b"key",
-                value: b"data",
-                op: OpType::Equal
+                key: Key(b"key"),
+                value: data::Value(b"data"),
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -473,9 +1303,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Value(Value {
-                key: b"key",
-                value: b"data",
-                op: OpType::GreaterThan
+                key: Key(b"key"),
+                value: data::Value(b"data"),
+                op: OpType::GreaterThan,
+                ..
             }))
         ));
 
@@ -485,9 +1316,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Value(Value {
-                key: b"key",
-                value: b"data",
-                op: OpType::LessThan
+                key: Key(b"key"),
+                value: data::Value(b"data"),
+                op: OpType::LessThan,
+                ..
             }))
         ));
     }
@@ -500,9 +1332,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Version(Version {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -512,9 +1345,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Version(Version {
-                key: b"key with spaces",
+                key: Key(b"key with spaces"),
                 value: 51515221,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -524,9 +1358,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Version(Version {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -536,9 +1371,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Version(Version {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::GreaterThan
+                op: OpType::GreaterThan,
+                ..
             }))
         ));
 
@@ -548,9 +1384,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Version(Version {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::LessThan
+                op: OpType::LessThan,
+                ..
             }))
         ));
     }
@@ -563,9 +1400,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Lease(Lease {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -575,9 +1413,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Lease(Lease {
-                key: b"key with spaces",
+                key: Key(b"key with spaces"),
                 value: 51515221,
-                op: OpType::Equal
+                op: OpType::Equal,
+                ..
             }))
         ));
 
@@ -587,9 +1426,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Lease(Lease {
-                key: b"key",
+                key: Key(b"key"),
                 value: 1,
-                op: OpType::GreaterThan
+                op: OpType::GreaterThan,
+                ..
             }))
         ));
 
@@ -599,10 +1439,593 @@ mod tests {
         assert!(matches!(
             result,
             Ok(Compare::Lease(Lease {
-                key: b"key",
+                key: Key(b"key"),
+                value: 1,
+                op: OpType::LessThan,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_prefix_followed_by_whitespaces() {
+        let data = b"create (key) = 1";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::CreateRevision(CreateRevision {
+                key: Key(b"key"),
+                ..
+            }))
+        ));
+
+        let data = b"mod\t(key) > 0";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::ModRevision(ModRevision {
+                key: Key(b"key"),
+                ..
+            }))
+        ));
+
+        let data = b"value (key) = \"toto\"";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::Value(Value {
+                key: Key(b"key"),
+                ..
+            }))
+        ));
+
+        let data = b"ver  (key) < 3";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::Version(Version {
+                key: Key(b"key"),
+                ..
+            }))
+        ));
+
+        let data = b"lease (key) = 5";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::Lease(Lease {
+                key: Key(b"key"),
+                ..
+            }))
+        ));
+
+        let data = b"modx (key) > 0";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(result.is_err());
+
+        // The parentheses of the key must be balanced
+        let data = b"mod((key) > 0";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(result.is_err());
+
+        // The key is a single word
+        for data in [&br#"mod("key" x) > 0"#[..], b"mod(key x) > 0"] {
+            assert!(
+                Compare::accept(&mut Scanner::new(data)).is_err(),
+                "{data:?}"
+            );
+        }
+        let data = br#"mod("key" ) > 0"#;
+        assert!(Compare::accept(&mut Scanner::new(data)).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_operators() {
+        for data in [
+            &b"mod(key) >= 0"[..],
+            b"mod(key) => 0",
+            b"mod(key) 0",
+            b"mod(key)",
+        ] {
+            assert!(
+                Compare::accept(&mut Scanner::new(data)).is_err(),
+                "{data:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_not_equal() {
+        let accept = |data: &'static [u8]| Compare::accept(&mut Scanner::new(data)).ok();
+        assert_eq!(
+            accept(b"create(key) != 1"),
+            Some(Compare::create_revision(b"key", OpType::NotEqual, 1))
+        );
+        assert_eq!(
+            accept(b"mod(key)!=2"),
+            Some(Compare::mod_revision(b"key", OpType::NotEqual, 2))
+        );
+        assert_eq!(
+            accept(b"value(\"key\") != \"foo\""),
+            Some(Compare::value(b"key", OpType::NotEqual, b"foo"))
+        );
+        assert_eq!(
+            accept(b"ver(key) != 3"),
+            Some(Compare::version(b"key", OpType::NotEqual, 3))
+        );
+        assert_eq!(
+            accept(b"lease(key) != 4"),
+            Some(Compare::lease(b"key", OpType::NotEqual, 4))
+        );
+
+        // `!=` is recognized before `=`, which may start or end a value
+        assert_eq!(
+            accept(b"val(key) != =a"),
+            Some(Compare::value(b"key", OpType::NotEqual, b"=a"))
+        );
+        assert_eq!(
+            accept(b"val(key) = !="),
+            Some(Compare::value(b"key", OpType::Equal, b"!="))
+        );
+        assert_eq!(accept(b"mod(key) ! 2"), None);
+    }
+
+    #[test]
+    fn test_unsupported_operator() {
+        let options = |etcd_version| ParseOptions {
+            etcd_version,
+            ..Default::default()
+        };
+        let data = b" mod(key) != 0";
+        assert_eq!(
+            Compare::accept(&mut Scanner::new(data)).ok(),
+            Some(Compare::mod_revision(b"key", OpType::NotEqual, 0))
+        );
+        assert_eq!(unsupported_operator(data, &options(None)), None);
+        assert_eq!(unsupported_operator(data, &options(Some((3, 3)))), None);
+        assert_eq!(
+            unsupported_operator(data, &options(Some((3, 2)))),
+            Some((10, "!="))
+        );
+        assert_eq!(
+            unsupported_operator(b"val(key)>=a", &options(Some((3, 5)))),
+            Some((8, ">="))
+        );
+        // Only the built-in targets are checked
+        assert_eq!(
+            unsupported_operator(b"held(key) <= 0", &options(Some((3, 5)))),
+            None
+        );
+        assert_eq!(
+            unsupported_operator(b"MOD(key) <= 0", &options(Some((3, 5)))),
+            None
+        );
+        let words = ParseOptions {
+            allow_target_words: true,
+            ..options(Some((3, 5)))
+        };
+        assert_eq!(
+            unsupported_operator(b"MOD(key) <= 0", &words),
+            Some((9, "<="))
+        );
+    }
+
+    #[test]
+    fn test_custom_compare() {
+        let accept = |data: &'static [u8], options: &ParseOptions| {
+            let mut scanner = Scanner::new(data);
+            Compare::accept_with(&mut scanner, options)
+                .map(|compare| (compare, scanner.current_position()))
+        };
+        let data = b"  held (lock1)  ";
+        assert!(accept(data, &ParseOptions::default()).is_err());
+
+        let options = ParseOptions {
+            compare_parsers: vec![Arc::new(Named(b"held")), Arc::new(Named(b"mod"))],
+            ..Default::default()
+        };
+        let (compare, position) = accept(data, &options).expect("Failed to parse");
+        assert_eq!(
+            compare,
+            Compare::Custom(CustomCompare {
+                target: b"held",
+                args: vec![b"lock1"],
+            })
+        );
+        assert_eq!(position, data.len());
+        assert_eq!(compare.key(), None);
+        assert!(compare.is_custom());
+
+        // The built-in targets can't be overridden
+        let (compare, _) = accept(b"mod(k) > 0", &options).expect("Failed to parse");
+        assert_eq!(compare, Compare::mod_revision(b"k", OpType::GreaterThan, 0));
+
+        // The arguments are checked by the parser, the target must match
+        for data in [
+            &b"held lock1"[..],
+            b"held",
+            b"helder(lock1)",
+            b"mod(k) >> 0",
+        ] {
+            assert!(accept(data, &options).is_err(), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn test_value_multiple_words() {
+        // An unquoted value takes the rest of the line, trailing whitespaces
+        // aside, the tabs among them being left unconsumed
+        for (data, value) in [
+            (&b"val(k) = a b c"[..], &b"a b c"[..]),
+            (b"val(k) = a  b\tc \t ", b"a  b\tc"),
+            (b"val(k) = a", b"a"),
+            (b"val(k) = \"a b\"", b"a b"),
+            (b"val(k) = a\t", b"a"),
+            (b"val(k) = \"a\"\t", b"a"),
+        ] {
+            let mut scanner = Scanner::new(data);
+            let result = Compare::accept(&mut scanner).expect("Failed to parse");
+            assert_eq!(
+                result,
+                Compare::value(b"k", OpType::Equal, value),
+                "{data:?}"
+            );
+            assert!(
+                scanner.remaining().iter().all(u8::is_ascii_whitespace),
+                "{data:?}"
+            );
+        }
+
+        // Nothing may follow a quoted value
+        let data = b"val(k) = \"a b\" c";
+        let mut scanner = Scanner::new(data);
+        Compare::accept(&mut scanner).expect("Failed to parse");
+        assert_ne!(scanner.current_position(), data.len());
+    }
+
+    #[test]
+    fn test_value_tight_operator() {
+        // Only the first operator byte is the operator, the rest is the value
+        for (data, op, value) in [
+            (&b"val(k)=a=b"[..], OpType::Equal, &b"a=b"[..]),
+            (b"val(k)==b", OpType::Equal, b"=b"),
+            (b"val(k)>a>b", OpType::GreaterThan, b"a>b"),
+            (b"val(k)<=", OpType::LessThan, b"="),
+            (b"val(k)=\"a b\"", OpType::Equal, b"a b"),
+        ] {
+            let mut scanner = Scanner::new(data);
+            let result = Compare::accept(&mut scanner).expect("Failed to parse");
+            assert_eq!(result, Compare::value(b"k", op, value), "{data:?}");
+            assert_eq!(scanner.current_position(), data.len(), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn test_prefix_words_as_keys() {
+        let data = b"mod(mod) = 1";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::ModRevision(ModRevision {
+                key: Key(b"mod"),
                 value: 1,
-                op: OpType::LessThan
+                op: OpType::Equal,
+                ..
+            }))
+        ));
+
+        let data = b"create(create) = 0";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::CreateRevision(CreateRevision {
+                key: Key(b"create"),
+                value: 0,
+                op: OpType::Equal,
+                ..
+            }))
+        ));
+
+        let data = b"val(value) = \"val\"";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::Value(Value {
+                key: Key(b"value"),
+                value: data::Value(b"val"),
+                ..
             }))
         ));
+
+        let data = b"lease(ver) = 5";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::Lease(Lease {
+                key: Key(b"ver"),
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_target_words() {
+        let options = ParseOptions {
+            allow_target_words: true,
+            ..Default::default()
+        };
+        let result = crate::parse_line(b"CREATE(k) = 0", &options);
+        assert!(matches!(
+            result,
+            Ok(Compare::CreateRevision(CreateRevision {
+                key: Key(b"k"),
+                value: 0,
+                op: OpType::Equal,
+                ..
+            }))
+        ));
+
+        let lines: [(&[u8], Compare); 4] = [
+            (
+                b"MOD(k) > 3",
+                Compare::mod_revision(b"k", OpType::GreaterThan, 3),
+            ),
+            (
+                b"VERSION(\"k\") < 2",
+                Compare::version(b"k", OpType::LessThan, 2),
+            ),
+            (
+                b"VALUE(k) = \"v\"",
+                Compare::value(b"k", OpType::Equal, b"v"),
+            ),
+            (b"LEASE(k) = 1", Compare::lease(b"k", OpType::Equal, 1)),
+        ];
+        for (line, expected) in lines {
+            let result: Compare = crate::parse_line(line, &options).expect("Failed to parse");
+            assert_eq!(result, expected);
+            // Only with the option
+            assert!(Compare::parse(line).is_err());
+        }
+
+        // The words are uppercase only
+        assert!(crate::parse_line::<Compare>(b"Create(k) = 0", &options).is_err());
+    }
+
+    fn accept_with_radix(data: &[u8]) -> ParseResult<Compare<'_>> {
+        let options = ParseOptions {
+            numeric_prefixes: true,
+            ..Default::default()
+        };
+        Compare::accept_with(&mut Scanner::new(data), &options)
+    }
+
+    #[test]
+    fn test_radix_prefixes() {
+        let data = b"mod(key) > 0xfF";
+        let result = accept_with_radix(data);
+        assert!(matches!(
+            result,
+            Ok(Compare::ModRevision(ModRevision { value: 255, .. }))
+        ));
+
+        let data = b"c(key) = 0o777";
+        let result = accept_with_radix(data);
+        assert!(matches!(
+            result,
+            Ok(Compare::CreateRevision(CreateRevision { value: 511, .. }))
+        ));
+
+        let data = b"lease(key) = 0b11";
+        let result = accept_with_radix(data);
+        assert!(matches!(result, Ok(Compare::Lease(Lease { value: 3, .. }))));
+
+        let data = b"ver(key) = 12";
+        let result = accept_with_radix(data);
+        assert!(matches!(
+            result,
+            Ok(Compare::Version(Version { value: 12, .. }))
+        ));
+
+        // Invalid digits for the radix
+        let data = b"mod(key) > 0b12";
+        assert!(accept_with_radix(data).is_err());
+        // No digits
+        let data = b"mod(key) > 0x";
+        assert!(accept_with_radix(data).is_err());
+        // Overflow
+        let data = b"mod(key) > 0x10000000000000000";
+        assert!(accept_with_radix(data).is_err());
+    }
+
+    #[test]
+    fn test_control_bytes() {
+        let data = b"mod(\"k\x00e\x01y\") > 0";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::ModRevision(ModRevision {
+                key: Key(b"k\x00e\x01y"),
+                value: 0,
+                op: OpType::GreaterThan,
+                ..
+            }))
+        ));
+
+        let data = b"val(key\x00) = \"\x00\x01\"";
+        let result = Compare::accept(&mut Scanner::new(data));
+        assert!(matches!(
+            result,
+            Ok(Compare::Value(Value {
+                key: Key(b"key\x00"),
+                value: data::Value(b"\x00\x01"),
+                op: OpType::Equal,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_existence_checks() {
+        for (data, absence, presence) in [
+            (&b"create(k) = 0"[..], true, false),
+            (b"c(k) < 1", true, false),
+            (b"ver(k) = 0", true, false),
+            (b"version(k) < 1", true, false),
+            (b"create(k) > 0", false, true),
+            (b"ver(k) > 0", false, true),
+            (b"create(k) = 3", false, false),
+            (b"version(k) > 1", false, false),
+            (b"mod(k) = 0", false, false),
+            (b"lease(k) = 0", false, false),
+            (b"val(k) = \"\"", false, false),
+        ] {
+            let compare = Compare::accept(&mut Scanner::new(data)).expect("Failed to parse");
+            assert_eq!(compare.checks_absence(), absence, "{compare:?}");
+            assert_eq!(compare.checks_presence(), presence, "{compare:?}");
+            assert_eq!(compare.is_existence_check(), absence || presence);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        // The variants are displayed like the compare wrapping them
+        fn display_variant(compare: &Compare) -> String {
+            match compare {
+                Compare::CreateRevision(compare) => compare.to_string(),
+                Compare::ModRevision(compare) => compare.to_string(),
+                Compare::Value(compare) => compare.to_string(),
+                Compare::Version(compare) => compare.to_string(),
+                Compare::Lease(compare) => compare.to_string(),
+                Compare::Custom(_) | Compare::Unknown(_) => unreachable!(),
+            }
+        }
+
+        for target in ["create", "mod", "value", "version", "lease"] {
+            for op in ["=", ">", "<", "!="] {
+                let line = match target {
+                    "value" => format!("{target}(\"key 1\") {op} \"some data\""),
+                    _ => format!("{target}(\"key 1\") {op} 12"),
+                };
+                let compare =
+                    Compare::accept(&mut Scanner::new(line.as_bytes())).expect("Failed to parse");
+                let displayed = compare.to_string();
+                assert_eq!(displayed, line);
+                assert_eq!(display_variant(&compare), line);
+                let reparsed = Compare::accept(&mut Scanner::new(displayed.as_bytes()))
+                    .expect("Failed to parse");
+                assert_eq!(reparsed, compare);
+            }
+        }
+
+        // The short forms are written in their long form
+        let compare =
+            Compare::accept(&mut Scanner::new(b"val(key1) = data")).expect("Failed to parse");
+        assert_eq!(compare.to_string(), "value(\"key1\") = \"data\"");
+        let compare = Compare::accept(&mut Scanner::new(b"m(key1) > 0")).expect("Failed to parse");
+        assert_eq!(compare.to_string(), "mod(\"key1\") > 0");
+        assert_eq!(
+            Compare::value(b"k\xff", OpType::Equal, b"").to_string(),
+            "value(\"k\\xff\") = \"\""
+        );
+
+        // The bytes the syntax can't express as is are escaped
+        let compare = Compare::version(b"key1)", OpType::Equal, 1);
+        assert_eq!(compare.to_string(), "version(\"key1\\x29\") = 1");
+        assert_eq!(
+            compare.to_text().expect("Failed to write"),
+            compare.to_string().as_bytes()
+        );
+        let compare = Compare::value(b"k", OpType::Equal, b"it's\n\"x\"");
+        assert_eq!(compare.to_string(), "value(\"k\") = \"it's\\n\\x22x\\x22\"");
+
+        // The displayed compares parse back to the same bytes
+        for compare in [
+            Compare::version(b"key1)", OpType::Equal, 1),
+            Compare::value(b"k", OpType::Equal, b"it's \"q\" x"),
+            Compare::value(b"(k\xff", OpType::NotEqual, b"caf\xc3\xa9\xfe"),
+            Compare::parse(br"val('(\x41)') = '\x42'").expect("Failed to parse"),
+            Compare::parse(br#"mod("a\"\x28") > 0"#).expect("Failed to parse"),
+        ] {
+            let displayed = compare.to_string();
+            let reparsed = Compare::parse(displayed.as_bytes()).expect("Failed to parse");
+            assert_eq!(reparsed, compare, "{displayed}");
+        }
+
+        // Quoted data which can't be written as is and holds an invalid
+        // escape sequence is marked
+        let compare = Compare::parse(b"val(k) = \"\\q\xff\"").expect("Failed to parse");
+        let displayed = compare.to_string();
+        assert_eq!(displayed, r#"value("k") = "\\q\xff" <invalid escape>"#);
+        assert!(Compare::parse(displayed.as_bytes()).is_err());
+        let compare = Compare::parse(b"mod(\"(\\q\xff)\") > 0").expect("Failed to parse");
+        let displayed = compare.to_string();
+        assert_eq!(displayed, r#"mod("\x28\\q\xff\x29" <invalid escape>) > 0"#);
+        assert!(Compare::parse(displayed.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_equality() {
+        // The bytes the key and the value stand for are compared, not how
+        // they're written
+        let parse = |line: &'static [u8]| Compare::parse(line).expect("Failed to parse");
+        assert_eq!(parse(br#"mod("\x41") > 0"#), parse(b"mod(A) > 0"));
+        assert_eq!(
+            parse(br#"mod("\x41") > 0"#),
+            Compare::mod_revision(b"A", OpType::GreaterThan, 0)
+        );
+        assert_ne!(parse(br"mod('\x41') > 0"), parse(br#"mod("\x41") > 0"#));
+        assert_eq!(parse(br#"val("\x41") = "\x42""#), parse(b"val(A) = B"));
+        assert_ne!(parse(br"val(A) = '\x42'"), parse(br#"val(A) = "\x42""#));
+        assert_eq!(
+            parse(br"val(A) = '\x42'"),
+            Compare::value(b"A", OpType::Equal, br"\x42")
+        );
+    }
+
+    #[test]
+    fn test_is_unsatisfiable() {
+        for (data, unsatisfiable) in [
+            (&b"version(k) < 0"[..], true),
+            (b"create(k) < 0", true),
+            (b"mod(k) < 0", true),
+            (b"lease(k) < 0", true),
+            (b"val(k) < \"\"", true),
+            (b"version(k) < 1", false),
+            (b"create(k) = 0", false),
+            (b"mod(k) > 0", false),
+            (b"val(k) < \"a\"", false),
+            (b"val(k) = \"\"", false),
+        ] {
+            let compare = Compare::accept(&mut Scanner::new(data)).expect("Failed to parse");
+            assert_eq!(compare.is_unsatisfiable(), unsatisfiable, "{compare:?}");
+        }
+    }
+
+    #[test]
+    fn test_guards_from_snapshot() {
+        assert_eq!(
+            guards_from_snapshot(&[(b"a", 12), (b"b", 7)]),
+            vec![
+                Compare::mod_revision(b"a", OpType::Equal, 12),
+                Compare::mod_revision(b"b", OpType::Equal, 7),
+            ]
+        );
+
+        let guards = guards_from_snapshot(&[(b"a", 0)]);
+        assert_eq!(
+            guards,
+            vec![Compare::create_revision(b"a", OpType::Equal, 0)]
+        );
+        assert!(guards[0].checks_absence());
+
+        assert_eq!(
+            guards_from_snapshot(&[(b"a", 0), (b"b", 3), (b"c", 0)]),
+            vec![
+                Compare::create_revision(b"a", OpType::Equal, 0),
+                Compare::mod_revision(b"b", OpType::Equal, 3),
+                Compare::create_revision(b"c", OpType::Equal, 0),
+            ]
+        );
+        assert!(guards_from_snapshot(&[]).is_empty());
     }
 }
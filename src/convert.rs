@@ -0,0 +1,212 @@
+//! Lossless-ness of the conversions
+//!
+//! Some nodes of a transaction have no equivalent in the representations it
+//! is converted to, e.g. an unknown line in a protobuf message. The
+//! conversions taking a [`Lossy`] policy either fail on the first of them, or
+//! leave them out and report each one along with the converted value.
+
+use crate::error::Error;
+use crate::Section;
+use elyze::errors::ParseResult;
+use std::fmt::{Display, Formatter};
+
+/// What a conversion does with the nodes it can't represent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Lossy {
+    /// Fail on the first node which can't be represented.
+    #[default]
+    Error,
+    /// Leave the nodes out, reporting each one.
+    Skip,
+    /// Keep an approximation of the nodes where the output allows it, e.g. a
+    /// comment in a client snippet, and leave the others out. Each node is
+    /// reported.
+    BestEffort,
+}
+
+/// A construct which can't be represented by a conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Construct {
+    /// A line kept as an unknown node.
+    UnknownNode,
+    /// A file reference, which must be resolved first.
+    UnresolvedFileRef,
+    /// An operation parsed by a registered parser.
+    CustomOperation,
+    /// A compare parsed by a registered parser.
+    CustomCompare,
+    /// An operation with flags the parser doesn't know.
+    UnknownFlag,
+    /// A noop operation, a placeholder to be filled in first.
+    Noop,
+    /// A key or a value holding an invalid escape sequence, which stands for
+    /// no bytes.
+    InvalidEscape,
+}
+
+impl Display for Construct {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Construct::UnknownNode => write!(f, "unknown line"),
+            Construct::UnresolvedFileRef => write!(f, "unresolved file reference"),
+            Construct::CustomOperation => write!(f, "custom operation"),
+            Construct::CustomCompare => write!(f, "custom compare"),
+            Construct::UnknownFlag => write!(f, "operation with unknown flags"),
+            Construct::Noop => write!(f, "noop operation"),
+            Construct::InvalidEscape => write!(f, "invalid escape sequence"),
+        }
+    }
+}
+
+/// A node which can't be represented by a conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertError {
+    /// The section of the node.
+    pub section: Section,
+    /// The position of the node in its section.
+    pub index: usize,
+    /// What the node is.
+    pub construct: Construct,
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at position {} of the {} section can't be converted",
+            self.construct, self.index, self.section
+        )
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// The conversions without policy fail with the error of the construct.
+impl From<ConvertError> for Error {
+    fn from(err: ConvertError) -> Self {
+        match err.construct {
+            Construct::UnknownNode => Error::UnknownNode,
+            Construct::UnresolvedFileRef => Error::UnresolvedFileRef,
+            Construct::CustomOperation | Construct::CustomCompare => Error::CustomOperation,
+            Construct::UnknownFlag => Error::UnknownFlag,
+            Construct::Noop => Error::Noop,
+            Construct::InvalidEscape => Error::InvalidEscape,
+        }
+    }
+}
+
+/// The bytes decoded from a key, a value or a range, see
+/// [`decode`](crate::escape::decode): the conversions send the bytes the
+/// data stands for, and an invalid escape sequence stands for none.
+pub(crate) fn decoded<T>(decoded: ParseResult<T>) -> Result<T, Construct> {
+    decoded.map_err(|_| Construct::InvalidEscape)
+}
+
+/// A converted transaction along with the nodes left out or approximated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Converted<T> {
+    /// The converted transaction.
+    pub value: T,
+    /// The nodes which couldn't be represented, in order.
+    pub report: Vec<ConvertError>,
+}
+
+/// Convert the nodes of the sections of a transaction, applying a policy to
+/// the nodes which can't be represented.
+pub(crate) struct Converter {
+    lossy: Lossy,
+    report: Vec<ConvertError>,
+}
+
+impl Converter {
+    pub(crate) fn new(lossy: Lossy) -> Self {
+        Converter {
+            lossy,
+            report: vec![],
+        }
+    }
+
+    /// Convert the nodes of `section`. Under the best effort policy, the
+    /// nodes which can't be represented are replaced by their approximation,
+    /// if any.
+    pub(crate) fn convert<T, U>(
+        &mut self,
+        section: Section,
+        nodes: &[T],
+        convert: impl Fn(&T) -> Result<U, Construct>,
+        approximate: impl Fn(&T, Construct) -> Option<U>,
+    ) -> Result<Vec<U>, ConvertError> {
+        let mut converted = vec![];
+        for (index, node) in nodes.iter().enumerate() {
+            let construct = match convert(node) {
+                Ok(item) => {
+                    converted.push(item);
+                    continue;
+                }
+                Err(construct) => construct,
+            };
+            let err = ConvertError {
+                section,
+                index,
+                construct,
+            };
+            match self.lossy {
+                Lossy::Error => return Err(err),
+                Lossy::Skip => {}
+                Lossy::BestEffort => converted.extend(approximate(node, construct)),
+            }
+            self.report.push(err);
+        }
+        Ok(converted)
+    }
+
+    /// Pair the converted transaction with the report.
+    pub(crate) fn finish<T>(self, value: T) -> Converted<T> {
+        Converted {
+            value,
+            report: self.report,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::convert::{Construct, ConvertError, Converter, Lossy};
+    use crate::error::Error;
+    use crate::Section;
+
+    fn convert(lossy: Lossy) -> Result<(Vec<u32>, Vec<ConvertError>), ConvertError> {
+        let mut converter = Converter::new(lossy);
+        let converted = converter.convert(
+            Section::Success,
+            &[1, -2, 3],
+            |&node| u32::try_from(node).map_err(|_| Construct::UnknownNode),
+            |_, _| Some(0),
+        )?;
+        let converted = converter.finish(converted);
+        Ok((converted.value, converted.report))
+    }
+
+    #[test]
+    fn test_converter() {
+        let err = ConvertError {
+            section: Section::Success,
+            index: 1,
+            construct: Construct::UnknownNode,
+        };
+        assert_eq!(convert(Lossy::Error), Err(err.clone()));
+        assert_eq!(convert(Lossy::Skip), Ok((vec![1, 3], vec![err.clone()])));
+        assert_eq!(
+            convert(Lossy::BestEffort),
+            Ok((vec![1, 0, 3], vec![err.clone()]))
+        );
+
+        assert_eq!(
+            err.to_string(),
+            "unknown line at position 1 of the success section can't be converted"
+        );
+        assert!(matches!(Error::from(err), Error::UnknownNode));
+    }
+}
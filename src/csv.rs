@@ -0,0 +1,156 @@
+//! Import of key/value pairs from CSV
+//!
+//! Each row of the CSV becomes a put operation, ready to be split into
+//! transactions and written out.
+
+use crate::error::Error;
+use crate::owned::OperationOwned;
+use std::io::Read;
+
+/// Options of [`operations_from_csv`].
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// The index of the column holding the keys, 0 by default.
+    pub key_column: usize,
+    /// The index of the column holding the values, 1 by default.
+    pub value_column: usize,
+    /// Prepended to every key, e.g. `app/config/`. Empty by default.
+    pub key_prefix: Vec<u8>,
+    /// Skip the first row, holding the column names. Disabled by default.
+    pub has_headers: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            key_column: 0,
+            value_column: 1,
+            key_prefix: vec![],
+            has_headers: false,
+        }
+    }
+}
+
+/// Convert a CSV error into an [`Error`], keeping I/O errors as is.
+fn csv_error(err: ::csv::Error, row: u64) -> Error {
+    let message = err.to_string();
+    match err.into_kind() {
+        ::csv::ErrorKind::Io(err) => Error::Io(err),
+        _ => Error::Csv { row, message },
+    }
+}
+
+/// Read key/value rows from a CSV into put operations, in order.
+///
+/// Fields are quoted and escaped the CSV way, so that a value may hold
+/// commas, double quotes and line feeds. Keys and values are read as bytes,
+/// without UTF-8 validation.
+///
+/// # Errors
+///
+/// An `Error::Csv` carrying the number of the row is returned for a malformed
+/// row, e.g. one missing the key or the value column or having another number
+/// of fields than the first row. A read failure is an `Error::Io`.
+pub fn operations_from_csv(
+    reader: impl Read,
+    options: &CsvOptions,
+) -> Result<Vec<OperationOwned>, Error> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(options.has_headers)
+        .from_reader(reader);
+    let mut operations = vec![];
+    let mut record = ::csv::ByteRecord::new();
+    let first_row = if options.has_headers { 2 } else { 1 };
+    for row in first_row.. {
+        match reader.read_byte_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => return Err(csv_error(err, row)),
+        }
+        let column = |index| {
+            record.get(index).ok_or_else(|| Error::Csv {
+                row,
+                message: format!("missing column {index}"),
+            })
+        };
+        let key = [&options.key_prefix, column(options.key_column)?].concat();
+        let value = column(options.value_column)?.to_vec();
+        operations.push(OperationOwned::Put {
+            key,
+            value,
+            lease: None,
+            unknown_flags: vec![],
+        });
+    }
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::{operations_from_csv, CsvOptions};
+    use crate::error::Error;
+    use crate::owned::OperationOwned;
+
+    fn put(key: &[u8], value: &[u8]) -> OperationOwned {
+        OperationOwned::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            lease: None,
+            unknown_flags: vec![],
+        }
+    }
+
+    #[test]
+    fn test_operations_from_csv() {
+        let data = "key1,value1\n\"key 2\",\"a, b\"\nkey3,\"multi\nline \"\"quoted\"\"\"\n";
+        let operations =
+            operations_from_csv(data.as_bytes(), &CsvOptions::default()).expect("Failed to import");
+        assert_eq!(
+            operations,
+            vec![
+                put(b"key1", b"value1"),
+                put(b"key 2", b"a, b"),
+                put(b"key3", b"multi\nline \"quoted\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operations_from_csv_options() {
+        let data = "value,ignored,key\nv1,x,k1\nv2,y,k2\n";
+        let options = CsvOptions {
+            key_column: 2,
+            value_column: 0,
+            key_prefix: b"app/".to_vec(),
+            has_headers: true,
+        };
+        let operations = operations_from_csv(data.as_bytes(), &options).expect("Failed to import");
+        assert_eq!(
+            operations,
+            vec![put(b"app/k1", b"v1"), put(b"app/k2", b"v2")]
+        );
+    }
+
+    #[test]
+    fn test_operations_from_csv_malformed() {
+        // Another number of fields than the first row
+        let data = "k1,v1\nk2,v2\nk3,v3,extra\n";
+        let result = operations_from_csv(data.as_bytes(), &CsvOptions::default());
+        assert!(
+            matches!(result, Err(Error::Csv { row: 3, .. })),
+            "{result:?}"
+        );
+
+        // Missing the value column
+        let data = "name\nk1\n";
+        let options = CsvOptions {
+            has_headers: true,
+            ..Default::default()
+        };
+        let result = operations_from_csv(data.as_bytes(), &options);
+        assert!(
+            matches!(&result, Err(Error::Csv { row: 2, message }) if message == "missing column 1"),
+            "{result:?}"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Keys and values of the transactional data structures
+//!
+//! Both wrap the bytes borrowed from the input, so that a key can't be passed
+//! where a value is expected. They dereference to the bytes, and match
+//! through their public field, e.g. `Key(b"key1")`.
+
+use std::borrow::Borrow;
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+
+/// A key, ordered byte by byte like etcd orders its keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key<'a>(pub &'a [u8]);
+
+/// A value, put by an operation or compared with a key's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Value<'a>(pub &'a [u8]);
+
+macro_rules! impl_bytes {
+    ($name:ident) => {
+        impl<'a> $name<'a> {
+            /// The bytes, borrowed from the input.
+            pub fn as_bytes(&self) -> &'a [u8] {
+                self.0
+            }
+
+            /// The bytes as a string, `None` if they aren't valid UTF-8.
+            pub fn as_str(&self) -> Option<&'a str> {
+                std::str::from_utf8(self.0).ok()
+            }
+
+            /// Copy the bytes out of the input.
+            pub fn to_owned(&self) -> Vec<u8> {
+                self.0.to_vec()
+            }
+        }
+
+        impl Deref for $name<'_> {
+            type Target = [u8];
+
+            fn deref(&self) -> &[u8] {
+                self.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name<'_> {
+            fn as_ref(&self) -> &[u8] {
+                self.0
+            }
+        }
+
+        /// Hashed and compared like the bytes, so that a map keyed by the
+        /// newtype is searched with bytes.
+        impl Borrow<[u8]> for $name<'_> {
+            fn borrow(&self) -> &[u8] {
+                self.0
+            }
+        }
+
+        impl<'a> From<&'a [u8]> for $name<'a> {
+            fn from(bytes: &'a [u8]) -> Self {
+                $name(bytes)
+            }
+        }
+
+        /// The bytes, invalid UTF-8 sequences being replaced by `U+FFFD`.
+        impl Display for $name<'_> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", String::from_utf8_lossy(self.0))
+            }
+        }
+
+        impl PartialEq<[u8]> for $name<'_> {
+            fn eq(&self, other: &[u8]) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&[u8]> for $name<'_> {
+            fn eq(&self, other: &&[u8]) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl<const N: usize> PartialEq<[u8; N]> for $name<'_> {
+            fn eq(&self, other: &[u8; N]) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl<const N: usize> PartialEq<&[u8; N]> for $name<'_> {
+            fn eq(&self, other: &&[u8; N]) -> bool {
+                self.0 == *other
+            }
+        }
+    };
+}
+
+impl_bytes!(Key);
+impl_bytes!(Value);
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Key, Value};
+
+    #[test]
+    fn test_key_ordering() {
+        let mut keys = [
+            Key(b"b"),
+            Key(b"a/b"),
+            Key(b""),
+            Key(b"a"),
+            Key(b"\xff"),
+            Key(b"a\0"),
+            Key(b"B"),
+        ];
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                Key(b""),
+                Key(b"B"),
+                Key(b"a"),
+                Key(b"a\0"),
+                Key(b"a/b"),
+                Key(b"b"),
+                Key(b"\xff"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Key(b"key1").to_string(), "key1");
+        assert_eq!(Value(b"caf\xc3\xa9 \xff").to_string(), "café \u{fffd}");
+        assert_eq!(Value(b"\xff").as_str(), None);
+        assert_eq!(Key(b"key1").as_str(), Some("key1"));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let key = Key(b"key1");
+        assert_eq!(key, b"key1");
+        assert_eq!(key.len(), 4);
+        assert!(key.starts_with(b"key"));
+        assert_eq!(key.to_owned(), b"key1".to_vec());
+        assert_eq!(Value::from(&b"v"[..]).as_bytes(), b"v");
+    }
+}
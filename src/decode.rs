@@ -0,0 +1,477 @@
+//! etcd v3 protobuf wire decoding
+//!
+//! Decodes the `etcdserverpb.TxnRequest` message sent to the
+//! `etcdserverpb.KV/Txn` gRPC method, the reverse of
+//! [`TxnData::to_protobuf`](crate::TxnData::to_protobuf). Only the messages
+//! and fields the text format can express are accepted.
+// Malformed input must be reported, never panic
+#![deny(clippy::arithmetic_side_effects)]
+
+use crate::compare::OpType;
+use crate::keyrange::KeyRange;
+use crate::operation::{Consistency, GetData, Operation};
+use crate::owned::{CompareOwned, OperationOwned, TxnDataOwned};
+use std::fmt::{Display, Formatter};
+
+/// An error returned when decoding a protobuf message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message ends in the middle of a field.
+    Truncated,
+    /// A varint is longer than 64 bits.
+    VarintOverflow,
+    /// A field is encoded with a wire type which doesn't match its definition.
+    WireType {
+        /// The message holding the field.
+        message: &'static str,
+        /// The field number.
+        field: u32,
+        /// The wire type read.
+        wire_type: u32,
+    },
+    /// A field can't be expressed by the text format, e.g. the `limit` of a
+    /// range request.
+    UnsupportedField {
+        /// The message holding the field.
+        message: &'static str,
+        /// The field number.
+        field: u32,
+    },
+    /// A compare result isn't known.
+    UnsupportedCompareResult(u64),
+    /// A compare target isn't known.
+    UnsupportedCompareTarget(u64),
+    /// A request op isn't a range, put or delete range request, e.g. a nested
+    /// transaction.
+    UnsupportedRequest(u32),
+    /// A request op holds no request.
+    EmptyRequest,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "the message is truncated"),
+            DecodeError::VarintOverflow => write!(f, "a varint overflows 64 bits"),
+            DecodeError::WireType {
+                message,
+                field,
+                wire_type,
+            } => write!(
+                f,
+                "field {field} of {message} has the unexpected wire type {wire_type}"
+            ),
+            DecodeError::UnsupportedField { message, field } => {
+                write!(f, "field {field} of {message} is not supported")
+            }
+            DecodeError::UnsupportedCompareResult(result) => {
+                write!(f, "unsupported compare result {result}")
+            }
+            DecodeError::UnsupportedCompareTarget(target) => {
+                write!(f, "unsupported compare target {target}")
+            }
+            DecodeError::UnsupportedRequest(field) => {
+                write!(f, "unsupported request op {field}")
+            }
+            DecodeError::EmptyRequest => write!(f, "a request op holds no request"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// ----------------------------------------------------------------------------
+// Wire format
+// ----------------------------------------------------------------------------
+
+/// The value of a field, as far as the wire format tells.
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32,
+}
+
+impl WireValue<'_> {
+    fn wire_type(&self) -> u32 {
+        match self {
+            WireValue::Varint(_) => 0,
+            WireValue::Fixed64 => 1,
+            WireValue::Bytes(_) => 2,
+            WireValue::Fixed32 => 5,
+        }
+    }
+}
+
+/// Read the fields of a message one by one.
+struct Reader<'a> {
+    data: &'a [u8],
+    message: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], message: &'static str) -> Self {
+        Reader { data, message }
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for (index, &byte) in self.data.iter().enumerate() {
+            if index == 10 || (index == 9 && byte > 1) {
+                return Err(DecodeError::VarintOverflow);
+            }
+            value |= u64::from(byte & 0x7f) << index.saturating_mul(7);
+            if byte & 0x80 == 0 {
+                self.data = self.data.get(index.saturating_add(1)..).unwrap_or_default();
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Truncated)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    /// Read the next field, `None` at the end of the message.
+    fn field(&mut self) -> Result<Option<(u32, WireValue<'a>)>, DecodeError> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+        let tag = self.varint()?;
+        let field = u32::try_from(tag >> 3).map_err(|_| DecodeError::VarintOverflow)?;
+        let wire_type = (tag & 0x7) as u32;
+        let value = match wire_type {
+            0 => WireValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                WireValue::Fixed64
+            }
+            2 => {
+                let len = usize::try_from(self.varint()?).map_err(|_| DecodeError::Truncated)?;
+                WireValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                WireValue::Fixed32
+            }
+            // Groups are deprecated and unused by etcd
+            _ => {
+                return Err(DecodeError::WireType {
+                    message: self.message,
+                    field,
+                    wire_type,
+                })
+            }
+        };
+        Ok(Some((field, value)))
+    }
+
+    fn wire_type_error(&self, field: u32, value: &WireValue) -> DecodeError {
+        DecodeError::WireType {
+            message: self.message,
+            field,
+            wire_type: value.wire_type(),
+        }
+    }
+
+    fn unsupported(&self, field: u32) -> DecodeError {
+        DecodeError::UnsupportedField {
+            message: self.message,
+            field,
+        }
+    }
+
+    fn as_varint(&self, field: u32, value: WireValue) -> Result<u64, DecodeError> {
+        match value {
+            WireValue::Varint(value) => Ok(value),
+            value => Err(self.wire_type_error(field, &value)),
+        }
+    }
+
+    fn as_bytes(&self, field: u32, value: WireValue<'a>) -> Result<&'a [u8], DecodeError> {
+        match value {
+            WireValue::Bytes(bytes) => Ok(bytes),
+            value => Err(self.wire_type_error(field, &value)),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Messages
+// ----------------------------------------------------------------------------
+
+/// Decode a `Compare` message.
+fn decode_compare(data: &[u8]) -> Result<CompareOwned, DecodeError> {
+    let mut reader = Reader::new(data, "Compare");
+    let (mut result, mut target, mut key) = (0, 0, &b""[..]);
+    // The fields of the target union, defaulting to zero like proto3 does
+    let (mut version, mut create_revision, mut mod_revision, mut lease) = (0, 0, 0, 0);
+    let mut value = &b""[..];
+    while let Some((field, wire)) = reader.field()? {
+        match field {
+            1 => result = reader.as_varint(field, wire)?,
+            2 => target = reader.as_varint(field, wire)?,
+            3 => key = reader.as_bytes(field, wire)?,
+            4 => version = reader.as_varint(field, wire)?,
+            5 => create_revision = reader.as_varint(field, wire)?,
+            6 => mod_revision = reader.as_varint(field, wire)?,
+            7 => value = reader.as_bytes(field, wire)?,
+            8 => lease = reader.as_varint(field, wire)?,
+            _ => return Err(reader.unsupported(field)),
+        }
+    }
+
+    let op = match result {
+        0 => OpType::Equal,
+        1 => OpType::GreaterThan,
+        2 => OpType::LessThan,
+        3 => OpType::NotEqual,
+        result => return Err(DecodeError::UnsupportedCompareResult(result)),
+    };
+    let key = key.to_vec();
+    let compare = match target {
+        0 => CompareOwned::Version {
+            key,
+            value: version,
+            op,
+        },
+        1 => CompareOwned::CreateRevision {
+            key,
+            value: create_revision,
+            op,
+        },
+        2 => CompareOwned::ModRevision {
+            key,
+            value: mod_revision,
+            op,
+        },
+        3 => CompareOwned::Value {
+            key,
+            value: value.to_vec(),
+            op,
+        },
+        4 => CompareOwned::Lease {
+            key,
+            value: lease,
+            op,
+        },
+        target => return Err(DecodeError::UnsupportedCompareTarget(target)),
+    };
+    Ok(compare)
+}
+
+/// Decode a `RangeRequest` message.
+fn decode_range(data: &[u8]) -> Result<OperationOwned, DecodeError> {
+    let mut reader = Reader::new(data, "RangeRequest");
+    let (mut key, mut range_end) = (&b""[..], &b""[..]);
+    // The revision filters, 0 meaning no filter
+    let (mut min_mod_rev, mut max_mod_rev) = (0, 0);
+    let (mut min_create_rev, mut max_create_rev) = (0, 0);
+    let mut serializable = false;
+    while let Some((field, wire)) = reader.field()? {
+        match field {
+            1 => key = reader.as_bytes(field, wire)?,
+            2 => range_end = reader.as_bytes(field, wire)?,
+            7 => serializable = reader.as_varint(field, wire)? != 0,
+            10..=13 => {
+                // int64 fields are encoded as their two's complement
+                let revision = reader.as_varint(field, wire)? as i64;
+                match field {
+                    10 => min_mod_rev = revision,
+                    11 => max_mod_rev = revision,
+                    12 => min_create_rev = revision,
+                    _ => max_create_rev = revision,
+                }
+            }
+            _ => return Err(reader.unsupported(field)),
+        }
+    }
+
+    let range = KeyRange {
+        key: key.into(),
+        range_end: range_end.to_vec(),
+    };
+    let filter = |revision: i64| Some(revision).filter(|&revision| revision != 0);
+    let get = GetData {
+        min_create_rev: filter(min_create_rev),
+        max_create_rev: filter(max_create_rev),
+        min_mod_rev: filter(min_mod_rev),
+        max_mod_rev: filter(max_mod_rev),
+        consistency: serializable.then_some(Consistency::Serializable),
+        ..GetData::from_range(&range)
+    };
+    Ok(OperationOwned::from(&Operation::Get(get)))
+}
+
+/// Decode a `PutRequest` message.
+fn decode_put(data: &[u8]) -> Result<OperationOwned, DecodeError> {
+    let mut reader = Reader::new(data, "PutRequest");
+    let (mut key, mut value, mut lease) = (&b""[..], &b""[..], 0);
+    while let Some((field, wire)) = reader.field()? {
+        match field {
+            1 => key = reader.as_bytes(field, wire)?,
+            2 => value = reader.as_bytes(field, wire)?,
+            3 => lease = reader.as_varint(field, wire)?,
+            _ => return Err(reader.unsupported(field)),
+        }
+    }
+    Ok(OperationOwned::Put {
+        key: key.to_vec(),
+        value: value.to_vec(),
+        // The lease 0 means no lease
+        lease: Some(lease).filter(|&lease| lease != 0),
+        unknown_flags: vec![],
+    })
+}
+
+/// Decode a `DeleteRangeRequest` message.
+fn decode_delete(data: &[u8]) -> Result<OperationOwned, DecodeError> {
+    let mut reader = Reader::new(data, "DeleteRangeRequest");
+    let mut key = &b""[..];
+    while let Some((field, wire)) = reader.field()? {
+        match field {
+            1 => key = reader.as_bytes(field, wire)?,
+            _ => return Err(reader.unsupported(field)),
+        }
+    }
+    Ok(OperationOwned::Delete { key: key.to_vec() })
+}
+
+/// Decode a `RequestOp` message.
+fn decode_operation(data: &[u8]) -> Result<OperationOwned, DecodeError> {
+    let mut reader = Reader::new(data, "RequestOp");
+    let mut operation = None;
+    while let Some((field, wire)) = reader.field()? {
+        let request = match field {
+            1..=3 => reader.as_bytes(field, wire)?,
+            field => return Err(DecodeError::UnsupportedRequest(field)),
+        };
+        // The last request of the oneof wins
+        operation = Some(match field {
+            1 => decode_range(request)?,
+            2 => decode_put(request)?,
+            _ => decode_delete(request)?,
+        });
+    }
+    operation.ok_or(DecodeError::EmptyRequest)
+}
+
+/// Decode the protobuf `TxnRequest` message of the etcd v3 `KV/Txn` gRPC
+/// method.
+///
+/// # Errors
+///
+/// If the bytes aren't a valid message, or hold requests or fields the text
+/// format can't express such as nested transactions, a [`DecodeError`] is
+/// returned.
+pub fn decode_txn_request(bytes: &[u8]) -> Result<TxnDataOwned, DecodeError> {
+    let mut reader = Reader::new(bytes, "TxnRequest");
+    let mut txn = TxnDataOwned::default();
+    while let Some((field, wire)) = reader.field()? {
+        match field {
+            1 => txn
+                .compares
+                .push(decode_compare(reader.as_bytes(field, wire)?)?),
+            2 => txn
+                .success
+                .push(decode_operation(reader.as_bytes(field, wire)?)?),
+            3 => txn
+                .failure
+                .push(decode_operation(reader.as_bytes(field, wire)?)?),
+            _ => return Err(reader.unsupported(field)),
+        }
+    }
+    Ok(txn)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::{decode_txn_request, DecodeError, Reader};
+    use crate::parse;
+
+    #[test]
+    fn test_varint() {
+        for (encoded, value) in [
+            (&[0x00][..], 0),
+            (&[0x7f], 127),
+            (&[0xac, 0x02], 300),
+            (
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+                u64::MAX,
+            ),
+        ] {
+            let mut reader = Reader::new(encoded, "test");
+            assert_eq!(reader.varint(), Ok(value));
+            assert!(reader.data.is_empty());
+        }
+
+        let mut reader = Reader::new(&[0xff, 0xff], "test");
+        assert_eq!(reader.varint(), Err(DecodeError::Truncated));
+        let mut reader = Reader::new(&[0xff; 11], "test");
+        assert_eq!(reader.varint(), Err(DecodeError::VarintOverflow));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for data in [
+            &b"mod(key1) > 0\nval(key2) = \"some value\"\n\nput key1 v --lease=1f\nget key2\n\ndel key1\n"[..],
+            b"ver(k) = 0\nc(k) < 3\nlease(k) = 0\n\nget --prefix --min-create-rev=-4 --max-mod-rev=9 a/\n\nput k \"\"\n",
+            b"\n\n\n\n",
+            b"\n\nget --prefix \"\"\nget --prefix \"\xff\"\n\n",
+            b"\n\nget --from-key a\nget a c --max-mod-rev=2\n\n",
+            b"\n\nget --serializable a\n\n",
+        ] {
+            let txn = parse(data).expect("Failed to parse");
+            let encoded = txn.to_protobuf().expect("Failed to encode");
+            assert_eq!(
+                decode_txn_request(&encoded).expect("Failed to decode"),
+                txn.to_owned()
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        // A nested transaction
+        assert_eq!(
+            decode_txn_request(&[0x12, 0x02, 0x22, 0x00]),
+            Err(DecodeError::UnsupportedRequest(4))
+        );
+        assert_eq!(
+            decode_txn_request(&[0x12, 0x00]),
+            Err(DecodeError::EmptyRequest)
+        );
+        // A compare result after NOT_EQUAL
+        assert_eq!(
+            decode_txn_request(&[0x0a, 0x02, 0x08, 0x04]),
+            Err(DecodeError::UnsupportedCompareResult(4))
+        );
+        // A range request with a limit
+        assert_eq!(
+            decode_txn_request(&[0x12, 0x04, 0x0a, 0x02, 0x18, 0x01]),
+            Err(DecodeError::UnsupportedField {
+                message: "RangeRequest",
+                field: 3
+            })
+        );
+        // A key encoded as a varint
+        assert_eq!(
+            decode_txn_request(&[0x0a, 0x02, 0x18, 0x01]),
+            Err(DecodeError::WireType {
+                message: "Compare",
+                field: 3,
+                wire_type: 0
+            })
+        );
+        assert_eq!(
+            decode_txn_request(&[0x0a, 0x05, 0x1a]),
+            Err(DecodeError::Truncated)
+        );
+    }
+}
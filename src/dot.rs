@@ -0,0 +1,347 @@
+//! Graphviz rendering of a transaction
+//!
+//! Draws the compares at the top, chained in evaluation order, then the
+//! success branch and the failure branch side by side, for design reviews.
+
+use crate::compare::{Compare, OpType};
+use crate::escape::decode_lossy;
+use crate::operation::{DataKind, Operation};
+use crate::writer::{consistency_flag, unknown_flag};
+use crate::TxnData;
+use std::fmt::Write;
+
+/// The number of characters of a key or a value shown in a label.
+const MAX_LABEL_DATA: usize = 32;
+
+/// A key or a value as shown in a label: control characters are escaped and
+/// the data is truncated to [`MAX_LABEL_DATA`] characters, an ellipsis marking
+/// the truncation.
+fn label_data(data: &[u8]) -> String {
+    let text = String::from_utf8_lossy(data);
+    let mut shown: String = text
+        .chars()
+        .take(MAX_LABEL_DATA)
+        .flat_map(|char| match char {
+            char if char.is_control() => char.escape_default().collect::<Vec<_>>(),
+            char => vec![char],
+        })
+        .collect();
+    if text.chars().nth(MAX_LABEL_DATA).is_some() {
+        shown.push('…');
+    }
+    shown
+}
+
+/// A key or a value as shown in a label, the bytes it stands for, see
+/// [`decode`](crate::escape::decode), or as written if it holds an invalid
+/// escape sequence.
+fn label_decoded(data: &[u8], kind: DataKind) -> String {
+    label_data(&decode_lossy(data, kind))
+}
+
+/// Escape a label as the content of a DOT double quoted string.
+fn escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for char in label.chars() {
+        if matches!(char, '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    escaped
+}
+
+fn operator(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "=",
+        OpType::GreaterThan => ">",
+        OpType::LessThan => "<",
+        OpType::NotEqual => "!=",
+    }
+}
+
+/// The label and the attributes of a compare node.
+fn compare_node(compare: &Compare) -> (String, &'static str) {
+    const STYLE: &str = "shape=diamond, style=filled, fillcolor=lightyellow";
+    let (target, (key, key_kind), op) = match compare {
+        Compare::CreateRevision(compare) => ("create", (compare.key, compare.key_kind), compare.op),
+        Compare::ModRevision(compare) => ("mod", (compare.key, compare.key_kind), compare.op),
+        Compare::Value(compare) => ("value", (compare.key, compare.key_kind), compare.op),
+        Compare::Version(compare) => ("version", (compare.key, compare.key_kind), compare.op),
+        Compare::Lease(compare) => ("lease", (compare.key, compare.key_kind), compare.op),
+        Compare::Custom(custom) => {
+            let mut label = label_data(custom.target);
+            for arg in &custom.args {
+                let _ = write!(label, " \"{}\"", label_data(arg));
+            }
+            return (
+                label,
+                "shape=diamond, style=\"dashed,filled\", fillcolor=lavender",
+            );
+        }
+        Compare::Unknown(line) => {
+            return (label_data(line), "shape=diamond, style=dashed");
+        }
+    };
+    let value = match compare {
+        Compare::Value(compare) => {
+            format!("\"{}\"", label_decoded(&compare.value, compare.value_kind))
+        }
+        _ => compare.number().unwrap_or_default().to_string(),
+    };
+    let label = format!(
+        "{target}(\"{}\") {} {value}",
+        label_decoded(&key, key_kind),
+        operator(op)
+    );
+    (label, STYLE)
+}
+
+/// The label and the attributes of an operation node.
+fn operation_node(operation: &Operation) -> (String, &'static str) {
+    match operation {
+        Operation::Put(put) => {
+            let mut label = format!(
+                "put \"{}\" \"{}\"",
+                label_decoded(&put.key, put.key_kind),
+                label_decoded(&put.value, put.value_kind)
+            );
+            if let Some(lease) = put.lease {
+                let _ = write!(label, " --lease={lease:x}");
+            }
+            for flag in &put.unknown_flags {
+                let _ = write!(label, " {}", label_data(unknown_flag(flag).as_bytes()));
+            }
+            (label, "shape=box, style=filled, fillcolor=lightblue")
+        }
+        Operation::PutFile(put) => (
+            format!(
+                "put \"{}\" @{}",
+                label_decoded(&put.key, put.key_kind),
+                label_data(put.path)
+            ),
+            "shape=note, style=filled, fillcolor=lightblue",
+        ),
+        Operation::Delete(delete) => (
+            format!("del \"{}\"", label_decoded(&delete.key, delete.key_kind)),
+            "shape=box, style=filled, fillcolor=lightpink",
+        ),
+        Operation::Get(get) => {
+            let mut label = format!("get \"{}\"", label_decoded(&get.key, get.key_kind));
+            if let Some(range_end) = get.range_end {
+                let _ = write!(
+                    label,
+                    " \"{}\"",
+                    label_decoded(&range_end, get.range_end_kind)
+                );
+            }
+            if get.prefix {
+                label.push_str(" --prefix");
+            }
+            if get.from_key {
+                label.push_str(" --from-key");
+            }
+            if let Some(consistency) = get.consistency {
+                let _ = write!(label, " {}", consistency_flag(consistency));
+            }
+            let filters = [
+                ("min-create-rev", get.min_create_rev),
+                ("max-create-rev", get.max_create_rev),
+                ("min-mod-rev", get.min_mod_rev),
+                ("max-mod-rev", get.max_mod_rev),
+            ];
+            for (name, revision) in filters {
+                if let Some(revision) = revision {
+                    let _ = write!(label, " --{name}={revision}");
+                }
+            }
+            for flag in &get.unknown_flags {
+                let _ = write!(label, " {}", label_data(unknown_flag(flag).as_bytes()));
+            }
+            (
+                label,
+                "shape=box, style=\"rounded,filled\", fillcolor=honeydew",
+            )
+        }
+        Operation::Noop => ("noop".into(), "shape=box, style=dotted"),
+        Operation::Custom(custom) => {
+            let mut label = label_data(custom.command);
+            for arg in &custom.args {
+                let _ = write!(label, " \"{}\"", label_data(arg));
+            }
+            (
+                label,
+                "shape=box, style=\"dashed,filled\", fillcolor=lavender",
+            )
+        }
+        Operation::Unknown(line) => (label_data(line), "shape=box, style=dashed"),
+    }
+}
+
+/// Write a branch as a cluster of chained operations, returning the ID of
+/// its first node.
+fn write_branch(dot: &mut String, name: &str, color: &str, operations: &[Operation]) -> String {
+    let _ = writeln!(dot, "    subgraph cluster_{name} {{");
+    let _ = writeln!(dot, "        label=\"{name}\";");
+    let _ = writeln!(dot, "        color={color};");
+    if operations.is_empty() {
+        let _ = writeln!(
+            dot,
+            "        {name}_empty [label=\"no operation\", shape=plaintext];"
+        );
+    }
+    for (index, operation) in operations.iter().enumerate() {
+        let (label, style) = operation_node(operation);
+        let _ = writeln!(
+            dot,
+            "        {name}{index} [label=\"{}\", {style}];",
+            escape(&label)
+        );
+    }
+    for index in 1..operations.len() {
+        let _ = writeln!(dot, "        {name}{} -> {name}{index};", index - 1);
+    }
+    dot.push_str("    }\n");
+
+    if operations.is_empty() {
+        format!("{name}_empty")
+    } else {
+        format!("{name}0")
+    }
+}
+
+impl TxnData<'_> {
+    /// Render the transaction as a Graphviz DOT graph.
+    ///
+    /// The compares are chained from the `txn` entry node, the last one
+    /// leading to the success branch when true and to the failure branch when
+    /// false. Node IDs only depend on the position of the nodes, e.g.
+    /// `compare0` or `success1`, so that the output of a transaction is
+    /// stable. Keys and values longer than 32 characters are truncated.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph txn {\n");
+        dot.push_str("    node [fontname=\"monospace\"];\n");
+        dot.push_str("    txn [label=\"txn\", shape=circle];\n");
+
+        let mut guard = String::from("txn");
+        if !self.compares.is_empty() {
+            dot.push_str("    subgraph cluster_compares {\n");
+            dot.push_str("        label=\"compares\";\n");
+            dot.push_str("        color=goldenrod;\n");
+            for (index, compare) in self.compares.iter().enumerate() {
+                let (label, style) = compare_node(compare);
+                let _ = writeln!(
+                    dot,
+                    "        compare{index} [label=\"{}\", {style}];",
+                    escape(&label)
+                );
+            }
+            dot.push_str("    }\n");
+
+            for index in 0..self.compares.len() {
+                let _ = writeln!(dot, "    {guard} -> compare{index};");
+                guard = format!("compare{index}");
+            }
+        }
+
+        let success = write_branch(&mut dot, "success", "darkgreen", &self.success);
+        let failure = write_branch(&mut dot, "failure", "red", &self.failure);
+        let _ = writeln!(
+            dot,
+            "    {guard} -> {success} [label=\"true\", color=darkgreen];"
+        );
+        let _ = writeln!(
+            dot,
+            "    {guard} -> {failure} [label=\"false\", color=red];"
+        );
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dot::{compare_node, escape, label_data, operation_node};
+    use crate::options::ParseOptions;
+    use crate::{parse, parse_with_options};
+
+    #[test]
+    fn test_label_data() {
+        assert_eq!(label_data(b"key1"), "key1");
+        assert_eq!(label_data(b"a\nb\tc\0"), "a\\nb\\tc\\u{0}");
+        assert_eq!(label_data(b"\xff"), "\u{fffd}");
+        assert_eq!(label_data(&[b'a'; 32]), "a".repeat(32));
+        assert_eq!(label_data(&[b'a'; 33]), format!("{}…", "a".repeat(32)));
+        // Truncated on characters, not bytes
+        assert_eq!(
+            label_data("é".repeat(40).as_bytes()),
+            format!("{}…", "é".repeat(32))
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("put \"k\" \"v\""), "put \\\"k\\\" \\\"v\\\"");
+        assert_eq!(escape("a\\nb"), "a\\\\nb");
+    }
+
+    #[test]
+    fn test_node_labels() {
+        let options = ParseOptions {
+            keep_unknown_lines: true,
+            allow_file_refs: true,
+            ..Default::default()
+        };
+        let txn = parse_with_options(
+            b"val(k) < \"a\\x20b\"\nlock(k)\n\ndel \"\\x6b\"\nget --prefix --min-mod-rev=3 app/\nput k @v.bin\nwatch k\n\n",
+            &options,
+        )
+        .expect("Failed to parse");
+        let labels: Vec<_> = txn
+            .compares
+            .iter()
+            .map(|compare| compare_node(compare).0)
+            .chain(
+                txn.success
+                    .iter()
+                    .map(|operation| operation_node(operation).0),
+            )
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                "value(\"k\") < \"a b\"",
+                "lock(k)",
+                "del \"k\"",
+                "get \"app/\" --prefix --min-mod-rev=3",
+                "put \"k\" @v.bin",
+                "watch k",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_dot_empty_branches() {
+        let txn = parse(b"\n\n\n\n").expect("Failed to parse");
+        assert_eq!(
+            txn.to_dot(),
+            "digraph txn {
+    node [fontname=\"monospace\"];
+    txn [label=\"txn\", shape=circle];
+    subgraph cluster_success {
+        label=\"success\";
+        color=darkgreen;
+        success_empty [label=\"no operation\", shape=plaintext];
+    }
+    subgraph cluster_failure {
+        label=\"failure\";
+        color=red;
+        failure_empty [label=\"no operation\", shape=plaintext];
+    }
+    txn -> success_empty [label=\"true\", color=darkgreen];
+    txn -> failure_empty [label=\"false\", color=red];
+}
+"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! etcd v3 protobuf wire encoding
+//!
+//! Encodes a transaction as the `etcdserverpb.TxnRequest` message sent to the
+//! `etcdserverpb.KV/Txn` gRPC method, without depending on generated types.
+//! Field numbers are the ones of etcd's
+//! [`rpc.proto`](https://github.com/etcd-io/etcd/blob/main/api/etcdserverpb/rpc.proto).
+
+use crate::compare::{Compare, OpType};
+use crate::convert::{decoded, Construct, ConvertError, Converted, Converter, Lossy};
+use crate::error::Error;
+use crate::operation::{Consistency, Operation};
+use crate::{Section, TxnData};
+
+// ----------------------------------------------------------------------------
+// Wire format
+// ----------------------------------------------------------------------------
+
+const VARINT: u32 = 0;
+const LENGTH_DELIMITED: u32 = 2;
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_tag(buffer: &mut Vec<u8>, field: u32, wire_type: u32) {
+    write_varint(buffer, u64::from((field << 3) | wire_type));
+}
+
+/// Write an integer field, even when zero since a field of a oneof is always
+/// present.
+fn write_int(buffer: &mut Vec<u8>, field: u32, value: u64) {
+    write_tag(buffer, field, VARINT);
+    write_varint(buffer, value);
+}
+
+/// Write a bytes or an embedded message field.
+fn write_bytes(buffer: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_tag(buffer, field, LENGTH_DELIMITED);
+    write_varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+// proto3 doesn't write the fields holding their default value
+
+fn write_optional_int(buffer: &mut Vec<u8>, field: u32, value: u64) {
+    if value != 0 {
+        write_int(buffer, field, value);
+    }
+}
+
+fn write_optional_bytes(buffer: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        write_bytes(buffer, field, bytes);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Messages
+// ----------------------------------------------------------------------------
+
+/// The `Compare.CompareResult` enum value.
+fn compare_result(op: OpType) -> u64 {
+    match op {
+        OpType::Equal => 0,
+        OpType::GreaterThan => 1,
+        OpType::LessThan => 2,
+        OpType::NotEqual => 3,
+    }
+}
+
+/// Encode a `Compare` message.
+fn encode_compare(compare: &Compare) -> Result<Vec<u8>, Construct> {
+    // The `Compare.CompareTarget` enum value and the field of the target union
+    let (target, field, op) = match compare {
+        Compare::Version(compare) => (0, 4, compare.op),
+        Compare::CreateRevision(compare) => (1, 5, compare.op),
+        Compare::ModRevision(compare) => (2, 6, compare.op),
+        Compare::Value(compare) => (3, 7, compare.op),
+        Compare::Lease(compare) => (4, 8, compare.op),
+        Compare::Custom(_) => return Err(Construct::CustomCompare),
+        Compare::Unknown(_) => return Err(Construct::UnknownNode),
+    };
+    let key = decoded(compare.decoded_key())?.unwrap_or_default();
+
+    let mut message = vec![];
+    write_optional_int(&mut message, 1, compare_result(op));
+    write_optional_int(&mut message, 2, target);
+    write_optional_bytes(&mut message, 3, &key);
+    match compare {
+        Compare::Value(compare) => {
+            write_bytes(&mut message, field, &decoded(compare.decoded_value())?)
+        }
+        _ => write_int(&mut message, field, compare.number().unwrap_or_default()),
+    }
+    Ok(message)
+}
+
+/// Encode a `RequestOp` message.
+fn encode_operation(operation: &Operation) -> Result<Vec<u8>, Construct> {
+    if !operation.unknown_flags().is_empty() {
+        return Err(Construct::UnknownFlag);
+    }
+    let mut request = vec![];
+    let field = match operation {
+        Operation::Get(get) => {
+            let range = decoded(get.effective_range())?;
+            write_optional_bytes(&mut request, 1, &range.key);
+            write_optional_bytes(&mut request, 2, &range.range_end);
+            let serializable = get.consistency == Some(Consistency::Serializable);
+            write_optional_int(&mut request, 7, serializable.into());
+            let filters = [
+                (10, get.min_mod_rev),
+                (11, get.max_mod_rev),
+                (12, get.min_create_rev),
+                (13, get.max_create_rev),
+            ];
+            for (field, revision) in filters {
+                // int64 fields are encoded as their two's complement
+                write_optional_int(&mut request, field, revision.unwrap_or_default() as u64);
+            }
+            1
+        }
+        Operation::Put(put) => {
+            write_optional_bytes(&mut request, 1, &decoded(put.decoded_key())?);
+            write_optional_bytes(&mut request, 2, &decoded(put.decoded_value())?);
+            write_optional_int(&mut request, 3, put.lease.unwrap_or_default());
+            2
+        }
+        Operation::Delete(delete) => {
+            let range = decoded(delete.effective_range())?;
+            write_optional_bytes(&mut request, 1, &range.key);
+            write_optional_bytes(&mut request, 2, &range.range_end);
+            3
+        }
+        Operation::PutFile(_) => return Err(Construct::UnresolvedFileRef),
+        Operation::Noop => return Err(Construct::Noop),
+        Operation::Custom(_) => return Err(Construct::CustomOperation),
+        Operation::Unknown(_) => return Err(Construct::UnknownNode),
+    };
+
+    let mut message = vec![];
+    write_bytes(&mut message, field, &request);
+    Ok(message)
+}
+
+impl TxnData<'_> {
+    /// Encode the transaction as the protobuf `TxnRequest` message of the
+    /// etcd v3 `KV/Txn` gRPC method. Keys and values are encoded as the bytes
+    /// they stand for, their escape sequences decoded.
+    ///
+    /// # Errors
+    ///
+    /// A transaction containing unknown nodes, unresolved file references or
+    /// invalid escape sequences can't be encoded.
+    pub fn to_protobuf(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.to_protobuf_with(Lossy::Error)?.value)
+    }
+
+    /// Encode the transaction as a protobuf `TxnRequest` message, applying
+    /// `lossy` to the nodes which can't be encoded. The message has no room
+    /// for an approximation, best effort leaves them out.
+    ///
+    /// # Errors
+    ///
+    /// Under [`Lossy::Error`], the first node which can't be encoded.
+    pub fn to_protobuf_with(&self, lossy: Lossy) -> Result<Converted<Vec<u8>>, ConvertError> {
+        let mut converter = Converter::new(lossy);
+        let fields = [
+            (
+                1,
+                converter.convert(Section::Compare, &self.compares, encode_compare, |_, _| {
+                    None
+                })?,
+            ),
+            (
+                2,
+                converter.convert(Section::Success, &self.success, encode_operation, |_, _| {
+                    None
+                })?,
+            ),
+            (
+                3,
+                converter.convert(Section::Failure, &self.failure, encode_operation, |_, _| {
+                    None
+                })?,
+            ),
+        ];
+        let mut message = vec![];
+        for (field, messages) in fields {
+            for encoded in messages {
+                write_bytes(&mut message, field, &encoded);
+            }
+        }
+        Ok(converter.finish(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encode::write_varint;
+    use crate::error::Error;
+    use crate::options::ParseOptions;
+    use crate::{parse, parse_with_options};
+
+    #[test]
+    fn test_varint() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xac, 0x02]),
+            (
+                -1i64 as u64,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+        ] {
+            let mut buffer = vec![];
+            write_varint(&mut buffer, value);
+            assert_eq!(buffer, encoded);
+        }
+    }
+
+    #[test]
+    fn test_to_protobuf() {
+        let txn = parse(
+            b"mod(key1) > 0\n\nput key1 v --lease=1f\n\ndel k\nget --min-mod-rev=5 --prefix a/",
+        )
+        .expect("Failed to parse");
+        let encoded = txn.to_protobuf().expect("Failed to encode");
+        assert_eq!(
+            encoded,
+            [
+                // compare: result GREATER, target MOD, key "key1", mod_revision 0
+                &[0x0a, 0x0c, 0x08, 0x01, 0x10, 0x02, 0x1a, 0x04][..],
+                b"key1",
+                &[0x30, 0x00],
+                // success: request_put, key "key1", value "v", lease 0x1f
+                &[0x12, 0x0d, 0x12, 0x0b, 0x0a, 0x04],
+                b"key1",
+                &[0x12, 0x01, b'v', 0x18, 0x1f],
+                // failure: request_delete_range, key "k"
+                &[0x1a, 0x05, 0x1a, 0x03, 0x0a, 0x01, b'k'],
+                // failure: request_range, key "a/", range_end "a0", min_mod_revision 5
+                &[0x1a, 0x0c, 0x0a, 0x0a, 0x0a, 0x02],
+                b"a/",
+                &[0x12, 0x02],
+                b"a0",
+                &[0x50, 0x05],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_to_protobuf_escapes() {
+        // The bytes the keys and the values stand for are encoded
+        let txn =
+            parse(b"val(\"k\\x31\") = 'it\\'s'\n\nput k \"a\\\"b\"\n\nget --prefix \"a\\x2f\"\n")
+                .expect("Failed to parse");
+        assert_eq!(
+            txn.to_protobuf().expect("Failed to encode"),
+            [
+                // compare: target VALUE, key "k1", value "it's"
+                &[0x0a, 0x0c, 0x10, 0x03, 0x1a, 0x02][..],
+                b"k1",
+                &[0x3a, 0x04],
+                b"it's",
+                // success: request_put, key "k", value "a\"b"
+                &[0x12, 0x0a, 0x12, 0x08, 0x0a, 0x01, b'k', 0x12, 0x03],
+                b"a\"b",
+                // failure: request_range, key "a/", range_end "a0"
+                &[0x1a, 0x0a, 0x0a, 0x08, 0x0a, 0x02],
+                b"a/",
+                &[0x12, 0x02],
+                b"a0",
+            ]
+            .concat()
+        );
+
+        // An invalid escape sequence stands for no bytes
+        for data in [
+            &b"mod(\"\\q\") > 0\n\n\n\n"[..],
+            b"\n\ndel \"\\q\"\n\n",
+            b"\n\nget a \"\\q\"\n\n",
+        ] {
+            let txn = parse(data).expect("Failed to parse");
+            assert!(
+                matches!(txn.to_protobuf(), Err(Error::InvalidEscape)),
+                "{data:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_protobuf_defaults() {
+        // An equal version compare only holds its key and its oneof value
+        let txn = parse(b"ver(k) = 0\n\n\n\n").expect("Failed to parse");
+        assert_eq!(
+            txn.to_protobuf().expect("Failed to encode"),
+            [0x0a, 0x05, 0x1a, 0x01, b'k', 0x20, 0x00]
+        );
+
+        let txn = parse(b"val(k) < \"\"\n\n\n\n").expect("Failed to parse");
+        assert_eq!(
+            txn.to_protobuf().expect("Failed to encode"),
+            [0x0a, 0x09, 0x08, 0x02, 0x10, 0x03, 0x1a, 0x01, b'k', 0x3a, 0x00]
+        );
+
+        assert_eq!(
+            parse(b"\n\n\n\n")
+                .expect("Failed to parse")
+                .to_protobuf()
+                .expect("Failed to encode"),
+            b""
+        );
+    }
+
+    #[test]
+    fn test_to_protobuf_unsupported() {
+        let options = ParseOptions {
+            keep_unknown_lines: true,
+            allow_file_refs: true,
+            ..Default::default()
+        };
+        let txn = parse_with_options(b"\n\nwatch key1\n\n", &options).expect("Failed to parse");
+        assert!(matches!(txn.to_protobuf(), Err(Error::UnknownNode)));
+
+        let txn =
+            parse_with_options(b"\n\nput key1 @value.bin\n\n", &options).expect("Failed to parse");
+        assert!(matches!(txn.to_protobuf(), Err(Error::UnresolvedFileRef)));
+    }
+}
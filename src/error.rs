@@ -0,0 +1,320 @@
+//! Errors returned by the parser
+
+use crate::validate::ValidationError;
+use crate::LineError;
+use elyze::errors::ParseError;
+use std::fmt::{Display, Formatter};
+
+/// An error covering both parse and validation failures.
+#[derive(Debug)]
+pub enum Error {
+    /// The input is not a valid transaction.
+    Parse(ParseError),
+    /// The transaction parsed but is rejected by the validation rules.
+    Validation(ValidationError),
+    /// The transaction contains unknown nodes and can't be converted.
+    UnknownNode,
+    /// The transaction contains file references which must be resolved first.
+    UnresolvedFileRef,
+    /// The transaction contains custom operations or compares, which etcd
+    /// doesn't know.
+    CustomOperation,
+    /// The transaction contains operations with flags the parser doesn't
+    /// know, captured by [`UnknownFlagPolicy::Capture`](crate::options::UnknownFlagPolicy::Capture).
+    UnknownFlag,
+    /// The transaction contains noop operations, placeholders which must be
+    /// filled in first, see
+    /// [`ParseOptions::allow_extensions`](crate::options::ParseOptions::allow_extensions).
+    Noop,
+    /// The transaction contains keys or values holding an invalid escape
+    /// sequence, e.g. the `\q` of `put k "\q"`, which stand for no bytes.
+    InvalidEscape,
+    /// Non-blank content follows the failure section, usually because of a
+    /// blank line inside the success or the failure section.
+    TrailingContent {
+        /// The position of the first unconsumed line in the input.
+        offset: usize,
+        /// The first unconsumed line.
+        line: Vec<u8>,
+    },
+    /// A compare is followed on its line by more than whitespaces, e.g. the
+    /// `x` of `mod(key) > 5x`.
+    TrailingJunk {
+        /// The position of the content following the compare in the input.
+        offset: usize,
+    },
+    /// A compare can never succeed, e.g. `mod(key) < 0`, rejected by
+    /// [`ParseOptions::strict_numbers`](crate::options::ParseOptions::strict_numbers).
+    UnsatisfiableCompare {
+        /// The position of the compare in the input.
+        offset: usize,
+    },
+    /// A compare or an operation line is longer than
+    /// [`ParseOptions::max_line_len`](crate::options::ParseOptions::max_line_len).
+    LineTooLong {
+        /// The position of the line in the input.
+        offset: usize,
+    },
+    /// A compare uses an operator the etcd version targeted by
+    /// [`ParseOptions::etcd_version`](crate::options::ParseOptions::etcd_version)
+    /// doesn't support.
+    UnsupportedOperator {
+        /// The position of the operator in the input.
+        offset: usize,
+        /// The operator, e.g. `!=`.
+        operator: String,
+    },
+    /// The trailing checksum doesn't match the input preceding it.
+    ChecksumMismatch {
+        /// The SHA-256 written on the checksum line.
+        expected: [u8; 32],
+        /// The SHA-256 of the input preceding the checksum line.
+        actual: [u8; 32],
+    },
+    /// The trailing checksum line required to verify the input is missing.
+    MissingChecksum,
+    /// The input declares a format version newer than the parser's, see
+    /// [`FORMAT_VERSION`](crate::FORMAT_VERSION).
+    UnsupportedFormatVersion {
+        /// The version declared by the input.
+        required: u32,
+        /// The latest version understood by the parser.
+        supported: u32,
+    },
+    /// The output of `etcdctl txn` can't be read.
+    InvalidOutput {
+        /// The number of the line, from 1.
+        line: usize,
+        /// What is wrong with the line.
+        message: String,
+    },
+    /// The transaction couldn't be read.
+    Io(std::io::Error),
+    /// The JSON body isn't a valid transaction.
+    #[cfg(feature = "json")]
+    Json(String),
+    /// A CSV row can't be imported.
+    #[cfg(feature = "csv")]
+    Csv {
+        /// The number of the row, from 1, a header row included.
+        row: u64,
+        /// What is wrong with the row.
+        message: String,
+    },
+}
+
+impl Error {
+    /// The position in the input the error points at, if any.
+    pub(crate) fn offset(&self) -> Option<usize> {
+        match self {
+            Error::TrailingContent { offset, .. }
+            | Error::TrailingJunk { offset }
+            | Error::UnsatisfiableCompare { offset }
+            | Error::LineTooLong { offset }
+            | Error::UnsupportedOperator { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "parse error: {}", describe(err)),
+            Error::Validation(err) => write!(f, "validation error: {err}"),
+            Error::UnknownNode => write!(f, "the transaction contains unknown nodes"),
+            Error::UnresolvedFileRef => {
+                write!(f, "the transaction contains unresolved file references")
+            }
+            Error::CustomOperation => {
+                write!(f, "the transaction contains custom operations or compares")
+            }
+            Error::UnknownFlag => {
+                write!(f, "the transaction contains operations with unknown flags")
+            }
+            Error::Noop => write!(f, "the transaction contains noop operations"),
+            Error::InvalidEscape => {
+                write!(f, "the transaction contains invalid escape sequences")
+            }
+            Error::TrailingContent { offset, line } => write!(
+                f,
+                "unexpected content after the failure section at offset {offset}: {:?}",
+                String::from_utf8_lossy(line)
+            ),
+            Error::TrailingJunk { offset } => {
+                write!(f, "unexpected content after the compare at offset {offset}")
+            }
+            Error::UnsatisfiableCompare { offset } => {
+                write!(f, "the compare at offset {offset} can never succeed")
+            }
+            Error::LineTooLong { offset } => {
+                write!(f, "the line at offset {offset} is too long")
+            }
+            Error::UnsupportedOperator { offset, operator } => write!(
+                f,
+                "the operator {operator} at offset {offset} isn't supported by the targeted etcd version"
+            ),
+            Error::ChecksumMismatch { expected, actual } => {
+                let hex = |digest: &[u8; 32]| -> String {
+                    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+                };
+                write!(
+                    f,
+                    "checksum mismatch: expected sha256:{}, found sha256:{}",
+                    hex(expected),
+                    hex(actual)
+                )
+            }
+            Error::MissingChecksum => write!(f, "the trailing checksum line is missing"),
+            Error::UnsupportedFormatVersion {
+                required,
+                supported,
+            } => write!(
+                f,
+                "this file requires format version {required}, the parser supports up to {supported}"
+            ),
+            Error::InvalidOutput { line, message } => {
+                write!(f, "invalid etcdctl output at line {line}: {message}")
+            }
+            Error::Io(err) => write!(f, "I/O error: {err}"),
+            #[cfg(feature = "json")]
+            Error::Json(err) => write!(f, "invalid JSON transaction: {err}"),
+            #[cfg(feature = "csv")]
+            Error::Csv { row, message } => write!(f, "invalid CSV row {row}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(err) => Some(err),
+            Error::Validation(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The message of a parse error, worded the same way as the other errors
+/// rather than as elyze words it.
+fn describe(err: &ParseError) -> String {
+    match err {
+        ParseError::UnexpectedEndOfInput => "unexpected end of input".to_string(),
+        ParseError::UnexpectedToken => "unexpected token".to_string(),
+        ParseError::Utf8Error(err) => format!("invalid UTF-8: {err}"),
+        ParseError::ParseIntError(err) => format!("invalid number: {err}"),
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Self {
+        Error::Validation(err)
+    }
+}
+
+/// Render a line error as a rustc-style snippet: the error, its line and
+/// column, then the line of the input with a caret under the offending byte.
+///
+/// ```text
+/// error: unexpected token in compare section
+///  --> 2:1
+///   |
+/// 2 | mod("key2") >> 0
+///   | ^
+/// ```
+pub fn render_error(input: &[u8], (section, offset, err): &LineError) -> String {
+    let (line, number, column) = locate(input, *offset);
+    let gutter = " ".repeat(number.to_string().len());
+
+    format!(
+        "error: {} in {section} section\n\
+         {gutter}--> {number}:{column}\n\
+         {gutter} |\n\
+         {number} | {}\n\
+         {gutter} | {}^\n",
+        describe(err),
+        String::from_utf8_lossy(line),
+        " ".repeat(column - 1)
+    )
+}
+
+/// The line of `input` holding `offset`, without its line ending, along with
+/// the number of the line and the column of `offset`, both from 1. The column
+/// counts characters, not bytes.
+pub(crate) fn locate(input: &[u8], offset: usize) -> (&[u8], usize, usize) {
+    let offset = offset.min(input.len());
+    let (before, after) = input.split_at(offset);
+    let start = before
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let end = after
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(input.len(), |newline| offset + newline);
+    let line = input.get(start..end).unwrap_or_default();
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    let number = before
+        .iter()
+        .take(start)
+        .filter(|&&byte| byte == b'\n')
+        .count()
+        + 1;
+    // A multibyte character is a single column, for the caret to align
+    let column = String::from_utf8_lossy(line.get(..offset - start).unwrap_or(line))
+        .chars()
+        .count();
+    (line, number, column + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::render_error;
+    use crate::Section;
+    use elyze::errors::ParseError;
+
+    #[test]
+    fn test_render_error() {
+        let input = b"mod(key1) > 0\n\nput key1 v\r\nfrobnicate key1\n\n";
+        let err = "unexpected token";
+        let line_error = |section, offset| (section, offset, ParseError::UnexpectedToken);
+        assert_eq!(
+            render_error(input, &line_error(Section::Success, 27)),
+            format!("error: {err} in success section\n --> 4:1\n  |\n4 | frobnicate key1\n  | ^\n")
+        );
+        assert_eq!(
+            render_error(input, &line_error(Section::Success, 38)),
+            format!(
+                "error: {err} in success section\n --> 4:12\n  |\n4 | frobnicate key1\n  |            ^\n"
+            )
+        );
+
+        // The caret stays under the line past a multibyte character
+        let input = "\n\nput é\n".as_bytes();
+        assert_eq!(
+            render_error(input, &line_error(Section::Success, 8)),
+            format!("error: {err} in success section\n --> 3:6\n  |\n3 | put é\n  |      ^\n")
+        );
+
+        // The line number widens the gutter
+        let input = [&b"\n"[..]; 10].concat();
+        assert_eq!(
+            render_error(&input, &line_error(Section::Failure, 10)),
+            format!("error: {err} in failure section\n  --> 11:1\n   |\n11 | \n   | ^\n")
+        );
+    }
+}
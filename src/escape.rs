@@ -0,0 +1,206 @@
+//! Escape sequences of quoted strings
+//!
+//! Quoted keys and values are kept as written by the parser, a backslash only
+//! preventing the following quote from closing the string. The escape
+//! sequences are decoded on demand.
+// Malformed input must be reported, never panic
+#![deny(clippy::arithmetic_side_effects)]
+
+use crate::operation::DataKind;
+use elyze::errors::{ParseError, ParseResult};
+use std::borrow::Cow;
+
+/// Parse hexadecimal digits into a number.
+fn hex(digits: &[u8]) -> ParseResult<u32> {
+    std::str::from_utf8(digits)
+        .ok()
+        .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+        .ok_or(ParseError::UnexpectedToken)
+}
+
+/// Decode the escape sequence following a backslash, returning its length.
+fn decode_escape(sequence: &[u8], decoded: &mut Vec<u8>) -> ParseResult<usize> {
+    let (byte, len) = match sequence.first() {
+        Some(b'\\') => (b'\\', 1),
+        Some(b'"') => (b'"', 1),
+        Some(b'\'') => (b'\'', 1),
+        Some(b'n') => (b'\n', 1),
+        Some(b'r') => (b'\r', 1),
+        Some(b't') => (b'\t', 1),
+        Some(b'0') => (b'\0', 1),
+        Some(b'x') => {
+            let digits = sequence.get(1..3).ok_or(ParseError::UnexpectedToken)?;
+            (hex(digits)? as u8, 3)
+        }
+        Some(b'u') => {
+            // `u{` followed by one to six hexadecimal digits and `}`
+            let braced = sequence
+                .get(1..)
+                .and_then(|rest| rest.strip_prefix(b"{"))
+                .ok_or(ParseError::UnexpectedToken)?;
+            let digits = braced
+                .iter()
+                .position(|&byte| byte == b'}')
+                .filter(|end| (1..=6).contains(end))
+                .and_then(|end| braced.get(..end))
+                .ok_or(ParseError::UnexpectedToken)?;
+            // Surrogates and values past U+10FFFF aren't scalar values
+            let char = char::from_u32(hex(digits)?).ok_or(ParseError::UnexpectedToken)?;
+            decoded.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+            // The digits along with the `u{` and `}` around them
+            return digits
+                .len()
+                .checked_add(3)
+                .ok_or(ParseError::UnexpectedToken);
+        }
+        _ => return Err(ParseError::UnexpectedToken),
+    };
+    decoded.push(byte);
+    Ok(len)
+}
+
+/// Decode the escape sequences of a quoted string.
+///
+/// The supported sequences are `\\`, `\"`, `\'`, `\n`, `\r`, `\t`, `\0`, `\xNN` for
+/// any byte and `\u{NNNN}` for a Unicode scalar value, written as its UTF-8
+/// encoding. A string without escape sequence is borrowed as is.
+///
+/// # Errors
+///
+/// An unknown escape sequence or an invalid codepoint, such as a surrogate,
+/// is an `UnexpectedToken` error.
+pub fn unescape(data: &[u8]) -> ParseResult<Cow<'_, [u8]>> {
+    let Some(first) = memchr::memchr(b'\\', data) else {
+        return Ok(Cow::Borrowed(data));
+    };
+
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut rest = data;
+    let mut next = Some(first);
+    while let Some(index) = next {
+        let (before, escape) = rest
+            .split_at_checked(index)
+            .ok_or(ParseError::UnexpectedToken)?;
+        decoded.extend_from_slice(before);
+        // Skip the backslash
+        let sequence = escape.get(1..).unwrap_or_default();
+        let len = decode_escape(sequence, &mut decoded)?;
+        rest = sequence.get(len..).ok_or(ParseError::UnexpectedToken)?;
+        next = memchr::memchr(b'\\', rest);
+    }
+    decoded.extend_from_slice(rest);
+    Ok(Cow::Owned(decoded))
+}
+
+/// Decode the escape sequences of a single-quoted string.
+///
+/// As in a shell, `\'` is the only sequence, any other backslash standing
+/// for itself. A string without escape sequence is borrowed as is.
+pub fn unescape_single_quoted(data: &[u8]) -> Cow<'_, [u8]> {
+    let Some(first) = memchr::memmem::find(data, b"\\'") else {
+        return Cow::Borrowed(data);
+    };
+
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut rest = data;
+    let mut next = Some(first);
+    while let Some((before, escape)) = next.and_then(|index| rest.split_at_checked(index)) {
+        decoded.extend_from_slice(before);
+        decoded.push(b'\'');
+        rest = escape.get(2..).unwrap_or_default();
+        next = memchr::memmem::find(rest, b"\\'");
+    }
+    decoded.extend_from_slice(rest);
+    Cow::Owned(decoded)
+}
+
+/// Decode the escape sequences of a key or a value written as `kind`, giving
+/// the bytes it stands for, see [`unescape`] and [`unescape_single_quoted`].
+///
+/// The bytes of an unquoted string or of [`DataKind::Bytes`] are borrowed as
+/// is.
+///
+/// # Errors
+///
+/// An invalid escape sequence of a double-quoted string is an
+/// `UnexpectedToken` error.
+pub fn decode(data: &[u8], kind: DataKind) -> ParseResult<Cow<'_, [u8]>> {
+    match kind {
+        DataKind::Unquoted | DataKind::Bytes => Ok(Cow::Borrowed(data)),
+        DataKind::SingleQuoted => Ok(unescape_single_quoted(data)),
+        DataKind::Quoted => unescape(data),
+    }
+}
+
+/// Whether two keys or values stand for the same bytes, however they are
+/// written. One holding an invalid escape sequence only equals the same
+/// bytes written the same way.
+pub(crate) fn same_data(
+    (left, left_kind): (&[u8], DataKind),
+    (right, right_kind): (&[u8], DataKind),
+) -> bool {
+    match (decode(left, left_kind), decode(right, right_kind)) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right && left_kind == right_kind,
+    }
+}
+
+/// Decode a key or a value written as `kind` into owned bytes, keeping the
+/// bytes as written if they hold an invalid escape sequence.
+pub(crate) fn decode_lossy(data: &[u8], kind: DataKind) -> Vec<u8> {
+    decode(data, kind).map_or_else(|_| data.to_vec(), Cow::into_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::escape::{unescape, unescape_single_quoted};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_unescape() {
+        assert!(matches!(unescape(b"plain"), Ok(Cow::Borrowed(b"plain"))));
+        for (data, expected) in [
+            (&br#"a\"b\\c"#[..], &b"a\"b\\c"[..]),
+            (br"\n\r\t\0", b"\n\r\t\0"),
+            (br"\x00\x7f\xFF", b"\x00\x7f\xff"),
+            (br"\u{00e9}", "é".as_bytes()),
+            (br"\u{e9}\u{1F600}!", "é😀!".as_bytes()),
+            (br"\u{10FFFF}", "\u{10FFFF}".as_bytes()),
+        ] {
+            assert_eq!(unescape(data).expect("Failed to unescape"), expected);
+        }
+    }
+
+    #[test]
+    fn test_unescape_invalid() {
+        for data in [
+            &br"\"[..],
+            br"\q",
+            br"\x0",
+            br"\xg0",
+            br"\u{}",
+            br"\u{1234567}",
+            br"\u{D800}",
+            br"\u{110000}",
+            br"\u{00e9",
+            br"\u{+e9}",
+        ] {
+            assert!(unescape(data).is_err(), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn test_unescape_single_quoted() {
+        assert!(matches!(
+            unescape_single_quoted(br"a\nb"),
+            Cow::Borrowed(br"a\nb")
+        ));
+        assert_eq!(unescape_single_quoted(br"it\'s"), &b"it's"[..]);
+        assert_eq!(unescape_single_quoted(br#"\'"\\'\'"#), &br#"'"\''"#[..]);
+        assert_eq!(
+            unescape(br"it\'s").expect("Failed to unescape"),
+            &b"it's"[..]
+        );
+    }
+}
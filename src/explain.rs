@@ -0,0 +1,420 @@
+//! Plain English descriptions of a transaction
+
+use crate::compare::{Compare, OpType};
+use crate::escape::decode_lossy;
+use crate::operation::{Consistency, DataKind, Operation};
+use crate::TxnData;
+use std::fmt::Write;
+
+/// Options of [`TxnData::explain_with`].
+#[derive(Debug, Clone)]
+pub struct ExplainOptions {
+    /// The number of characters of a value shown, the longer values being
+    /// elided with an ellipsis. 32 by default.
+    pub max_value_len: usize,
+
+    /// Describe the compares in words and detail the operations, leases and
+    /// revision filters included. Enabled by default, the terse output only
+    /// names the operations and their keys.
+    pub verbose: bool,
+}
+
+impl Default for ExplainOptions {
+    fn default() -> Self {
+        ExplainOptions {
+            max_value_len: 32,
+            verbose: true,
+        }
+    }
+}
+
+fn operator(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "equal to",
+        OpType::GreaterThan => "greater than",
+        OpType::LessThan => "less than",
+        OpType::NotEqual => "not equal to",
+    }
+}
+
+fn symbol(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "=",
+        OpType::GreaterThan => ">",
+        OpType::LessThan => "<",
+        OpType::NotEqual => "!=",
+    }
+}
+
+/// A key shown lossily, as the bytes it stands for, see
+/// [`decode`](crate::escape::decode). One holding an invalid escape sequence
+/// is shown as written: the explanation doesn't fail.
+fn shown(data: &[u8], kind: DataKind) -> String {
+    String::from_utf8_lossy(&decode_lossy(data, kind)).into_owned()
+}
+
+/// A value shown quoted, elided past `max_len` characters.
+fn elide(value: &[u8], max_len: usize) -> String {
+    let value = String::from_utf8_lossy(value);
+    let mut shown: String = value.chars().take(max_len).collect();
+    if value.chars().nth(max_len).is_some() {
+        shown.push('…');
+    }
+    format!("{shown:?}")
+}
+
+/// Describe a compare, e.g. `key1's mod revision must be greater than 0`.
+fn explain_compare(compare: &Compare) -> String {
+    let (key, target, op, value) = match compare {
+        Compare::CreateRevision(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "create revision",
+            compare.op,
+            compare.value.to_string(),
+        ),
+        Compare::ModRevision(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "mod revision",
+            compare.op,
+            compare.value.to_string(),
+        ),
+        Compare::Value(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "value",
+            compare.op,
+            format!(
+                "{:?}",
+                String::from_utf8_lossy(&decode_lossy(&compare.value, compare.value_kind))
+            ),
+        ),
+        Compare::Version(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "version",
+            compare.op,
+            compare.value.to_string(),
+        ),
+        Compare::Lease(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "lease",
+            compare.op,
+            compare.value.to_string(),
+        ),
+        Compare::Custom(custom) => {
+            let mut explanation =
+                format!("custom compare {}", String::from_utf8_lossy(custom.target));
+            for arg in &custom.args {
+                let _ = write!(explanation, " {:?}", String::from_utf8_lossy(arg));
+            }
+            return explanation;
+        }
+        Compare::Unknown(line) => {
+            return format!("unknown compare `{}`", String::from_utf8_lossy(line));
+        }
+    };
+    format!("{key}'s {target} must be {} {value}", operator(op))
+}
+
+/// Describe a compare as the condition of a transaction, e.g. `key1's mod
+/// revision is greater than 0` or `key1 exists (create revision > 0)`.
+fn explain_condition(compare: &Compare, options: &ExplainOptions) -> String {
+    let (key, target, op, value) = match compare {
+        Compare::CreateRevision(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "create revision",
+            compare.op,
+            None,
+        ),
+        Compare::ModRevision(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "mod revision",
+            compare.op,
+            None,
+        ),
+        Compare::Value(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "value",
+            compare.op,
+            Some(decode_lossy(&compare.value, compare.value_kind)),
+        ),
+        Compare::Version(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "version",
+            compare.op,
+            None,
+        ),
+        Compare::Lease(compare) => (
+            shown(&compare.key, compare.key_kind),
+            "lease",
+            compare.op,
+            None,
+        ),
+        Compare::Custom(custom) => {
+            let mut explanation = format!("custom {}", String::from_utf8_lossy(custom.target));
+            if options.verbose {
+                for arg in &custom.args {
+                    let _ = write!(explanation, " {}", elide(arg, options.max_value_len));
+                }
+            }
+            return explanation;
+        }
+        Compare::Unknown(line) => {
+            return format!("unknown compare `{}`", String::from_utf8_lossy(line));
+        }
+    };
+    let value = match value {
+        Some(value) => elide(&value, options.max_value_len),
+        None => compare.number().unwrap_or_default().to_string(),
+    };
+
+    if !options.verbose {
+        return format!("{key}'s {target} {} {value}", symbol(op));
+    }
+    let check = format!("{target} {} {value}", symbol(op));
+    if compare.checks_presence() {
+        format!("{key} exists ({check})")
+    } else if compare.checks_absence() {
+        format!("{key} doesn't exist ({check})")
+    } else {
+        format!("{key}'s {target} is {} {value}", operator(op))
+    }
+}
+
+/// Describe an operation, e.g. `put "value1" in key1`.
+fn explain_operation(operation: &Operation, options: &ExplainOptions) -> String {
+    match operation {
+        Operation::Put(put) if options.verbose => {
+            let mut explanation = format!(
+                "put {} in {}",
+                elide(
+                    &decode_lossy(&put.value, put.value_kind),
+                    options.max_value_len
+                ),
+                shown(&put.key, put.key_kind)
+            );
+            if let Some(lease) = put.lease {
+                let _ = write!(explanation, ", attached to lease {lease:x}");
+            }
+            explanation
+        }
+        Operation::PutFile(put) if options.verbose => format!(
+            "put the content of {} in {}",
+            String::from_utf8_lossy(put.path),
+            shown(&put.key, put.key_kind)
+        ),
+        Operation::Put(put) => format!("put {}", shown(&put.key, put.key_kind)),
+        Operation::PutFile(put) => format!("put {}", shown(&put.key, put.key_kind)),
+        Operation::Delete(delete) => {
+            format!("delete {}", shown(&delete.key, delete.key_kind))
+        }
+        Operation::Get(get) => {
+            let key = shown(&get.key, get.key_kind);
+            let mut explanation = if get.prefix {
+                format!("get the keys prefixed by {key}")
+            } else if get.from_key {
+                format!("get the keys from {key} on")
+            } else if let Some(range_end) = get.range_end {
+                let range_end = shown(&range_end, get.range_end_kind);
+                format!("get the keys from {key} up to {range_end}, excluded")
+            } else {
+                format!("get {key}")
+            };
+            if get.consistency == Some(Consistency::Serializable) {
+                explanation.push_str(", possibly stale");
+            }
+            if options.verbose {
+                let filters: Vec<_> = [
+                    ("created at or after", get.min_create_rev),
+                    ("created at or before", get.max_create_rev),
+                    ("modified at or after", get.min_mod_rev),
+                    ("modified at or before", get.max_mod_rev),
+                ]
+                .into_iter()
+                .filter_map(|(filter, revision)| {
+                    revision.map(|revision| format!("{filter} revision {revision}"))
+                })
+                .collect();
+                if !filters.is_empty() {
+                    let _ = write!(explanation, ", {}", filters.join(" and "));
+                }
+            }
+            explanation
+        }
+        Operation::Noop => "do nothing".into(),
+        Operation::Custom(custom) => {
+            let mut explanation = format!("custom {}", String::from_utf8_lossy(custom.command));
+            if options.verbose {
+                for arg in &custom.args {
+                    let _ = write!(explanation, " {}", elide(arg, options.max_value_len));
+                }
+            }
+            explanation
+        }
+        Operation::Unknown(line) => {
+            format!("unknown operation `{}`", String::from_utf8_lossy(line))
+        }
+    }
+}
+
+/// Write a branch as a heading followed by one bullet per operation.
+fn write_branch(
+    explanation: &mut String,
+    heading: &str,
+    operations: &[Operation],
+    options: &ExplainOptions,
+) {
+    let _ = writeln!(explanation, "{heading}:");
+    if operations.is_empty() {
+        explanation.push_str("- nothing\n");
+    }
+    for operation in operations {
+        let _ = writeln!(explanation, "- {}", explain_operation(operation, options));
+    }
+}
+
+impl TxnData<'_> {
+    /// Describe each compare of the transaction in plain English, e.g.
+    /// `key1's mod revision must be greater than 0`.
+    pub fn explain_compares(&self) -> impl Iterator<Item = String> + '_ {
+        self.compares.iter().map(explain_compare)
+    }
+
+    /// Describe the transaction in plain English with the default options,
+    /// see [`TxnData::explain_with`].
+    pub fn explain(&self) -> String {
+        self.explain_with(&ExplainOptions::default())
+    }
+
+    /// Describe the transaction in plain English, e.g.
+    ///
+    /// ```text
+    /// If key1's mod revision is greater than 0:
+    /// - put "overwrote-key1" in key1
+    /// Otherwise:
+    /// - put "created-key1" in key1
+    /// ```
+    ///
+    /// The compares are joined with `and` into the condition, followed by one
+    /// bullet per operation of each branch. Without compare, the success
+    /// branch is always applied and the failure branch never is. Keys are
+    /// shown lossily, values are quoted.
+    pub fn explain_with(&self, options: &ExplainOptions) -> String {
+        let mut explanation = String::new();
+        if self.compares.is_empty() {
+            write_branch(&mut explanation, "Always", &self.success, options);
+            if !self.failure.is_empty() {
+                write_branch(&mut explanation, "Never", &self.failure, options);
+            }
+            return explanation;
+        }
+
+        let conditions: Vec<_> = self
+            .compares
+            .iter()
+            .map(|compare| explain_condition(compare, options))
+            .collect();
+        let heading = format!("If {}", conditions.join(" and "));
+        write_branch(&mut explanation, &heading, &self.success, options);
+        write_branch(&mut explanation, "Otherwise", &self.failure, options);
+        explanation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::explain::{elide, explain_operation, ExplainOptions};
+    use crate::options::ParseOptions;
+    use crate::{parse, parse_with_options};
+
+    #[test]
+    fn test_elide() {
+        assert_eq!(elide(b"value", 5), "\"value\"");
+        assert_eq!(elide(b"values", 5), "\"value…\"");
+        assert_eq!(elide("ééé".as_bytes(), 2), "\"éé…\"");
+        assert_eq!(elide(b"a\"b\n", 8), "\"a\\\"b\\n\"");
+    }
+
+    #[test]
+    fn test_explain_operations() {
+        let options = ParseOptions {
+            allow_file_refs: true,
+            ..Default::default()
+        };
+        let txn = parse_with_options(
+            b"\n\nput k v --lease=1f\nput k @v.bin\nget --prefix --min-mod-rev=3 --max-create-rev=9 app/\n\n",
+            &options,
+        )
+        .expect("Failed to parse");
+        let explain = |options: &ExplainOptions| -> Vec<_> {
+            txn.success
+                .iter()
+                .map(|operation| explain_operation(operation, options))
+                .collect()
+        };
+        assert_eq!(
+            explain(&ExplainOptions::default()),
+            vec![
+                "put \"v\" in k, attached to lease 1f",
+                "put the content of v.bin in k",
+                "get the keys prefixed by app/, created at or before revision 9 and modified at or after revision 3",
+            ]
+        );
+        let terse = ExplainOptions {
+            verbose: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            explain(&terse),
+            vec!["put k", "put k", "get the keys prefixed by app/"]
+        );
+    }
+
+    #[test]
+    fn test_explain_conditions() {
+        let txn = parse(b"c(k) = 0\nver(k) > 0\nval(k) < \"a long value\"\n\n\n\n")
+            .expect("Failed to parse");
+        let options = ExplainOptions {
+            max_value_len: 6,
+            ..Default::default()
+        };
+        assert_eq!(
+            txn.explain_with(&options),
+            "If k doesn't exist (create revision = 0) and k exists (version > 0) and k's value is less than \"a long…\":\n- nothing\nOtherwise:\n- nothing\n"
+        );
+        let terse = ExplainOptions {
+            verbose: false,
+            ..options
+        };
+        assert_eq!(
+            txn.explain_with(&terse),
+            "If k's create revision = 0 and k's version > 0 and k's value < \"a long…\":\n- nothing\nOtherwise:\n- nothing\n"
+        );
+    }
+
+    #[test]
+    fn test_explain_escapes() {
+        // The bytes the keys and the values stand for are shown, data holding
+        // an invalid escape sequence as written
+        let txn =
+            parse(b"val(\"k\\x31\") = 'it\\'s'\n\nput \"\\x41\" \"a\\\"b\"\nput k \"\\q\"\n\n")
+                .expect("Failed to parse");
+        assert_eq!(
+            txn.explain(),
+            "If k1's value is equal to \"it's\":\n- put \"a\\\"b\" in A\n- put \"\\\\q\" in k\nOtherwise:\n- nothing\n"
+        );
+        assert_eq!(
+            txn.explain_compares().collect::<Vec<_>>(),
+            vec!["k1's value must be equal to \"it's\""]
+        );
+    }
+
+    #[test]
+    fn test_explain_without_compare() {
+        let txn = parse(b"\n\nput k v\n\ndel k\n").expect("Failed to parse");
+        assert_eq!(
+            txn.explain(),
+            "Always:\n- put \"v\" in k\nNever:\n- delete k\n"
+        );
+        let txn = parse(b"\n\n\n\n").expect("Failed to parse");
+        assert_eq!(txn.explain(), "Always:\n- nothing\n");
+    }
+}
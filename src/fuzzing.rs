@@ -0,0 +1,171 @@
+//! `arbitrary` implementations of the owned transactional data structures
+//!
+//! Used by the structure-aware fuzz targets, see the `fuzz` directory.
+
+use crate::compare::OpType;
+use crate::operation::Consistency;
+use crate::options::DEFAULT_QUOTE_CHARS;
+use crate::owned::{CompareOwned, OperationOwned, TxnDataOwned};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+/// The most items generated in a section.
+const MAX_ITEMS: usize = 8;
+
+/// Generate a key or a value, favoring the shapes likely to trip the
+/// serializer: empty, long, quote-heavy and non-UTF-8.
+fn arbitrary_bytes(u: &mut Unstructured<'_>) -> Result<Vec<u8>> {
+    let bytes = match u.int_in_range(0..=4u8)? {
+        0 => vec![],
+        1 => {
+            let len = u.int_in_range(256..=4096usize)?;
+            vec![u.arbitrary::<u8>()?; len]
+        }
+        2 => {
+            let len = u.int_in_range(1..=16usize)?;
+            (0..len)
+                .map(|_| u.choose(b"\"\"'\\ \t\n()").copied())
+                .collect::<Result<_>>()?
+        }
+        3 => {
+            let len = u.int_in_range(1..=16usize)?;
+            (0..len)
+                .map(|_| u.int_in_range(0x80..=0xffu8))
+                .collect::<Result<_>>()?
+        }
+        _ => {
+            let len = u.int_in_range(1..=16usize)?;
+            (0..len)
+                .map(|_| u.int_in_range(b'!'..=b'~'))
+                .collect::<Result<_>>()?
+        }
+    };
+    Ok(bytes)
+}
+
+/// Generate a non-empty line which doesn't parse as any node, nor quotes a
+/// string which would run over the following lines.
+fn arbitrary_unknown_line(u: &mut Unstructured<'_>) -> Result<Vec<u8>> {
+    let mut line = b"?".to_vec();
+    let len = u.int_in_range(0..=16usize)?;
+    for _ in 0..len {
+        line.push(u.int_in_range(b' '..=b'~')?);
+    }
+    line.retain(|byte| !DEFAULT_QUOTE_CHARS.contains(byte));
+    Ok(line)
+}
+
+/// Generate the path of a file reference, written unquoted after a `@`.
+fn arbitrary_path(u: &mut Unstructured<'_>) -> Result<Vec<u8>> {
+    let len = u.int_in_range(1..=16usize)?;
+    (0..len).map(|_| u.choose(b"abcdef./_-").copied()).collect()
+}
+
+fn arbitrary_items<'a, T: Arbitrary<'a>>(u: &mut Unstructured<'a>) -> Result<Vec<T>> {
+    let len = u.int_in_range(0..=MAX_ITEMS)?;
+    (0..len).map(|_| T::arbitrary(u)).collect()
+}
+
+impl<'a> Arbitrary<'a> for OpType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        u.choose(&[
+            OpType::Equal,
+            OpType::GreaterThan,
+            OpType::LessThan,
+            OpType::NotEqual,
+        ])
+        .copied()
+    }
+}
+
+impl<'a> Arbitrary<'a> for CompareOwned {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let compare = match u.int_in_range(0..=5u8)? {
+            0 => CompareOwned::CreateRevision {
+                key: arbitrary_bytes(u)?,
+                value: u.arbitrary()?,
+                op: u.arbitrary()?,
+            },
+            1 => CompareOwned::ModRevision {
+                key: arbitrary_bytes(u)?,
+                value: u.arbitrary()?,
+                op: u.arbitrary()?,
+            },
+            2 => CompareOwned::Value {
+                key: arbitrary_bytes(u)?,
+                value: arbitrary_bytes(u)?,
+                op: u.arbitrary()?,
+            },
+            3 => CompareOwned::Version {
+                key: arbitrary_bytes(u)?,
+                value: u.arbitrary()?,
+                op: u.arbitrary()?,
+            },
+            4 => CompareOwned::Lease {
+                key: arbitrary_bytes(u)?,
+                value: u.arbitrary()?,
+                op: u.arbitrary()?,
+            },
+            _ => CompareOwned::Unknown(arbitrary_unknown_line(u)?),
+        };
+        Ok(compare)
+    }
+}
+
+impl<'a> Arbitrary<'a> for OperationOwned {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let operation = match u.int_in_range(0..=5u8)? {
+            0 => OperationOwned::Put {
+                key: arbitrary_bytes(u)?,
+                value: arbitrary_bytes(u)?,
+                lease: u.arbitrary()?,
+                unknown_flags: vec![],
+            },
+            1 => OperationOwned::Delete {
+                key: arbitrary_bytes(u)?,
+            },
+            2 => {
+                let key = arbitrary_bytes(u)?;
+                // At most one of the range flags, as etcdctl requires
+                let (prefix, from_key, range_end) = match u.int_in_range(0..=3u8)? {
+                    0 => (false, false, None),
+                    1 => (true, false, None),
+                    2 => (false, true, None),
+                    _ => (false, false, Some(arbitrary_bytes(u)?)),
+                };
+                OperationOwned::Get {
+                    key,
+                    prefix,
+                    from_key,
+                    range_end,
+                    min_create_rev: u.arbitrary()?,
+                    max_create_rev: u.arbitrary()?,
+                    min_mod_rev: u.arbitrary()?,
+                    max_mod_rev: u.arbitrary()?,
+                    consistency: match u.int_in_range(0..=2u8)? {
+                        0 => None,
+                        1 => Some(Consistency::Linearizable),
+                        _ => Some(Consistency::Serializable),
+                    },
+                    unknown_flags: vec![],
+                }
+            }
+            3 => OperationOwned::PutFile {
+                key: arbitrary_bytes(u)?,
+                path: arbitrary_path(u)?,
+            },
+            4 => OperationOwned::Noop,
+            _ => OperationOwned::Unknown(arbitrary_unknown_line(u)?),
+        };
+        Ok(operation)
+    }
+}
+
+impl<'a> Arbitrary<'a> for TxnDataOwned {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(TxnDataOwned {
+            compares: arbitrary_items(u)?,
+            success: arbitrary_items(u)?,
+            failure: arbitrary_items(u)?,
+        })
+    }
+}
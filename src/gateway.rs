@@ -0,0 +1,557 @@
+//! etcd v3 gRPC-gateway JSON bodies
+//!
+//! The [HTTP gateway](https://etcd.io/docs/latest/dev-guide/api_grpc_gateway/)
+//! accepts a transaction as a JSON body on `/v3/kv/txn`, keys and values being
+//! base64 encoded.
+
+use crate::compare::{Compare, OpType};
+use crate::convert::{decoded, Construct, ConvertError, Converted, Converter, Lossy};
+use crate::error::Error;
+use crate::keyrange::KeyRange;
+use crate::operation::{Consistency, GetData, Operation};
+use crate::owned::{CompareOwned, OperationOwned, TxnDataOwned};
+use crate::{Section, TxnData};
+use serde_json::{Map, Value};
+
+// ----------------------------------------------------------------------------
+// Base64
+// ----------------------------------------------------------------------------
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk.first().copied().unwrap_or(0),
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (group >> (18 - 6 * index)) & 0x3f;
+                encoded.extend(ALPHABET.get(sextet as usize).map(|&byte| char::from(byte)));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(data: &str) -> Result<Vec<u8>, Error> {
+    let data = data.trim_end_matches('=');
+    let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+    let mut group = 0u32;
+    let mut bits = 0;
+    for byte in data.bytes() {
+        let sextet = ALPHABET
+            .iter()
+            .position(|candidate| *candidate == byte)
+            .ok_or_else(|| Error::Json(format!("invalid base64 character {:?}", byte as char)))?;
+        group = (group << 6) | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((group >> bits) as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+// ----------------------------------------------------------------------------
+// Rendering
+// ----------------------------------------------------------------------------
+
+fn result_name(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "EQUAL",
+        OpType::GreaterThan => "GREATER",
+        OpType::LessThan => "LESS",
+        OpType::NotEqual => "NOT_EQUAL",
+    }
+}
+
+fn render_compare(compare: &Compare) -> Result<String, Construct> {
+    let (target, op, value) = match compare {
+        Compare::CreateRevision(compare) => (
+            "CREATE",
+            compare.op,
+            format!("\"createRevision\":\"{}\"", compare.value),
+        ),
+        Compare::ModRevision(compare) => (
+            "MOD",
+            compare.op,
+            format!("\"modRevision\":\"{}\"", compare.value),
+        ),
+        Compare::Value(compare) => (
+            "VALUE",
+            compare.op,
+            format!(
+                "\"value\":\"{}\"",
+                base64_encode(&decoded(compare.decoded_value())?)
+            ),
+        ),
+        Compare::Version(compare) => (
+            "VERSION",
+            compare.op,
+            format!("\"version\":\"{}\"", compare.value),
+        ),
+        Compare::Lease(compare) => (
+            "LEASE",
+            compare.op,
+            format!("\"lease\":\"{}\"", compare.value),
+        ),
+        Compare::Custom(_) => return Err(Construct::CustomCompare),
+        Compare::Unknown(_) => return Err(Construct::UnknownNode),
+    };
+    let key = decoded(compare.decoded_key())?.unwrap_or_default();
+    Ok(format!(
+        "{{\"key\":\"{}\",\"target\":\"{target}\",\"result\":\"{}\",{value}}}",
+        base64_encode(&key),
+        result_name(op)
+    ))
+}
+
+fn render_operation(operation: &Operation) -> Result<String, Construct> {
+    if !operation.unknown_flags().is_empty() {
+        return Err(Construct::UnknownFlag);
+    }
+    match operation {
+        Operation::Put(put) => Ok(format!(
+            "{{\"requestPut\":{{\"key\":\"{}\",\"value\":\"{}\"{}}}}}",
+            base64_encode(&decoded(put.decoded_key())?),
+            base64_encode(&decoded(put.decoded_value())?),
+            put.lease
+                .map(|lease| format!(",\"lease\":\"{lease}\""))
+                .unwrap_or_default()
+        )),
+        Operation::Delete(delete) => Ok(format!(
+            "{{\"requestDeleteRange\":{{\"key\":\"{}\"}}}}",
+            base64_encode(&decoded(delete.decoded_key())?)
+        )),
+        Operation::Get(get) => {
+            let range = decoded(get.effective_range())?;
+            let mut fields = format!("\"key\":\"{}\"", base64_encode(&range.key));
+            if !range.is_single_key() {
+                fields += &format!(",\"rangeEnd\":\"{}\"", base64_encode(&range.range_end));
+            }
+            let filters = [
+                ("minModRevision", get.min_mod_rev),
+                ("maxModRevision", get.max_mod_rev),
+                ("minCreateRevision", get.min_create_rev),
+                ("maxCreateRevision", get.max_create_rev),
+            ];
+            if get.consistency == Some(Consistency::Serializable) {
+                fields += ",\"serializable\":true";
+            }
+            for (name, revision) in filters {
+                if let Some(revision) = revision {
+                    fields += &format!(",\"{name}\":\"{revision}\"");
+                }
+            }
+            Ok(format!("{{\"requestRange\":{{{fields}}}}}"))
+        }
+        Operation::PutFile(_) => Err(Construct::UnresolvedFileRef),
+        Operation::Noop => Err(Construct::Noop),
+        Operation::Custom(_) => Err(Construct::CustomOperation),
+        Operation::Unknown(_) => Err(Construct::UnknownNode),
+    }
+}
+
+fn render_list(items: &[String]) -> String {
+    format!("[{}]", items.join(","))
+}
+
+impl TxnData<'_> {
+    /// Render the transaction as the JSON body expected by the etcd v3 gRPC-gateway
+    /// `/v3/kv/txn` endpoint. Keys and values are encoded as the bytes they
+    /// stand for, their escape sequences decoded.
+    ///
+    /// # Errors
+    ///
+    /// A transaction containing unknown nodes, unresolved file references or
+    /// invalid escape sequences can't be rendered.
+    pub fn to_gateway_json(&self) -> Result<String, Error> {
+        Ok(self.to_gateway_json_with(Lossy::Error)?.value)
+    }
+
+    /// Render the transaction as a gRPC-gateway JSON body, applying `lossy`
+    /// to the nodes which can't be rendered. JSON has no comments to hold an
+    /// approximation, best effort leaves them out.
+    ///
+    /// # Errors
+    ///
+    /// Under [`Lossy::Error`], the first node which can't be rendered.
+    pub fn to_gateway_json_with(&self, lossy: Lossy) -> Result<Converted<String>, ConvertError> {
+        let mut converter = Converter::new(lossy);
+        let compares =
+            converter.convert(Section::Compare, &self.compares, render_compare, |_, _| {
+                None
+            })?;
+        let success =
+            converter.convert(Section::Success, &self.success, render_operation, |_, _| {
+                None
+            })?;
+        let failure =
+            converter.convert(Section::Failure, &self.failure, render_operation, |_, _| {
+                None
+            })?;
+        Ok(converter.finish(format!(
+            "{{\"compare\":{},\"success\":{},\"failure\":{}}}",
+            render_list(&compares),
+            render_list(&success),
+            render_list(&failure)
+        )))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Parsing
+// ----------------------------------------------------------------------------
+
+/// Get a field by either its JSON (lowerCamelCase) or its proto (snake_case) name.
+fn field<'v>(
+    object: &'v Map<String, Value>,
+    json_name: &str,
+    proto_name: &str,
+) -> Option<&'v Value> {
+    object.get(json_name).or_else(|| object.get(proto_name))
+}
+
+fn as_object<'v>(value: &'v Value, what: &str) -> Result<&'v Map<String, Value>, Error> {
+    value
+        .as_object()
+        .ok_or_else(|| Error::Json(format!("{what} must be an object")))
+}
+
+fn bytes_field(object: &Map<String, Value>, name: &str) -> Result<Vec<u8>, Error> {
+    match object.get(name) {
+        None => Ok(vec![]),
+        Some(Value::String(data)) => base64_decode(data),
+        Some(_) => Err(Error::Json(format!("{name} must be a base64 string"))),
+    }
+}
+
+/// Gateway int64 fields are encoded as strings, plain numbers are accepted too.
+fn number_field(value: Option<&Value>, name: &str) -> Result<u64, Error> {
+    match value {
+        None => Ok(0),
+        Some(Value::String(number)) => number
+            .parse()
+            .map_err(|_| Error::Json(format!("{name} must be a positive integer"))),
+        Some(Value::Number(number)) => number
+            .as_u64()
+            .ok_or_else(|| Error::Json(format!("{name} must be a positive integer"))),
+        Some(_) => Err(Error::Json(format!("{name} must be an integer"))),
+    }
+}
+
+/// Gateway int64 fields are encoded as strings, plain numbers are accepted too.
+fn revision_field(value: Option<&Value>, name: &str) -> Result<Option<i64>, Error> {
+    match value {
+        None => Ok(None),
+        Some(Value::String(number)) => number
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Json(format!("{name} must be an integer"))),
+        Some(Value::Number(number)) => number
+            .as_i64()
+            .map(Some)
+            .ok_or_else(|| Error::Json(format!("{name} must be an integer"))),
+        Some(_) => Err(Error::Json(format!("{name} must be an integer"))),
+    }
+}
+
+fn parse_compare(value: &Value) -> Result<CompareOwned, Error> {
+    let object = as_object(value, "compare")?;
+    let key = bytes_field(object, "key")?;
+    let op = match object
+        .get("result")
+        .and_then(Value::as_str)
+        .unwrap_or("EQUAL")
+    {
+        "EQUAL" => OpType::Equal,
+        "GREATER" => OpType::GreaterThan,
+        "LESS" => OpType::LessThan,
+        "NOT_EQUAL" => OpType::NotEqual,
+        result => return Err(Error::Json(format!("unsupported compare result {result}"))),
+    };
+    let compare = match object
+        .get("target")
+        .and_then(Value::as_str)
+        .unwrap_or("VERSION")
+    {
+        "CREATE" => CompareOwned::CreateRevision {
+            key,
+            value: number_field(
+                field(object, "createRevision", "create_revision"),
+                "createRevision",
+            )?,
+            op,
+        },
+        "MOD" => CompareOwned::ModRevision {
+            key,
+            value: number_field(field(object, "modRevision", "mod_revision"), "modRevision")?,
+            op,
+        },
+        "VALUE" => CompareOwned::Value {
+            key,
+            value: bytes_field(object, "value")?,
+            op,
+        },
+        "VERSION" => CompareOwned::Version {
+            key,
+            value: number_field(object.get("version"), "version")?,
+            op,
+        },
+        "LEASE" => CompareOwned::Lease {
+            key,
+            value: number_field(object.get("lease"), "lease")?,
+            op,
+        },
+        target => return Err(Error::Json(format!("unsupported compare target {target}"))),
+    };
+    Ok(compare)
+}
+
+fn parse_operation(value: &Value) -> Result<OperationOwned, Error> {
+    let object = as_object(value, "request op")?;
+    if let Some(put) = field(object, "requestPut", "request_put") {
+        let put = as_object(put, "requestPut")?;
+        return Ok(OperationOwned::Put {
+            key: bytes_field(put, "key")?,
+            value: bytes_field(put, "value")?,
+            // The lease 0 means no lease
+            lease: Some(number_field(put.get("lease"), "lease")?).filter(|&lease| lease != 0),
+            unknown_flags: vec![],
+        });
+    }
+    if let Some(delete) = field(object, "requestDeleteRange", "request_delete_range") {
+        let delete = as_object(delete, "requestDeleteRange")?;
+        return Ok(OperationOwned::Delete {
+            key: bytes_field(delete, "key")?,
+        });
+    }
+    if let Some(range) = field(object, "requestRange", "request_range") {
+        let range = as_object(range, "requestRange")?;
+        let key = bytes_field(range, "key")?;
+        let range_end = match field(range, "rangeEnd", "range_end") {
+            None => vec![],
+            Some(Value::String(end)) => base64_decode(end)?,
+            Some(_) => return Err(Error::Json("rangeEnd must be a base64 string".to_string())),
+        };
+        let key_range = KeyRange {
+            key: key.as_slice().into(),
+            range_end,
+        };
+        let get = GetData {
+            min_create_rev: revision_field(
+                field(range, "minCreateRevision", "min_create_revision"),
+                "minCreateRevision",
+            )?,
+            max_create_rev: revision_field(
+                field(range, "maxCreateRevision", "max_create_revision"),
+                "maxCreateRevision",
+            )?,
+            min_mod_rev: revision_field(
+                field(range, "minModRevision", "min_mod_revision"),
+                "minModRevision",
+            )?,
+            max_mod_rev: revision_field(
+                field(range, "maxModRevision", "max_mod_revision"),
+                "maxModRevision",
+            )?,
+            consistency: match field(range, "serializable", "serializable") {
+                None | Some(Value::Bool(false)) => None,
+                Some(Value::Bool(true)) => Some(Consistency::Serializable),
+                Some(_) => {
+                    return Err(Error::Json("serializable must be a boolean".to_string()));
+                }
+            },
+            ..GetData::from_range(&key_range)
+        };
+        return Ok(OperationOwned::from(&Operation::Get(get)));
+    }
+    Err(Error::Json("unsupported request op".to_string()))
+}
+
+fn parse_list<T>(
+    object: &Map<String, Value>,
+    name: &str,
+    parse: impl Fn(&Value) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    match object.get(name) {
+        None | Some(Value::Null) => Ok(vec![]),
+        Some(Value::Array(items)) => items.iter().map(parse).collect(),
+        Some(_) => Err(Error::Json(format!("{name} must be an array"))),
+    }
+}
+
+/// Parse an etcd v3 gRPC-gateway `/v3/kv/txn` JSON body.
+///
+/// # Errors
+///
+/// If the body isn't valid JSON or doesn't describe a transaction, an
+/// `Error::Json` is returned.
+pub fn parse_json(data: &[u8]) -> Result<TxnDataOwned, Error> {
+    let body: Value = serde_json::from_slice(data).map_err(|err| Error::Json(err.to_string()))?;
+    let body = as_object(&body, "transaction")?;
+    Ok(TxnDataOwned {
+        compares: parse_list(body, "compare", parse_compare)?,
+        success: parse_list(body, "success", parse_operation)?,
+        failure: parse_list(body, "failure", parse_operation)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::convert::{Construct, Lossy};
+    use crate::error::Error;
+    use crate::gateway::{base64_decode, base64_encode, parse_json};
+    use crate::parse;
+
+    #[test]
+    fn test_base64() {
+        for (data, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"key1", "a2V5MQ=="),
+        ] {
+            assert_eq!(base64_encode(data), encoded);
+            assert_eq!(base64_decode(encoded).expect("Invalid base64"), data);
+        }
+    }
+
+    #[test]
+    fn test_to_gateway_json_get_filters() {
+        let txn = parse(b"\n\nget --min-mod-rev=5 --prefix app/\n\n").expect("Failed to parse");
+        let json = txn.to_gateway_json().expect("Failed to render");
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"compare":[],"success":[{"requestRange":{"key":"YXBwLw==","rangeEnd":"YXBwMA==","#,
+                r#""minModRevision":"5"}}],"failure":[]}"#
+            )
+        );
+        assert_eq!(
+            parse_json(json.as_bytes()).expect("Failed to parse JSON"),
+            txn.to_owned()
+        );
+    }
+
+    #[test]
+    fn test_to_gateway_json_serializable() {
+        let txn = parse(b"\n\nget --serializable k\n\n").expect("Failed to parse");
+        let json = txn.to_gateway_json().expect("Failed to render");
+        assert_eq!(
+            json,
+            r#"{"compare":[],"success":[{"requestRange":{"key":"aw==","serializable":true}}],"failure":[]}"#
+        );
+        assert_eq!(
+            parse_json(json.as_bytes()).expect("Failed to parse JSON"),
+            txn.to_owned()
+        );
+        let json = br#"{"success":[{"requestRange":{"key":"aw==","serializable":1}}]}"#;
+        assert!(parse_json(json).is_err());
+    }
+
+    #[test]
+    fn test_to_gateway_json_get_ranges() {
+        let txn = parse(b"\n\nget --from-key a\nget a c\n\n").expect("Failed to parse");
+        let json = txn.to_gateway_json().expect("Failed to render");
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"compare":[],"success":[{"requestRange":{"key":"YQ==","rangeEnd":"AA=="}},"#,
+                r#"{"requestRange":{"key":"YQ==","rangeEnd":"Yw=="}}],"failure":[]}"#
+            )
+        );
+        assert_eq!(
+            parse_json(json.as_bytes()).expect("Failed to parse JSON"),
+            txn.to_owned()
+        );
+    }
+
+    #[test]
+    fn test_to_gateway_json_empty_prefix() {
+        let txn = parse(b"\n\nget --prefix \"\"\n\n").expect("Failed to parse");
+        let json = txn.to_gateway_json().expect("Failed to render");
+        assert_eq!(
+            json,
+            r#"{"compare":[],"success":[{"requestRange":{"key":"AA==","rangeEnd":"AA=="}}],"failure":[]}"#
+        );
+        assert_eq!(
+            parse_json(json.as_bytes()).expect("Failed to parse JSON"),
+            txn.to_owned()
+        );
+    }
+
+    #[test]
+    fn test_to_gateway_json() {
+        let data = b"mod(\"key1\") > 0\n\nput key1 \"overwrote-key1\"\n\nput \"key1\" \"created-key1\"\nget key2";
+        let txn = parse(data).expect("Failed to parse");
+        let json = txn.to_gateway_json().expect("Failed to render");
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"compare":[{"key":"a2V5MQ==","target":"MOD","result":"GREATER","modRevision":"0"}],"#,
+                r#""success":[{"requestPut":{"key":"a2V5MQ==","value":"b3Zlcndyb3RlLWtleTE="}}],"#,
+                r#""failure":[{"requestPut":{"key":"a2V5MQ==","value":"Y3JlYXRlZC1rZXkx"}},"#,
+                r#"{"requestRange":{"key":"a2V5Mg=="}}]}"#
+            )
+        );
+        assert_eq!(
+            parse_json(json.as_bytes()).expect("Failed to parse JSON"),
+            txn.to_owned()
+        );
+    }
+
+    #[test]
+    fn test_to_gateway_json_escapes() {
+        // The bytes the keys and the values stand for are sent
+        let data = br#"val("k\x31") = 'it\'s'
+
+put k "a\"b"
+get --prefix "app\x2f"
+
+del '\x41'
+"#;
+        let txn = parse(data).expect("Failed to parse");
+        let json = txn.to_gateway_json().expect("Failed to render");
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"compare":[{"key":"azE=","target":"VALUE","result":"EQUAL","value":"aXQncw=="}],"#,
+                r#""success":[{"requestPut":{"key":"aw==","value":"YSJi"}},"#,
+                r#"{"requestRange":{"key":"YXBwLw==","rangeEnd":"YXBwMA=="}}],"#,
+                r#""failure":[{"requestDeleteRange":{"key":"XHg0MQ=="}}]}"#
+            )
+        );
+        assert_eq!(
+            parse_json(json.as_bytes()).expect("Failed to parse JSON"),
+            txn.to_owned()
+        );
+
+        // An invalid escape sequence stands for no bytes
+        let txn = parse(b"\n\nput k \"\\q\"\n\n").expect("Failed to parse");
+        assert!(matches!(txn.to_gateway_json(), Err(Error::InvalidEscape)));
+        let converted = txn
+            .to_gateway_json_with(Lossy::Skip)
+            .expect("Failed to render");
+        assert_eq!(converted.report[0].construct, Construct::InvalidEscape);
+    }
+
+    #[test]
+    fn test_parse_json_proto_names() {
+        let json = br#"{"compare":[{"key":"a2V5MQ==","target":"CREATE","create_revision":0}],"success":[{"request_delete_range":{"key":"a2V5MQ=="}}]}"#;
+        let txn = parse_json(json).expect("Failed to parse JSON");
+        assert_eq!(
+            txn.as_txn(),
+            parse(b"c(key1) = 0\n\ndel key1\n\n").expect("Failed to parse")
+        );
+    }
+}
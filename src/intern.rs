@@ -0,0 +1,464 @@
+//! Key interning for large repetitive transactions
+//!
+//! Generated transactions often repeat a handful of keys a huge number of
+//! times. Interning stores each distinct key once in an [`Interner`] and
+//! replaces it in the transaction by a small [`Symbol`].
+
+use crate::compare::{Compare, OpType};
+use crate::operation::{Consistency, Operation};
+use crate::TxnData;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// ----------------------------------------------------------------------------
+// Interner
+// ----------------------------------------------------------------------------
+
+/// An identifier of a byte string stored in an [`Interner`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// A table of distinct byte strings.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Arc<[u8]>, Symbol>,
+    strings: Vec<Arc<[u8]>>,
+    intern_values: bool,
+}
+
+impl Interner {
+    /// Create an interner storing the keys only.
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// Create an interner storing both the keys and the values.
+    pub fn with_values() -> Self {
+        Interner {
+            intern_values: true,
+            ..Interner::default()
+        }
+    }
+
+    /// Intern a byte string, returning the symbol of the already stored copy if any.
+    pub fn intern(&mut self, data: &[u8]) -> Symbol {
+        if let Some(symbol) = self.symbols.get(data) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let data: Arc<[u8]> = Arc::from(data);
+        self.strings.push(data.clone());
+        self.symbols.insert(data, symbol);
+        symbol
+    }
+
+    /// Get the byte string of a symbol.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&[u8]> {
+        self.strings.get(symbol.0 as usize).map(|data| &**data)
+    }
+
+    /// Get the bytes of an interned value.
+    pub fn resolve_value<'b>(&'b self, value: &'b InternedValue) -> Option<&'b [u8]> {
+        match value {
+            InternedValue::Symbol(symbol) => self.resolve(*symbol),
+            InternedValue::Bytes(bytes) => Some(bytes),
+        }
+    }
+
+    /// Iterate over the stored symbols along with their byte string.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &[u8])> {
+        self.strings
+            .iter()
+            .enumerate()
+            .map(|(index, data)| (Symbol(index as u32), &**data))
+    }
+
+    /// The number of distinct byte strings stored.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Whether no byte string is stored.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    fn intern_value(&mut self, data: &[u8]) -> InternedValue {
+        if self.intern_values {
+            InternedValue::Symbol(self.intern(data))
+        } else {
+            InternedValue::Bytes(data.into())
+        }
+    }
+}
+
+/// A value which is interned or not depending on the [`Interner`] used.
+#[derive(Debug, PartialEq)]
+pub enum InternedValue {
+    /// An interned value.
+    Symbol(Symbol),
+    /// A value owned as is.
+    Bytes(Box<[u8]>),
+}
+
+// ----------------------------------------------------------------------------
+// Interned AST
+// ----------------------------------------------------------------------------
+
+/// An interned [`Compare`].
+#[derive(Debug, PartialEq)]
+pub enum CompareInterned {
+    /// A create revision compare operation.
+    CreateRevision {
+        /// The key to compare.
+        key: Symbol,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A modify revision compare operation.
+    ModRevision {
+        /// The key to compare.
+        key: Symbol,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A value compare operation.
+    Value {
+        /// The key to compare.
+        key: Symbol,
+        /// The value to compare with.
+        value: InternedValue,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A version compare operation.
+    Version {
+        /// The key to compare.
+        key: Symbol,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A lease compare operation.
+    Lease {
+        /// The key to compare.
+        key: Symbol,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A compare parsed by a registered parser.
+    Custom {
+        /// The target of the compare.
+        target: Box<[u8]>,
+        /// The arguments returned by the parser.
+        args: Box<[Box<[u8]>]>,
+    },
+    /// A line kept as is because it can't be parsed.
+    Unknown(Box<[u8]>),
+}
+
+impl CompareInterned {
+    /// The compared key, if any.
+    pub fn key(&self) -> Option<Symbol> {
+        match self {
+            CompareInterned::CreateRevision { key, .. }
+            | CompareInterned::ModRevision { key, .. }
+            | CompareInterned::Value { key, .. }
+            | CompareInterned::Version { key, .. }
+            | CompareInterned::Lease { key, .. } => Some(*key),
+            CompareInterned::Custom { .. } | CompareInterned::Unknown(_) => None,
+        }
+    }
+}
+
+/// An interned [`Operation`].
+#[derive(Debug, PartialEq)]
+pub enum OperationInterned {
+    /// A put operation.
+    Put {
+        /// The key to put.
+        key: Symbol,
+        /// The value to put.
+        value: InternedValue,
+        /// The lease to attach the key to.
+        lease: Option<u64>,
+        /// The flags the parser doesn't know, along with their value.
+        unknown_flags: Vec<(String, Option<String>)>,
+    },
+    /// A delete operation.
+    Delete {
+        /// The key to delete.
+        key: Symbol,
+    },
+    /// A get operation.
+    Get {
+        /// The key to get.
+        key: Symbol,
+        /// Get the keys starting with the key.
+        prefix: bool,
+        /// Get the keys from the key on.
+        from_key: bool,
+        /// The end of the range of keys to get, excluded.
+        range_end: Option<Symbol>,
+        /// Only get the keys created at or after this revision.
+        min_create_rev: Option<i64>,
+        /// Only get the keys created at or before this revision.
+        max_create_rev: Option<i64>,
+        /// Only get the keys modified at or after this revision.
+        min_mod_rev: Option<i64>,
+        /// Only get the keys modified at or before this revision.
+        max_mod_rev: Option<i64>,
+        /// The consistency of the read, linearizable when `None`.
+        consistency: Option<Consistency>,
+        /// The flags the parser doesn't know, along with their value.
+        unknown_flags: Vec<(String, Option<String>)>,
+    },
+    /// A put operation whose value is read from a file.
+    PutFile {
+        /// The key to put.
+        key: Symbol,
+        /// The path of the file holding the value.
+        path: Box<[u8]>,
+    },
+    /// An operation doing nothing.
+    Noop,
+    /// An operation parsed by a registered parser.
+    Custom {
+        /// The command of the operation.
+        command: Box<[u8]>,
+        /// The arguments returned by the parser.
+        args: Box<[Box<[u8]>]>,
+    },
+    /// A line kept as is because it can't be parsed.
+    Unknown(Box<[u8]>),
+}
+
+impl OperationInterned {
+    /// The key of the operation, if any.
+    pub fn key(&self) -> Option<Symbol> {
+        match self {
+            OperationInterned::Put { key, .. }
+            | OperationInterned::Delete { key }
+            | OperationInterned::Get { key, .. }
+            | OperationInterned::PutFile { key, .. } => Some(*key),
+            OperationInterned::Noop
+            | OperationInterned::Custom { .. }
+            | OperationInterned::Unknown(_) => None,
+        }
+    }
+}
+
+/// A transaction whose keys are interned.
+#[derive(Debug, PartialEq)]
+pub struct TxnDataInterned {
+    /// A list of operations to compare against the current state.
+    pub compares: Vec<CompareInterned>,
+    /// A list of operations to apply if the compare operations pass.
+    pub success: Vec<OperationInterned>,
+    /// A list of operations to apply if the compare operations fail.
+    pub failure: Vec<OperationInterned>,
+}
+
+impl TxnDataInterned {
+    /// Iterate over the keys of the transaction: compares first, then the
+    /// success and failure operations.
+    pub fn keys(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.compares.iter().filter_map(CompareInterned::key).chain(
+            self.success
+                .iter()
+                .chain(&self.failure)
+                .filter_map(OperationInterned::key),
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Conversion
+// ----------------------------------------------------------------------------
+
+impl TxnData<'_> {
+    /// Convert the transaction into its interned form, storing the keys (and the
+    /// values if the interner has been built with [`Interner::with_values`]) in
+    /// the interner.
+    pub fn into_interned(self, interner: &mut Interner) -> TxnDataInterned {
+        TxnDataInterned {
+            compares: self
+                .compares
+                .into_iter()
+                .map(|compare| intern_compare(compare, interner))
+                .collect(),
+            success: self
+                .success
+                .into_iter()
+                .map(|operation| intern_operation(operation, interner))
+                .collect(),
+            failure: self
+                .failure
+                .into_iter()
+                .map(|operation| intern_operation(operation, interner))
+                .collect(),
+        }
+    }
+}
+
+fn intern_compare(compare: Compare, interner: &mut Interner) -> CompareInterned {
+    match compare {
+        Compare::CreateRevision(compare) => CompareInterned::CreateRevision {
+            key: interner.intern(&compare.key),
+            value: compare.value,
+            op: compare.op,
+        },
+        Compare::ModRevision(compare) => CompareInterned::ModRevision {
+            key: interner.intern(&compare.key),
+            value: compare.value,
+            op: compare.op,
+        },
+        Compare::Value(compare) => CompareInterned::Value {
+            key: interner.intern(&compare.key),
+            value: interner.intern_value(&compare.value),
+            op: compare.op,
+        },
+        Compare::Version(compare) => CompareInterned::Version {
+            key: interner.intern(&compare.key),
+            value: compare.value,
+            op: compare.op,
+        },
+        Compare::Lease(compare) => CompareInterned::Lease {
+            key: interner.intern(&compare.key),
+            value: compare.value,
+            op: compare.op,
+        },
+        Compare::Custom(custom) => CompareInterned::Custom {
+            target: custom.target.into(),
+            args: custom.args.into_iter().map(Into::into).collect(),
+        },
+        Compare::Unknown(line) => CompareInterned::Unknown(line.into()),
+    }
+}
+
+fn intern_operation(operation: Operation, interner: &mut Interner) -> OperationInterned {
+    match operation {
+        Operation::Put(put) => OperationInterned::Put {
+            key: interner.intern(&put.key),
+            value: interner.intern_value(&put.value),
+            lease: put.lease,
+            unknown_flags: put.unknown_flags,
+        },
+        Operation::Delete(delete) => OperationInterned::Delete {
+            key: interner.intern(&delete.key),
+        },
+        Operation::Get(get) => OperationInterned::Get {
+            key: interner.intern(&get.key),
+            prefix: get.prefix,
+            from_key: get.from_key,
+            range_end: get.range_end.map(|range_end| interner.intern(&range_end)),
+            min_create_rev: get.min_create_rev,
+            max_create_rev: get.max_create_rev,
+            min_mod_rev: get.min_mod_rev,
+            max_mod_rev: get.max_mod_rev,
+            consistency: get.consistency,
+            unknown_flags: get.unknown_flags,
+        },
+        Operation::PutFile(put) => OperationInterned::PutFile {
+            key: interner.intern(&put.key),
+            path: put.path.into(),
+        },
+        Operation::Noop => OperationInterned::Noop,
+        Operation::Custom(custom) => OperationInterned::Custom {
+            command: custom.command.into(),
+            args: custom.args.into_iter().map(Into::into).collect(),
+        },
+        Operation::Unknown(line) => OperationInterned::Unknown(line.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intern::{InternedValue, Interner, OperationInterned};
+    use crate::parse;
+
+    #[test]
+    fn test_into_interned() {
+        let data = b"mod(\"key1\") > 0\n\nput key1 \"overwrote-key1\"\n\nput \"key1\" \"created-key1\"\nget key2";
+        let mut interner = Interner::new();
+        let txn = parse(data)
+            .expect("Failed to parse")
+            .into_interned(&mut interner);
+
+        assert_eq!(interner.len(), 2);
+        let keys: Vec<_> = txn
+            .keys()
+            .map(|key| interner.resolve(key).expect("Unknown symbol"))
+            .collect();
+        assert_eq!(keys, vec![b"key1", b"key1", b"key1", b"key2"]);
+        assert!(matches!(
+            &txn.success[0],
+            OperationInterned::Put {
+                value: InternedValue::Bytes(value),
+                ..
+            } if &**value == b"overwrote-key1"
+        ));
+
+        let mut interner = Interner::with_values();
+        let txn = parse(data)
+            .expect("Failed to parse")
+            .into_interned(&mut interner);
+        assert_eq!(interner.len(), 4);
+        let OperationInterned::Put { value, .. } = &txn.failure[0] else {
+            panic!("Expected a put operation");
+        };
+        assert_eq!(interner.resolve_value(value), Some(&b"created-key1"[..]));
+    }
+
+    #[test]
+    fn test_interned_memory_usage() {
+        let mut data = b"\n\n".to_vec();
+        for index in 0..100_000 {
+            data.extend_from_slice(
+                format!(
+                    "put /registry/services/endpoints/default/service-{} {index}\n",
+                    index % 16
+                )
+                .as_bytes(),
+            );
+        }
+        data.push(b'\n');
+        let txn = parse(&data).expect("Failed to parse");
+        assert_eq!(txn.success.len(), 100_000);
+
+        // An owned transaction allocates every key and value
+        let owned_size: usize = txn
+            .success
+            .iter()
+            .map(|operation| match operation {
+                crate::operation::Operation::Put(put) => put.key.len() + put.value.len(),
+                _ => 0,
+            })
+            .sum();
+
+        let mut interner = Interner::new();
+        let txn = txn.into_interned(&mut interner);
+        let interned_size: usize = interner.iter().map(|(_, key)| key.len()).sum::<usize>()
+            + txn
+                .success
+                .iter()
+                .map(|operation| match operation {
+                    OperationInterned::Put {
+                        value: InternedValue::Bytes(value),
+                        ..
+                    } => value.len(),
+                    _ => 0,
+                })
+                .sum::<usize>();
+
+        assert_eq!(interner.len(), 16);
+        assert!(interned_size * 5 < owned_size);
+    }
+}
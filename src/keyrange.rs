@@ -0,0 +1,173 @@
+//! Key ranges of the operations
+//!
+//! etcd requests address keys as a `[key, range_end)` range: an empty range
+//! end selects the single key, the `\0` range end every key from `key` on.
+
+use std::borrow::Cow;
+
+/// The range end of every key from a key on, also used for a prefix made of
+/// `0xff` bytes only.
+const NO_PREFIX_END: &[u8] = b"\0";
+
+/// The end of the range holding every key starting with `prefix`, as computed
+/// by `etcdctl get --prefix`.
+///
+/// The last byte below `0xff` is incremented, the `0xff` bytes following it
+/// being dropped. A prefix made of `0xff` bytes only, or the empty prefix,
+/// gets the `\0` range end spanning up to the last key.
+pub fn prefix_range_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    NO_PREFIX_END.to_vec()
+}
+
+/// The end of the range holding every key from a key on, as used by
+/// `etcdctl get --from-key`.
+pub fn from_key_range_end() -> Vec<u8> {
+    NO_PREFIX_END.to_vec()
+}
+
+/// The keys addressed by an operation, the `key` and `range_end` fields of an
+/// etcd request. The key is borrowed unless it had escape sequences to
+/// decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRange<'a> {
+    /// The first key of the range.
+    pub key: Cow<'a, [u8]>,
+    /// The end of the range, excluded. Empty when only `key` is addressed.
+    pub range_end: Vec<u8>,
+}
+
+impl<'a> KeyRange<'a> {
+    /// The range of a single key.
+    pub fn single(key: impl Into<Cow<'a, [u8]>>) -> Self {
+        KeyRange {
+            key: key.into(),
+            range_end: vec![],
+        }
+    }
+
+    /// The range of every key starting with `prefix`.
+    ///
+    /// etcd rejects an empty key, the empty prefix is the whole keyspace
+    /// starting at `\0`.
+    pub fn prefix(prefix: impl Into<Cow<'a, [u8]>>) -> Self {
+        let prefix = prefix.into();
+        if prefix.is_empty() {
+            return KeyRange::from_key(NO_PREFIX_END);
+        }
+        KeyRange {
+            range_end: prefix_range_end(&prefix),
+            key: prefix,
+        }
+    }
+
+    /// The range of every key from `key` on.
+    pub fn from_key(key: impl Into<Cow<'a, [u8]>>) -> Self {
+        KeyRange {
+            key: key.into(),
+            range_end: from_key_range_end(),
+        }
+    }
+
+    /// Whether only `key` is addressed.
+    pub fn is_single_key(&self) -> bool {
+        self.range_end.is_empty()
+    }
+
+    /// Whether `key` is addressed by the range.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        if self.is_single_key() {
+            return *self.key == *key;
+        }
+        *self.key <= *key && (self.range_end == NO_PREFIX_END || key < self.range_end.as_slice())
+    }
+
+    /// The prefix of the keys held by the range, `None` if it isn't the range
+    /// of a prefix.
+    pub fn as_prefix(&self) -> Option<&[u8]> {
+        if *self.key == *NO_PREFIX_END && self.range_end == NO_PREFIX_END {
+            Some(b"")
+        } else if !self.is_single_key() && self.range_end == prefix_range_end(&self.key) {
+            Some(&self.key)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyrange::{from_key_range_end, prefix_range_end, KeyRange};
+
+    #[test]
+    fn test_prefix_range_end() {
+        assert_eq!(prefix_range_end(b"app/"), b"app0");
+        assert_eq!(prefix_range_end(b"a"), b"b");
+        assert_eq!(prefix_range_end(b"a\xfe"), b"a\xff");
+        assert_eq!(prefix_range_end(b"a\xff"), b"b");
+        assert_eq!(prefix_range_end(b"a\xff\xff"), b"b");
+        assert_eq!(prefix_range_end(b"\xfe\xff"), b"\xff");
+        assert_eq!(prefix_range_end(b"\xff"), b"\0");
+        assert_eq!(prefix_range_end(b"\xff\xff\xff"), b"\0");
+        assert_eq!(prefix_range_end(b""), b"\0");
+        assert_eq!(from_key_range_end(), b"\0");
+    }
+
+    #[test]
+    fn test_key_range() {
+        let range = KeyRange::single(&b"key"[..]);
+        assert!(range.is_single_key());
+        assert_eq!(range.as_prefix(), None);
+
+        let range = KeyRange::prefix(&b"app/"[..]);
+        assert_eq!(range.key, &b"app/"[..]);
+        assert_eq!(range.range_end, b"app0");
+        assert_eq!(range.as_prefix(), Some(&b"app/"[..]));
+
+        // The empty prefix is the whole keyspace
+        let range = KeyRange::prefix(&b""[..]);
+        assert_eq!(range.key, &b"\0"[..]);
+        assert_eq!(range.range_end, b"\0");
+        assert_eq!(range.as_prefix(), Some(&b""[..]));
+
+        let range = KeyRange::prefix(&b"\xff\xff"[..]);
+        assert_eq!(range.range_end, b"\0");
+        assert_eq!(range.as_prefix(), Some(&b"\xff\xff"[..]));
+
+        let range = KeyRange::from_key(&b"key"[..]);
+        assert!(!range.is_single_key());
+        assert_eq!(range.as_prefix(), None);
+    }
+
+    #[test]
+    fn test_key_range_contains() {
+        let range = KeyRange::single(&b"key"[..]);
+        assert!(range.contains(b"key"));
+        assert!(!range.contains(b"key1"));
+
+        let range = KeyRange::prefix(&b"app/"[..]);
+        assert!(range.contains(b"app/"));
+        assert!(range.contains(b"app/db"));
+        assert!(!range.contains(b"app"));
+        assert!(!range.contains(b"app0"));
+
+        let range = KeyRange::prefix(&b"\xff"[..]);
+        assert!(range.contains(b"\xff\xff"));
+        assert!(!range.contains(b"\xfe"));
+
+        let range = KeyRange::prefix(&b""[..]);
+        assert!(range.contains(b"\0"));
+        assert!(range.contains(b"\xff"));
+
+        let range = KeyRange::from_key(&b"b"[..]);
+        assert!(range.contains(b"b"));
+        assert!(range.contains(b"zzz"));
+        assert!(!range.contains(b"a"));
+    }
+}
@@ -1,33 +1,457 @@
 #![doc = include_str!("../Readme.md")]
-use crate::compare::Compare;
+// Malformed input must be reported, never panic
+#![deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::expect_used)]
+use crate::compare::{unsupported_operator, Compare, OpType};
+use crate::error::Error;
 use crate::operation::Operation;
-use elyze::bytes::matchers::match_pattern;
-use elyze::bytes::primitives::whitespace::OptionalWhitespaces;
-use elyze::bytes::token::Token;
+use crate::options::{ChecksumMode, ParseOptions, DEFAULT_QUOTE_CHARS};
+use crate::warning::Warning;
 use elyze::errors::{ParseError, ParseResult};
-use elyze::matcher::Match;
-use elyze::peek::{peek, DefaultPeekableImplementation, PeekableImplementation, UntilEnd};
-use elyze::recognizer::recognize;
+use elyze::peek::{peek, UntilEnd};
 use elyze::scanner::Scanner;
-use elyze::separated_list::SeparatedList;
 use elyze::visitor::Visitor;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 
+pub mod batch;
+mod checksum;
 pub mod compare;
+pub mod convert;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod data;
+#[cfg(feature = "proto")]
+pub mod decode;
+pub mod dot;
+#[cfg(feature = "proto")]
+pub mod encode;
+pub mod error;
+pub mod escape;
+pub mod explain;
+#[cfg(feature = "arbitrary")]
+mod fuzzing;
+#[cfg(feature = "json")]
+pub mod gateway;
+pub mod intern;
+pub mod keyrange;
+pub mod markdown;
+#[cfg(feature = "backend-nom")]
+mod nom_backend;
 pub mod operation;
+pub mod options;
+pub mod output;
+pub mod owned;
+pub mod reader;
+pub mod snippet;
+pub mod split;
+pub mod table;
+pub mod validate;
+mod version;
+pub mod warning;
+pub mod writer;
 
 /// Parse a transactional data structure from a byte slice.
 ///
+/// The whole input must be consumed: only whitespaces may follow the failure
+/// section. A shebang line, e.g. `#!/usr/bin/env etcdctl-txn`, is skipped when
+/// it is the very first line.
+///
+/// Nodes borrow their keys and values from the input: the only allocations
+/// are the ones of the three vectors holding the nodes of each section as
+/// they grow.
+///
 /// # Errors
 ///
-/// If the parser encounters an unexpected token, a `ParseError` is returned.
+/// If the parser encounters an unexpected token, an `Error::Parse` is
+/// returned. If non-blank content follows the failure section, an
+/// `Error::TrailingContent` is returned. If a compare is followed on its line
+/// by more than whitespaces, an `Error::TrailingJunk` is returned.
 ///
 /// # Examples
 ///
 ///
-pub fn parse(data: &[u8]) -> ParseResult<TxnData> {
-    TxnData::accept(&mut Scanner::new(data))
+pub fn parse(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    parse_with_options(data, &ParseOptions::default())
+}
+
+/// Parse a transactional data structure from a byte slice using the given options.
+///
+/// # Errors
+///
+/// If the parser encounters an unexpected token, or if the parsed transaction
+/// is rejected by one of the enabled options, an `Error::Parse` is returned.
+/// If non-blank content follows the failure section, an
+/// `Error::TrailingContent` is returned. If a compare is followed on its line
+/// by more than whitespaces, an `Error::TrailingJunk` is returned. If a line
+/// is longer than [`ParseOptions::max_line_len`], an `Error::LineTooLong` is
+/// returned. If a compare uses an operator the etcd version targeted by
+/// [`ParseOptions::etcd_version`] doesn't support, an
+/// `Error::UnsupportedOperator` is returned.
+pub fn parse_with_options<'a>(
+    data: &'a [u8],
+    options: &ParseOptions,
+) -> Result<TxnData<'a>, Error> {
+    parse_with_warnings(data, options).map(|parsed| parsed.txn)
+}
+
+/// Parse a transactional data structure using the given options, keeping the
+/// warnings emitted along the way.
+///
+/// # Errors
+///
+/// If the parser encounters an unexpected token, or if the parsed transaction
+/// is rejected by one of the enabled options, an `Error::Parse` is returned.
+/// If non-blank content follows the failure section, an
+/// `Error::TrailingContent` is returned. If a compare is followed on its line
+/// by more than whitespaces, an `Error::TrailingJunk` is returned. If a line
+/// is longer than [`ParseOptions::max_line_len`], an `Error::LineTooLong` is
+/// returned. If a compare uses an operator the etcd version targeted by
+/// [`ParseOptions::etcd_version`] doesn't support, an
+/// `Error::UnsupportedOperator` is returned.
+pub fn parse_with_warnings<'a>(
+    data: &'a [u8],
+    options: &ParseOptions,
+) -> Result<Parsed<'a>, Error> {
+    let (data, checksum) = match options.trailing_checksum {
+        ChecksumMode::Off => (data, None),
+        ChecksumMode::Strip => checksum::split_checksum(data, false)?,
+        ChecksumMode::Verify => checksum::split_checksum(data, true)?,
+    };
+    let mut report = Report::default();
+    let mut scanner = Scanner::new(data);
+    let shebang = shebang_len(data);
+    if let Some(line) = data.get(..shebang).filter(|line| !line.is_empty()) {
+        // Kept for formatters to write it back
+        report.warnings.push(Warning::Shebang {
+            line: line.strip_suffix(b"\n").unwrap_or(line),
+        });
+    }
+    let (directive, format_version) =
+        version::split_directive(data.get(shebang..).unwrap_or_default())?;
+    scanner.bump_by(shebang + directive);
+    check_line_lengths(data, shebang + directive, options)?;
+    let txn = TxnData::accept_with(&mut scanner, options, &mut report)
+        .map_err(|err| report.diagnosis.take().unwrap_or(Error::Parse(err)))?;
+    check_end(data, scanner.current_position(), options)?;
+    if let Some(format_version) = format_version {
+        version::check_syntax(&txn, format_version)?;
+    }
+
+    if options.strict_etcdctl {
+        check_etcdctl_quotes(data.get(shebang..).unwrap_or_default(), options)?;
+    }
+
+    Ok(Parsed {
+        txn,
+        warnings: report.warnings,
+        metadata: Metadata {
+            checksum,
+            format_version,
+        },
+    })
+}
+
+/// Parse one transaction from the front of a byte slice, returning it along
+/// with the number of bytes consumed.
+///
+/// The transaction ends with its failure section, at the first blank line
+/// following it or at the end of the input. The blank line is consumed, the
+/// rest of the input is left untouched for the caller to parse.
+///
+/// # Errors
+///
+/// If the parser encounters an unexpected token, an `Error::Parse` is returned.
+pub fn parse_prefix(data: &[u8]) -> Result<(TxnData<'_>, usize), Error> {
+    let mut scanner = Scanner::new(data);
+    let txn = TxnData::accept_with(
+        &mut scanner,
+        &ParseOptions::default(),
+        &mut Report::default(),
+    )?;
+    Ok((txn, scanner.current_position()))
 }
 
+/// Parse a transaction written in the flat labeled format, each line being
+/// prefixed with the section it belongs to:
+///
+/// ```text
+/// compare: mod(key1) > 0
+/// success: put key1 value1
+/// failure: del key1
+/// ```
+///
+/// Lines without a label are success operations. Lines may be in any order,
+/// blank lines are ignored and no separator between sections is expected.
+///
+/// # Errors
+///
+/// If a line can't be parsed, an `Error::Parse` is returned.
+pub fn parse_labeled(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    let options = ParseOptions::default();
+    let mut txn = TxnData {
+        compares: vec![],
+        success: vec![],
+        failure: vec![],
+    };
+    for (_, line) in Lines::new(data, options.quote_chars) {
+        let line = line.trim_ascii_start();
+        if let Some(compare) = line.strip_prefix(b"compare:") {
+            txn.compares.push(parse_line(compare, &options)?);
+        } else if let Some(operation) = line.strip_prefix(b"failure:") {
+            txn.failure.push(parse_line(operation, &options)?);
+        } else if let Some(operation) = line.strip_prefix(b"success:") {
+            txn.success.push(parse_line(operation, &options)?);
+        } else if !is_blank(line) {
+            txn.success.push(parse_line(line, &options)?);
+        }
+    }
+    Ok(txn)
+}
+
+/// Parse a transaction written in the flat format, compares and operations
+/// being interleaved in a single block:
+///
+/// ```text
+/// mod(key1) > 0
+/// put key1 value1
+/// val(key2) = "v"
+/// del key2
+/// ```
+///
+/// Each line is told apart by its syntax, the compares going to `compares`
+/// and the operations to `success`, in order. Blank lines are ignored.
+///
+/// # Errors
+///
+/// If a line is neither a compare nor an operation, or is both, an
+/// `Error::Parse` is returned.
+pub fn parse_flat(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    parse_flat_with_options(data, &ParseOptions::default())
+}
+
+/// Parse a transaction written in the flat format with options, see
+/// [`parse_flat`].
+///
+/// A line parsed as an operation by a registered parser, see
+/// [`ParseOptions::operation_parsers`], and as a compare by another, see
+/// [`ParseOptions::compare_parsers`], is ambiguous.
+///
+/// # Errors
+///
+/// If a line is neither a compare nor an operation, or is both, an
+/// `Error::Parse` is returned. If a line is longer than
+/// [`ParseOptions::max_line_len`], an `Error::LineTooLong` is returned.
+pub fn parse_flat_with_options<'a>(
+    data: &'a [u8],
+    options: &ParseOptions,
+) -> Result<TxnData<'a>, Error> {
+    let mut txn = TxnData {
+        compares: vec![],
+        success: vec![],
+        failure: vec![],
+    };
+    check_line_lengths(data, 0, options)?;
+    for (_, line) in Lines::new(data, options.quote_chars) {
+        if is_blank(line) {
+            continue;
+        }
+        match (parse_line(line, options), parse_line(line, options)) {
+            (Ok(compare), Err(_)) => txn.compares.push(compare),
+            (Err(_), Ok(operation)) => txn.success.push(operation),
+            // Neither, or ambiguous
+            _ => return Err(ParseError::UnexpectedToken.into()),
+        }
+    }
+    Ok(txn)
+}
+
+/// Parse a transaction written on a single line, see
+/// [`TxnData::to_compact_string`]:
+///
+/// ```text
+/// mod("key1") > 0 ; put "key1" "value1", get "key2" ; del "key1"
+/// ```
+///
+/// The sections are separated by `;` and the nodes of a section by `,`, both
+/// being part of the data when quoted.
+///
+/// # Errors
+///
+/// If there aren't exactly three sections or a node can't be parsed, an
+/// `Error::Parse` is returned.
+pub fn parse_compact(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    fn parse_nodes<'a, T: SectionLine<'a>>(section: &'a [u8]) -> ParseResult<Vec<T>> {
+        split_unquoted(section, b',')
+            .map(<[u8]>::trim_ascii)
+            .filter(|node| !node.is_empty())
+            .map(|node| parse_line(node, &ParseOptions::default()))
+            .collect()
+    }
+
+    let mut sections = split_unquoted(data, b';');
+    let (Some(compares), Some(success), Some(failure), None) = (
+        sections.next(),
+        sections.next(),
+        sections.next(),
+        sections.next(),
+    ) else {
+        return Err(ParseError::UnexpectedToken.into());
+    };
+    Ok(TxnData {
+        compares: parse_nodes(compares)?,
+        success: parse_nodes(success)?,
+        failure: parse_nodes(failure)?,
+    })
+}
+
+/// An error found on a line of a section, along with its position in the input.
+pub type LineError = (Section, usize, ParseError);
+
+/// Parse a transactional data structure, going on past the lines which
+/// can't be parsed to report all of them at once.
+///
+/// Each line which can't be parsed is kept as an unknown node, see
+/// [`ParseOptions::keep_unknown_lines`], and its error is collected along
+/// with its section and its position in the input. Non-blank content after
+/// the failure section is reported the same way.
+///
+/// A structural error, such as a missing section, can't be recovered from:
+/// no transaction is returned and the error is the last one collected.
+pub fn parse_collect_errors(data: &[u8]) -> (Option<TxnData<'_>>, Vec<LineError>) {
+    let mut report = Report {
+        errors: Some(vec![]),
+        ..Default::default()
+    };
+    let mut scanner = Scanner::new(data);
+    scanner.bump_by(shebang_len(data));
+    let txn = TxnData::accept_with(&mut scanner, &ParseOptions::default(), &mut report).ok();
+    let mut errors = report.errors.unwrap_or_default();
+
+    if txn.is_some() {
+        if let Err(Error::TrailingContent { offset, .. }) =
+            check_end(data, scanner.current_position(), &ParseOptions::default())
+        {
+            errors.push((Section::Failure, offset, ParseError::UnexpectedToken));
+        }
+    }
+
+    (txn, errors)
+}
+
+/// Parse a transactional data structure and run the default validation rules.
+///
+/// # Errors
+///
+/// If the parser encounters an unexpected token, an `Error::Parse` is returned.
+/// If the transaction is rejected by [`TxnData::validate`], an `Error::Validation`
+/// is returned.
+pub fn parse_checked(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    let txn = parse(data)?;
+    txn.validate()?;
+    Ok(txn)
+}
+
+/// A branch of the transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Branch {
+    /// The operations applied if the compare operations pass.
+    Success,
+    /// The operations applied if the compare operations fail.
+    Failure,
+}
+
+impl Display for Branch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Branch::Success => write!(f, "success"),
+            Branch::Failure => write!(f, "failure"),
+        }
+    }
+}
+
+/// A write applied by a transaction, see [`TxnData::writes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Write<'a> {
+    /// The key is set to the value.
+    Put {
+        /// The written key.
+        key: &'a [u8],
+        /// The written value.
+        value: &'a [u8],
+    },
+    /// The key is deleted.
+    Delete {
+        /// The deleted key.
+        key: &'a [u8],
+    },
+}
+
+/// A write as an entry of a write-ahead log, see [`TxnData::to_wal_entries`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalEntry<'a> {
+    /// The sequence index of the entry, from 1 like the indexes of a raft
+    /// log.
+    pub index: u64,
+    /// The write of the entry.
+    pub write: Write<'a>,
+}
+
+/// A section of the transaction text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// The compare section.
+    Compare,
+    /// The success section.
+    Success,
+    /// The failure section.
+    Failure,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Section::Compare => write!(f, "compare"),
+            Section::Success => write!(f, "success"),
+            Section::Failure => write!(f, "failure"),
+        }
+    }
+}
+
+/// A parsed transaction along with the warnings emitted while parsing it.
+#[derive(Debug, PartialEq)]
+pub struct Parsed<'a> {
+    /// The parsed transaction.
+    pub txn: TxnData<'a>,
+    /// The warnings emitted while parsing the transaction.
+    pub warnings: Vec<Warning<'a>>,
+    /// What was found around the transaction.
+    pub metadata: Metadata,
+}
+
+/// What was found around a transaction, outside of its sections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// The SHA-256 of the trailing checksum line, see
+    /// [`ParseOptions::trailing_checksum`].
+    pub checksum: Option<[u8; 32]>,
+    /// The format version declared by a `%txn-version` directive.
+    pub format_version: Option<u32>,
+}
+
+/// The latest version of the text format understood by the parser.
+///
+/// A file may declare the version it is written in on its first line, after
+/// the shebang if any, e.g. `%txn-version 2`. A file declaring a newer version
+/// is rejected with an `Error::UnsupportedFormatVersion`, and a file
+/// declaring an older version may only use the syntax of that version:
+///
+/// - version 1 is the original format,
+/// - version 2 adds the operation flags, `--lease`, `--prefix` and the
+///   revision filters of gets.
+///
+/// Without directive, the whole syntax is accepted.
+pub const FORMAT_VERSION: u32 = 2;
+
 /// A transactional data structure.
 #[derive(Debug, PartialEq)]
 pub struct TxnData<'a> {
@@ -39,61 +463,502 @@ pub struct TxnData<'a> {
     pub failure: Vec<Operation<'a>>,
 }
 
-struct LineFeed;
+/// The transaction in its canonical text format, as written by
+/// [`TxnData::write_to`], so that the output parses back to the transaction.
+///
+/// A key or a value which isn't valid UTF-8 is written escaped between double
+/// quotes, its invalid bytes as `\xNN` escape sequences, so that it parses
+/// back to the same bytes. Formatting doesn't fail: quoted data which can't
+/// be written as is and holds an invalid escape sequence, which
+/// [`TxnData::to_text`] rejects, is marked as `"..." <invalid escape>`, which
+/// doesn't parse. The lines kept as is, the custom nodes and the paths of the
+/// file references are written as is, their invalid bytes as `\xNN`.
+impl Display for TxnData<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writer::display_with(f, |text, inexpressible| {
+            self.write_with(text, inexpressible)
+        })
+    }
+}
 
-impl<'a> Visitor<'a, u8> for LineFeed {
-    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        recognize(Token::Ln, scanner)?;
-        Ok(LineFeed)
+/// A section at the start of the remaining input.
+struct Block<'a> {
+    /// The lines of the section.
+    data: &'a [u8],
+    /// The length of the section along with the blank line ending it.
+    len: usize,
+}
+
+/// The section at the start of the remaining input, ended by a blank line.
+///
+/// A blank line inside a quoted string doesn't end the section, the same way
+/// a line feed inside one doesn't end the line, see [`Lines`].
+#[deny(clippy::arithmetic_side_effects)]
+fn next_section<'a>(scanner: &Scanner<'a, u8>, quotes: &[u8]) -> Option<Block<'a>> {
+    let rest = scanner.remaining();
+    let mut tracker = QuoteTracker::new(quotes);
+    let end = rest.iter().enumerate().position(|(index, &byte)| {
+        !tracker.is_quoted(byte)
+            && rest
+                .get(index..)
+                .is_some_and(|rest| rest.starts_with(b"\n\n"))
+    })?;
+    Some(Block {
+        data: rest.get(..end)?,
+        len: end.checked_add(2)?,
+    })
+}
+
+/// An item parsed from a single line of a section.
+trait SectionLine<'a>: Visitor<'a, u8> {
+    /// Parse the item, taking into account the options altering its syntax.
+    fn accept_with(scanner: &mut Scanner<'a, u8>, _options: &ParseOptions) -> ParseResult<Self> {
+        Self::accept(scanner)
+    }
+
+    /// Parse a whole line as the item with the nom grammar.
+    #[cfg(feature = "backend-nom")]
+    fn parse_nom(line: &'a [u8], options: &ParseOptions) -> ParseResult<Self>;
+
+    /// Keep a line which can't be parsed as an unknown node.
+    fn unknown(line: &'a [u8]) -> Self;
+
+    /// Why the options reject the parsed item, `offset` being its position in
+    /// the input.
+    fn reject(&self, _offset: usize, _options: &ParseOptions) -> Option<Error> {
+        None
     }
 }
 
-#[derive(Clone, Default)]
-struct SectionEnd;
+impl<'a> SectionLine<'a> for Compare<'a> {
+    fn accept_with(scanner: &mut Scanner<'a, u8>, options: &ParseOptions) -> ParseResult<Self> {
+        Compare::accept_with(scanner, options)
+    }
+
+    #[cfg(feature = "backend-nom")]
+    fn parse_nom(line: &'a [u8], options: &ParseOptions) -> ParseResult<Self> {
+        nom_backend::compare(line, options)
+    }
 
-impl Match<u8> for SectionEnd {
-    fn is_matching(&self, data: &[u8]) -> (bool, usize) {
-        match_pattern(b"\n\n", data)
+    fn unknown(line: &'a [u8]) -> Self {
+        Compare::Unknown(line)
     }
 
-    fn size(&self) -> usize {
-        2
+    fn reject(&self, offset: usize, options: &ParseOptions) -> Option<Error> {
+        // Only the numeric compares are concerned
+        (options.strict_numbers && self.number().is_some() && self.is_unsatisfiable())
+            .then_some(Error::UnsatisfiableCompare { offset })
     }
 }
 
-impl PeekableImplementation for SectionEnd {
-    type Type = DefaultPeekableImplementation;
+impl<'a> SectionLine<'a> for Operation<'a> {
+    fn accept_with(scanner: &mut Scanner<'a, u8>, options: &ParseOptions) -> ParseResult<Self> {
+        Operation::accept_with(scanner, options)
+    }
+
+    #[cfg(feature = "backend-nom")]
+    fn parse_nom(line: &'a [u8], options: &ParseOptions) -> ParseResult<Self> {
+        nom_backend::operation(line, options)
+    }
+
+    fn unknown(line: &'a [u8]) -> Self {
+        Operation::Unknown(line)
+    }
 }
 
-impl<'a> Visitor<'a, u8> for TxnData<'a> {
+/// Iterator over the lines of a section along with their offset in the section.
+///
+/// A line feed inside a string quoted by one of `quotes` doesn't end the line.
+struct Lines<'a> {
+    data: &'a [u8],
+    quotes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(data: &'a [u8], quotes: &'a [u8]) -> Self {
+        Lines {
+            data,
+            quotes,
+            position: 0,
+        }
+    }
+}
+
+#[deny(clippy::arithmetic_side_effects)]
+impl<'a> Iterator for Lines<'a> {
+    type Item = (usize, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self
+            .data
+            .get(self.position..)
+            .filter(|rest| !rest.is_empty())?;
+        let line = first_line(rest, self.quotes);
+        let start = self.position;
+        // The line feed is skipped
+        self.position = start.saturating_add(line.len()).saturating_add(1);
+        Some((start, line))
+    }
+}
+
+/// The first line of `data`, without its line feed. A line feed inside a
+/// string quoted by one of `quotes` doesn't end the line.
+pub(crate) fn first_line<'a>(data: &'a [u8], quotes: &[u8]) -> &'a [u8] {
+    let mut tracker = QuoteTracker::new(quotes);
+    let end = data
+        .iter()
+        .position(|&byte| !tracker.is_quoted(byte) && byte == b'\n')
+        .unwrap_or(data.len());
+    let (line, _) = data.split_at(end);
+    line
+}
+
+/// The length of the shebang line starting the input, line feed included, or
+/// zero if there is none.
+///
+/// Executable scripts start with e.g. `#!/usr/bin/env etcdctl-txn`.
+#[deny(clippy::arithmetic_side_effects)]
+fn shebang_len(data: &[u8]) -> usize {
+    if !data.starts_with(b"#!") {
+        return 0;
+    }
+    data.iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(data.len(), |end| end.saturating_add(1))
+}
+
+/// The length of the blank lines padding the input before its first compare,
+/// or zero if the input doesn't start with a compare.
+///
+/// Leading blank lines are otherwise significant: a transaction without
+/// compares starts with the blank line ending its empty compare section.
+fn padding_len(data: &[u8], options: &ParseOptions) -> usize {
+    match Lines::new(data, options.quote_chars).find(|(_, line)| !is_blank(line)) {
+        Some((start, line)) if parse_line::<Compare>(line, options).is_ok() => start,
+        _ => 0,
+    }
+}
+
+/// Tells whether the bytes fed in order are inside a string quoted by one of
+/// `quotes`, closed by the character opening it.
+///
+/// A quote only opens a string at the start of a word, so that an apostrophe
+/// inside an unquoted word, e.g. `it's`, is part of the word.
+pub(crate) struct QuoteTracker<'a> {
+    quotes: &'a [u8],
+    open: Option<u8>,
+    word_start: bool,
+}
+
+impl<'a> QuoteTracker<'a> {
+    pub(crate) fn new(quotes: &'a [u8]) -> Self {
+        QuoteTracker {
+            quotes,
+            open: None,
+            word_start: true,
+        }
+    }
+
+    /// Feed the next byte, returning whether it's inside a string, quotes
+    /// included.
+    pub(crate) fn is_quoted(&mut self, byte: u8) -> bool {
+        let quoted = match self.open {
+            Some(open) if open == byte => {
+                self.open = None;
+                true
+            }
+            None if self.word_start && self.quotes.contains(&byte) => {
+                self.open = Some(byte);
+                true
+            }
+            open => open.is_some(),
+        };
+        self.word_start =
+            byte.is_ascii_whitespace() || matches!(byte, b'(' | b'=' | b'<' | b'>' | b',' | b';');
+        quoted
+    }
+
+    /// The quote opening the current string, if any.
+    pub(crate) fn open(&self) -> Option<u8> {
+        self.open
+    }
+}
+
+/// Split on a separator which isn't inside a quoted string.
+fn split_unquoted(data: &[u8], separator: u8) -> impl Iterator<Item = &[u8]> {
+    let mut quotes = QuoteTracker::new(DEFAULT_QUOTE_CHARS);
+    data.split(move |&byte| !quotes.is_quoted(byte) && byte == separator)
+}
+
+/// Check that no string is single-quoted, etcdctl reading a single quote as
+/// part of the key or the value.
+fn check_etcdctl_quotes(data: &[u8], options: &ParseOptions) -> ParseResult<()> {
+    let mut quotes = QuoteTracker::new(options.quote_chars);
+    for &byte in data {
+        quotes.is_quoted(byte);
+        if quotes.open() == Some(b'\'') {
+            return Err(ParseError::UnexpectedToken);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_blank(data: &[u8]) -> bool {
+    data.iter().all(u8::is_ascii_whitespace)
+}
+
+/// The spaces and tabs separating the words of a line, if any.
+///
+/// Elyze's `OptionalWhitespaces` only skips spaces, tabs are skipped too the
+/// same way as by the nom backend.
+pub(crate) struct OptionalBlanks;
+
+impl<'a> Visitor<'a, u8> for OptionalBlanks {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
+        let len = scanner
+            .remaining()
+            .iter()
+            .take_while(|&&byte| byte == b' ' || byte == b'\t')
+            .count();
+        scanner.bump_by(len);
+        Ok(OptionalBlanks)
+    }
+}
 
-        // Read the compare section
-        let section_compare = peek(SectionEnd, scanner)?.ok_or(ParseError::UnexpectedToken)?;
+/// Check that no line from `position` on is longer than
+/// [`ParseOptions::max_line_len`], blank lines aside.
+fn check_line_lengths(data: &[u8], position: usize, options: &ParseOptions) -> Result<(), Error> {
+    let Some(max_line_len) = options.max_line_len else {
+        return Ok(());
+    };
+    let rest = data.get(position..).unwrap_or_default();
+    match Lines::new(rest, options.quote_chars)
+        .find(|(_, line)| line.len() > max_line_len && !is_blank(line))
+    {
+        Some((start, _)) => Err(Error::LineTooLong {
+            offset: position + start,
+        }),
+        None => Ok(()),
+    }
+}
 
-        let mut section_compare_scanner = Scanner::new(section_compare.peeked_slice());
-        let compares =
-            SeparatedList::<u8, Compare, LineFeed>::accept(&mut section_compare_scanner)?.data;
-        scanner.bump_by(section_compare.end_slice);
+/// The position in `line` of the content following its compare, if a compare
+/// parses at its start but more than whitespaces follow it, e.g. the `x` of
+/// `mod(key) > 5x`.
+fn compare_junk(line: &[u8], options: &ParseOptions) -> Option<usize> {
+    let mut scanner = Scanner::new(line);
+    Compare::accept_with(&mut scanner, options).ok()?;
+    let end = scanner.current_position();
+    let junk = line
+        .get(end..)?
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())?;
+    Some(end + junk)
+}
 
-        // Read the success section
-        let section_success = peek(SectionEnd, scanner)?.ok_or(ParseError::UnexpectedToken)?;
+/// Why the compare `line`, at `position` in the input, doesn't parse, if the
+/// reason is more specific than an unexpected token.
+fn diagnose_compare(line: &[u8], position: usize, options: &ParseOptions) -> Option<Error> {
+    if let Some((start, operator)) = unsupported_operator(line, options) {
+        return Some(Error::UnsupportedOperator {
+            offset: position + start,
+            operator: operator.into(),
+        });
+    }
+    compare_junk(line, options).map(|junk| Error::TrailingJunk {
+        offset: position + junk,
+    })
+}
+
+/// Check that only whitespaces follow the transaction ending at `position`.
+fn check_end(data: &[u8], position: usize, options: &ParseOptions) -> Result<(), Error> {
+    let rest = data.get(position..).unwrap_or_default();
+    match Lines::new(rest, options.quote_chars).find(|(_, line)| !is_blank(line)) {
+        Some((start, line)) => Err(Error::TrailingContent {
+            offset: position + start,
+            line: line.to_vec(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// What is reported while parsing a transaction, besides the transaction itself.
+#[derive(Default)]
+struct Report<'a> {
+    warnings: Vec<Warning<'a>>,
+    /// The errors recovered from, only collected by [`parse_collect_errors`].
+    errors: Option<Vec<LineError>>,
+    /// Why the line which failed the parse doesn't parse, if more specific
+    /// than an unexpected token, see [`diagnose_compare`].
+    diagnosis: Option<Error>,
+}
+
+impl Report<'_> {
+    /// Record a structural error, which can't be recovered from.
+    fn fail(&mut self, section: Section, offset: usize) -> ParseError {
+        if let Some(errors) = &mut self.errors {
+            errors.push((section, offset, ParseError::UnexpectedToken));
+        }
+        ParseError::UnexpectedToken
+    }
+}
+
+/// Parse a whole line as a node, with the nom grammar when the `backend-nom`
+/// feature is enabled.
+fn parse_line<'a, T: SectionLine<'a>>(line: &'a [u8], options: &ParseOptions) -> ParseResult<T> {
+    #[cfg(feature = "backend-nom")]
+    {
+        T::parse_nom(line, options)
+    }
+    #[cfg(not(feature = "backend-nom"))]
+    {
+        parse_line_elyze(line, options)
+    }
+}
 
-        let mut section_success_scanner = Scanner::new(section_success.peeked_slice());
-        let success =
-            SeparatedList::<u8, Operation, LineFeed>::accept(&mut section_success_scanner)?.data;
-        scanner.bump_by(section_success.end_slice);
+/// Parse a whole line as a node with the elyze grammar.
+#[cfg_attr(all(feature = "backend-nom", not(test)), allow(dead_code))]
+fn parse_line_elyze<'a, T: SectionLine<'a>>(
+    line: &'a [u8],
+    options: &ParseOptions,
+) -> ParseResult<T> {
+    let mut line_scanner = Scanner::new(line);
+    let item = T::accept_with(&mut line_scanner, options)?;
+    // The whole line must be consumed
+    match line.get(line_scanner.current_position()..) {
+        Some(rest) if is_blank(rest) => Ok(item),
+        _ => Err(ParseError::UnexpectedToken),
+    }
+}
 
-        // Read the failure section
-        let section_failure =
-            peek(UntilEnd::default(), scanner)?.ok_or(ParseError::UnexpectedToken)?;
+/// Parse each line of a section, `offset` being the position of the section in the input.
+fn parse_section<'a, T: SectionLine<'a>>(
+    data: &'a [u8],
+    section: Section,
+    offset: usize,
+    options: &ParseOptions,
+    report: &mut Report<'a>,
+) -> ParseResult<Vec<T>> {
+    let mut items = vec![];
+    for (start, line) in Lines::new(data, options.quote_chars) {
+        if is_blank(line) {
+            continue;
+        }
 
-        let mut section_failure_scanner = Scanner::new(section_failure.peeked_slice());
-        let failure =
-            SeparatedList::<u8, Operation, LineFeed>::accept(&mut section_failure_scanner)?.data;
+        let diagnose = || match section {
+            Section::Compare => diagnose_compare(line, offset + start, options),
+            _ => None,
+        };
+        let parsed = match section {
+            Section::Compare if unsupported_operator(line, options).is_some() => {
+                Err(ParseError::UnexpectedToken)
+            }
+            _ => parse_line::<T>(line, options),
+        };
+        match (parsed, &mut report.errors) {
+            (Ok(item), _) => {
+                if let Some(rejection) = item.reject(offset + start, options) {
+                    report.diagnosis = Some(rejection);
+                    return Err(ParseError::UnexpectedToken);
+                }
+                items.push(item);
+            }
+            (Err(err), Some(errors)) => {
+                // Pointing at the junk or the operator rather than at the line
+                let position = diagnose()
+                    .and_then(|diagnosis| diagnosis.offset())
+                    .unwrap_or(offset + start);
+                errors.push((section, position, err));
+                items.push(T::unknown(line));
+            }
+            (Err(_), None) if options.keep_unknown_lines => {
+                report.warnings.push(Warning::UnknownLine {
+                    section,
+                    offset: offset + start,
+                    line,
+                });
+                items.push(T::unknown(line));
+            }
+            (Err(err), None) => {
+                report.diagnosis = diagnose();
+                return Err(err);
+            }
+        }
+    }
+    Ok(items)
+}
 
-        scanner.bump_by(section_failure.end_slice);
+impl<'a> TxnData<'a> {
+    fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        options: &ParseOptions,
+        report: &mut Report<'a>,
+    ) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
+        if let Some(rest) = peek(UntilEnd::default(), scanner)? {
+            scanner.bump_by(padding_len(rest.peeked_slice(), options));
+        }
+
+        // Read the compare section
+        let offset = scanner.current_position();
+        let section_compare = next_section(scanner, options.quote_chars)
+            .ok_or_else(|| report.fail(Section::Compare, offset))?;
+        let mut compares = parse_section(
+            section_compare.data,
+            Section::Compare,
+            offset,
+            options,
+            report,
+        )?;
+        scanner.bump_by(section_compare.len);
+
+        // Blocks starting with a compare are part of the compare section
+        while let Some(block) =
+            next_section(scanner, options.quote_chars).filter(|_| options.lenient_blank_lines)
+        {
+            let first_line =
+                Lines::new(block.data, options.quote_chars).find(|(_, line)| !is_blank(line));
+            if first_line.is_none_or(|(_, line)| parse_line::<Compare>(line, options).is_err()) {
+                break;
+            }
+            compares.extend(parse_section(
+                block.data,
+                Section::Compare,
+                scanner.current_position(),
+                options,
+                report,
+            )?);
+            scanner.bump_by(block.len);
+        }
+
+        // Read the success section
+        let offset = scanner.current_position();
+        let section_success = next_section(scanner, options.quote_chars)
+            .ok_or_else(|| report.fail(Section::Success, offset))?;
+        let success = parse_section(
+            section_success.data,
+            Section::Success,
+            offset,
+            options,
+            report,
+        )?;
+        scanner.bump_by(section_success.len);
+
+        // Read the failure section, which ends the transaction
+        let offset = scanner.current_position();
+        let section_failure = next_section(scanner, options.quote_chars).unwrap_or(Block {
+            data: scanner.remaining(),
+            len: scanner.remaining().len(),
+        });
+        let failure = parse_section(
+            section_failure.data,
+            Section::Failure,
+            offset,
+            options,
+            report,
+        )?;
+        scanner.bump_by(section_failure.len);
 
         Ok(TxnData {
             compares,
@@ -101,4 +966,216 @@ impl<'a> Visitor<'a, u8> for TxnData<'a> {
             failure,
         })
     }
+
+    /// Whether the transaction has no compare, its success branch always runs.
+    pub fn is_unconditional(&self) -> bool {
+        self.compares.is_empty()
+    }
+
+    /// Whether the success and failure branches hold the same operations, in
+    /// which case the same operations run whatever the compares outcome.
+    pub fn branches_identical(&self) -> bool {
+        self.success == self.failure
+    }
+
+    /// The lease IDs referenced by the lease compares and the leased put
+    /// operations, in order of first appearance.
+    ///
+    /// Useful to check the leases exist before applying the transaction.
+    pub fn referenced_leases(&self) -> Vec<u64> {
+        let compares = self.compares.iter().filter_map(|compare| match compare {
+            Compare::Lease(lease) => Some(lease.value),
+            _ => None,
+        });
+        let puts =
+            self.success
+                .iter()
+                .chain(&self.failure)
+                .filter_map(|operation| match operation {
+                    Operation::Put(put) => put.lease,
+                    _ => None,
+                });
+
+        let mut leases = vec![];
+        for lease in compares.chain(puts) {
+            if !leases.contains(&lease) {
+                leases.push(lease);
+            }
+        }
+        leases
+    }
+
+    /// Split the compares into the existence checks, see
+    /// [`Compare::is_existence_check`], and the value and revision checks.
+    ///
+    /// Both lists keep the order of the compares. Unknown compares land in
+    /// the second one.
+    pub fn partition_compares(&self) -> (Vec<&Compare<'a>>, Vec<&Compare<'a>>) {
+        self.compares
+            .iter()
+            .partition(|compare| compare.is_existence_check())
+    }
+
+    /// The operator and the expected value of the value compares, by key.
+    ///
+    /// Useful to build the read set of the transaction. When a key has
+    /// several value compares, the last one is kept.
+    pub fn value_expectations(&self) -> HashMap<&'a [u8], (OpType, &'a [u8])> {
+        self.compares
+            .iter()
+            .filter_map(|compare| match compare {
+                Compare::Value(compare) => Some((
+                    compare.key.as_bytes(),
+                    (compare.op, compare.value.as_bytes()),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The operations of both branches whose key is exactly `key`, success
+    /// first, each in order.
+    ///
+    /// A get or a delete with `--prefix` only matches its own key, ranges are
+    /// left to the caller.
+    pub fn operations_for_key<'b>(&'b self, key: &[u8]) -> Vec<(Branch, &'b Operation<'a>)> {
+        let success = self
+            .success
+            .iter()
+            .map(|operation| (Branch::Success, operation));
+        let failure = self
+            .failure
+            .iter()
+            .map(|operation| (Branch::Failure, operation));
+        success
+            .chain(failure)
+            .filter(|(_, operation)| operation.key().as_deref() == Some(key))
+            .collect()
+    }
+
+    /// The writes of the branch applied when the compares evaluate to
+    /// `condition`, in order.
+    ///
+    /// Gets don't write and are left out, as are the file references, which
+    /// must be resolved first, the custom operations and the unknown lines.
+    /// The leases of the puts are dropped.
+    pub fn writes(&self, condition: bool) -> Vec<Write<'a>> {
+        let branch = match condition {
+            true => &self.success,
+            false => &self.failure,
+        };
+        branch
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Put(put) => Some(Write::Put {
+                    key: put.key.as_bytes(),
+                    value: put.value.as_bytes(),
+                }),
+                Operation::Delete(delete) => Some(Write::Delete {
+                    key: delete.key.as_bytes(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The writes of the branch applied when the compares evaluate to
+    /// `condition` as write-ahead log entries, indexed in order from 1.
+    ///
+    /// The writes are the ones of [`TxnData::writes`], e.g. to be appended to
+    /// a mock etcd WAL.
+    pub fn to_wal_entries(&self, condition: bool) -> Vec<WalEntry<'a>> {
+        (1..)
+            .zip(self.writes(condition))
+            .map(|(index, write)| WalEntry { index, write })
+            .collect()
+    }
+
+    /// Whether both transactions mean the same, whatever the way they are
+    /// written.
+    ///
+    /// Nodes are compared in order, section by section. The normalization is:
+    ///
+    /// - keys and values are compared by the bytes they stand for, their
+    ///   escape sequences decoded, however they were quoted, as node equality
+    ///   already does: `put k "\x41"` means `put k A`, while `put k '\x41'`
+    ///   puts the four bytes as written;
+    /// - compare targets are compared by kind, `mod` and `m` being the same;
+    /// - unknown lines kept by [`ParseOptions::keep_unknown_lines`] are
+    ///   compared word by word, runs of ASCII whitespace being equal.
+    ///
+    /// Reordering nodes changes the meaning.
+    pub fn meaning_eq(&self, other: &TxnData) -> bool {
+        fn same_words(a: &[u8], b: &[u8]) -> bool {
+            let words =
+                |line| <[u8]>::split(line, u8::is_ascii_whitespace).filter(|word| !word.is_empty());
+            words(a).eq(words(b))
+        }
+
+        fn same_operations(a: &[Operation], b: &[Operation]) -> bool {
+            a.len() == b.len()
+                && a.iter().zip(b).all(|pair| match pair {
+                    (Operation::Unknown(a), Operation::Unknown(b)) => same_words(a, b),
+                    (a, b) => a == b,
+                })
+        }
+
+        self.compares.len() == other.compares.len()
+            && self
+                .compares
+                .iter()
+                .zip(&other.compares)
+                .all(|pair| match pair {
+                    (Compare::Unknown(a), Compare::Unknown(b)) => same_words(a, b),
+                    (a, b) => a == b,
+                })
+            && same_operations(&self.success, &other.success)
+            && same_operations(&self.failure, &other.failure)
+    }
+
+    /// Move the sections out of the transaction, in the order of the format:
+    /// the compares, then the success and the failure operations.
+    pub fn into_parts(self) -> (Vec<Compare<'a>>, Vec<Operation<'a>>, Vec<Operation<'a>>) {
+        (self.compares, self.success, self.failure)
+    }
+
+    /// Whether the transaction contains lines kept as unknown nodes by
+    /// [`ParseOptions::keep_unknown_lines`].
+    ///
+    /// Such a transaction can't be converted nor executed.
+    pub fn has_unknown(&self) -> bool {
+        self.compares.iter().any(Compare::is_unknown)
+            || self
+                .success
+                .iter()
+                .chain(&self.failure)
+                .any(Operation::is_unknown)
+    }
+
+    /// Whether a compare can never succeed, see
+    /// [`Compare::is_unsatisfiable`], leaving the success operations dead.
+    pub fn has_unsatisfiable_compare(&self) -> bool {
+        self.compares.iter().any(Compare::is_unsatisfiable)
+    }
+
+    /// A one-line summary of the transaction for log lines, e.g.
+    /// `txn: 1 compare, 1 success op, 2 failure ops`.
+    pub fn summary(&self) -> String {
+        let count = |count: usize, noun: &str| match count {
+            1 => format!("1 {noun}"),
+            count => format!("{count} {noun}s"),
+        };
+        format!(
+            "txn: {}, {}, {}",
+            count(self.compares.len(), "compare"),
+            count(self.success.len(), "success op"),
+            count(self.failure.len(), "failure op")
+        )
+    }
+}
+
+impl<'a> Visitor<'a, u8> for TxnData<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        TxnData::accept_with(scanner, &ParseOptions::default(), &mut Report::default())
+    }
 }
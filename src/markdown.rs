@@ -0,0 +1,274 @@
+//! Markdown rendering of a transaction
+//!
+//! A short document with one table for the compares and one per branch, to
+//! be posted in a pull request description or an audit log.
+
+use crate::compare::{Compare, OpType};
+use crate::escape::decode_lossy;
+use crate::operation::{DataKind, Operation};
+use crate::writer::{consistency_flag, unknown_flag};
+use crate::TxnData;
+use std::fmt::Write;
+
+/// The maximum length of a key or a value, in characters, truncation marker
+/// included.
+const MAX_DATA_WIDTH: usize = 64;
+
+/// Keys and values as code spans, escaped so that they can't break the table
+/// layout: control characters are escaped, pipes are backslash escaped, and
+/// the span is fenced by more backticks than the data holds in a row. Data
+/// longer than [`MAX_DATA_WIDTH`] characters is truncated, followed by a note
+/// giving its length.
+fn code(data: &[u8]) -> String {
+    if data.is_empty() {
+        return "*empty*".into();
+    }
+    let text: Vec<char> = String::from_utf8_lossy(data)
+        .chars()
+        .flat_map(|char| match char {
+            char if char.is_control() => char.escape_default().collect::<Vec<_>>(),
+            char => vec![char],
+        })
+        .collect();
+    let truncated = text.len() > MAX_DATA_WIDTH;
+    let mut text: String = match truncated {
+        true => text.into_iter().take(MAX_DATA_WIDTH - 1).collect(),
+        false => text.into_iter().collect(),
+    };
+    if truncated {
+        text.push('…');
+    }
+
+    let longest_run = text
+        .split(|char| char != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run + 1);
+    // A space keeps a backtick at an end from merging with the fence, and is
+    // stripped when rendered
+    let padding = match text.starts_with('`') || text.ends_with('`') {
+        true => " ",
+        false => "",
+    };
+    let mut span = format!(
+        "{fence}{padding}{}{padding}{fence}",
+        text.replace('|', "\\|")
+    );
+    if truncated {
+        let _ = write!(span, " *(truncated, {} bytes)*", data.len());
+    }
+    span
+}
+
+/// A key or a value as a code span, see [`code`], the bytes it stands for,
+/// see [`decode`](crate::escape::decode), or as written if it holds an
+/// invalid escape sequence.
+fn code_decoded(data: &[u8], kind: DataKind) -> String {
+    code(&decode_lossy(data, kind))
+}
+
+fn symbol(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "`=`",
+        OpType::GreaterThan => "`>`",
+        OpType::LessThan => "`<`",
+        OpType::NotEqual => "`!=`",
+    }
+}
+
+/// The key, target, operator and value of a compare.
+fn guard_row(compare: &Compare) -> [String; 4] {
+    let (target, (key, key_kind), op) = match compare {
+        Compare::CreateRevision(compare) => ("create", (compare.key, compare.key_kind), compare.op),
+        Compare::ModRevision(compare) => ("mod", (compare.key, compare.key_kind), compare.op),
+        Compare::Value(compare) => ("value", (compare.key, compare.key_kind), compare.op),
+        Compare::Version(compare) => ("version", (compare.key, compare.key_kind), compare.op),
+        Compare::Lease(compare) => ("lease", (compare.key, compare.key_kind), compare.op),
+        Compare::Custom(custom) => {
+            return [
+                String::new(),
+                code(custom.target),
+                String::new(),
+                code(&custom.args.join(&b' ')),
+            ];
+        }
+        Compare::Unknown(line) => {
+            return [String::new(), "unknown".into(), String::new(), code(line)];
+        }
+    };
+    let value = match compare {
+        Compare::Value(compare) => code_decoded(&compare.value, compare.value_kind),
+        _ => code(compare.number().unwrap_or_default().to_string().as_bytes()),
+    };
+    [
+        code_decoded(&key, key_kind),
+        target.into(),
+        symbol(op).into(),
+        value,
+    ]
+}
+
+/// The operation, key, and value or options of an operation.
+fn operation_row(operation: &Operation) -> [String; 3] {
+    match operation {
+        Operation::Put(put) => {
+            let mut value = code_decoded(&put.value, put.value_kind);
+            if let Some(lease) = put.lease {
+                let _ = write!(value, " `--lease={lease:x}`");
+            }
+            for flag in &put.unknown_flags {
+                let _ = write!(value, " {}", code(unknown_flag(flag).as_bytes()));
+            }
+            ["put".into(), code_decoded(&put.key, put.key_kind), value]
+        }
+        Operation::PutFile(put) => [
+            "put".into(),
+            code_decoded(&put.key, put.key_kind),
+            code(&[&b"@"[..], put.path].concat()),
+        ],
+        Operation::Delete(delete) => [
+            "del".into(),
+            code_decoded(&delete.key, delete.key_kind),
+            String::new(),
+        ],
+        Operation::Get(get) => {
+            let mut options = vec![];
+            if get.prefix {
+                options.push("`--prefix`".to_string());
+            }
+            if get.from_key {
+                options.push("`--from-key`".to_string());
+            }
+            if let Some(consistency) = get.consistency {
+                options.push(format!("`{}`", consistency_flag(consistency)));
+            }
+            let filters = [
+                ("min-create-rev", get.min_create_rev),
+                ("max-create-rev", get.max_create_rev),
+                ("min-mod-rev", get.min_mod_rev),
+                ("max-mod-rev", get.max_mod_rev),
+            ];
+            for (name, revision) in filters {
+                if let Some(revision) = revision {
+                    options.push(format!("`--{name}={revision}`"));
+                }
+            }
+            options.extend(
+                get.unknown_flags
+                    .iter()
+                    .map(|flag| code(unknown_flag(flag).as_bytes())),
+            );
+            let mut key = code_decoded(&get.key, get.key_kind);
+            if let Some(range_end) = get.range_end {
+                let _ = write!(key, " {}", code_decoded(&range_end, get.range_end_kind));
+            }
+            ["get".into(), key, options.join(" ")]
+        }
+        Operation::Noop => ["noop".into(), String::new(), String::new()],
+        Operation::Custom(custom) => [
+            code(custom.command),
+            String::new(),
+            code(&custom.args.join(&b' ')),
+        ],
+        Operation::Unknown(line) => ["unknown".into(), String::new(), code(line)],
+    }
+}
+
+/// Write a table under its `title`, or `empty` in its place if there is no
+/// row.
+fn write_table<const N: usize>(
+    markdown: &mut String,
+    title: &str,
+    headers: [&str; N],
+    rows: Vec<[String; N]>,
+    empty: &str,
+) {
+    let _ = writeln!(markdown, "### {title}\n");
+    if rows.is_empty() {
+        let _ = writeln!(markdown, "*{empty}*");
+        return;
+    }
+    let _ = writeln!(markdown, "| {} |", headers.join(" | "));
+    let _ = writeln!(markdown, "|{}", " --- |".repeat(N));
+    for row in rows {
+        let _ = writeln!(markdown, "| {} |", row.join(" | "));
+    }
+}
+
+impl TxnData<'_> {
+    /// Render the transaction as a Markdown document, e.g. for a pull request
+    /// description:
+    ///
+    /// ```text
+    /// ### Guards
+    ///
+    /// | Key | Target | Operator | Value |
+    /// | --- | --- | --- | --- |
+    /// | `key1` | mod | `>` | `0` |
+    /// ```
+    ///
+    /// A "Guards" table lists the compares, "On success" and "On failure"
+    /// tables the operations of each branch. Keys and values are code spans,
+    /// escaped so that pipes, backticks and control characters don't break
+    /// the tables. Those longer than 64 characters are truncated, with a note
+    /// giving their length.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        write_table(
+            &mut markdown,
+            "Guards",
+            ["Key", "Target", "Operator", "Value"],
+            self.compares.iter().map(guard_row).collect(),
+            "No guards, the success operations always run.",
+        );
+        for (title, operations) in [("On success", &self.success), ("On failure", &self.failure)] {
+            markdown.push('\n');
+            write_table(
+                &mut markdown,
+                title,
+                ["Operation", "Key", "Value / options"],
+                operations.iter().map(operation_row).collect(),
+                "No operations.",
+            );
+        }
+        markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::markdown::{code, guard_row, operation_row, MAX_DATA_WIDTH};
+    use crate::parse;
+
+    #[test]
+    fn test_code() {
+        assert_eq!(code(b"key1"), "`key1`");
+        assert_eq!(code(b""), "*empty*");
+        assert_eq!(code(b"a|b"), "`a\\|b`");
+        assert_eq!(code(b"a`b"), "``a`b``");
+        assert_eq!(code(b"a``b`"), "``` a``b` ```");
+        assert_eq!(code(b"`"), "`` ` ``");
+        assert_eq!(code(b"a\nb\t"), "`a\\nb\\t`");
+        assert_eq!(
+            code(&b"v".repeat(100)),
+            format!(
+                "`{}…` *(truncated, 100 bytes)*",
+                "v".repeat(MAX_DATA_WIDTH - 1)
+            )
+        );
+    }
+
+    #[test]
+    fn test_rows_decoded() {
+        // The bytes the keys and the values stand for are shown, data holding
+        // an invalid escape sequence as written
+        let txn = parse(b"val(\"k\\x31\") = 'it\\'s'\n\nput \"\\x41\" \"\\q\"\n\n")
+            .expect("Failed to parse");
+        assert_eq!(
+            guard_row(&txn.compares[0]),
+            ["`k1`", "value", "`=`", "`it's`"]
+        );
+        assert_eq!(operation_row(&txn.success[0]), ["put", "`A`", "`\\q`"]);
+    }
+}
@@ -0,0 +1,681 @@
+//! Line grammar implemented with nom, selected by the `backend-nom` feature
+//!
+//! Only the compares and the operations are parsed here: the structure of a
+//! transaction, its directive, sections and lines, is shared by both
+//! backends. The grammar follows the elyze one quirk for quirk, e.g. a key
+//! ends at its first space, and the first alternative which parses wins even
+//! if the line doesn't end there. `tests/conformance.rs` runs against
+//! whichever backend is enabled.
+// Malformed input must be reported, never panic
+#![deny(clippy::arithmetic_side_effects)]
+
+use crate::compare::{
+    Compare, CompareParser, CreateRevision, CustomCompare, Lease, ModRevision, NumericCompare,
+    OpType, Value, Version,
+};
+use crate::data::{self, Key};
+use crate::first_line;
+use crate::operation::{
+    closing_quote, CustomData, DataKind, DeleteData, Flag, GetData, Operation, OperationParser,
+    PutData, PutFileData,
+};
+use crate::options::{ParseOptions, UnknownFlagPolicy};
+use elyze::errors::{ParseError, ParseResult};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take, take_till, take_till1, take_while};
+use nom::character::complete::{space0, u64 as decimal};
+use nom::combinator::{map, opt, value, verify};
+use nom::error::ErrorKind;
+use nom::number::complete::u8 as byte;
+use nom::{IResult, Parser};
+use std::sync::Arc;
+
+type NomResult<'a, T> = IResult<&'a [u8], T>;
+
+/// A key or a value, along with how it's written.
+type Written<'a> = (&'a [u8], DataKind);
+
+/// Reject `input`, letting the enclosing alternative try the next branch.
+fn reject<T>(input: &[u8]) -> NomResult<'_, T> {
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        ErrorKind::Verify,
+    )))
+}
+
+/// A whole line must be consumed, only whitespaces may follow the node.
+fn whole<T>((rest, item): (&[u8], T)) -> ParseResult<T> {
+    match crate::is_blank(rest) {
+        true => Ok(item),
+        false => Err(ParseError::UnexpectedToken),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Keys and values
+// ----------------------------------------------------------------------------
+
+/// A string opened and closed by the same character of `quotes`, kept as
+/// written, escapes included.
+fn quoted<'a>(input: &'a [u8], quotes: &[u8]) -> NomResult<'a, (&'a [u8], DataKind)> {
+    let (data, quote) = verify(byte, |quote| quotes.contains(quote)).parse(input)?;
+    let Some(end) = closing_quote(data, quote) else {
+        return reject(input);
+    };
+    let (rest, quoted) = take(end).parse(data)?;
+    // The closing quote
+    let (rest, _) = take(1usize).parse(rest)?;
+    Ok((rest, (quoted, DataKind::quoted_by(quote))))
+}
+
+/// A string running until the next space, which is consumed.
+fn unquoted(input: &[u8]) -> NomResult<'_, &[u8]> {
+    let (rest, data) = take_till(|byte| byte == b' ').parse(input)?;
+    let (rest, _) = opt(tag(&b" "[..])).parse(rest)?;
+    Ok((rest, data))
+}
+
+/// A key or a value quoted by one of `quotes`, or unquoted.
+fn data<'a>(input: &'a [u8], quotes: &[u8]) -> NomResult<'a, (&'a [u8], DataKind)> {
+    alt((
+        |input| quoted(input, quotes),
+        map(unquoted, |data| (data, DataKind::Unquoted)),
+    ))
+    .parse(input)
+}
+
+/// A key or a value which must end before the line feed, if any, a line feed
+/// inside a quoted string not ending the line.
+fn data_until_ln<'a>(input: &'a [u8], quotes: &[u8]) -> NomResult<'a, (&'a [u8], DataKind)> {
+    let until_ln = first_line(input, quotes);
+    let (rest, data) = data(until_ln, quotes)?;
+    // Resume right after the data, the line feed isn't consumed
+    let (rest, _) = take(until_ln.len().saturating_sub(rest.len())).parse(input)?;
+    Ok((rest, data))
+}
+
+/// A `--name` or `--name=value` flag.
+fn flag(input: &[u8]) -> NomResult<'_, Flag<'_>> {
+    let (rest, word) = unquoted(input)?;
+    match Flag::from_word(word) {
+        Some(flag) => Ok((rest, flag)),
+        None => reject(input),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Compares
+// ----------------------------------------------------------------------------
+
+fn op_type(input: &[u8]) -> NomResult<'_, OpType> {
+    alt((
+        value(OpType::Equal, tag(&b"="[..])),
+        value(OpType::GreaterThan, tag(&b">"[..])),
+        value(OpType::LessThan, tag(&b"<"[..])),
+        value(OpType::NotEqual, tag(&b"!="[..])),
+    ))
+    .parse(input)
+}
+
+/// A number with an optional `0x`, `0o` or `0b` radix prefix.
+fn radix_number(input: &[u8]) -> NomResult<'_, u64> {
+    let (radix, digits) = match input {
+        [b'0', b'x', digits @ ..] => (16, digits),
+        [b'0', b'o', digits @ ..] => (8, digits),
+        [b'0', b'b', digits @ ..] => (2, digits),
+        _ => return decimal(input),
+    };
+    let (rest, digits) = take_while(|byte: u8| byte.is_ascii_alphanumeric()).parse(digits)?;
+    match std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| u64::from_str_radix(digits, radix).ok())
+    {
+        Some(value) => Ok((rest, value)),
+        None => reject(input),
+    }
+}
+
+/// The target name of a compare, everything up to the parenthesized key.
+fn target<'a>(input: &'a [u8], is_named: impl Fn(&[u8]) -> bool) -> NomResult<'a, &'a [u8]> {
+    let (input, _) = space0(input)?;
+    verify(take_till(|byte| byte == b'('), |prefix: &[u8]| {
+        is_named(prefix.trim_ascii_end())
+    })
+    .parse(input)
+}
+
+/// A parenthesized key, the parentheses nesting. The parentheses hold a
+/// single word, the key, which blanks may follow.
+fn key<'a>(input: &'a [u8], quotes: &[u8]) -> NomResult<'a, Written<'a>> {
+    let (inner, _) = tag(&b"("[..]).parse(input)?;
+    let mut depth = 1usize;
+    let close = inner.iter().position(|&byte| {
+        match byte {
+            b'(' => depth = depth.saturating_add(1),
+            b')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        depth == 0
+    });
+    let Some(close) = close else {
+        return reject(input);
+    };
+    let (rest, inner) = take(close).parse(inner)?;
+    let (rest, _) = take(1usize).parse(rest)?;
+    let (inner, key) = data(inner, quotes)?;
+    if !inner.iter().all(|&byte| byte == b' ' || byte == b'\t') {
+        return reject(input);
+    }
+    Ok((rest, key))
+}
+
+/// A compare against a numeric target, `<name>(<key>) <op> <number>`.
+fn numeric<'a, T: NumericCompare<'a>>(
+    input: &'a [u8],
+    options: &ParseOptions,
+    number: fn(&'a [u8]) -> NomResult<'a, u64>,
+) -> NomResult<'a, T> {
+    let (input, _) = target(input, |name| {
+        T::is_named(name) || (options.allow_target_words && name == T::WORD)
+    })?;
+    let (input, (key, key_kind)) = key(input, options.quote_chars)?;
+    let (input, _) = space0(input)?;
+    let (input, op) = op_type(input)?;
+    let (input, _) = space0(input)?;
+    let (input, value) = number(input)?;
+    Ok((input, T::new(key, key_kind, op, value)))
+}
+
+/// A value compare, whose unquoted value runs until the end of the line.
+fn value_compare<'a>(input: &'a [u8], options: &ParseOptions) -> NomResult<'a, Compare<'a>> {
+    let quotes = options.quote_chars;
+    let (input, _) = target(input, |name| {
+        Value::is_named(name) || (options.allow_target_words && name == Value::WORD)
+    })?;
+    let (input, (key, key_kind)) = key(input, quotes)?;
+    let (input, _) = space0(input)?;
+    let (input, op) = op_type(input)?;
+    let (input, _) = space0(input)?;
+    let (input, (value, value_kind)) = match quoted(input, quotes) {
+        Ok((rest, quoted)) => (rest, quoted),
+        Err(_) => {
+            let value = input.trim_ascii_end();
+            let (rest, _) = take(value.len()).parse(input)?;
+            (rest, (value, DataKind::Unquoted))
+        }
+    };
+    let (input, _) = space0(input)?;
+    Ok((
+        input,
+        Compare::Value(Value {
+            key: Key(key),
+            key_kind,
+            value: data::Value(value),
+            value_kind,
+            op,
+        }),
+    ))
+}
+
+/// A line whose first word, ending where `ends_word` says, is handled by one
+/// of `parsers`. The whole line is consumed.
+fn custom<'a, P: ?Sized>(
+    input: &'a [u8],
+    parsers: &[Arc<P>],
+    name: impl Fn(&P) -> &[u8],
+    ends_word: fn(u8) -> bool,
+    parse_args: impl Fn(&P, &'a [u8]) -> ParseResult<Vec<&'a [u8]>>,
+) -> NomResult<'a, (&'a [u8], Vec<&'a [u8]>)> {
+    let (line, _) = space0(input)?;
+    let (args, word) = take_till(ends_word).parse(line)?;
+    let Some(parser) = parsers.iter().find(|parser| name(parser.as_ref()) == word) else {
+        return reject(input);
+    };
+    match parse_args(parser.as_ref(), args.trim_ascii_start()) {
+        Ok(args) => Ok((&[], (word, args))),
+        Err(_) => reject(input),
+    }
+}
+
+/// Parse a whole line as a compare operation.
+pub(crate) fn compare<'a>(line: &'a [u8], options: &ParseOptions) -> ParseResult<Compare<'a>> {
+    let number: fn(&'a [u8]) -> NomResult<'a, u64> = match options.numeric_prefixes {
+        true => radix_number,
+        false => decimal,
+    };
+    let compare = alt((
+        map(
+            |input| numeric::<ModRevision>(input, options, number),
+            Compare::ModRevision,
+        ),
+        map(
+            |input| numeric::<CreateRevision>(input, options, number),
+            Compare::CreateRevision,
+        ),
+        |input| value_compare(input, options),
+        map(
+            |input| numeric::<Version>(input, options, number),
+            Compare::Version,
+        ),
+        map(
+            |input| numeric::<Lease>(input, options, number),
+            Compare::Lease,
+        ),
+    ))
+    .parse(line);
+    let compare = match compare {
+        // The built-in targets can't be overridden
+        Err(_) if !options.compare_parsers.is_empty() => custom(
+            line,
+            &options.compare_parsers,
+            <dyn CompareParser>::target,
+            |byte| byte.is_ascii_whitespace() || byte == b'(',
+            |parser, args| parser.parse_args(args),
+        )
+        .map(|(rest, (target, args))| (rest, Compare::Custom(CustomCompare { target, args }))),
+        compare => compare,
+    };
+    whole(compare.map_err(|_| ParseError::UnexpectedToken)?)
+}
+
+// ----------------------------------------------------------------------------
+// Operations
+// ----------------------------------------------------------------------------
+
+/// The command word starting an operation.
+fn command<'a>(input: &'a [u8], name: &[u8]) -> NomResult<'a, &'a [u8]> {
+    let (input, _) = space0(input)?;
+    verify(
+        take_till1(|byte| matches!(byte, b' ' | b'\t' | b'\n' | b'\r')),
+        |command: &[u8]| command == name,
+    )
+    .parse(input)
+}
+
+/// The flags of a put operation, before its key or after its value. A lease
+/// flag whose ID isn't hexadecimal isn't a flag, but the key.
+fn put_flags<'a>(
+    mut input: &'a [u8],
+    put: &mut PutData<'a>,
+    policy: UnknownFlagPolicy,
+) -> NomResult<'a, ()> {
+    loop {
+        let (rest, flag) = opt(verify(flag, |flag: &Flag| {
+            flag.name != b"lease" || flag.lease().is_some()
+        }))
+        .parse(input)?;
+        let (rest, _) = space0(rest)?;
+        input = rest;
+        let Some(flag) = flag else {
+            return Ok((input, ()));
+        };
+        match flag.lease() {
+            Some(lease) if put.lease.is_none() => put.lease = Some(lease),
+            Some(_) => return reject(input),
+            None => {
+                if flag.unknown(policy, &mut put.unknown_flags).is_err() {
+                    return reject(input);
+                }
+            }
+        }
+    }
+}
+
+/// A `key=value` argument, split on its first unquoted `=`, if nothing but
+/// flags follows it.
+fn assignment<'a>(input: &'a [u8], quotes: &[u8]) -> NomResult<'a, (Written<'a>, Written<'a>)> {
+    let (input, key) = alt((
+        |input| quoted(input, quotes),
+        map(
+            verify(
+                take_till(|byte| matches!(byte, b' ' | b'=')),
+                |key: &[u8]| !key.is_empty(),
+            ),
+            |key| (key, DataKind::Unquoted),
+        ),
+    ))
+    .parse(input)?;
+    let (input, _) = tag(&b"="[..]).parse(input)?;
+    let (input, value) = data(input, quotes)?;
+    // A second argument makes it the two arguments form
+    if !input
+        .split(u8::is_ascii_whitespace)
+        .all(|word| word.is_empty() || Flag::from_word(word).is_some())
+    {
+        return reject(input);
+    }
+    Ok((input, (key, value)))
+}
+
+fn put<'a>(
+    input: &'a [u8],
+    quotes: &[u8],
+    policy: UnknownFlagPolicy,
+    assignments: bool,
+) -> NomResult<'a, PutData<'a>> {
+    let (input, _) = command(input, b"put")?;
+    let (input, _) = space0(input)?;
+    let mut put = PutData {
+        key: Key(b""),
+        value: data::Value(b""),
+        lease: None,
+        key_kind: DataKind::default(),
+        value_kind: DataKind::default(),
+        unknown_flags: vec![],
+    };
+    let (input, ()) = put_flags(input, &mut put, policy)?;
+    let assigned = match assignments {
+        true => opt(|input| assignment(input, quotes)).parse(input)?,
+        false => (input, None),
+    };
+    let (input, ((key, key_kind), (value, value_kind))) = match assigned {
+        (input, Some(assignment)) => (input, assignment),
+        (input, None) => {
+            let (input, key) = data(input, quotes)?;
+            let (input, _) = space0(input)?;
+            let (input, value) = data(input, quotes)?;
+            (input, (key, value))
+        }
+    };
+    let (input, _) = space0(input)?;
+    let (input, ()) = put_flags(input, &mut put, policy)?;
+    put.key = Key(key);
+    put.key_kind = key_kind;
+    put.value = data::Value(value);
+    put.value_kind = value_kind;
+    Ok((input, put))
+}
+
+fn put_file<'a>(input: &'a [u8], quotes: &[u8]) -> NomResult<'a, PutFileData<'a>> {
+    let (input, _) = command(input, b"put")?;
+    let (input, _) = space0(input)?;
+    let (input, (key, key_kind)) = data(input, quotes)?;
+    let (input, _) = space0(input)?;
+    // A quoted value is never a file reference
+    let (rest, word) = unquoted(input)?;
+    let Some(path) = word.strip_prefix(b"@").filter(|path| !path.is_empty()) else {
+        return reject(input);
+    };
+    let (rest, _) = space0(rest)?;
+    Ok((
+        rest,
+        PutFileData {
+            key: Key(key),
+            key_kind,
+            path,
+        },
+    ))
+}
+
+fn delete<'a>(input: &'a [u8], quotes: &[u8]) -> NomResult<'a, DeleteData<'a>> {
+    let (input, _) = command(input, b"del")?;
+    let (input, _) = space0(input)?;
+    let (input, (key, key_kind)) = data_until_ln(input, quotes)?;
+    let (input, _) = space0(input)?;
+    Ok((
+        input,
+        DeleteData {
+            key: Key(key),
+            key_kind,
+        },
+    ))
+}
+
+/// A get operation, its flags around its key.
+fn get<'a>(
+    input: &'a [u8],
+    quotes: &[u8],
+    policy: UnknownFlagPolicy,
+) -> NomResult<'a, GetData<'a>> {
+    let (mut input, _) = command(input, b"get")?;
+    let mut get = GetData::new(b"");
+    let mut key = None;
+    loop {
+        (input, _) = space0(input)?;
+        if let Ok((rest, flag)) = flag(input) {
+            if get.apply(flag, policy).is_err() {
+                return reject(input);
+            }
+            input = rest;
+            continue;
+        }
+        let until_ln = first_line(input, quotes);
+        if key.is_some() && (get.range_end.is_some() || crate::is_blank(until_ln)) {
+            break;
+        }
+        let (rest, data) = data_until_ln(input, quotes)?;
+        match key {
+            None => key = Some(data),
+            Some(_) => {
+                get.range_end = Some(Key(data.0));
+                get.range_end_kind = data.1;
+            }
+        }
+        input = rest;
+    }
+    if let Some((key, key_kind)) = key {
+        get.key = Key(key);
+        get.key_kind = key_kind;
+    }
+    if get.check_range().is_err() {
+        return reject(input);
+    }
+    Ok((input, get))
+}
+
+/// Parse a whole line as an operation.
+pub(crate) fn operation<'a>(line: &'a [u8], options: &ParseOptions) -> ParseResult<Operation<'a>> {
+    let quotes = options.quote_chars;
+    let policy = options.unknown_flag;
+    let operation = alt((
+        map(
+            |input| match options.allow_file_refs {
+                true => put_file(input, quotes),
+                false => reject(input),
+            },
+            Operation::PutFile,
+        ),
+        map(
+            |input| put(input, quotes, policy, options.allow_assignments),
+            Operation::Put,
+        ),
+        map(|input| delete(input, quotes), Operation::Delete),
+        map(|input| get(input, quotes, policy), Operation::Get),
+        map(
+            |input| match options.allow_extensions {
+                true => command(input, b"noop"),
+                false => reject(input),
+            },
+            |_| Operation::Noop,
+        ),
+        // The built-in operations can't be overridden
+        map(
+            |input| {
+                custom(
+                    input,
+                    &options.operation_parsers,
+                    <dyn OperationParser>::command,
+                    |byte| byte.is_ascii_whitespace(),
+                    |parser, args| parser.parse_args(args),
+                )
+            },
+            |(command, args)| Operation::Custom(CustomData { command, args }),
+        ),
+    ))
+    .parse(line);
+    whole(operation.map_err(|_| ParseError::UnexpectedToken)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::{Compare, CompareParser};
+    use crate::operation::{Operation, OperationParser};
+    use crate::options::{ParseOptions, UnknownFlagPolicy};
+    use crate::{parse_line, parse_line_elyze};
+    use elyze::errors::{ParseError, ParseResult};
+    use std::sync::Arc;
+
+    /// The words the lines of the corpus start with.
+    const HEADS: &[&[u8]] = &[
+        b"put", b"get", b"del", b"mod", b"m", b"create", b"c", b"val", b"value", b"ver",
+        b"version", b"lease", b"CREATE", b"MOD", b"VALUE", b"VERSION", b"LEASE", b"locked",
+        b"watch", b"noop", b" ", b"\t",
+    ];
+
+    /// The pieces the rest of the lines are made of, around the edges of the
+    /// grammar.
+    const PIECES: &[&[u8]] = &[
+        b" ",
+        b"  ",
+        b"\t",
+        b"\n",
+        b"\r",
+        b"(",
+        b")",
+        b"\"",
+        b"'",
+        b"`",
+        b"\\",
+        b"=",
+        b">",
+        b"<",
+        b"!=",
+        b"0",
+        b"42",
+        b"0x1f",
+        b"0o7",
+        b"0b",
+        b"18446744073709551616",
+        b"--lease=1f",
+        b"--lease=xyz",
+        b"--lease=",
+        b"--prefix",
+        b"--prefix=1",
+        b"--from-key",
+        b"--serializable",
+        b"--consistency=s",
+        b"--consistency=x",
+        b"--min-mod-rev=3",
+        b"--max-create-rev=x",
+        b"--weird",
+        b"--",
+        b"@",
+        b"@path",
+        b"key",
+        b"k=v",
+        b"value 1",
+        b"\xff",
+        "é".as_bytes(),
+    ];
+
+    /// Split the arguments of a custom node on whitespaces, rejecting none.
+    fn words(args: &[u8]) -> ParseResult<Vec<&[u8]>> {
+        match args
+            .split(u8::is_ascii_whitespace)
+            .filter(|arg| !arg.is_empty())
+            .collect::<Vec<_>>()
+        {
+            words if words.is_empty() => Err(ParseError::UnexpectedToken),
+            words => Ok(words),
+        }
+    }
+
+    #[derive(Debug)]
+    struct Locked;
+
+    impl CompareParser for Locked {
+        fn target(&self) -> &[u8] {
+            b"locked"
+        }
+
+        fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+            words(args)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Watch;
+
+    impl OperationParser for Watch {
+        fn command(&self) -> &[u8] {
+            b"watch"
+        }
+
+        fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+            words(args)
+        }
+    }
+
+    /// Generate the lines of the corpus, from a fixed seed so that a failure
+    /// reproduces.
+    fn corpus(len: usize) -> Vec<Vec<u8>> {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = |bound: usize| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state.checked_rem(bound as u64).unwrap_or_default() as usize
+        };
+        (0..len)
+            .map(|_| {
+                let mut line = HEADS
+                    .get(next(HEADS.len()))
+                    .copied()
+                    .unwrap_or_default()
+                    .to_vec();
+                for _ in 0..next(10) {
+                    line.extend_from_slice(
+                        PIECES.get(next(PIECES.len())).copied().unwrap_or_default(),
+                    );
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// Both backends parse the corpus the same way, under each set of options.
+    #[test]
+    fn test_same_as_elyze() {
+        let options = [
+            ParseOptions::default(),
+            ParseOptions {
+                numeric_prefixes: true,
+                allow_assignments: true,
+                allow_target_words: true,
+                allow_file_refs: true,
+                allow_extensions: true,
+                quote_chars: b"\"'`",
+                unknown_flag: UnknownFlagPolicy::Capture,
+                ..Default::default()
+            },
+            ParseOptions {
+                unknown_flag: UnknownFlagPolicy::Ignore,
+                compare_parsers: vec![Arc::new(Locked)],
+                operation_parsers: vec![Arc::new(Watch)],
+                ..Default::default()
+            },
+        ];
+        for line in corpus(20_000) {
+            for options in &options {
+                let nom: Option<Compare> = parse_line(&line, options).ok();
+                let elyze: Option<Compare> = parse_line_elyze(&line, options).ok();
+                assert_eq!(
+                    format!("{nom:?}"),
+                    format!("{elyze:?}"),
+                    "{:?}",
+                    String::from_utf8_lossy(&line)
+                );
+
+                let nom: Option<Operation> = parse_line(&line, options).ok();
+                let elyze: Option<Operation> = parse_line_elyze(&line, options).ok();
+                assert_eq!(
+                    format!("{nom:?}"),
+                    format!("{elyze:?}"),
+                    "{:?}",
+                    String::from_utf8_lossy(&line)
+                );
+            }
+        }
+    }
+}
@@ -1,27 +1,133 @@
 //! Transactional operations
+// Malformed input must be reported, never panic
+#![deny(clippy::arithmetic_side_effects)]
 
+use crate::data::{Key, Value};
+use crate::escape::{decode, same_data};
+use crate::keyrange::{from_key_range_end, KeyRange};
+use crate::options::{ParseOptions, UnknownFlagPolicy, DEFAULT_QUOTE_CHARS};
+use crate::{first_line, OptionalBlanks};
 use elyze::acceptor::Acceptor;
-use elyze::bytes::components::groups::GroupKind;
-use elyze::bytes::primitives::string::DataString;
-use elyze::bytes::primitives::whitespace::OptionalWhitespaces;
 use elyze::bytes::token::Token;
 use elyze::errors::{ParseError, ParseResult};
-use elyze::peek::{peek, UntilEnd};
-use elyze::peeker::Peeker;
+use elyze::peek::{peek, Peeking, UntilEnd};
 use elyze::scanner::Scanner;
 use elyze::visitor::Visitor;
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+
+/// Peek until the token, or until the end of the input if it isn't found.
+///
+/// Unlike a `Peeker`, the peekables aren't boxed: nothing is allocated.
+fn peek_until<'a>(token: Token, scanner: &mut Scanner<'a, u8>) -> ParseResult<Peeking<'a, u8>> {
+    match peek(token, scanner)? {
+        Some(peeked) => Ok(peeked),
+        None => peek(UntilEnd::default(), scanner)?.ok_or(ParseError::UnexpectedToken),
+    }
+}
+
+/// Peek until the end of the line, a line feed inside a string quoted by
+/// `quotes` not ending it.
+fn peek_until_ln<'a>(scanner: &mut Scanner<'a, u8>, quotes: &[u8]) -> ParseResult<&'a [u8]> {
+    let rest = peek(UntilEnd::default(), scanner)?
+        .map(|rest| rest.peeked_slice())
+        .unwrap_or_default();
+    Ok(first_line(rest, quotes))
+}
+
+/// Run `accept` on the rest of the input, advancing the scanner only if it
+/// succeeds, the way an `Acceptor` tries its alternatives.
+pub(crate) fn attempt<'a, T>(
+    scanner: &mut Scanner<'a, u8>,
+    accept: impl FnOnce(&mut Scanner<'a, u8>) -> ParseResult<T>,
+) -> ParseResult<Option<T>> {
+    let rest = peek(UntilEnd::default(), scanner)?.map_or(&[][..], |rest| rest.peeked_slice());
+    let mut fork = Scanner::new(rest);
+    match accept(&mut fork) {
+        Ok(item) => {
+            scanner.bump_by(fork.current_position());
+            Ok(Some(item))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Accept the command starting an operation, a word running until the next
+/// whitespace which must be `name`: `del--prefix` isn't a `del` command.
+fn accept_command(scanner: &mut Scanner<'_, u8>, name: &str) -> ParseResult<()> {
+    let rest = peek(UntilEnd::default(), scanner)?.map_or(&[][..], |rest| rest.peeked_slice());
+    let len = rest
+        .iter()
+        .position(|byte| matches!(byte, b' ' | b'\t' | b'\n' | b'\r'))
+        .unwrap_or(rest.len());
+    if rest.get(..len) != Some(name.as_bytes()) {
+        return Err(ParseError::UnexpectedToken);
+    }
+    scanner.bump_by(len);
+    Ok(())
+}
 
 // ----------------------------------------------------------------------------
 // QuotedString
 // ----------------------------------------------------------------------------
 
-struct QuotedString<'a>(&'a [u8]);
+/// A string between double or single quotes, or one of the configured quote
+/// characters, which may span lines.
+///
+/// A backslash escapes the byte following it, which doesn't close the string.
+/// Between single quotes, as in a shell, it only escapes a single quote. The
+/// string is kept as written, escapes included.
+struct QuotedString<'a>(&'a [u8], DataKind);
+
+/// The position of the closing `quote`, `data` following the opening one.
+///
+/// Jumps between the quote and backslash candidates found by `memchr`, long
+/// values such as certificates are scanned at memory bandwidth rather than
+/// byte by byte.
+pub(crate) fn closing_quote(data: &[u8], quote: u8) -> Option<usize> {
+    let mut position = 0usize;
+    loop {
+        let found = position.checked_add(memchr::memchr2(quote, b'\\', data.get(position..)?)?)?;
+        if data.get(found) == Some(&quote) {
+            return Some(found);
+        }
+        let next = found.checked_add(1)?;
+        position = match (quote, data.get(next)) {
+            // A lone backslash between single quotes
+            (b'\'', byte) if byte != Some(&b'\'') => next,
+            // Skip the escaped byte
+            _ => next.checked_add(1)?,
+        };
+    }
+}
+
+impl<'a> QuotedString<'a> {
+    /// Parse a string opened and closed by the same character of `quotes`.
+    fn accept_with(scanner: &mut Scanner<'a, u8>, quotes: &[u8]) -> ParseResult<Self> {
+        let rest = peek(UntilEnd::default(), scanner)?
+            .ok_or(ParseError::UnexpectedToken)?
+            .peeked_slice();
+        let (&quote, data) = rest
+            .split_first()
+            .filter(|(quote, _)| quotes.contains(quote))
+            .ok_or(ParseError::UnexpectedToken)?;
+        let quoted = closing_quote(data, quote)
+            .and_then(|end| data.get(..end))
+            .ok_or(ParseError::UnexpectedToken)?;
+        // Both quotes are consumed
+        scanner.bump_by(
+            quoted
+                .len()
+                .checked_add(2)
+                .ok_or(ParseError::UnexpectedToken)?,
+        );
+        Ok(QuotedString(quoted, DataKind::quoted_by(quote)))
+    }
+}
 
 impl<'a> Visitor<'a, u8> for QuotedString<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        let peeked = peek(GroupKind::DoubleQuotes, scanner)?.ok_or(ParseError::UnexpectedToken)?;
-        scanner.bump_by(peeked.end_slice);
-        Ok(QuotedString(peeked.peeked_slice()))
+        QuotedString::accept_with(scanner, DEFAULT_QUOTE_CHARS)
     }
 }
 
@@ -33,14 +139,7 @@ struct UnquotedString<'a>(&'a [u8]);
 
 impl<'a> Visitor<'a, u8> for UnquotedString<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        let peeked = {
-            let peeked = Peeker::new(scanner)
-                .add_peekable(Token::Whitespace)
-                .add_peekable(UntilEnd::default())
-                .peek()?
-                .ok_or(ParseError::UnexpectedToken)?;
-            peeked
-        };
+        let peeked = peek_until(Token::Whitespace, scanner)?;
 
         scanner.bump_by(peeked.end_slice);
         Ok(UnquotedString(peeked.peeked_slice()))
@@ -51,18 +150,197 @@ impl<'a> Visitor<'a, u8> for UnquotedString<'a> {
 // Data
 //----------------------------------------------------------------------------
 
+/// How a key or a value is written, which tells how to decode it, see
+/// [`decode`](crate::escape::decode).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DataKind {
+    /// Between double quotes.
+    Quoted,
+    /// Running until the next whitespace.
+    Unquoted,
+    /// Between single quotes, `\'` being the only escape sequence.
+    SingleQuoted,
+    /// Not written but given as the bytes themselves, e.g. to a constructor
+    /// such as [`Operation::put`]. The writer picks how to write them.
+    #[default]
+    Bytes,
+}
+
+impl DataKind {
+    /// The kind of a string opened by `quote`.
+    pub(crate) fn quoted_by(quote: u8) -> Self {
+        match quote {
+            b'\'' => DataKind::SingleQuoted,
+            _ => DataKind::Quoted,
+        }
+    }
+}
+
+/// A key or a value, either quoted or running until the next whitespace.
+///
+/// All forms are binary safe: NUL and the other control bytes are kept as
+/// is, only the opening quote ends a quoted string and only a space, a tab or
+/// a line feed ends an unquoted one.
 pub struct Data<'a> {
     pub(crate) data: &'a [u8],
+    pub(crate) kind: DataKind,
+}
+
+impl<'a> Data<'a> {
+    /// The bytes of the key or the value, without the quotes.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// How the key or the value is written.
+    pub fn kind(&self) -> DataKind {
+        self.kind
+    }
+}
+
+impl<'a> Data<'a> {
+    /// Parse a key or a value quoted by one of `quotes`, or unquoted.
+    pub(crate) fn accept_with(scanner: &mut Scanner<'a, u8>, quotes: &[u8]) -> ParseResult<Self> {
+        // A quoted string only advances the scanner once fully read
+        if let Ok(quoted) = QuotedString::accept_with(scanner, quotes) {
+            return Ok(Data {
+                data: quoted.0,
+                kind: quoted.1,
+            });
+        }
+        Ok(Data {
+            data: UnquotedString::accept(scanner)?.0,
+            kind: DataKind::Unquoted,
+        })
+    }
 }
 
 impl<'a> Visitor<'a, u8> for Data<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        let accepted = Acceptor::new(scanner)
-            .try_or(|x: QuotedString| x.0)?
-            .try_or(|x: UnquotedString| x.0)?
-            .finish()
-            .ok_or(ParseError::UnexpectedToken)?;
-        Ok(Data { data: accepted })
+        Data::accept_with(scanner, DEFAULT_QUOTE_CHARS)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Flags
+// ----------------------------------------------------------------------------
+
+/// A `--name` or `--name=value` flag of an operation.
+pub(crate) struct Flag<'a> {
+    pub(crate) name: &'a [u8],
+    pub(crate) value: Option<&'a [u8]>,
+}
+
+impl<'a> Flag<'a> {
+    /// Read a whitespace delimited word as a flag, `None` unless it starts
+    /// with `--` followed by a name.
+    pub(crate) fn from_word(word: &'a [u8]) -> Option<Self> {
+        let flag = word.strip_prefix(b"--").filter(|flag| !flag.is_empty())?;
+        let mut parts = flag.splitn(2, |&byte| byte == b'=');
+        Some(Flag {
+            name: parts.next().unwrap_or_default(),
+            value: parts.next(),
+        })
+    }
+}
+
+impl<'a> Visitor<'a, u8> for Flag<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        Flag::from_word(UnquotedString::accept(scanner)?.0).ok_or(ParseError::UnexpectedToken)
+    }
+}
+
+impl Flag<'_> {
+    /// Apply `policy` to the flag, which the operation doesn't know.
+    pub(crate) fn unknown(
+        &self,
+        policy: UnknownFlagPolicy,
+        unknown_flags: &mut Vec<(String, Option<String>)>,
+    ) -> ParseResult<()> {
+        match policy {
+            UnknownFlagPolicy::Error => Err(ParseError::UnexpectedToken),
+            UnknownFlagPolicy::Ignore => Ok(()),
+            UnknownFlagPolicy::Capture => {
+                let text = |data: &[u8]| String::from_utf8_lossy(data).into_owned();
+                unknown_flags.push((text(self.name), self.value.map(text)));
+                Ok(())
+            }
+        }
+    }
+
+    /// The value of the flag as a decimal revision.
+    fn revision(&self) -> ParseResult<i64> {
+        self.value
+            .and_then(|value| std::str::from_utf8(value).ok())
+            .and_then(|value| value.parse().ok())
+            .ok_or(ParseError::UnexpectedToken)
+    }
+
+    /// The ID of a `--lease=<id>` flag, `None` for another flag or an ID
+    /// which isn't hexadecimal.
+    pub(crate) fn lease(&self) -> Option<u64> {
+        let id = Some(self)
+            .filter(|flag| flag.name == b"lease")
+            .and_then(|flag| flag.value)
+            .filter(|id| !id.is_empty() && id.iter().all(u8::is_ascii_hexdigit))?;
+        std::str::from_utf8(id)
+            .ok()
+            .and_then(|id| u64::from_str_radix(id, 16).ok())
+    }
+}
+
+/// The `--lease=<id>` flag of a put operation, the ID being hexadecimal as
+/// printed by `etcdctl lease grant`.
+struct LeaseFlag(u64);
+
+impl<'a> Visitor<'a, u8> for LeaseFlag {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        Flag::accept(scanner)?
+            .lease()
+            .map(LeaseFlag)
+            .ok_or(ParseError::UnexpectedToken)
+    }
+}
+
+/// A flag other than `--lease`, which a put operation doesn't know.
+struct OtherFlag<'a>(Flag<'a>);
+
+impl<'a> Visitor<'a, u8> for OtherFlag<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        match Flag::accept(scanner)? {
+            flag if flag.name == b"lease" => Err(ParseError::UnexpectedToken),
+            flag => Ok(OtherFlag(flag)),
+        }
+    }
+}
+
+/// A flag of a put operation.
+enum PutFlag<'a> {
+    Lease(u64),
+    Unknown(Flag<'a>),
+}
+
+/// Parse the flags of a put operation, before its key or after its value.
+///
+/// A lease flag whose ID isn't hexadecimal isn't a flag, but the key.
+fn accept_put_flags<'a>(
+    scanner: &mut Scanner<'a, u8>,
+    put: &mut PutData<'a>,
+    policy: UnknownFlagPolicy,
+) -> ParseResult<()> {
+    loop {
+        let flag = Acceptor::new(scanner)
+            .try_or(|flag: LeaseFlag| PutFlag::Lease(flag.0))?
+            .try_or(|flag: OtherFlag<'a>| PutFlag::Unknown(flag.0))?
+            .finish();
+        OptionalBlanks::accept(scanner)?;
+        match flag {
+            Some(PutFlag::Lease(lease)) if put.lease.is_none() => put.lease = Some(lease),
+            Some(PutFlag::Lease(_)) => return Err(ParseError::UnexpectedToken),
+            Some(PutFlag::Unknown(flag)) => flag.unknown(policy, &mut put.unknown_flags)?,
+            None => return Ok(()),
+        }
     }
 }
 
@@ -70,28 +348,232 @@ impl<'a> Visitor<'a, u8> for Data<'a> {
 // Put Operation
 // ----------------------------------------------------------------------------
 
-/// A put operation.
-#[derive(Debug, PartialEq)]
+/// A put operation, written `put [--lease=<id>] key value`, or `put key=value`
+/// with [`ParseOptions::allow_assignments`].
+///
+/// The lease flag may also follow the value. How the key and the value are
+/// written doesn't take part in equality, the bytes they stand for do, see
+/// [`PutData::decoded_key`].
+#[derive(Debug)]
 pub struct PutData<'a> {
     /// The key to put.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
     /// The value to put.
-    pub value: &'a [u8],
+    pub value: Value<'a>,
+    /// The lease to attach the key to.
+    pub lease: Option<u64>,
+    /// How the key is written.
+    pub key_kind: DataKind,
+    /// How the value is written.
+    pub value_kind: DataKind,
+    /// The flags the parser doesn't know, along with their value, see
+    /// [`UnknownFlagPolicy::Capture`].
+    pub unknown_flags: Vec<(String, Option<String>)>,
+}
+
+impl PartialEq for PutData<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        same_data((&self.key, self.key_kind), (&other.key, other.key_kind))
+            && same_data(
+                (&self.value, self.value_kind),
+                (&other.value, other.value_kind),
+            )
+            && self.lease == other.lease
+            && self.unknown_flags == other.unknown_flags
+    }
+}
+
+impl<'a> PutData<'a> {
+    /// The key with its escape sequences decoded when quoted, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_key(&self) -> ParseResult<Cow<'a, [u8]>> {
+        decode(self.key.as_bytes(), self.key_kind)
+    }
+
+    /// The value with its escape sequences decoded when quoted, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_value(&self) -> ParseResult<Cow<'a, [u8]>> {
+        decode(self.value.as_bytes(), self.value_kind)
+    }
+}
+
+/// Parse a `key=value` argument, split on its first unquoted `=`, if nothing
+/// but flags follows it.
+///
+/// A key holding a `=` is quoted, e.g. `"a=b"=c`, a value may hold any.
+fn accept_assignment<'a>(
+    scanner: &mut Scanner<'a, u8>,
+    quotes: &[u8],
+) -> ParseResult<(Data<'a>, Data<'a>)> {
+    let key = match QuotedString::accept_with(scanner, quotes) {
+        Ok(quoted) => Data {
+            data: quoted.0,
+            kind: quoted.1,
+        },
+        Err(_) => {
+            let word = peek_until(Token::Whitespace, scanner)?.peeked_slice();
+            let key = word
+                .iter()
+                .position(|&byte| byte == b'=')
+                .and_then(|end| word.get(..end))
+                .filter(|key| !key.is_empty())
+                .ok_or(ParseError::UnexpectedToken)?;
+            scanner.bump_by(key.len());
+            Data {
+                data: key,
+                kind: DataKind::Unquoted,
+            }
+        }
+    };
+    let rest = peek(UntilEnd::default(), scanner)?
+        .map(|rest| rest.peeked_slice())
+        .unwrap_or_default();
+    if !rest.starts_with(b"=") {
+        return Err(ParseError::UnexpectedToken);
+    }
+    scanner.bump_by(1);
+    let value = Data::accept_with(scanner, quotes)?;
+
+    // A second argument makes it the two arguments form
+    let rest = peek(UntilEnd::default(), scanner)?
+        .map(|rest| rest.peeked_slice())
+        .unwrap_or_default();
+    if !rest
+        .split(u8::is_ascii_whitespace)
+        .all(|word| word.is_empty() || Flag::from_word(word).is_some())
+    {
+        return Err(ParseError::UnexpectedToken);
+    }
+    Ok((key, value))
+}
+
+impl<'a> PutData<'a> {
+    /// Parse a put operation whose key and value may be quoted by `quotes`,
+    /// applying `unknown_flag` to the flags it doesn't know. The key and the
+    /// value may be written `key=value` if `assignments` is set.
+    pub(crate) fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        quotes: &[u8],
+        unknown_flag: UnknownFlagPolicy,
+        assignments: bool,
+    ) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
+        accept_command(scanner, "put")?;
+        OptionalBlanks::accept(scanner)?;
+
+        // The flags are applied as they come, the key and the value are
+        // filled in once read
+        let mut put = PutData {
+            key: Key(b""),
+            value: Value(b""),
+            lease: None,
+            key_kind: DataKind::default(),
+            value_kind: DataKind::default(),
+            unknown_flags: vec![],
+        };
+        accept_put_flags(scanner, &mut put, unknown_flag)?;
+        let assignment = match assignments {
+            true => attempt(scanner, |scanner| accept_assignment(scanner, quotes))?,
+            false => None,
+        };
+        let (key, value) = match assignment {
+            Some(assignment) => assignment,
+            None => {
+                let key = Data::accept_with(scanner, quotes)?;
+                OptionalBlanks::accept(scanner)?;
+                (key, Data::accept_with(scanner, quotes)?)
+            }
+        };
+        OptionalBlanks::accept(scanner)?;
+        accept_put_flags(scanner, &mut put, unknown_flag)?;
+        put.key = Key(key.data);
+        put.key_kind = key.kind;
+        put.value = Value(value.data);
+        put.value_kind = value.kind;
+        Ok(put)
+    }
 }
 
 impl<'a> Visitor<'a, u8> for PutData<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
-        let command = DataString::<&str>::accept(scanner)?.0;
-        if command != "put" {
-            return Err(ParseError::UnexpectedToken);
-        }
-        OptionalWhitespaces::accept(scanner)?;
-        let key = Data::accept(scanner)?.data;
-        OptionalWhitespaces::accept(scanner)?;
-        let value = Data::accept(scanner)?.data;
-        OptionalWhitespaces::accept(scanner)?;
-        Ok(PutData { key, value })
+        PutData::accept_with(
+            scanner,
+            DEFAULT_QUOTE_CHARS,
+            UnknownFlagPolicy::default(),
+            false,
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Put File Operation
+// ----------------------------------------------------------------------------
+
+/// A put operation whose value is read from a file, written `put key @path`.
+///
+/// How the key is written doesn't take part in equality, the bytes it stands
+/// for do.
+#[derive(Debug)]
+pub struct PutFileData<'a> {
+    /// The key to put.
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
+    /// The path of the file holding the value.
+    pub path: &'a [u8],
+}
+
+impl PartialEq for PutFileData<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        same_data((&self.key, self.key_kind), (&other.key, other.key_kind))
+            && self.path == other.path
+    }
+}
+
+impl<'a> PutFileData<'a> {
+    /// The key with its escape sequences decoded when quoted, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_key(&self) -> ParseResult<Cow<'a, [u8]>> {
+        decode(self.key.as_bytes(), self.key_kind)
+    }
+
+    /// Parse a file reference whose key may be quoted by `quotes`.
+    pub(crate) fn accept_with(scanner: &mut Scanner<'a, u8>, quotes: &[u8]) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
+        accept_command(scanner, "put")?;
+        OptionalBlanks::accept(scanner)?;
+        let key = Data::accept_with(scanner, quotes)?;
+        OptionalBlanks::accept(scanner)?;
+        // A quoted value is never a file reference
+        let path = UnquotedString::accept(scanner)?
+            .0
+            .strip_prefix(b"@")
+            .filter(|path| !path.is_empty())
+            .ok_or(ParseError::UnexpectedToken)?;
+        OptionalBlanks::accept(scanner)?;
+        Ok(PutFileData {
+            key: Key(key.data),
+            key_kind: key.kind,
+            path,
+        })
+    }
+}
+
+impl<'a> Visitor<'a, u8> for PutFileData<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        PutFileData::accept_with(scanner, DEFAULT_QUOTE_CHARS)
     }
 }
 
@@ -100,32 +582,70 @@ impl<'a> Visitor<'a, u8> for PutData<'a> {
 // ----------------------------------------------------------------------------
 
 /// A delete operation.
-#[derive(Debug, PartialEq)]
+///
+/// How the key is written doesn't take part in equality, the bytes it stands
+/// for do.
+#[derive(Debug)]
 pub struct DeleteData<'a> {
     /// The key to delete.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
 }
 
-impl<'a> Visitor<'a, u8> for DeleteData<'a> {
-    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
-        let command = DataString::<&str>::accept(scanner)?.0;
-        if command != "del" {
-            return Err(ParseError::UnexpectedToken);
-        }
-        OptionalWhitespaces::accept(scanner)?;
-        let until_ln = Peeker::new(scanner)
-            .add_peekable(Token::Ln)
-            .add_peekable(UntilEnd::default())
-            .peek()?
-            .ok_or(ParseError::UnexpectedToken)?;
-        let mut scanner_until_ln = Scanner::new(until_ln.peeked_slice());
+impl PartialEq for DeleteData<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        same_data((&self.key, self.key_kind), (&other.key, other.key_kind))
+    }
+}
 
-        let key = Data::accept(&mut scanner_until_ln)?.data;
+impl<'a> DeleteData<'a> {
+    /// The key with its escape sequences decoded when quoted, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_key(&self) -> ParseResult<Cow<'a, [u8]>> {
+        decode(self.key.as_bytes(), self.key_kind)
+    }
+}
+
+impl<'a> DeleteData<'a> {
+    /// The keys deleted by the operation, the key decoded, see
+    /// [`DeleteData::decoded_key`].
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn effective_range(&self) -> ParseResult<KeyRange<'a>> {
+        Ok(KeyRange::single(self.decoded_key()?))
+    }
+}
+
+impl<'a> DeleteData<'a> {
+    /// Parse a delete operation whose key may be quoted by `quotes`.
+    pub(crate) fn accept_with(scanner: &mut Scanner<'a, u8>, quotes: &[u8]) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
+        accept_command(scanner, "del")?;
+        OptionalBlanks::accept(scanner)?;
+        let until_ln = peek_until_ln(scanner, quotes)?;
+        let mut scanner_until_ln = Scanner::new(until_ln);
+
+        let key = Data::accept_with(&mut scanner_until_ln, quotes)?;
         scanner.bump_by(scanner_until_ln.current_position());
-        OptionalWhitespaces::accept(scanner)?;
+        OptionalBlanks::accept(scanner)?;
 
-        Ok(DeleteData { key })
+        Ok(DeleteData {
+            key: Key(key.data),
+            key_kind: key.kind,
+        })
+    }
+}
+
+impl<'a> Visitor<'a, u8> for DeleteData<'a> {
+    fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
+        DeleteData::accept_with(scanner, DEFAULT_QUOTE_CHARS)
     }
 }
 
@@ -133,44 +653,371 @@ impl<'a> Visitor<'a, u8> for DeleteData<'a> {
 // Get Operation
 // ----------------------------------------------------------------------------
 
-/// A get operation.
-#[derive(Debug, PartialEq)]
+/// The consistency of a get operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Consistency {
+    /// The read goes through the consensus, `--consistency=l`.
+    #[default]
+    Linearizable,
+    /// The read is served by the local data of the member, which may be
+    /// stale, `--consistency=s` or `--serializable`.
+    Serializable,
+}
+
+/// A get operation, written `get [flags] key [range_end]`.
+///
+/// The flags may also follow the key, the revision filters are written
+/// `--min-mod-rev=<revision>`. How the key and the range end are written
+/// doesn't take part in equality, the bytes they stand for do.
+#[derive(Debug)]
 pub struct GetData<'a> {
     /// The key to get.
-    pub key: &'a [u8],
+    pub key: Key<'a>,
+    /// How the key is written.
+    pub key_kind: DataKind,
+    /// Get the keys starting with the key, `--prefix`.
+    pub prefix: bool,
+    /// Get the keys from the key on, `--from-key`.
+    pub from_key: bool,
+    /// The end of the range of keys to get, excluded, written after the key.
+    pub range_end: Option<Key<'a>>,
+    /// How the range end is written.
+    pub range_end_kind: DataKind,
+    /// Only get the keys created at or after this revision, `--min-create-rev`.
+    pub min_create_rev: Option<i64>,
+    /// Only get the keys created at or before this revision, `--max-create-rev`.
+    pub max_create_rev: Option<i64>,
+    /// Only get the keys modified at or after this revision, `--min-mod-rev`.
+    pub min_mod_rev: Option<i64>,
+    /// Only get the keys modified at or before this revision, `--max-mod-rev`.
+    pub max_mod_rev: Option<i64>,
+    /// The consistency of the read, `--consistency` or `--serializable`,
+    /// linearizable as with etcdctl when `None`.
+    pub consistency: Option<Consistency>,
+    /// The flags the parser doesn't know, along with their value, see
+    /// [`UnknownFlagPolicy::Capture`].
+    pub unknown_flags: Vec<(String, Option<String>)>,
+}
+
+impl PartialEq for GetData<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let same_range_end = match (self.range_end, other.range_end) {
+            (Some(range_end), Some(other_range_end)) => same_data(
+                (&range_end, self.range_end_kind),
+                (&other_range_end, other.range_end_kind),
+            ),
+            (range_end, other_range_end) => range_end.is_none() && other_range_end.is_none(),
+        };
+        same_data((&self.key, self.key_kind), (&other.key, other.key_kind))
+            && self.prefix == other.prefix
+            && self.from_key == other.from_key
+            && same_range_end
+            && self.min_create_rev == other.min_create_rev
+            && self.max_create_rev == other.max_create_rev
+            && self.min_mod_rev == other.min_mod_rev
+            && self.max_mod_rev == other.max_mod_rev
+            && self.consistency == other.consistency
+            && self.unknown_flags == other.unknown_flags
+    }
+}
+
+impl<'a> GetData<'a> {
+    /// Create a get operation without any flag.
+    pub fn new(key: &'a [u8]) -> Self {
+        GetData {
+            key: Key(key),
+            key_kind: DataKind::default(),
+            prefix: false,
+            from_key: false,
+            range_end: None,
+            range_end_kind: DataKind::default(),
+            min_create_rev: None,
+            max_create_rev: None,
+            min_mod_rev: None,
+            max_mod_rev: None,
+            consistency: None,
+            unknown_flags: vec![],
+        }
+    }
+
+    /// The key with its escape sequences decoded when quoted, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_key(&self) -> ParseResult<Cow<'a, [u8]>> {
+        decode(self.key.as_bytes(), self.key_kind)
+    }
+
+    /// The range end with its escape sequences decoded when quoted, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_range_end(&self) -> ParseResult<Option<Cow<'a, [u8]>>> {
+        self.range_end
+            .map(|range_end| decode(range_end.as_bytes(), self.range_end_kind))
+            .transpose()
+    }
+
+    /// The keys read by the operation, interpreting `--prefix`, `--from-key`
+    /// and the range end, the key and the range end decoded, see
+    /// [`GetData::decoded_key`].
+    ///
+    /// As with etcdctl, the empty key read from on is the whole keyspace.
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn effective_range(&self) -> ParseResult<KeyRange<'a>> {
+        let key = self.decoded_key()?;
+        Ok(if self.prefix || (self.from_key && key.is_empty()) {
+            KeyRange::prefix(key)
+        } else if self.from_key {
+            KeyRange::from_key(key)
+        } else if let Some(range_end) = self.decoded_range_end()? {
+            KeyRange {
+                key,
+                range_end: range_end.into_owned(),
+            }
+        } else {
+            KeyRange::single(key)
+        })
+    }
+
+    /// The `key` and `range_end` fields of the range request of the
+    /// operation, see [`GetData::effective_range`]. The range end is `None`
+    /// when only the key is read.
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn to_range(&self) -> ParseResult<(Vec<u8>, Option<Vec<u8>>)> {
+        let range = self.effective_range()?;
+        let range_end = Some(range.range_end).filter(|range_end| !range_end.is_empty());
+        Ok((range.key.into_owned(), range_end))
+    }
+
+    /// The get operation reading `range`, without revision filter: the range
+    /// of a prefix is read with `--prefix`, the one of every key from a key on
+    /// with `--from-key`, any other with its range end.
+    pub fn from_range(range: &'a KeyRange<'a>) -> Self {
+        let mut get = GetData::new(&range.key);
+        if let Some(prefix) = range.as_prefix() {
+            get.key = Key(prefix);
+            get.prefix = true;
+        } else if range.range_end == from_key_range_end() {
+            get.from_key = true;
+        } else if !range.is_single_key() {
+            get.range_end = Some(Key(&range.range_end));
+        }
+        get
+    }
+
+    /// Check that at most one of `--prefix`, `--from-key` and the range end
+    /// is given, as etcdctl requires.
+    pub(crate) fn check_range(&self) -> ParseResult<()> {
+        match (self.prefix, self.from_key, self.range_end) {
+            (true, true, _) | (true, _, Some(_)) | (_, true, Some(_)) => {
+                Err(ParseError::UnexpectedToken)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Set the consistency of the read, which conflicts with another one
+    /// given before, e.g. `--consistency=l --serializable`.
+    fn set_consistency(&mut self, consistency: Consistency) -> ParseResult<()> {
+        match self.consistency.replace(consistency) {
+            Some(previous) if previous != consistency => Err(ParseError::UnexpectedToken),
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply a flag of the get command, `unknown_flag` to a flag it doesn't
+    /// know.
+    pub(crate) fn apply(&mut self, flag: Flag, unknown_flag: UnknownFlagPolicy) -> ParseResult<()> {
+        let filter = match flag.name {
+            b"prefix" if flag.value.is_none() => {
+                self.prefix = true;
+                return Ok(());
+            }
+            b"from-key" if flag.value.is_none() => {
+                self.from_key = true;
+                return Ok(());
+            }
+            b"consistency" => {
+                return match flag.value {
+                    Some(b"l") => self.set_consistency(Consistency::Linearizable),
+                    Some(b"s") => self.set_consistency(Consistency::Serializable),
+                    _ => Err(ParseError::UnexpectedToken),
+                };
+            }
+            // The shorthand of `--consistency=s`
+            b"serializable" if flag.value.is_none() => {
+                return self.set_consistency(Consistency::Serializable);
+            }
+            b"min-create-rev" => &mut self.min_create_rev,
+            b"max-create-rev" => &mut self.max_create_rev,
+            b"min-mod-rev" => &mut self.min_mod_rev,
+            b"max-mod-rev" => &mut self.max_mod_rev,
+            _ => return flag.unknown(unknown_flag, &mut self.unknown_flags),
+        };
+        *filter = Some(flag.revision()?);
+        Ok(())
+    }
+}
+
+impl<'a> GetData<'a> {
+    /// Parse a get operation whose key may be quoted by `quotes`, applying
+    /// `unknown_flag` to the flags it doesn't know.
+    pub(crate) fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        quotes: &[u8],
+        unknown_flag: UnknownFlagPolicy,
+    ) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
+        accept_command(scanner, "get")?;
+
+        // The flags are applied as they come, the key and the range end are
+        // filled in once read
+        let mut get = GetData::new(b"");
+        let mut key = None;
+        loop {
+            OptionalBlanks::accept(scanner)?;
+            let flag = Acceptor::new(scanner)
+                .try_or(|flag: Flag<'a>| flag)?
+                .finish();
+            if let Some(flag) = flag {
+                get.apply(flag, unknown_flag)?;
+                continue;
+            }
+
+            let until_ln = peek_until_ln(scanner, quotes)?;
+            if key.is_some() && (get.range_end.is_some() || crate::is_blank(until_ln)) {
+                break;
+            }
+            let mut scanner_until_ln = Scanner::new(until_ln);
+
+            let data = Data::accept_with(&mut scanner_until_ln, quotes)?;
+            scanner.bump_by(scanner_until_ln.current_position());
+            match key {
+                None => key = Some(data),
+                Some(_) => {
+                    get.range_end = Some(Key(data.data));
+                    get.range_end_kind = data.kind;
+                }
+            }
+        }
+
+        let key = key.ok_or(ParseError::UnexpectedToken)?;
+        get.key = Key(key.data);
+        get.key_kind = key.kind;
+        get.check_range()?;
+        Ok(get)
+    }
 }
 
 impl<'a> Visitor<'a, u8> for GetData<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        OptionalWhitespaces::accept(scanner)?;
-        let command = DataString::<&str>::accept(scanner)?.0;
-        if command != "get" {
-            return Err(ParseError::UnexpectedToken);
-        }
+        GetData::accept_with(scanner, DEFAULT_QUOTE_CHARS, UnknownFlagPolicy::default())
+    }
+}
 
-        OptionalWhitespaces::accept(scanner)?;
+// ----------------------------------------------------------------------------
+// Custom operations
+// ----------------------------------------------------------------------------
 
-        let until_ln = Peeker::new(scanner)
-            .add_peekable(Token::Ln)
-            .add_peekable(UntilEnd::default())
-            .peek()?
-            .ok_or(ParseError::UnexpectedToken)?;
-        let mut scanner_until_ln = Scanner::new(until_ln.peeked_slice());
+/// A parser of an operation etcdctl doesn't know, registered in
+/// [`ParseOptions::operation_parsers`].
+///
+/// ```
+/// use elyze::errors::ParseResult;
+/// use etcd_txn_parser::operation::OperationParser;
+///
+/// /// `watch <key>`
+/// #[derive(Debug)]
+/// struct Watch;
+///
+/// impl OperationParser for Watch {
+///     fn command(&self) -> &[u8] {
+///         b"watch"
+///     }
+///
+///     fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+///         Ok(args.split(u8::is_ascii_whitespace).filter(|arg| !arg.is_empty()).collect())
+///     }
+/// }
+/// ```
+pub trait OperationParser: Debug + Send + Sync {
+    /// The first word of the lines parsed, e.g. `watch`.
+    fn command(&self) -> &[u8];
 
-        let key = Data::accept(&mut scanner_until_ln)?.data;
-        scanner.bump_by(scanner_until_ln.current_position());
-        OptionalWhitespaces::accept(scanner)?;
+    /// Split the rest of the line, leading whitespaces excluded, into the
+    /// arguments of the operation.
+    ///
+    /// # Errors
+    ///
+    /// An error rejects the line.
+    fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>>;
+}
 
-        Ok(GetData { key })
+/// An operation parsed by a registered [`OperationParser`].
+#[derive(Debug, PartialEq)]
+pub struct CustomData<'a> {
+    /// The command of the operation, e.g. `watch`.
+    pub command: &'a [u8],
+    /// The arguments returned by the parser.
+    pub args: Vec<&'a [u8]>,
+}
+
+impl<'a> CustomData<'a> {
+    /// Parse a line with the first of the parsers handling its command.
+    fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        parsers: &[std::sync::Arc<dyn OperationParser>],
+    ) -> ParseResult<Self> {
+        OptionalBlanks::accept(scanner)?;
+        let rest = peek(UntilEnd::default(), scanner)?
+            .ok_or(ParseError::UnexpectedToken)?
+            .peeked_slice();
+        let end = rest
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .unwrap_or(rest.len());
+        let (command, args) = rest
+            .split_at_checked(end)
+            .ok_or(ParseError::UnexpectedToken)?;
+        let parser = parsers
+            .iter()
+            .find(|parser| parser.command() == command)
+            .ok_or(ParseError::UnexpectedToken)?;
+        let args = parser.parse_args(args.trim_ascii_start())?;
+        scanner.bump_by(rest.len());
+        Ok(CustomData { command, args })
     }
 }
 
+// ----------------------------------------------------------------------------
+// Noop Operation
+// ----------------------------------------------------------------------------
+
+/// Parse a `noop` operation, see [`ParseOptions::allow_extensions`].
+fn accept_noop(scanner: &mut Scanner<'_, u8>) -> ParseResult<()> {
+    OptionalBlanks::accept(scanner)?;
+    accept_command(scanner, "noop")
+}
+
 // ----------------------------------------------------------------------------
 // Operation
 // ----------------------------------------------------------------------------
 
 /// A transactional operation.
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Operation<'a> {
     /// A put operation.
     Put(PutData<'a>),
@@ -178,36 +1025,488 @@ pub enum Operation<'a> {
     Delete(DeleteData<'a>),
     /// A get operation.
     Get(GetData<'a>),
+    /// A put operation whose value is read from a file, see
+    /// [`ParseOptions::allow_file_refs`](crate::options::ParseOptions::allow_file_refs).
+    PutFile(PutFileData<'a>),
+    /// An operation doing nothing, written `noop`, a placeholder for a branch
+    /// to be filled in later, see
+    /// [`ParseOptions::allow_extensions`](crate::options::ParseOptions::allow_extensions).
+    Noop,
+    /// An operation parsed by one of the
+    /// [`ParseOptions::operation_parsers`](crate::options::ParseOptions::operation_parsers).
+    Custom(CustomData<'a>),
+    /// A line kept as is because it can't be parsed, see
+    /// [`ParseOptions::keep_unknown_lines`](crate::options::ParseOptions::keep_unknown_lines).
+    Unknown(&'a [u8]),
+}
+
+impl<'a> Operation<'a> {
+    /// Parse a whole line as an operation, e.g. `put key1 value1`.
+    ///
+    /// A trailing line feed is allowed. Errors can be rendered with
+    /// [`render_error`](crate::error::render_error).
+    pub fn parse(line: &'a [u8]) -> ParseResult<Self> {
+        crate::parse_line(line, &ParseOptions::default())
+    }
+
+    /// Parse an operation with the syntax selected by the options.
+    pub(crate) fn accept_with(
+        scanner: &mut Scanner<'a, u8>,
+        options: &ParseOptions,
+    ) -> ParseResult<Self> {
+        let quotes = options.quote_chars;
+        if options.allow_file_refs {
+            if let Some(put) =
+                attempt(scanner, |scanner| PutFileData::accept_with(scanner, quotes))?
+            {
+                return Ok(Operation::PutFile(put));
+            }
+        }
+        let (unknown_flag, assignments) = (options.unknown_flag, options.allow_assignments);
+        if let Some(put) = attempt(scanner, |scanner| {
+            PutData::accept_with(scanner, quotes, unknown_flag, assignments)
+        })? {
+            return Ok(Operation::Put(put));
+        }
+        if let Some(delete) = attempt(scanner, |scanner| DeleteData::accept_with(scanner, quotes))?
+        {
+            return Ok(Operation::Delete(delete));
+        }
+        if let Some(get) = attempt(scanner, |scanner| {
+            GetData::accept_with(scanner, quotes, unknown_flag)
+        })? {
+            return Ok(Operation::Get(get));
+        }
+        if options.allow_extensions && attempt(scanner, accept_noop)?.is_some() {
+            return Ok(Operation::Noop);
+        }
+        // The built-in operations can't be overridden
+        CustomData::accept_with(scanner, &options.operation_parsers).map(Operation::Custom)
+    }
+}
+
+/// The operation as a line of the text format, as written by
+/// [`Operation::to_text`], e.g. `put "key1" "value1"`, so that the output
+/// parses back to the operation.
+///
+/// The key and the value parse back to the same bytes, see
+/// [`TxnData`](crate::TxnData)'s `Display`: the ones which aren't valid UTF-8
+/// are written escaped between double quotes, e.g. `put "k\xff" v` is
+/// displayed `put "k\xff" "v"`. Quoted data which can't be written as is and
+/// holds an invalid escape sequence is marked as `"..." <invalid escape>`,
+/// which doesn't parse.
+impl Display for Operation<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        crate::writer::display_with(f, |text, inexpressible| {
+            self.write_with(text, inexpressible)
+        })
+    }
 }
 
 impl<'a> Visitor<'a, u8> for Operation<'a> {
     fn accept(scanner: &mut Scanner<'a, u8>) -> ParseResult<Self> {
-        let operation = Acceptor::new(scanner)
-            .try_or(Operation::Put)?
-            .try_or(Operation::Delete)?
-            .try_or(Operation::Get)?
-            .finish()
-            .ok_or(ParseError::UnexpectedToken)?;
-        Ok(operation)
+        Operation::accept_with(scanner, &ParseOptions::default())
+    }
+}
+
+/// The kind of a transactional operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OperationKind {
+    /// A put operation.
+    Put,
+    /// A delete operation.
+    Delete,
+    /// A get operation.
+    Get,
+    /// A put operation whose value is read from a file.
+    PutFile,
+    /// An operation doing nothing.
+    Noop,
+    /// An operation parsed by a registered parser.
+    Custom,
+    /// A line kept as is because it can't be parsed.
+    Unknown,
+}
+
+impl<'a> Operation<'a> {
+    /// Create a put operation.
+    pub fn put(key: &'a [u8], value: &'a [u8]) -> Self {
+        Operation::Put(PutData {
+            key: Key(key),
+            value: Value(value),
+            lease: None,
+            key_kind: DataKind::default(),
+            value_kind: DataKind::default(),
+            unknown_flags: vec![],
+        })
+    }
+
+    /// Create a put operation attaching the key to a lease.
+    pub fn put_with_lease(key: &'a [u8], value: &'a [u8], lease: u64) -> Self {
+        Operation::Put(PutData {
+            key: Key(key),
+            value: Value(value),
+            lease: Some(lease),
+            key_kind: DataKind::default(),
+            value_kind: DataKind::default(),
+            unknown_flags: vec![],
+        })
+    }
+
+    /// Create a delete operation.
+    pub fn delete(key: &'a [u8]) -> Self {
+        Operation::Delete(DeleteData {
+            key: Key(key),
+            key_kind: DataKind::default(),
+        })
+    }
+
+    /// Create a get operation.
+    pub fn get(key: &'a [u8]) -> Self {
+        Operation::Get(GetData::new(key))
+    }
+
+    /// Create a put operation whose value is read from a file.
+    pub fn put_file(key: &'a [u8], path: &'a [u8]) -> Self {
+        Operation::PutFile(PutFileData {
+            key: Key(key),
+            key_kind: DataKind::default(),
+            path,
+        })
+    }
+
+    /// The kind of the operation.
+    pub fn kind(&self) -> OperationKind {
+        match self {
+            Operation::Put(_) => OperationKind::Put,
+            Operation::Delete(_) => OperationKind::Delete,
+            Operation::Get(_) => OperationKind::Get,
+            Operation::PutFile(_) => OperationKind::PutFile,
+            Operation::Noop => OperationKind::Noop,
+            Operation::Custom(_) => OperationKind::Custom,
+            Operation::Unknown(_) => OperationKind::Unknown,
+        }
+    }
+
+    /// The key of the operation, `None` for a noop, a custom or an unknown
+    /// operation.
+    pub fn key(&self) -> Option<Key<'a>> {
+        match self {
+            Operation::Put(put) => Some(put.key),
+            Operation::Delete(delete) => Some(delete.key),
+            Operation::Get(get) => Some(get.key),
+            Operation::PutFile(put) => Some(put.key),
+            Operation::Noop | Operation::Custom(_) | Operation::Unknown(_) => None,
+        }
+    }
+
+    /// The key of the operation with its escape sequences decoded when
+    /// quoted, `None` for a noop, a custom or an unknown operation, see
+    /// [`decode`](crate::escape::decode).
+    ///
+    /// # Errors
+    ///
+    /// An invalid escape sequence is an `UnexpectedToken` error.
+    pub fn decoded_key(&self) -> ParseResult<Option<Cow<'a, [u8]>>> {
+        match self {
+            Operation::Put(put) => put.decoded_key().map(Some),
+            Operation::Delete(delete) => delete.decoded_key().map(Some),
+            Operation::Get(get) => get.decoded_key().map(Some),
+            Operation::PutFile(put) => put.decoded_key().map(Some),
+            Operation::Noop | Operation::Custom(_) | Operation::Unknown(_) => Ok(None),
+        }
+    }
+
+    /// The value put by the operation, `None` unless this is a put operation.
+    pub fn value(&self) -> Option<&'a [u8]> {
+        match self {
+            Operation::Put(put) => Some(put.value.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// The flags of the operation the parser doesn't know, kept under
+    /// [`UnknownFlagPolicy::Capture`].
+    pub fn unknown_flags(&self) -> &[(String, Option<String>)] {
+        match self {
+            Operation::Put(put) => &put.unknown_flags,
+            Operation::Get(get) => &get.unknown_flags,
+            _ => &[],
+        }
+    }
+
+    /// Whether this is a put operation.
+    pub fn is_put(&self) -> bool {
+        matches!(self, Operation::Put(_))
+    }
+
+    /// Whether this is a delete operation.
+    pub fn is_delete(&self) -> bool {
+        matches!(self, Operation::Delete(_))
+    }
+
+    /// Whether this is a get operation.
+    pub fn is_get(&self) -> bool {
+        matches!(self, Operation::Get(_))
+    }
+
+    /// Whether this is a put operation whose value is read from a file.
+    pub fn is_put_file(&self) -> bool {
+        matches!(self, Operation::PutFile(_))
+    }
+
+    /// Whether this is an operation doing nothing.
+    pub fn is_noop(&self) -> bool {
+        matches!(self, Operation::Noop)
+    }
+
+    /// Whether this is an operation parsed by a registered parser.
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Operation::Custom(_))
+    }
+
+    /// Whether this is a line kept as is because it can't be parsed.
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, Operation::Unknown(_))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::operation::GetData;
+    use crate::data::Value;
+    use crate::data::{self, Key};
+    use crate::operation::{Consistency, DataKind, GetData, Operation, PutData, QuotedString};
+    use crate::options::{UnknownFlagPolicy, DEFAULT_QUOTE_CHARS};
+    use elyze::bytes::components::groups::GroupKind;
+    use elyze::peek::peek;
+    use elyze::scanner::Scanner;
     use elyze::visitor::Visitor;
 
+    #[test]
+    fn test_quoted_string() {
+        // The group matcher used before scanning with memchr
+        fn reference(data: &[u8]) -> Option<(&[u8], usize)> {
+            let scanner = Scanner::new(data);
+            let peeked = peek(GroupKind::DoubleQuotes, &scanner).ok()??;
+            Some((peeked.peeked_slice(), peeked.end_slice))
+        }
+
+        let long = [b"\"".as_slice(), &[b'a'; 40000], b"\" rest"].concat();
+        for data in [
+            &b"\"key\""[..],
+            b"\"\"",
+            b"\"key with spaces\" value",
+            b"\"multi\nline\" \"second\"",
+            b"\"unterminated",
+            b"\"",
+            b"key\"",
+            b"",
+            &long,
+        ] {
+            let mut scanner = Scanner::new(data);
+            let result = QuotedString::accept(&mut scanner)
+                .ok()
+                .map(|quoted| (quoted.0, scanner.current_position()));
+            assert_eq!(result, reference(data), "{data:?}");
+        }
+
+        // The group matcher doesn't unescape a backslash and reads past the
+        // end after a trailing escape
+        for (data, expected) in [
+            (&b"\"a\\\\\" b"[..], Some((&b"a\\\\"[..], 5))),
+            (b"\"a\\\"", None),
+            (b"\"a\\", None),
+            (b"\"\x00\xff'\\\" \\\"", None),
+        ] {
+            let mut scanner = Scanner::new(data);
+            let result = QuotedString::accept(&mut scanner)
+                .ok()
+                .map(|quoted| (quoted.0, scanner.current_position()));
+            assert_eq!(result, expected, "{data:?}");
+        }
+    }
+
     #[test]
     fn test_get_data() {
         let data = b"get \"key\"";
         let mut scanner = elyze::scanner::Scanner::new(data);
         let result = super::GetData::accept(&mut scanner);
-        assert!(matches!(result, Ok(GetData { key: b"key" })));
+        assert!(matches!(
+            result,
+            Ok(GetData {
+                key: Key(b"key"),
+                ..
+            })
+        ));
 
         let data = b"get key";
         let mut scanner = elyze::scanner::Scanner::new(data);
         let result = super::GetData::accept(&mut scanner);
-        assert!(matches!(result, Ok(GetData { key: b"key" })));
+        assert!(matches!(
+            result,
+            Ok(GetData {
+                key: Key(b"key"),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_get_data_flags() {
+        let data = b"get --min-mod-rev=5 --prefix app/";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let get = super::GetData::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(get.key, b"app/");
+        assert!(get.prefix);
+        assert_eq!(get.min_mod_rev, Some(5));
+        assert_eq!(get.max_mod_rev, None);
+        assert_eq!(get.min_create_rev, None);
+
+        let data = b"get \"key\" --max-create-rev=-1 --min-create-rev=2";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let get = super::GetData::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(get.key, b"key");
+        assert!(!get.prefix);
+        assert_eq!(get.min_create_rev, Some(2));
+        assert_eq!(get.max_create_rev, Some(-1));
+
+        for data in [
+            &b"get --min-mod-rev key"[..],
+            b"get --min-mod-rev=x key",
+            b"get --prefix=1 key",
+            b"get --rev=3 key",
+            // Empty flag names and values
+            b"get --=5 key",
+            b"get --min-mod-rev= key",
+            b"get --prefix= key",
+        ] {
+            let mut scanner = elyze::scanner::Scanner::new(data);
+            assert!(super::GetData::accept(&mut scanner).is_err(), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn test_get_data_consistency() {
+        let get = |data: &'static [u8]| {
+            let mut scanner = elyze::scanner::Scanner::new(data);
+            super::GetData::accept(&mut scanner).map(|get| get.consistency)
+        };
+        assert_eq!(get(b"get key").ok(), Some(None));
+        assert_eq!(
+            get(b"get --consistency=s key").ok(),
+            Some(Some(Consistency::Serializable))
+        );
+        assert_eq!(
+            get(b"get key --consistency=l").ok(),
+            Some(Some(Consistency::Linearizable))
+        );
+        // The shorthand of `--consistency=s`
+        assert_eq!(
+            get(b"get --serializable key").ok(),
+            Some(Some(Consistency::Serializable))
+        );
+        assert_eq!(
+            get(b"get --consistency=s key --serializable").ok(),
+            Some(Some(Consistency::Serializable))
+        );
+
+        for data in [
+            &b"get --consistency=l --serializable key"[..],
+            b"get --serializable key --consistency=l",
+            b"get --consistency=s --consistency=l key",
+            b"get --consistency=x key",
+            b"get --consistency key",
+            b"get --serializable=1 key",
+        ] {
+            assert!(get(data).is_err(), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn test_get_data_range() {
+        let get = |data: &'static [u8]| {
+            let mut scanner = elyze::scanner::Scanner::new(data);
+            super::GetData::accept(&mut scanner).expect("Failed to parse")
+        };
+
+        let range = get(b"get \"a\" c --min-mod-rev=3");
+        assert_eq!(range.key, b"a");
+        assert_eq!(range.range_end, Some(Key(b"c")));
+        assert!(get(b"get --from-key a").from_key);
+
+        for data in [
+            &b"get --prefix --from-key a"[..],
+            b"get --prefix a c",
+            b"get a c --from-key",
+            b"get --from-key=1 a",
+        ] {
+            let mut scanner = elyze::scanner::Scanner::new(data);
+            assert!(super::GetData::accept(&mut scanner).is_err(), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn test_get_data_to_range() {
+        let to_range = |data: &'static [u8]| {
+            let mut scanner = elyze::scanner::Scanner::new(data);
+            let get = super::GetData::accept(&mut scanner).expect("Failed to parse");
+            get.to_range().expect("Failed to decode")
+        };
+
+        // A single key
+        assert_eq!(to_range(b"get a"), (b"a".to_vec(), None));
+        // A prefix
+        assert_eq!(
+            to_range(b"get --prefix app/"),
+            (b"app/".to_vec(), Some(b"app0".to_vec()))
+        );
+        assert_eq!(
+            to_range(b"get --prefix \"\""),
+            (b"\0".to_vec(), Some(b"\0".to_vec()))
+        );
+        // Every key from a key on
+        assert_eq!(
+            to_range(b"get --from-key a"),
+            (b"a".to_vec(), Some(b"\0".to_vec()))
+        );
+        assert_eq!(
+            to_range(b"get --from-key \"\""),
+            (b"\0".to_vec(), Some(b"\0".to_vec()))
+        );
+        // An explicit range
+        assert_eq!(to_range(b"get a c"), (b"a".to_vec(), Some(b"c".to_vec())));
+        // The key and the range end are decoded first
+        assert_eq!(
+            to_range(br#"get --prefix "app\x2f""#),
+            (b"app/".to_vec(), Some(b"app0".to_vec()))
+        );
+        assert_eq!(
+            to_range(br#"get 'a' "\x63""#),
+            (b"a".to_vec(), Some(b"c".to_vec()))
+        );
+
+        let mut scanner = elyze::scanner::Scanner::new(br#"get "a\q""#);
+        let get = super::GetData::accept(&mut scanner).expect("Failed to parse");
+        assert!(get.to_range().is_err());
+    }
+
+    #[test]
+    fn test_get_data_from_range() {
+        for data in [
+            &b"get a"[..],
+            b"get --prefix app/",
+            b"get --prefix \"\"",
+            b"get --from-key a",
+            b"get a c",
+            br#"get --prefix "app\x2f""#,
+            br#"get "\x61" 'c'"#,
+        ] {
+            let mut scanner = elyze::scanner::Scanner::new(data);
+            let get = super::GetData::accept(&mut scanner).expect("Failed to parse");
+            let range = get.effective_range().expect("Failed to decode");
+            assert_eq!(super::GetData::from_range(&range), get, "{data:?}");
+        }
     }
 
     #[test]
@@ -215,12 +1514,52 @@ mod tests {
         let data = b"del \"key\"";
         let mut scanner = elyze::scanner::Scanner::new(data);
         let result = super::DeleteData::accept(&mut scanner);
-        assert!(matches!(result, Ok(super::DeleteData { key: b"key" })));
+        assert!(matches!(
+            result,
+            Ok(super::DeleteData {
+                key: Key(b"key"),
+                ..
+            })
+        ));
 
         let data = b"del key";
         let mut scanner = elyze::scanner::Scanner::new(data);
         let result = super::DeleteData::accept(&mut scanner);
-        assert!(matches!(result, Ok(super::DeleteData { key: b"key" })));
+        assert!(matches!(
+            result,
+            Ok(super::DeleteData {
+                key: Key(b"key"),
+                ..
+            })
+        ));
+
+        // The command is a whole word
+        let data = b"del--prefix key";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        assert!(super::DeleteData::accept(&mut scanner).is_err());
+    }
+
+    #[test]
+    fn test_put_file_data() {
+        let data = b"put key @data.bin";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::PutFileData::accept(&mut scanner);
+        assert!(matches!(
+            result,
+            Ok(super::PutFileData {
+                key: Key(b"key"),
+                key_kind: super::DataKind::Unquoted,
+                path: b"data.bin"
+            })
+        ));
+
+        let data = b"put key \"@data.bin\"";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        assert!(super::PutFileData::accept(&mut scanner).is_err());
+
+        let data = b"put key @";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        assert!(super::PutFileData::accept(&mut scanner).is_err());
     }
 
     #[test]
@@ -231,8 +1570,10 @@ mod tests {
         assert!(matches!(
             result,
             Ok(super::PutData {
-                key: b"key",
-                value: b"value"
+                key: Key(b"key"),
+                value: data::Value(b"value"),
+                lease: None,
+                ..
             })
         ));
 
@@ -241,16 +1582,284 @@ mod tests {
         let result = super::PutData::accept(&mut scanner);
 
         if let Ok(result) = &result {
-            println!("{:?}", String::from_utf8_lossy(result.key));
-            println!("{:?}", String::from_utf8_lossy(result.value));
+            println!("{:?}", String::from_utf8_lossy(result.key.as_bytes()));
+            println!("{:?}", String::from_utf8_lossy(result.value.as_bytes()));
         }
 
         assert!(matches!(
             result,
             Ok(super::PutData {
-                key: b"key",
-                value: b"value"
+                key: Key(b"key"),
+                value: data::Value(b"value"),
+                lease: None,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_control_bytes() {
+        let data = b"put \"k\x00ey\" \"va\x00l\x01ue\"";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::PutData::accept(&mut scanner);
+        assert!(matches!(
+            result,
+            Ok(super::PutData {
+                key: Key(b"k\x00ey"),
+                value: data::Value(b"va\x00l\x01ue"),
+                lease: None,
+                ..
+            })
+        ));
+
+        // Unquoted tokens keep their control bytes too
+        let data = b"put key\x00 \x01value\x00";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::PutData::accept(&mut scanner);
+        assert!(matches!(
+            result,
+            Ok(super::PutData {
+                key: Key(b"key\x00"),
+                value: data::Value(b"\x01value\x00"),
+                lease: None,
+                ..
+            })
+        ));
+
+        let data = b"get \"\x00\"";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::GetData::accept(&mut scanner);
+        assert!(matches!(
+            result,
+            Ok(GetData {
+                key: Key(b"\x00"),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_put_data_lease() {
+        let data = b"put --lease=1f key value";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::PutData::accept(&mut scanner);
+        assert!(matches!(
+            result,
+            Ok(super::PutData {
+                key: Key(b"key"),
+                value: data::Value(b"value"),
+                lease: Some(31),
+                ..
+            })
+        ));
+
+        let data = b"put \"key\" \"value\" --lease=694d77aa9e38260f";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::PutData::accept(&mut scanner);
+        assert!(matches!(
+            result,
+            Ok(super::PutData {
+                key: Key(b"key"),
+                value: data::Value(b"value"),
+                lease: Some(0x694d77aa9e38260f),
+                ..
+            })
+        ));
+
+        // Not an hexadecimal lease ID
+        let data = b"put --lease=xyz key value";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::PutData::accept(&mut scanner);
+        assert!(matches!(
+            result,
+            Ok(super::PutData {
+                key: Key(b"--lease=xyz"),
+                value: data::Value(b"key"),
+                lease: None,
+                ..
             })
         ));
     }
+
+    #[test]
+    fn test_put_data_unknown_flags() {
+        let accept = |data: &[u8], policy| {
+            let mut scanner = Scanner::new(data);
+            super::PutData::accept_with(&mut scanner, DEFAULT_QUOTE_CHARS, policy, false)
+                .map(|put| (put.key.to_vec(), put.lease, put.unknown_flags))
+        };
+        let data = b"put --prev-kv --lease=1f key value --ignore-value=1";
+        assert!(accept(data, UnknownFlagPolicy::Error).is_err());
+        assert_eq!(
+            accept(data, UnknownFlagPolicy::Ignore).expect("Failed to parse"),
+            (b"key".to_vec(), Some(31), vec![])
+        );
+        assert_eq!(
+            accept(data, UnknownFlagPolicy::Capture).expect("Failed to parse"),
+            (
+                b"key".to_vec(),
+                Some(31),
+                vec![
+                    ("prev-kv".to_string(), None),
+                    ("ignore-value".to_string(), Some("1".to_string()))
+                ]
+            )
+        );
+
+        // A lease flag is never unknown, even when invalid
+        assert_eq!(
+            accept(b"put --lease=xyz key value", UnknownFlagPolicy::Capture)
+                .expect("Failed to parse"),
+            (b"--lease=xyz".to_vec(), None, vec![])
+        );
+        assert!(accept(
+            b"put --lease=1 key value --lease=2",
+            UnknownFlagPolicy::Capture
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_data_kind() {
+        let mut scanner = elyze::scanner::Scanner::new(b"\"key\"");
+        let data = super::Data::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(data.data(), b"key");
+        assert_eq!(data.kind(), super::DataKind::Quoted);
+
+        let mut scanner = elyze::scanner::Scanner::new(b"key");
+        let data = super::Data::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(data.data(), b"key");
+        assert_eq!(data.kind(), super::DataKind::Unquoted);
+
+        let mut scanner = elyze::scanner::Scanner::new(br"'a\'b\c'");
+        let data = super::Data::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(data.data(), br"a\'b\c");
+        assert_eq!(data.kind(), super::DataKind::SingleQuoted);
+
+        let data = b"put key \"value\"";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::PutData::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(result.key_kind, super::DataKind::Unquoted);
+        assert_eq!(result.value_kind, super::DataKind::Quoted);
+
+        let data = b"del \"key\"";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = super::DeleteData::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(result.key_kind, super::DataKind::Quoted);
+
+        let data = b"get key";
+        let mut scanner = elyze::scanner::Scanner::new(data);
+        let result = GetData::accept(&mut scanner).expect("Failed to parse");
+        assert_eq!(result.key_kind, super::DataKind::Unquoted);
+    }
+
+    #[test]
+    fn test_data_equality() {
+        // The bytes the data stands for are compared, not how it's written
+        let parse = |line: &'static [u8]| Operation::parse(line).expect("Failed to parse");
+        assert_eq!(parse(br#"put k "\x41""#), parse(b"put k A"));
+        assert_eq!(parse(br#"put k "\x41""#), Operation::put(b"k", b"A"));
+        assert_eq!(parse(br"put 'k\'' v"), parse(br#"put "k'" v"#));
+        assert_ne!(parse(br"put k '\x41'"), parse(br#"put k "\x41""#));
+        assert_eq!(parse(br"put k '\x41'"), Operation::put(b"k", br"\x41"));
+        assert_eq!(parse(br#"del "\x41""#), parse(b"del A"));
+        assert_ne!(parse(br"del '\x41'"), parse(br#"del "\x41""#));
+        assert_eq!(parse(br#"get "\x41" "\x42""#), parse(b"get A B"));
+        assert_ne!(parse(br"get a '\x42'"), parse(br#"get a "\x42""#));
+
+        // Data with an invalid escape sequence is only equal to itself
+        assert_eq!(parse(br#"put k "\q""#), parse(br#"put k "\q""#));
+        assert_ne!(parse(br#"put k "\q""#), parse(br"put k '\q'"));
+    }
+
+    #[test]
+    fn test_display() {
+        for (data, expected) in [
+            (&b"put key1 value1"[..], "put \"key1\" \"value1\""),
+            (
+                b"put \"key 1\" \"some value\"",
+                "put \"key 1\" \"some value\"",
+            ),
+            (b"put key1 \"\"", "put \"key1\" \"\""),
+            (b"put key1 'say \"hi\"'", "put \"key1\" 'say \"hi\"'"),
+            (b"del key1", "del \"key1\""),
+            (b"get key1", "get \"key1\""),
+            (
+                b"get app/ --prefix --serializable",
+                "get \"app/\" --prefix --consistency=s",
+            ),
+        ] {
+            let operation = Operation::accept(&mut Scanner::new(data)).expect("Failed to parse");
+            let displayed = operation.to_string();
+            assert_eq!(displayed, expected);
+            let reparsed = Operation::accept(&mut Scanner::new(displayed.as_bytes()))
+                .expect("Failed to parse");
+            assert_eq!(reparsed, operation);
+        }
+
+        // Bytes which aren't valid UTF-8 are escaped
+        let operation = Operation::accept(&mut Scanner::new(b"put k\xff \"caf\xc3\xa9\xfe\""))
+            .expect("Failed to parse");
+        let displayed = operation.to_string();
+        assert_eq!(displayed, "put \"k\\xff\" \"caf\u{e9}\\xfe\"");
+        let reparsed = Operation::parse(displayed.as_bytes()).expect("Failed to parse");
+        assert_eq!(reparsed, operation);
+        let Operation::Put(put) = reparsed else {
+            panic!("expected a put, got {reparsed:?}");
+        };
+        assert_eq!(put.decoded_key().expect("Failed to decode"), &b"k\xff"[..]);
+        assert_eq!(
+            put.decoded_value().expect("Failed to decode"),
+            &b"caf\xc3\xa9\xfe"[..]
+        );
+
+        // A value holding both quotes and a space is escaped
+        let operation = Operation::put(b"k", b"it's \"q\" x");
+        let displayed = operation.to_string();
+        assert_eq!(displayed, "put \"k\" \"it's \\x22q\\x22 x\"");
+        assert_eq!(
+            operation.to_text().expect("Failed to write"),
+            displayed.as_bytes()
+        );
+        let reparsed = Operation::parse(displayed.as_bytes()).expect("Failed to parse");
+        assert_eq!(reparsed, operation);
+        let Operation::Put(put) = reparsed else {
+            panic!("expected a put, got {reparsed:?}");
+        };
+        assert_eq!(
+            put.decoded_value().expect("Failed to decode"),
+            &b"it's \"q\" x"[..]
+        );
+
+        // The escape sequences are kept between their quotes
+        for line in [&br"put k '\x41'"[..], br#"del "\x41""#, br"get 'a\'b' '\n'"] {
+            let operation = Operation::parse(line).expect("Failed to parse");
+            let displayed = operation.to_string();
+            assert_eq!(
+                Operation::parse(displayed.as_bytes()).expect("Failed to parse"),
+                operation,
+                "{displayed}"
+            );
+        }
+        assert_eq!(
+            Operation::parse(br"put k '\x41'")
+                .expect("Failed to parse")
+                .to_string(),
+            r#"put "k" '\x41'"#
+        );
+
+        // Quoted data which can't be written as is and holds an invalid
+        // escape sequence is marked
+        let operation = Operation::Put(PutData {
+            key: Key(b"k"),
+            value: Value(b"\"\\q"),
+            lease: None,
+            key_kind: DataKind::Unquoted,
+            value_kind: DataKind::Quoted,
+            unknown_flags: vec![],
+        });
+        let displayed = operation.to_string();
+        assert_eq!(displayed, r#"put "k" "\x22\\q" <invalid escape>"#);
+        assert!(Operation::parse(displayed.as_bytes()).is_err());
+    }
 }
@@ -0,0 +1,181 @@
+//! Parser options
+
+use crate::compare::CompareParser;
+use crate::operation::OperationParser;
+use std::sync::Arc;
+
+/// The quote characters of the default syntax, see
+/// [`ParseOptions::quote_chars`].
+pub const DEFAULT_QUOTE_CHARS: &[u8] = b"\"'";
+
+/// How a trailing `# sha256:<digest>` checksum line is handled, see
+/// [`ParseOptions::trailing_checksum`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumMode {
+    /// The line isn't recognized, failing the parse.
+    #[default]
+    Off,
+    /// The line is stripped and its checksum returned, unchecked.
+    Strip,
+    /// The line is required, stripped and its checksum checked against the
+    /// SHA-256 of the input preceding it.
+    Verify,
+}
+
+/// How a flag an operation doesn't know is handled, see
+/// [`ParseOptions::unknown_flag`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownFlagPolicy {
+    /// The flag fails the parse.
+    #[default]
+    Error,
+    /// The flag is dropped.
+    Ignore,
+    /// The flag is kept in the `unknown_flags` of the operation.
+    Capture,
+}
+
+/// Options tweaking how a transaction is parsed.
+///
+/// The default options parse exactly what [`parse`](crate::parse) accepts.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Reject numeric values of zero where etcd requires a positive number.
+    ///
+    /// Revisions, versions and lease IDs are unsigned, so a `<` comparison
+    /// against zero can never succeed; such a compare is reported as an
+    /// [`Error::UnsatisfiableCompare`](crate::error::Error::UnsatisfiableCompare)
+    /// instead of silently producing a dead success branch.
+    pub strict_numbers: bool,
+
+    /// Reject the syntax etcdctl doesn't accept, so that a transaction parsed
+    /// in this mode is read the same by etcdctl.
+    ///
+    /// etcdctl only quotes with double quotes, a single quote being part of
+    /// the key or the value, so a single-quoted string is an error.
+    pub strict_etcdctl: bool,
+
+    /// Keep the lines which can't be parsed instead of failing the whole parse.
+    ///
+    /// Such a line becomes a `Compare::Unknown` or an `Operation::Unknown` node
+    /// carrying the raw line, and a [`Warning::UnknownLine`](crate::warning::Warning::UnknownLine)
+    /// is emitted for it.
+    pub keep_unknown_lines: bool,
+
+    /// Parse `put key @path` as a reference to a file holding the value.
+    ///
+    /// The parser doesn't read the file, the operation becomes an
+    /// `Operation::PutFile` for the caller to resolve. A value starting with
+    /// `@` must be quoted to be put as is.
+    pub allow_file_refs: bool,
+
+    /// Accept the operations this crate adds to etcdctl's syntax: `noop`, an
+    /// operation doing nothing, e.g. as the placeholder of a templated
+    /// branch.
+    ///
+    /// The built-in operations can't be overridden, `noop` is parsed before
+    /// the [`operation_parsers`](ParseOptions::operation_parsers).
+    pub allow_extensions: bool,
+
+    /// Accept `0x`, `0o` and `0b` prefixed numbers in numeric compares.
+    ///
+    /// Some exports write revisions in hexadecimal, octal or binary notation,
+    /// e.g. `mod(key) > 0o17`. Without this option only decimal numbers are
+    /// accepted.
+    pub numeric_prefixes: bool,
+
+    /// Accept `put key=value` as `put key value`, e.g. for the `key=value`
+    /// pairs of configuration tools.
+    ///
+    /// The argument is split on its first unquoted `=`, a key holding a `=`
+    /// being quoted: `put "a=b"=c`. The form only applies to a put with a
+    /// single argument, `put a=b c` still puts `c` under `a=b`.
+    pub allow_assignments: bool,
+
+    /// Accept the uppercase target words of etcd's compare dumps, `CREATE`,
+    /// `MOD`, `VERSION`, `VALUE` and `LEASE`, e.g. `CREATE(key) = 0`.
+    pub allow_target_words: bool,
+
+    /// Accept blank lines between the compares.
+    ///
+    /// A blank line ends a section, so a stray one inside the compare section
+    /// would shift the following sections. With this option, a block whose
+    /// first line is a compare still belongs to the compare section.
+    ///
+    /// Success and failure operations can't be told apart: a blank line
+    /// between two operations still ends the section, which is reported as
+    /// [`Error::TrailingContent`](crate::error::Error::TrailingContent).
+    pub lenient_blank_lines: bool,
+
+    /// The characters quoting keys and values, `"` and `'` by default.
+    ///
+    /// Some DSLs quote with backticks, e.g. `` put `key with space` value ``.
+    /// A string opened with one of the characters is closed by the same
+    /// character, the others being part of the string. Between single quotes,
+    /// `\'` is the only escape sequence.
+    pub quote_chars: &'static [u8],
+
+    /// Parsers of the operations etcdctl doesn't know, e.g. `watch key`.
+    ///
+    /// A line which isn't a built-in operation is parsed by the first parser
+    /// handling its command into an `Operation::Custom`.
+    pub operation_parsers: Vec<Arc<dyn OperationParser>>,
+
+    /// Parsers of the compare targets etcd doesn't know, e.g. `locked(name)`.
+    ///
+    /// A line which isn't a built-in compare is parsed by the first parser
+    /// handling its target into a `Compare::Custom`.
+    pub compare_parsers: Vec<Arc<dyn CompareParser>>,
+
+    /// Recognize a last line holding the SHA-256 of the input preceding it,
+    /// e.g. `# sha256:e3b0c442...`, returned in
+    /// [`Parsed::metadata`](crate::Parsed::metadata).
+    pub trailing_checksum: ChecksumMode,
+
+    /// The longest a compare or an operation line may be, in bytes, quoted
+    /// line feeds included. No limit by default.
+    ///
+    /// Bounds the work spent on a single line of an untrusted input, a longer
+    /// one failing the parse with [`Error::LineTooLong`](crate::error::Error::LineTooLong).
+    pub max_line_len: Option<usize>,
+
+    /// What to do with the flags put and get operations don't know, e.g.
+    /// `put --prev-kv key value`.
+    ///
+    /// A put takes its flags before its key or after its value, a get around
+    /// its key.
+    pub unknown_flag: UnknownFlagPolicy,
+
+    /// The etcd version the transaction targets, as `(major, minor)`,
+    /// rejecting the compare operators it doesn't support. Any operator is
+    /// accepted by default.
+    ///
+    /// `!=` requires etcd 3.3. `>=` and `<=` aren't supported by any version:
+    /// rather than read as `>` or `<` followed by a value starting with `=`,
+    /// they are reported. Such a compare fails the parse with
+    /// [`Error::UnsupportedOperator`](crate::error::Error::UnsupportedOperator).
+    pub etcd_version: Option<(u32, u32)>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            strict_numbers: false,
+            strict_etcdctl: false,
+            keep_unknown_lines: false,
+            allow_file_refs: false,
+            allow_extensions: false,
+            numeric_prefixes: false,
+            allow_assignments: false,
+            allow_target_words: false,
+            lenient_blank_lines: false,
+            quote_chars: DEFAULT_QUOTE_CHARS,
+            operation_parsers: vec![],
+            compare_parsers: vec![],
+            trailing_checksum: ChecksumMode::Off,
+            max_line_len: None,
+            unknown_flag: UnknownFlagPolicy::Error,
+            etcd_version: None,
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Output of `etcdctl txn`
+//!
+//! `etcdctl txn` prints `SUCCESS` or `FAILURE`, then the response of each
+//! operation of the branch which ran, each preceded by a blank line:
+//!
+//! ```text
+//! SUCCESS
+//!
+//! OK
+//!
+//! key1
+//! value1
+//! key2
+//! value2
+//!
+//! 1
+//! ```
+//!
+//! A put prints `OK`, a get one line for the key then one for the value of
+//! each key found, and a delete the number of deleted keys. The previous
+//! key-value pairs asked with `--prev-kv` follow the put and delete lines.
+// Malformed input must be reported, never panic
+
+use crate::error::Error;
+use crate::operation::Operation;
+use crate::TxnData;
+
+/// A key-value pair printed by `etcdctl`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValue<'a> {
+    /// The key.
+    pub key: &'a [u8],
+    /// The value.
+    pub value: &'a [u8],
+}
+
+/// The response to an operation of a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OpResponse<'a> {
+    /// The response to a put operation.
+    Put {
+        /// The previous key-value pair, if printed.
+        prev_kv: Option<KeyValue<'a>>,
+    },
+    /// The response to a delete operation.
+    Delete {
+        /// The number of deleted keys.
+        deleted: u64,
+        /// The deleted key-value pairs, if printed.
+        prev_kvs: Vec<KeyValue<'a>>,
+    },
+    /// The response to a get operation.
+    Get {
+        /// The key-value pairs found, none if the key doesn't exist.
+        kvs: Vec<KeyValue<'a>>,
+    },
+}
+
+impl OpResponse<'_> {
+    /// Whether the response answers the operation, a put answering both put
+    /// operations and file references.
+    fn answers(&self, operation: &Operation) -> bool {
+        matches!(
+            (self, operation),
+            (
+                OpResponse::Put { .. },
+                Operation::Put(_) | Operation::PutFile(_)
+            ) | (OpResponse::Delete { .. }, Operation::Delete(_))
+                | (OpResponse::Get { .. }, Operation::Get(_))
+        )
+    }
+}
+
+/// The output of `etcdctl txn`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxnOutput<'a> {
+    /// Whether the compares succeeded, the success branch having run.
+    pub succeeded: bool,
+    /// The responses to the operations of the branch which ran, in order.
+    pub responses: Vec<OpResponse<'a>>,
+}
+
+impl<'a> TxnOutput<'a> {
+    /// Pair each operation of the branch which ran with its response.
+    ///
+    /// Returns `None` if the responses don't match the operations of the
+    /// branch, in number or in kind, e.g. if the output comes from another
+    /// transaction. Custom operations and unknown lines never match.
+    pub fn zip<'t, 'o>(
+        &self,
+        txn: &'t TxnData<'o>,
+    ) -> Option<Vec<(&'t Operation<'o>, &OpResponse<'a>)>> {
+        let operations = match self.succeeded {
+            true => &txn.success,
+            false => &txn.failure,
+        };
+        if operations.len() != self.responses.len() {
+            return None;
+        }
+        operations
+            .iter()
+            .zip(&self.responses)
+            .map(|(operation, response)| {
+                response.answers(operation).then_some((operation, response))
+            })
+            .collect()
+    }
+}
+
+/// Pair the lines as keys followed by their values.
+fn key_values<'a>(lines: &[&'a [u8]]) -> Option<Vec<KeyValue<'a>>> {
+    let pairs = lines.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    Some(
+        pairs
+            .filter_map(|pair| match *pair {
+                [key, value] => Some(KeyValue { key, value }),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+/// Parse the lines of a response, told apart by their shape: a get prints an
+/// even number of lines, a put `OK` followed by at most one key-value pair
+/// and a delete a number followed by an even number of lines.
+fn parse_response<'a>(lines: &[&'a [u8]]) -> Option<OpResponse<'a>> {
+    match *lines {
+        [b"OK"] => Some(OpResponse::Put { prev_kv: None }),
+        [b"OK", key, value] => Some(OpResponse::Put {
+            prev_kv: Some(KeyValue { key, value }),
+        }),
+        [deleted, ref rest @ ..] if rest.len() % 2 == 0 => {
+            let deleted = std::str::from_utf8(deleted)
+                .ok()
+                .filter(|deleted| deleted.bytes().all(|byte| byte.is_ascii_digit()))
+                .and_then(|deleted| deleted.parse().ok())?;
+            Some(OpResponse::Delete {
+                deleted,
+                prev_kvs: key_values(rest)?,
+            })
+        }
+        _ => Some(OpResponse::Get {
+            kvs: key_values(lines)?,
+        }),
+    }
+}
+
+fn invalid_output(line: usize, message: &str) -> Error {
+    Error::InvalidOutput {
+        line,
+        message: message.to_string(),
+    }
+}
+
+/// Parse the output of `etcdctl txn`, written with the default `simple`
+/// format.
+///
+/// The lines may end with `\r\n`. As the format doesn't escape the keys and
+/// the values, an empty value or one holding a line feed can't be read back.
+///
+/// # Errors
+///
+/// An `Error::InvalidOutput` carrying the number of the offending line, from
+/// 1, is returned if the output doesn't start with `SUCCESS` or `FAILURE`, or
+/// if a response is neither a put, a delete nor a get.
+pub fn parse_txn_output(text: &[u8]) -> Result<TxnOutput<'_>, Error> {
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    let mut lines = text
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+
+    let succeeded = match lines.next() {
+        Some(b"SUCCESS") => true,
+        Some(b"FAILURE") => false,
+        _ => return Err(invalid_output(1, "expected SUCCESS or FAILURE")),
+    };
+
+    let lines: Vec<_> = lines.collect();
+    if lines.first().is_some_and(|line| !line.is_empty()) {
+        return Err(invalid_output(2, "expected a blank line"));
+    }
+
+    let mut responses = vec![];
+    // The index of the line following the blank line opening the response
+    let mut start = 1;
+    for block in lines.split(|line| line.is_empty()).skip(1) {
+        let response = parse_response(block)
+            .ok_or_else(|| invalid_output(start + 2, "unexpected response"))?;
+        responses.push(response);
+        start += block.len() + 1;
+    }
+    Ok(TxnOutput {
+        succeeded,
+        responses,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::output::{parse_response, parse_txn_output, KeyValue, OpResponse};
+
+    const KV: KeyValue = KeyValue {
+        key: b"k",
+        value: b"v",
+    };
+
+    #[test]
+    fn test_parse_response() {
+        for (lines, expected) in [
+            (&[&b"OK"[..]][..], OpResponse::Put { prev_kv: None }),
+            (&[b"OK", b"k", b"v"], OpResponse::Put { prev_kv: Some(KV) }),
+            (
+                &[b"0"],
+                OpResponse::Delete {
+                    deleted: 0,
+                    prev_kvs: vec![],
+                },
+            ),
+            (
+                &[b"1", b"k", b"v"],
+                OpResponse::Delete {
+                    deleted: 1,
+                    prev_kvs: vec![KV],
+                },
+            ),
+            (&[], OpResponse::Get { kvs: vec![] }),
+            (&[b"k", b"v"], OpResponse::Get { kvs: vec![KV] }),
+            // Keys looking like a put or a delete
+            (
+                &[b"OK", b"v"],
+                OpResponse::Get {
+                    kvs: vec![KeyValue {
+                        key: b"OK",
+                        value: b"v",
+                    }],
+                },
+            ),
+            (
+                &[b"1", b"v"],
+                OpResponse::Get {
+                    kvs: vec![KeyValue {
+                        key: b"1",
+                        value: b"v",
+                    }],
+                },
+            ),
+        ] {
+            assert_eq!(parse_response(lines), Some(expected), "{lines:?}");
+        }
+
+        for lines in [
+            &[&b"k"[..]][..],
+            &[b"OK", b"k", b"v", b"k", b"v"],
+            &[b"+1"],
+            &[b"k", b"v", b"k"],
+        ] {
+            assert_eq!(parse_response(lines), None, "{lines:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_txn_output() {
+        let output =
+            parse_txn_output(b"FAILURE\r\n\r\nOK\r\n\r\n\r\nk\r\nv\r\n").expect("Failed to parse");
+        assert!(!output.succeeded);
+        assert_eq!(
+            output.responses,
+            vec![
+                OpResponse::Put { prev_kv: None },
+                OpResponse::Get { kvs: vec![] },
+                OpResponse::Get { kvs: vec![KV] },
+            ]
+        );
+
+        let output = parse_txn_output(b"SUCCESS\n").expect("Failed to parse");
+        assert!(output.succeeded);
+        assert!(output.responses.is_empty());
+
+        // A trailing get without key leaves a blank last line
+        let output = parse_txn_output(b"SUCCESS\n\n").expect("Failed to parse");
+        assert_eq!(output.responses, vec![OpResponse::Get { kvs: vec![] }]);
+    }
+
+    #[test]
+    fn test_parse_txn_output_invalid() {
+        for (text, line) in [
+            (&b""[..], 1),
+            (b"OK\n", 1),
+            (b"success\n\nOK\n", 1),
+            (b"SUCCESS\nOK\n", 2),
+            (b"SUCCESS\n\nOK\n\nk\n", 5),
+            (b"SUCCESS\n\nOK\nk\nv\nk\nv\n", 3),
+        ] {
+            match parse_txn_output(text) {
+                Err(Error::InvalidOutput { line: actual, .. }) => {
+                    assert_eq!(actual, line, "{text:?}")
+                }
+                result => panic!("Unexpected result for {text:?}: {result:?}"),
+            }
+        }
+    }
+}
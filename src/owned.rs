@@ -0,0 +1,533 @@
+//! Owned transactional data structures
+//!
+//! [`TxnData`] borrows the parsed input, the types of this module own their
+//! keys and values so they can outlive the input buffer.
+//!
+//! The owned keys and values are the bytes the written ones stand for, their
+//! escape sequences decoded, see [`decode`](crate::escape::decode). Data with
+//! an invalid escape sequence is kept as written.
+
+use crate::compare::{Compare, CustomCompare, OpType};
+use crate::data::{self, Key};
+use crate::escape::decode_lossy;
+use crate::operation::{Consistency, CustomData, DataKind, GetData, Operation, PutData};
+use crate::TxnData;
+use std::collections::HashMap;
+
+/// An owned [`Compare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompareOwned {
+    /// A create revision compare operation.
+    CreateRevision {
+        /// The key to compare.
+        key: Vec<u8>,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A modify revision compare operation.
+    ModRevision {
+        /// The key to compare.
+        key: Vec<u8>,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A value compare operation.
+    Value {
+        /// The key to compare.
+        key: Vec<u8>,
+        /// The value to compare with.
+        value: Vec<u8>,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A version compare operation.
+    Version {
+        /// The key to compare.
+        key: Vec<u8>,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A lease compare operation.
+    Lease {
+        /// The key to compare.
+        key: Vec<u8>,
+        /// The value to compare with.
+        value: u64,
+        /// The comparison operator.
+        op: OpType,
+    },
+    /// A compare parsed by a registered parser.
+    Custom {
+        /// The target of the compare.
+        target: Vec<u8>,
+        /// The arguments returned by the parser.
+        args: Vec<Vec<u8>>,
+    },
+    /// A line kept as is because it can't be parsed.
+    Unknown(Vec<u8>),
+}
+
+impl CompareOwned {
+    /// Borrow the compare as a [`Compare`].
+    pub fn as_compare(&self) -> Compare<'_> {
+        match self {
+            CompareOwned::CreateRevision { key, value, op } => {
+                Compare::create_revision(key, *op, *value)
+            }
+            CompareOwned::ModRevision { key, value, op } => Compare::mod_revision(key, *op, *value),
+            CompareOwned::Value { key, value, op } => Compare::value(key, *op, value),
+            CompareOwned::Version { key, value, op } => Compare::version(key, *op, *value),
+            CompareOwned::Lease { key, value, op } => Compare::lease(key, *op, *value),
+            CompareOwned::Custom { target, args } => Compare::Custom(CustomCompare {
+                target,
+                args: args.iter().map(Vec::as_slice).collect(),
+            }),
+            CompareOwned::Unknown(line) => Compare::Unknown(line),
+        }
+    }
+}
+
+impl From<&Compare<'_>> for CompareOwned {
+    fn from(compare: &Compare<'_>) -> Self {
+        match compare {
+            Compare::CreateRevision(compare) => CompareOwned::CreateRevision {
+                key: decode_lossy(&compare.key, compare.key_kind),
+                value: compare.value,
+                op: compare.op,
+            },
+            Compare::ModRevision(compare) => CompareOwned::ModRevision {
+                key: decode_lossy(&compare.key, compare.key_kind),
+                value: compare.value,
+                op: compare.op,
+            },
+            Compare::Value(compare) => CompareOwned::Value {
+                key: decode_lossy(&compare.key, compare.key_kind),
+                value: decode_lossy(&compare.value, compare.value_kind),
+                op: compare.op,
+            },
+            Compare::Version(compare) => CompareOwned::Version {
+                key: decode_lossy(&compare.key, compare.key_kind),
+                value: compare.value,
+                op: compare.op,
+            },
+            Compare::Lease(compare) => CompareOwned::Lease {
+                key: decode_lossy(&compare.key, compare.key_kind),
+                value: compare.value,
+                op: compare.op,
+            },
+            Compare::Custom(custom) => CompareOwned::Custom {
+                target: custom.target.to_vec(),
+                args: custom.args.iter().map(|arg| arg.to_vec()).collect(),
+            },
+            Compare::Unknown(line) => CompareOwned::Unknown(line.to_vec()),
+        }
+    }
+}
+
+/// An owned [`Operation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationOwned {
+    /// A put operation.
+    Put {
+        /// The key to put.
+        key: Vec<u8>,
+        /// The value to put.
+        value: Vec<u8>,
+        /// The lease to attach the key to.
+        lease: Option<u64>,
+        /// The flags the parser doesn't know, along with their value.
+        unknown_flags: Vec<(String, Option<String>)>,
+    },
+    /// A delete operation.
+    Delete {
+        /// The key to delete.
+        key: Vec<u8>,
+    },
+    /// A get operation.
+    Get {
+        /// The key to get.
+        key: Vec<u8>,
+        /// Get the keys starting with the key.
+        prefix: bool,
+        /// Get the keys from the key on.
+        from_key: bool,
+        /// The end of the range of keys to get, excluded.
+        range_end: Option<Vec<u8>>,
+        /// Only get the keys created at or after this revision.
+        min_create_rev: Option<i64>,
+        /// Only get the keys created at or before this revision.
+        max_create_rev: Option<i64>,
+        /// Only get the keys modified at or after this revision.
+        min_mod_rev: Option<i64>,
+        /// Only get the keys modified at or before this revision.
+        max_mod_rev: Option<i64>,
+        /// The consistency of the read, linearizable when `None`.
+        consistency: Option<Consistency>,
+        /// The flags the parser doesn't know, along with their value.
+        unknown_flags: Vec<(String, Option<String>)>,
+    },
+    /// A put operation whose value is read from a file.
+    PutFile {
+        /// The key to put.
+        key: Vec<u8>,
+        /// The path of the file holding the value.
+        path: Vec<u8>,
+    },
+    /// An operation doing nothing.
+    Noop,
+    /// An operation parsed by a registered parser.
+    Custom {
+        /// The command of the operation.
+        command: Vec<u8>,
+        /// The arguments returned by the parser.
+        args: Vec<Vec<u8>>,
+    },
+    /// A line kept as is because it can't be parsed.
+    Unknown(Vec<u8>),
+}
+
+impl OperationOwned {
+    /// Borrow the operation as an [`Operation`].
+    pub fn as_operation(&self) -> Operation<'_> {
+        match self {
+            OperationOwned::Put {
+                key,
+                value,
+                lease,
+                unknown_flags,
+            } => Operation::Put(PutData {
+                key: Key(key),
+                value: data::Value(value),
+                lease: *lease,
+                key_kind: DataKind::default(),
+                value_kind: DataKind::default(),
+                unknown_flags: unknown_flags.clone(),
+            }),
+            OperationOwned::Delete { key } => Operation::delete(key),
+            OperationOwned::Get {
+                key,
+                prefix,
+                from_key,
+                range_end,
+                min_create_rev,
+                max_create_rev,
+                min_mod_rev,
+                max_mod_rev,
+                consistency,
+                unknown_flags,
+            } => Operation::Get(GetData {
+                prefix: *prefix,
+                from_key: *from_key,
+                range_end: range_end.as_deref().map(Key),
+                min_create_rev: *min_create_rev,
+                max_create_rev: *max_create_rev,
+                min_mod_rev: *min_mod_rev,
+                max_mod_rev: *max_mod_rev,
+                consistency: *consistency,
+                unknown_flags: unknown_flags.clone(),
+                ..GetData::new(key)
+            }),
+            OperationOwned::PutFile { key, path } => Operation::put_file(key, path),
+            OperationOwned::Noop => Operation::Noop,
+            OperationOwned::Custom { command, args } => Operation::Custom(CustomData {
+                command,
+                args: args.iter().map(Vec::as_slice).collect(),
+            }),
+            OperationOwned::Unknown(line) => Operation::Unknown(line),
+        }
+    }
+}
+
+impl From<&Operation<'_>> for OperationOwned {
+    fn from(operation: &Operation<'_>) -> Self {
+        match operation {
+            Operation::Put(put) => OperationOwned::Put {
+                key: decode_lossy(&put.key, put.key_kind),
+                value: decode_lossy(&put.value, put.value_kind),
+                lease: put.lease,
+                unknown_flags: put.unknown_flags.clone(),
+            },
+            Operation::Delete(delete) => OperationOwned::Delete {
+                key: decode_lossy(&delete.key, delete.key_kind),
+            },
+            Operation::Get(get) => OperationOwned::Get {
+                key: decode_lossy(&get.key, get.key_kind),
+                prefix: get.prefix,
+                from_key: get.from_key,
+                range_end: get
+                    .range_end
+                    .map(|range_end| decode_lossy(&range_end, get.range_end_kind)),
+                min_create_rev: get.min_create_rev,
+                max_create_rev: get.max_create_rev,
+                min_mod_rev: get.min_mod_rev,
+                max_mod_rev: get.max_mod_rev,
+                consistency: get.consistency,
+                unknown_flags: get.unknown_flags.clone(),
+            },
+            Operation::PutFile(put) => OperationOwned::PutFile {
+                key: decode_lossy(&put.key, put.key_kind),
+                path: put.path.to_vec(),
+            },
+            Operation::Noop => OperationOwned::Noop,
+            Operation::Custom(custom) => OperationOwned::Custom {
+                command: custom.command.to_vec(),
+                args: custom.args.iter().map(|arg| arg.to_vec()).collect(),
+            },
+            Operation::Unknown(line) => OperationOwned::Unknown(line.to_vec()),
+        }
+    }
+}
+
+/// An owned [`TxnData`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxnDataOwned {
+    /// A list of operations to compare against the current state.
+    pub compares: Vec<CompareOwned>,
+    /// A list of operations to apply if the compare operations pass.
+    pub success: Vec<OperationOwned>,
+    /// A list of operations to apply if the compare operations fail.
+    pub failure: Vec<OperationOwned>,
+}
+
+impl TxnDataOwned {
+    /// Borrow the transaction as a [`TxnData`].
+    pub fn as_txn(&self) -> TxnData<'_> {
+        TxnData {
+            compares: self.compares.iter().map(CompareOwned::as_compare).collect(),
+            success: self
+                .success
+                .iter()
+                .map(OperationOwned::as_operation)
+                .collect(),
+            failure: self
+                .failure
+                .iter()
+                .map(OperationOwned::as_operation)
+                .collect(),
+        }
+    }
+}
+
+impl TxnDataOwned {
+    /// Replace every file reference by a put of the value returned by `resolve`
+    /// for its path.
+    ///
+    /// # Errors
+    ///
+    /// Stops at the first error returned by `resolve`.
+    pub fn resolve_file_refs<E>(
+        &mut self,
+        mut resolve: impl FnMut(&[u8]) -> Result<Vec<u8>, E>,
+    ) -> Result<(), E> {
+        for operation in self.success.iter_mut().chain(&mut self.failure) {
+            if let OperationOwned::PutFile { key, path } = operation {
+                *operation = OperationOwned::Put {
+                    key: std::mem::take(key),
+                    value: resolve(path)?,
+                    lease: None,
+                    unknown_flags: vec![],
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the transaction replacing each distinct key by a placeholder, `k0`
+    /// for the first key met, `k1` for the next one and so on.
+    ///
+    /// The keys are numbered by first occurrence through the compares, then
+    /// the success and the failure branches, so that the output is stable for
+    /// snapshot tests. A value equal to one of the keys is replaced by the
+    /// placeholder of the key, the other values are kept.
+    pub fn anonymize_keys(&self) -> TxnDataOwned {
+        let txn = self.as_txn();
+        let keys = txn.compares.iter().filter_map(Compare::key).chain(
+            txn.success
+                .iter()
+                .chain(&txn.failure)
+                .filter_map(Operation::key),
+        );
+        let mut placeholders = HashMap::new();
+        for key in keys {
+            let next = placeholders.len();
+            placeholders
+                .entry(key)
+                .or_insert_with(|| format!("k{next}").into_bytes());
+        }
+        let rename = |data: &mut Vec<u8>| {
+            if let Some(placeholder) = placeholders.get(data.as_slice()) {
+                data.clone_from(placeholder);
+            }
+        };
+
+        let mut owned = self.clone();
+        for compare in &mut owned.compares {
+            match compare {
+                CompareOwned::Value { key, value, .. } => {
+                    rename(key);
+                    rename(value);
+                }
+                CompareOwned::CreateRevision { key, .. }
+                | CompareOwned::ModRevision { key, .. }
+                | CompareOwned::Version { key, .. }
+                | CompareOwned::Lease { key, .. } => rename(key),
+                CompareOwned::Custom { .. } | CompareOwned::Unknown(_) => {}
+            }
+        }
+        for operation in owned.success.iter_mut().chain(&mut owned.failure) {
+            match operation {
+                OperationOwned::Put { key, value, .. } => {
+                    rename(key);
+                    rename(value);
+                }
+                OperationOwned::Get { key, range_end, .. } => {
+                    rename(key);
+                    if let Some(range_end) = range_end {
+                        rename(range_end);
+                    }
+                }
+                OperationOwned::Delete { key } | OperationOwned::PutFile { key, .. } => rename(key),
+                OperationOwned::Noop
+                | OperationOwned::Custom { .. }
+                | OperationOwned::Unknown(_) => {}
+            }
+        }
+        owned
+    }
+}
+
+impl From<&TxnData<'_>> for TxnDataOwned {
+    fn from(txn: &TxnData<'_>) -> Self {
+        TxnDataOwned {
+            compares: txn.compares.iter().map(CompareOwned::from).collect(),
+            success: txn.success.iter().map(OperationOwned::from).collect(),
+            failure: txn.failure.iter().map(OperationOwned::from).collect(),
+        }
+    }
+}
+
+impl TxnData<'_> {
+    /// Copy the transaction into a [`TxnDataOwned`] which doesn't borrow the input.
+    pub fn to_owned(&self) -> TxnDataOwned {
+        TxnDataOwned::from(self)
+    }
+
+    /// Copy the transaction replacing the lease IDs of the lease compares and
+    /// the leased put operations found in `leases`, the other IDs are kept.
+    ///
+    /// Useful to replay a transaction against a cluster granting other lease
+    /// IDs.
+    pub fn rewrite_leases(&self, leases: &HashMap<u64, u64>) -> TxnDataOwned {
+        let mut owned = self.to_owned();
+        let ids = owned
+            .compares
+            .iter_mut()
+            .filter_map(|compare| match compare {
+                CompareOwned::Lease { value, .. } => Some(value),
+                _ => None,
+            })
+            .chain(
+                owned
+                    .success
+                    .iter_mut()
+                    .chain(&mut owned.failure)
+                    .filter_map(|operation| match operation {
+                        OperationOwned::Put {
+                            lease: Some(lease), ..
+                        } => Some(lease),
+                        _ => None,
+                    }),
+            );
+        for id in ids {
+            if let Some(&rewritten) = leases.get(id) {
+                *id = rewritten;
+            }
+        }
+        owned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::OpType;
+    use crate::owned::{CompareOwned, OperationOwned, TxnDataOwned};
+    use crate::parse;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_to_owned() {
+        let owned = {
+            let data = b"mod(\"key1\") > 0\n\nput key1 \"overwrote-key1\"\n\ndel key2".to_vec();
+            parse(&data).expect("Failed to parse").to_owned()
+        };
+        assert_eq!(
+            owned,
+            TxnDataOwned {
+                compares: vec![CompareOwned::ModRevision {
+                    key: b"key1".to_vec(),
+                    value: 0,
+                    op: OpType::GreaterThan
+                }],
+                success: vec![OperationOwned::Put {
+                    key: b"key1".to_vec(),
+                    value: b"overwrote-key1".to_vec(),
+                    lease: None,
+                    unknown_flags: vec![]
+                }],
+                failure: vec![OperationOwned::Delete {
+                    key: b"key2".to_vec()
+                }]
+            }
+        );
+        assert_eq!(owned.as_txn().to_owned(), owned);
+
+        // Handed off to another thread
+        let sent = owned.clone();
+        let received = std::thread::spawn(move || sent)
+            .join()
+            .expect("Failed to join");
+        assert_eq!(received, owned);
+    }
+
+    #[test]
+    fn test_rewrite_leases() {
+        let txn = parse(
+            b"lease(key1) = 31\n\nput key1 v --lease=1f\nput key2 v --lease=2a\n\nput key3 v",
+        )
+        .expect("Failed to parse");
+        let rewritten = txn.rewrite_leases(&HashMap::from([(0x1f, 0x7b), (0x99, 0x1)]));
+        assert_eq!(
+            rewritten,
+            parse(
+                b"lease(key1) = 123\n\nput key1 v --lease=7b\nput key2 v --lease=2a\n\nput key3 v"
+            )
+            .expect("Failed to parse")
+            .to_owned()
+        );
+        assert_eq!(txn.rewrite_leases(&HashMap::new()), txn.to_owned());
+    }
+
+    #[test]
+    fn test_anonymize_keys() {
+        let txn = parse(
+            b"val(users/1) = users/2\nmod(\"users/2\") > 0\n\nput users/3 x\ndel users/1\n\nget --prefix users/\nput users/3 users/1\n",
+        )
+        .expect("Failed to parse")
+        .to_owned();
+        let anonymized = txn.anonymize_keys();
+        assert_eq!(
+            anonymized,
+            parse(b"val(k0) = k1\nmod(k1) > 0\n\nput k2 x\ndel k0\n\nget --prefix k3\nput k2 k0\n")
+                .expect("Failed to parse")
+                .to_owned()
+        );
+        // Already anonymized keys keep their placeholders
+        assert_eq!(anonymized.anonymize_keys(), anonymized);
+    }
+}
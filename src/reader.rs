@@ -0,0 +1,397 @@
+//! Parsing of transactions read from a reader
+
+use crate::error::{locate, Error};
+use crate::options::ParseOptions;
+use crate::owned::TxnDataOwned;
+use crate::{is_blank, parse, parse_collect_errors, parse_with_options, QuoteTracker};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
+
+/// Read a whole transaction from a reader and parse it.
+///
+/// The transaction is returned in its owned form as the read buffer is
+/// internal.
+///
+/// # Errors
+///
+/// Returns an `Error::Io` if the reader fails and an `Error::Parse` if the
+/// transaction can't be parsed.
+pub fn parse_reader<R: Read>(mut reader: R) -> Result<TxnDataOwned, Error> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    Ok(parse(&data)?.to_owned())
+}
+
+/// An error reading or parsing a transaction file, see [`parse_file`].
+///
+/// Displayed with the path and the position of the error first, e.g.
+/// `foo.txn:2:1: parse error: unexpected token`.
+#[derive(Debug)]
+pub struct FileError {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The line and the column of the error, both from 1, if the error
+    /// points at a position in the file.
+    pub position: Option<(usize, usize)>,
+    /// The error, an `Error::Io` if the file couldn't be read.
+    pub error: Error,
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.path.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, "{line}:{column}:")?;
+        }
+        write!(f, " {}", self.error)
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The position in `data` the error points at, if any. A parse error points
+/// at the first line which can't be parsed.
+fn error_offset(data: &[u8], err: &Error) -> Option<usize> {
+    match err {
+        Error::Parse(_) => parse_collect_errors(data)
+            .1
+            .first()
+            .map(|(_, offset, _)| *offset),
+        err => err.offset(),
+    }
+}
+
+/// Read a transaction file and parse it.
+///
+/// The transaction is returned in its owned form as the read buffer is
+/// internal.
+///
+/// # Errors
+///
+/// Returns a [`FileError`] holding the path of the file, along with an
+/// `Error::Io` if the file can't be read, or the parse error and its position
+/// if the transaction can't be parsed.
+pub fn parse_file(path: impl AsRef<Path>) -> Result<TxnDataOwned, FileError> {
+    let path = path.as_ref();
+    let fail = |error, position| FileError {
+        path: path.to_path_buf(),
+        position,
+        error,
+    };
+    let data = std::fs::read(path).map_err(|err| fail(err.into(), None))?;
+    parse(&data).map(|txn| txn.to_owned()).map_err(|err| {
+        let position = error_offset(&data, &err).map(|offset| {
+            let (_, line, column) = locate(&data, offset);
+            (line, column)
+        });
+        fail(err, position)
+    })
+}
+
+/// Read a whole transaction from an asynchronous reader and parse it.
+///
+/// The input is read chunk by chunk without blocking, then parsed once the
+/// reader reaches its end.
+///
+/// # Cancellation safety
+///
+/// Everything read so far lives in a buffer owned by the future: dropping the
+/// future mid-parse releases it and leaves no shared state behind, but the
+/// bytes already consumed from the reader are lost.
+///
+/// # Errors
+///
+/// Returns an `Error::Io` if the reader fails and an `Error::Parse` if the
+/// transaction can't be parsed.
+#[cfg(feature = "tokio")]
+pub async fn parse_async<R: tokio::io::AsyncRead + Unpin>(
+    mut reader: R,
+) -> Result<TxnDataOwned, Error> {
+    use tokio::io::AsyncReadExt;
+
+    let mut data = vec![];
+    reader.read_to_end(&mut data).await?;
+    Ok(parse(&data)?.to_owned())
+}
+
+/// An iterator over the transactions of a reader, separated by `---` lines:
+///
+/// ```text
+/// mod(key1) > 0
+///
+/// put key1 value1
+///
+/// ---
+/// put key2 value2
+/// ```
+///
+/// Only the lines of the transaction being read are buffered, each one being
+/// parsed once the line ending it is read, so that a log larger than the
+/// memory can be gone through. A `---` line inside a quoted value doesn't end
+/// its transaction, and blank transactions, e.g. after a trailing `---`, are
+/// skipped.
+///
+/// A quoted value may span several lines, so a quote left open, e.g. the one
+/// of `put key "value`, runs until the end of the input: the rest of the
+/// input is read as a single transaction which fails to parse.
+///
+/// A transaction which can't be parsed yields its error and the iteration
+/// goes on with the next one. A failing reader ends the iteration.
+pub struct TxnReader<R> {
+    reader: R,
+    options: ParseOptions,
+    quotes: QuoteTracker<'static>,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> TxnReader<R> {
+    /// Read the transactions of `reader` with the default options.
+    pub fn new(reader: R) -> Self {
+        TxnReader::with_options(reader, ParseOptions::default())
+    }
+
+    /// Read the transactions of `reader`, each one being parsed with
+    /// `options`.
+    pub fn with_options(reader: R, options: ParseOptions) -> Self {
+        TxnReader {
+            reader,
+            quotes: QuoteTracker::new(options.quote_chars),
+            options,
+            buffer: vec![],
+            done: false,
+        }
+    }
+
+    /// Read the lines of the next transaction, `None` at the end of the input.
+    fn read_txn(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        loop {
+            let start = self.buffer.len();
+            if self.reader.read_until(b'\n', &mut self.buffer)? == 0 {
+                self.done = true;
+                let txn = std::mem::take(&mut self.buffer);
+                return Ok(Some(txn).filter(|txn| !is_blank(txn)));
+            }
+            let line = self.buffer.get(start..).unwrap_or_default();
+            let separator = self.quotes.open().is_none() && line.trim_ascii() == b"---";
+            for &byte in line {
+                self.quotes.is_quoted(byte);
+            }
+            if separator {
+                self.buffer.truncate(start);
+                let txn = std::mem::take(&mut self.buffer);
+                if !is_blank(&txn) {
+                    // The next transaction starts outside of any quote
+                    self.quotes = QuoteTracker::new(self.options.quote_chars);
+                    return Ok(Some(txn));
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TxnReader<R> {
+    type Item = Result<TxnDataOwned, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_txn() {
+            Ok(txn) => {
+                let txn = txn?;
+                Some(parse_with_options(&txn, &self.options).map(|txn| txn.to_owned()))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::parse;
+    use crate::reader::{parse_file, parse_reader, TxnReader};
+    use std::io::{BufReader, Read};
+
+    const TRANSACTION: &[u8] =
+        b"mod(\"key1\") > 0\n\nput key1 \"overwrote-key1\"\n\nput \"key1\" \"created-key1\"\nput key2 \"some extra key\"";
+
+    #[test]
+    fn test_parse_reader() {
+        let txn = parse_reader(TRANSACTION).expect("Failed to parse");
+        assert_eq!(txn, parse(TRANSACTION).expect("Failed to parse").to_owned());
+
+        let result = parse_reader(&b"not a transaction"[..]);
+        assert!(matches!(result, Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("etcd-txn-parser-{}.txn", std::process::id()));
+
+        let err = parse_file(&path).expect_err("Expected an error");
+        assert_eq!(err.path, path);
+        assert!(matches!(err.error, Error::Io(_)));
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}: I/O error", path.display())));
+
+        // A directory can't be read
+        let err = parse_file(&dir).expect_err("Expected an error");
+        assert!(matches!(err.error, Error::Io(_)));
+        assert_eq!(err.position, None);
+
+        std::fs::write(&path, TRANSACTION).expect("Failed to write");
+        let txn = parse_file(&path);
+        std::fs::write(&path, "mod(key1) > 0\nmod(key2) >> 0\n\nput key1 v\n\n")
+            .expect("Failed to write");
+        let malformed = parse_file(&path);
+        std::fs::write(&path, "mod(key1) > 0\nmod(key2) > 5x\n\nput key1 v\n\n")
+            .expect("Failed to write");
+        let junk = parse_file(&path);
+        std::fs::remove_file(&path).expect("Failed to remove");
+
+        assert_eq!(
+            txn.expect("Failed to parse"),
+            parse(TRANSACTION).expect("Failed to parse").to_owned()
+        );
+        let err = malformed.expect_err("Expected an error");
+        assert_eq!(err.position, Some((2, 1)));
+        assert_eq!(
+            err.to_string(),
+            format!("{}:2:1: parse error: unexpected token", path.display())
+        );
+        let err = junk.expect_err("Expected an error");
+        assert_eq!(err.position, Some((2, 14)));
+    }
+
+    /// A reader delivering its input a few bytes at a time.
+    struct Chunked<'a>(&'a [u8]);
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(3);
+            let (chunk, rest) = self.0.split_at(len);
+            buf[..len].copy_from_slice(chunk);
+            self.0 = rest;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_txn_reader() {
+        let second = b"\n\nput key2 \"a\n---\nb\"\n\nget key2\n";
+        let input = [
+            TRANSACTION,
+            b"\n---\n",
+            second,
+            b"---\r\n---\nnot a transaction\n---\n\n\nput key3 value3\n\n---\n",
+        ]
+        .concat();
+        let reader = TxnReader::new(BufReader::with_capacity(4, Chunked(&input)));
+        let transactions: Vec<_> = reader.collect();
+        let [Ok(first), Ok(quoted), Err(Error::Parse(_)), Ok(last)] = transactions.as_slice()
+        else {
+            panic!("Unexpected transactions: {transactions:?}");
+        };
+        assert_eq!(
+            *first,
+            parse(TRANSACTION).expect("Failed to parse").to_owned()
+        );
+        // The separator inside the quoted value is part of the value
+        assert_eq!(*quoted, parse(second).expect("Failed to parse").to_owned());
+        assert_eq!(
+            *last,
+            parse(b"\n\nput key3 value3\n\n")
+                .expect("Failed to parse")
+                .to_owned()
+        );
+
+        assert_eq!(TxnReader::new(&b"\n---\n\n"[..]).count(), 0);
+    }
+
+    #[test]
+    fn test_txn_reader_unbalanced_quote() {
+        // The open quote takes the following separators and transactions
+        let input =
+            b"\n\nput key1 \"value1\n---\n\n\nput key2 value2\n\n---\n\n\nput key3 value3\n";
+        let transactions: Vec<_> = TxnReader::new(&input[..]).collect();
+        let [Err(Error::Parse(_))] = transactions.as_slice() else {
+            panic!("Unexpected transactions: {transactions:?}");
+        };
+
+        // A balanced quote leaves the next transaction alone
+        let input = b"\n\nput key1 \"a\nb\"\n\n---\n\n\nput key2 \"value2\"\n\n";
+        let transactions: Vec<_> = TxnReader::new(&input[..]).collect();
+        let [Ok(_), Ok(last)] = transactions.as_slice() else {
+            panic!("Unexpected transactions: {transactions:?}");
+        };
+        assert_eq!(
+            *last,
+            parse(b"\n\nput key2 \"value2\"\n\n")
+                .expect("Failed to parse")
+                .to_owned()
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_parse_async() {
+        use crate::reader::parse_async;
+        use std::time::Duration;
+
+        // Split the input across several reads, one of them inside a quoted value
+        let (first, rest) = TRANSACTION.split_at(11);
+        let (second, rest) = rest.split_at(25);
+        let (third, fourth) = rest.split_at(30);
+        assert_eq!(second, b" > 0\n\nput key1 \"overwrote");
+
+        let reader = tokio_test::io::Builder::new()
+            .read(first)
+            .read(second)
+            .wait(Duration::from_millis(1))
+            .read(third)
+            .read(fourth)
+            .build();
+
+        let txn = tokio_test::block_on(parse_async(reader)).expect("Failed to parse");
+        assert_eq!(txn, parse(TRANSACTION).expect("Failed to parse").to_owned());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_parse_async_cancelled() {
+        use crate::reader::parse_async;
+        use std::future::Future;
+        use std::pin::pin;
+        use std::task::{Context, Poll, Waker};
+        use tokio::io::AsyncWriteExt;
+
+        tokio_test::block_on(async {
+            let (mut writer, reader) = tokio::io::duplex(1024);
+            writer
+                .write_all(&TRANSACTION[..20])
+                .await
+                .expect("Failed to write");
+
+            {
+                let mut future = pin!(parse_async(reader));
+                let mut context = Context::from_waker(Waker::noop());
+                assert!(matches!(future.as_mut().poll(&mut context), Poll::Pending));
+                // Dropping the future mid-parse releases the reader and the partial input
+            }
+
+            assert!(writer.write_all(&TRANSACTION[20..]).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,858 @@
+//! Client code generation
+//!
+//! Renders a transaction as the equivalent Rust code for the
+//! [`etcd-client`](https://docs.rs/etcd-client) crate or Go code for the
+//! [`clientv3`](https://pkg.go.dev/go.etcd.io/etcd/client/v3) package, to
+//! move ad-hoc transaction files into an application, or as the Rust
+//! expression building the parsed [`TxnData`] itself, for test fixtures.
+
+use crate::compare::{Compare, OpType};
+use crate::convert::{decoded, Construct, ConvertError, Converted, Converter, Lossy};
+use crate::data::Key;
+use crate::error::Error;
+use crate::escape::decode;
+use crate::operation::{Consistency, DataKind, GetData, Operation};
+use crate::{Section, TxnData};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// The number as sent on the wire, etcd integers being signed.
+fn int(value: u64) -> i64 {
+    value as i64
+}
+
+/// A lease ID, in hexadecimal like `etcdctl` prints it.
+fn lease_id(lease: u64) -> String {
+    match int(lease) {
+        lease if lease >= 0 => format!("0x{lease:x}"),
+        lease => lease.to_string(),
+    }
+}
+
+/// The revision filters of a get operation, along with their option name in
+/// the Rust and in the Go client.
+fn get_filters(get: &GetData) -> [(Option<i64>, &'static str, &'static str); 4] {
+    [
+        (get.min_create_rev, "min_create_revision", "MinCreateRev"),
+        (get.max_create_rev, "max_create_revision", "MaxCreateRev"),
+        (get.min_mod_rev, "min_mod_revision", "MinModRev"),
+        (get.max_mod_rev, "max_mod_revision", "MaxModRev"),
+    ]
+}
+
+/// Write the rendered items between `open` and `close`, one per line indented
+/// by `indent`. The closing delimiter is indented by half of `indent`, lining
+/// it up with the opening line.
+fn write_call(
+    snippet: &mut String,
+    (open, close): (&str, &str),
+    indent: &str,
+    items: impl IntoIterator<Item = String>,
+) {
+    snippet.push_str(open);
+    let mut items = items.into_iter().peekable();
+    if items.peek().is_some() {
+        snippet.push('\n');
+        for item in items {
+            let _ = writeln!(snippet, "{indent}{item},");
+        }
+        snippet.push_str(&indent[..indent.len() / 2]);
+    }
+    snippet.push_str(close);
+}
+
+/// A line comment standing for a node which can't be rendered, for the best
+/// effort conversions. Rust and Go share the syntax, and the comma following
+/// the item is commented out along with it.
+fn comment(
+    construct: Construct,
+    write: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+) -> String {
+    let mut line = vec![];
+    let _ = write(&mut line);
+    format!("// {construct}: {:?}", String::from_utf8_lossy(&line))
+}
+
+/// Render the sections of the transaction with `render_compare` and
+/// `render_operation`, applying `lossy` to the nodes which can't be rendered.
+fn render_sections(
+    txn: &TxnData,
+    lossy: Lossy,
+    render_compare: impl Fn(&Compare) -> Result<String, Construct>,
+    render_operation: impl Fn(&Operation) -> Result<String, Construct>,
+) -> Result<Converted<[Vec<String>; 3]>, ConvertError> {
+    let mut converter = Converter::new(lossy);
+    let compare_comment =
+        |compare: &Compare, construct| Some(comment(construct, |line| compare.write_to(line)));
+    let operation_comment = |operation: &Operation, construct| {
+        Some(comment(construct, |line| operation.write_to(line)))
+    };
+    let sections = [
+        converter.convert(
+            Section::Compare,
+            &txn.compares,
+            render_compare,
+            compare_comment,
+        )?,
+        converter.convert(
+            Section::Success,
+            &txn.success,
+            &render_operation,
+            operation_comment,
+        )?,
+        converter.convert(
+            Section::Failure,
+            &txn.failure,
+            &render_operation,
+            operation_comment,
+        )?,
+    ];
+    Ok(converter.finish(sections))
+}
+
+// ----------------------------------------------------------------------------
+// Rust
+// ----------------------------------------------------------------------------
+
+/// A Rust byte string literal.
+fn rust_bytes(data: &[u8]) -> String {
+    let escaped: String = data
+        .iter()
+        .flat_map(|&byte| std::ascii::escape_default(byte))
+        .map(char::from)
+        .collect();
+    format!("b\"{escaped}\"")
+}
+
+fn rust_compare_op(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "CompareOp::Equal",
+        OpType::GreaterThan => "CompareOp::Greater",
+        OpType::LessThan => "CompareOp::Less",
+        OpType::NotEqual => "CompareOp::NotEqual",
+    }
+}
+
+fn rust_compare(compare: &Compare) -> Result<String, Construct> {
+    let (function, op, value) = match compare {
+        Compare::CreateRevision(compare) => (
+            "create_revision",
+            compare.op,
+            int(compare.value).to_string(),
+        ),
+        Compare::ModRevision(compare) => {
+            ("mod_revision", compare.op, int(compare.value).to_string())
+        }
+        Compare::Value(compare) => (
+            "value",
+            compare.op,
+            rust_bytes(&decoded(compare.decoded_value())?),
+        ),
+        Compare::Version(compare) => ("version", compare.op, int(compare.value).to_string()),
+        Compare::Lease(compare) => ("lease", compare.op, lease_id(compare.value)),
+        Compare::Custom(_) => return Err(Construct::CustomCompare),
+        Compare::Unknown(_) => return Err(Construct::UnknownNode),
+    };
+    let key = decoded(compare.decoded_key())?.unwrap_or_default();
+    Ok(format!(
+        "Compare::{function}({}, {}, {value})",
+        rust_bytes(&key),
+        rust_compare_op(op)
+    ))
+}
+
+fn rust_operation(operation: &Operation) -> Result<String, Construct> {
+    if !operation.unknown_flags().is_empty() {
+        return Err(Construct::UnknownFlag);
+    }
+    let snippet = match operation {
+        Operation::Put(put) => {
+            let options = match put.lease {
+                Some(lease) => format!("Some(PutOptions::new().with_lease({}))", lease_id(lease)),
+                None => "None".to_string(),
+            };
+            format!(
+                "TxnOp::put({}, {}, {options})",
+                rust_bytes(&decoded(put.decoded_key())?),
+                rust_bytes(&decoded(put.decoded_value())?)
+            )
+        }
+        Operation::Delete(delete) => format!(
+            "TxnOp::delete({}, None)",
+            rust_bytes(&decoded(delete.decoded_key())?)
+        ),
+        Operation::Get(get) => {
+            let mut options = String::new();
+            if get.prefix {
+                options += ".with_prefix()";
+            }
+            if get.from_key {
+                options += ".with_from_key()";
+            }
+            if let Some(range_end) = decoded(get.decoded_range_end())? {
+                let _ = write!(options, ".with_range({})", rust_bytes(&range_end));
+            }
+            if get.consistency == Some(Consistency::Serializable) {
+                options += ".with_serializable()";
+            }
+            for (revision, name, _) in get_filters(get) {
+                if let Some(revision) = revision {
+                    let _ = write!(options, ".with_{name}({revision})");
+                }
+            }
+            let options = match options.is_empty() {
+                true => "None".to_string(),
+                false => format!("Some(GetOptions::new(){options})"),
+            };
+            format!(
+                "TxnOp::get({}, {options})",
+                rust_bytes(&decoded(get.decoded_key())?)
+            )
+        }
+        Operation::PutFile(_) => return Err(Construct::UnresolvedFileRef),
+        Operation::Noop => return Err(Construct::Noop),
+        Operation::Custom(_) => return Err(Construct::CustomOperation),
+        Operation::Unknown(_) => return Err(Construct::UnknownNode),
+    };
+    Ok(snippet)
+}
+
+// ----------------------------------------------------------------------------
+// Rust literal
+// ----------------------------------------------------------------------------
+
+/// A struct literal wrapped in an enum variant, one field per line. The
+/// fields are indented by `indent` and the closing brace lines up with the
+/// variant, indented by `indent` minus four spaces.
+fn rust_struct(variant: &str, fields: &[(&str, String)], indent: &str) -> String {
+    let mut literal = format!("{variant} {{\n");
+    for (name, value) in fields {
+        let _ = writeln!(literal, "{indent}{name}: {value},");
+    }
+    let _ = write!(literal, "{}}})", &indent[4..]);
+    literal
+}
+
+fn rust_option<T>(value: Option<T>, render: impl Fn(T) -> String) -> String {
+    match value {
+        Some(value) => format!("Some({})", render(value)),
+        None => "None".to_string(),
+    }
+}
+
+/// The arguments of a custom node as a vector literal.
+fn rust_args(args: &[&[u8]]) -> String {
+    // Slicing gives the byte strings of different lengths a common type
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| format!("&{}[..]", rust_bytes(arg)))
+        .collect();
+    format!("vec![{}]", args.join(", "))
+}
+
+/// A key or a value as the bytes it stands for, along with its kind as a
+/// [`DataKind`] literal, see [`decode`](crate::escape::decode). Decoded
+/// escape sequences make it [`DataKind::Bytes`], while data holding an
+/// invalid escape sequence is kept as written.
+fn rust_data(data: &[u8], kind: DataKind) -> (Cow<'_, [u8]>, String) {
+    let (data, kind) = match decode(data, kind) {
+        Ok(Cow::Owned(decoded)) => (Cow::Owned(decoded), DataKind::Bytes),
+        Ok(Cow::Borrowed(_)) | Err(_) => (Cow::Borrowed(data), kind),
+    };
+    (data, format!("DataKind::{kind:?}"))
+}
+
+/// A key as a [`Key`](crate::data::Key) literal.
+fn rust_key(key: &[u8]) -> String {
+    format!("Key({})", rust_bytes(key))
+}
+
+/// A value as a [`Value`](crate::data::Value) literal.
+fn rust_value(value: &[u8]) -> String {
+    format!("data::Value({})", rust_bytes(value))
+}
+
+/// The unknown flags of an operation as a vector literal.
+fn rust_flags(flags: &[(String, Option<String>)]) -> String {
+    let flags: Vec<_> = flags
+        .iter()
+        .map(|(name, value)| {
+            let value = rust_option(value.as_ref(), |value| format!("{value:?}.to_string()"));
+            format!("({name:?}.to_string(), {value})")
+        })
+        .collect();
+    format!("vec![{}]", flags.join(", "))
+}
+
+fn rust_literal_compare(compare: &Compare, indent: &str) -> String {
+    let numeric = |key: Key, key_kind: DataKind, value: u64, op: OpType| {
+        let (key, key_kind) = rust_data(&key, key_kind);
+        vec![
+            ("key", rust_key(&key)),
+            ("key_kind", key_kind),
+            ("value", value.to_string()),
+            ("op", format!("OpType::{op:?}")),
+        ]
+    };
+    let (variant, fields) = match compare {
+        Compare::CreateRevision(compare) => (
+            "CreateRevision(CreateRevision",
+            numeric(compare.key, compare.key_kind, compare.value, compare.op),
+        ),
+        Compare::ModRevision(compare) => (
+            "ModRevision(ModRevision",
+            numeric(compare.key, compare.key_kind, compare.value, compare.op),
+        ),
+        Compare::Value(compare) => {
+            let (key, key_kind) = rust_data(&compare.key, compare.key_kind);
+            let (value, value_kind) = rust_data(&compare.value, compare.value_kind);
+            (
+                "Value(Value",
+                vec![
+                    ("key", rust_key(&key)),
+                    ("key_kind", key_kind),
+                    ("value", rust_value(&value)),
+                    ("value_kind", value_kind),
+                    ("op", format!("OpType::{:?}", compare.op)),
+                ],
+            )
+        }
+        Compare::Version(compare) => (
+            "Version(Version",
+            numeric(compare.key, compare.key_kind, compare.value, compare.op),
+        ),
+        Compare::Lease(compare) => (
+            "Lease(Lease",
+            numeric(compare.key, compare.key_kind, compare.value, compare.op),
+        ),
+        Compare::Custom(custom) => {
+            let fields = [
+                ("target", rust_bytes(custom.target)),
+                ("args", rust_args(&custom.args)),
+            ];
+            return rust_struct("Compare::Custom(CustomCompare", &fields, indent);
+        }
+        Compare::Unknown(line) => return format!("Compare::Unknown({})", rust_bytes(line)),
+    };
+    rust_struct(&format!("Compare::{variant}"), &fields, indent)
+}
+
+fn rust_literal_operation(operation: &Operation, indent: &str) -> String {
+    let (variant, fields) = match operation {
+        Operation::Put(put) => {
+            let (key, key_kind) = rust_data(&put.key, put.key_kind);
+            let (value, value_kind) = rust_data(&put.value, put.value_kind);
+            (
+                "Put(PutData",
+                vec![
+                    ("key", rust_key(&key)),
+                    ("value", rust_value(&value)),
+                    (
+                        "lease",
+                        rust_option(put.lease, |lease| format!("0x{lease:x}")),
+                    ),
+                    ("key_kind", key_kind),
+                    ("value_kind", value_kind),
+                    ("unknown_flags", rust_flags(&put.unknown_flags)),
+                ],
+            )
+        }
+        Operation::PutFile(put) => {
+            let (key, key_kind) = rust_data(&put.key, put.key_kind);
+            (
+                "PutFile(PutFileData",
+                vec![
+                    ("key", rust_key(&key)),
+                    ("key_kind", key_kind),
+                    ("path", rust_bytes(put.path)),
+                ],
+            )
+        }
+        Operation::Delete(delete) => {
+            let (key, key_kind) = rust_data(&delete.key, delete.key_kind);
+            (
+                "Delete(DeleteData",
+                vec![("key", rust_key(&key)), ("key_kind", key_kind)],
+            )
+        }
+        Operation::Get(get) => {
+            let revision = |revision: i64| revision.to_string();
+            let (key, key_kind) = rust_data(&get.key, get.key_kind);
+            let range_end = get
+                .range_end
+                .map(|range_end| rust_data(range_end.as_bytes(), get.range_end_kind));
+            // The kind of a missing range end is kept
+            let range_end_kind = match &range_end {
+                Some((_, kind)) => kind.clone(),
+                None => format!("DataKind::{:?}", get.range_end_kind),
+            };
+            (
+                "Get(GetData",
+                vec![
+                    ("key", rust_key(&key)),
+                    ("key_kind", key_kind),
+                    ("prefix", get.prefix.to_string()),
+                    ("from_key", get.from_key.to_string()),
+                    (
+                        "range_end",
+                        rust_option(range_end, |(range_end, _)| rust_key(&range_end)),
+                    ),
+                    ("range_end_kind", range_end_kind),
+                    ("min_create_rev", rust_option(get.min_create_rev, revision)),
+                    ("max_create_rev", rust_option(get.max_create_rev, revision)),
+                    ("min_mod_rev", rust_option(get.min_mod_rev, revision)),
+                    ("max_mod_rev", rust_option(get.max_mod_rev, revision)),
+                    (
+                        "consistency",
+                        rust_option(get.consistency, |consistency| {
+                            format!("Consistency::{consistency:?}")
+                        }),
+                    ),
+                    ("unknown_flags", rust_flags(&get.unknown_flags)),
+                ],
+            )
+        }
+        Operation::Custom(custom) => (
+            "Custom(CustomData",
+            vec![
+                ("command", rust_bytes(custom.command)),
+                ("args", rust_args(&custom.args)),
+            ],
+        ),
+        Operation::Noop => return "Operation::Noop".into(),
+        Operation::Unknown(line) => return format!("Operation::Unknown({})", rust_bytes(line)),
+    };
+    rust_struct(&format!("Operation::{variant}"), &fields, indent)
+}
+
+// ----------------------------------------------------------------------------
+// Go
+// ----------------------------------------------------------------------------
+
+/// A Go interpreted string literal, holding the bytes as is.
+fn go_string(data: &[u8]) -> String {
+    let mut literal = String::from("\"");
+    for &byte in data {
+        match byte {
+            b'"' => literal += "\\\"",
+            b'\\' => literal += "\\\\",
+            b'\n' => literal += "\\n",
+            b'\r' => literal += "\\r",
+            b'\t' => literal += "\\t",
+            b' '..=b'~' => literal.push(char::from(byte)),
+            _ => {
+                let _ = write!(literal, "\\x{byte:02x}");
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn go_compare_op(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "\"=\"",
+        OpType::GreaterThan => "\">\"",
+        OpType::LessThan => "\"<\"",
+        OpType::NotEqual => "\"!=\"",
+    }
+}
+
+fn go_compare(compare: &Compare) -> Result<String, Construct> {
+    let (target, op, value) = match compare {
+        Compare::CreateRevision(compare) => {
+            ("CreateRevision", compare.op, int(compare.value).to_string())
+        }
+        Compare::ModRevision(compare) => {
+            ("ModRevision", compare.op, int(compare.value).to_string())
+        }
+        Compare::Value(compare) => (
+            "Value",
+            compare.op,
+            go_string(&decoded(compare.decoded_value())?),
+        ),
+        Compare::Version(compare) => ("Version", compare.op, int(compare.value).to_string()),
+        Compare::Lease(compare) => (
+            "LeaseValue",
+            compare.op,
+            format!("clientv3.LeaseID({})", lease_id(compare.value)),
+        ),
+        Compare::Custom(_) => return Err(Construct::CustomCompare),
+        Compare::Unknown(_) => return Err(Construct::UnknownNode),
+    };
+    let key = decoded(compare.decoded_key())?.unwrap_or_default();
+    Ok(format!(
+        "clientv3.Compare(clientv3.{target}({}), {}, {value})",
+        go_string(&key),
+        go_compare_op(op)
+    ))
+}
+
+fn go_operation(operation: &Operation) -> Result<String, Construct> {
+    if !operation.unknown_flags().is_empty() {
+        return Err(Construct::UnknownFlag);
+    }
+    let snippet = match operation {
+        Operation::Put(put) => {
+            let options = match put.lease {
+                Some(lease) => format!(
+                    ", clientv3.WithLease(clientv3.LeaseID({}))",
+                    lease_id(lease)
+                ),
+                None => String::new(),
+            };
+            format!(
+                "clientv3.OpPut({}, {}{options})",
+                go_string(&decoded(put.decoded_key())?),
+                go_string(&decoded(put.decoded_value())?)
+            )
+        }
+        Operation::Delete(delete) => format!(
+            "clientv3.OpDelete({})",
+            go_string(&decoded(delete.decoded_key())?)
+        ),
+        Operation::Get(get) => {
+            let mut options = String::new();
+            if get.prefix {
+                options += ", clientv3.WithPrefix()";
+            }
+            if get.from_key {
+                options += ", clientv3.WithFromKey()";
+            }
+            if let Some(range_end) = decoded(get.decoded_range_end())? {
+                let _ = write!(options, ", clientv3.WithRange({})", go_string(&range_end));
+            }
+            if get.consistency == Some(Consistency::Serializable) {
+                options += ", clientv3.WithSerializable()";
+            }
+            for (revision, _, name) in get_filters(get) {
+                if let Some(revision) = revision {
+                    let _ = write!(options, ", clientv3.With{name}({revision})");
+                }
+            }
+            format!(
+                "clientv3.OpGet({}{options})",
+                go_string(&decoded(get.decoded_key())?)
+            )
+        }
+        Operation::PutFile(_) => return Err(Construct::UnresolvedFileRef),
+        Operation::Noop => return Err(Construct::Noop),
+        Operation::Custom(_) => return Err(Construct::CustomOperation),
+        Operation::Unknown(_) => return Err(Construct::UnknownNode),
+    };
+    Ok(snippet)
+}
+
+impl TxnData<'_> {
+    /// Render the transaction as an `etcd_client::Txn` building expression.
+    ///
+    /// The expression refers to `Compare`, `CompareOp`, `Txn`, `TxnOp`,
+    /// `PutOptions` and `GetOptions` of the `etcd_client` crate, which must be
+    /// imported. Keys and values are written as byte string literals.
+    ///
+    /// # Errors
+    ///
+    /// A transaction containing unknown nodes, unresolved file references or
+    /// invalid escape sequences can't be rendered.
+    pub fn to_rust_snippet(&self) -> Result<String, Error> {
+        Ok(self.to_rust_snippet_with(Lossy::Error)?.value)
+    }
+
+    /// Render the transaction as an `etcd_client::Txn` building expression,
+    /// applying `lossy` to the nodes which can't be rendered. Best effort
+    /// writes a comment holding the node in its place.
+    ///
+    /// # Errors
+    ///
+    /// Under [`Lossy::Error`], the first node which can't be rendered.
+    pub fn to_rust_snippet_with(&self, lossy: Lossy) -> Result<Converted<String>, ConvertError> {
+        let rendered = render_sections(self, lossy, rust_compare, rust_operation)?;
+        let [compares, success, failure] = rendered.value;
+        let mut snippet = String::from("Txn::new()\n");
+        let indent = "        ";
+        write_call(&mut snippet, ("    .when(vec![", "])\n"), indent, compares);
+        write_call(
+            &mut snippet,
+            ("    .and_then(vec![", "])\n"),
+            indent,
+            success,
+        );
+        write_call(
+            &mut snippet,
+            ("    .or_else(vec![", "])\n"),
+            indent,
+            failure,
+        );
+        Ok(Converted {
+            value: snippet,
+            report: rendered.report,
+        })
+    }
+
+    /// Render the transaction as the Rust expression building it, e.g. to
+    /// paste a parsed transaction into a test.
+    ///
+    /// The expression refers to [`TxnData`], the compare and operation types
+    /// of the transaction, [`OpType`],
+    /// [`DataKind`](crate::operation::DataKind),
+    /// [`Consistency`](crate::operation::Consistency), [`Key`](crate::data::Key)
+    /// and the [`data`](crate::data) module, which must be imported. Keys and
+    /// values are written as byte string literals wrapped in their types, the
+    /// bytes they stand for once their escape sequences are decoded, see
+    /// [`DataKind::Bytes`](crate::operation::DataKind::Bytes). Unknown nodes
+    /// are rendered as well.
+    pub fn to_rust_literal(&self) -> String {
+        let mut literal = String::from("TxnData {\n");
+        let indent = "        ";
+        let item_indent = "            ";
+        write_call(
+            &mut literal,
+            ("    compares: vec![", "],\n"),
+            indent,
+            self.compares
+                .iter()
+                .map(|compare| rust_literal_compare(compare, item_indent)),
+        );
+        write_call(
+            &mut literal,
+            ("    success: vec![", "],\n"),
+            indent,
+            self.success
+                .iter()
+                .map(|operation| rust_literal_operation(operation, item_indent)),
+        );
+        write_call(
+            &mut literal,
+            ("    failure: vec![", "],\n"),
+            indent,
+            self.failure
+                .iter()
+                .map(|operation| rust_literal_operation(operation, item_indent)),
+        );
+        literal.push_str("}\n");
+        literal
+    }
+
+    /// Render the transaction as a `clientv3` transaction statement.
+    ///
+    /// The statement runs the transaction on the `cli` client with the `ctx`
+    /// context, both must be in scope. Keys and values are written as Go
+    /// string literals.
+    ///
+    /// # Errors
+    ///
+    /// A transaction containing unknown nodes, unresolved file references or
+    /// invalid escape sequences can't be rendered.
+    pub fn to_go_snippet(&self) -> Result<String, Error> {
+        Ok(self.to_go_snippet_with(Lossy::Error)?.value)
+    }
+
+    /// Render the transaction as a `clientv3` transaction statement, applying
+    /// `lossy` to the nodes which can't be rendered. Best effort writes a
+    /// comment holding the node in its place.
+    ///
+    /// # Errors
+    ///
+    /// Under [`Lossy::Error`], the first node which can't be rendered.
+    pub fn to_go_snippet_with(&self, lossy: Lossy) -> Result<Converted<String>, ConvertError> {
+        let rendered = render_sections(self, lossy, go_compare, go_operation)?;
+        let [compares, success, failure] = rendered.value;
+        let mut snippet = String::from("resp, err := cli.Txn(ctx)");
+        write_call(&mut snippet, (".If(", ")"), "\t", compares);
+        write_call(&mut snippet, (".Then(", ")"), "\t", success);
+        write_call(&mut snippet, (".Else(", ")"), "\t", failure);
+        snippet += ".Commit()\n";
+        Ok(Converted {
+            value: snippet,
+            report: rendered.report,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::options::ParseOptions;
+    use crate::snippet::{go_string, rust_bytes};
+    use crate::{parse, parse_with_options};
+
+    #[test]
+    fn test_escaping() {
+        for (data, rust, go) in [
+            (&b"key1"[..], r#"b"key1""#, r#""key1""#),
+            (b"", r#"b"""#, r#""""#),
+            (b"a \"b\"\\", r#"b"a \"b\"\\""#, r#""a \"b\"\\""#),
+            (b"'\t\n", r#"b"\'\t\n""#, r#""'\t\n""#),
+            (b"\x00\x7f\xff", r#"b"\x00\x7f\xff""#, r#""\x00\x7f\xff""#),
+        ] {
+            assert_eq!(rust_bytes(data), rust);
+            assert_eq!(go_string(data), go);
+        }
+    }
+
+    #[test]
+    fn test_to_rust_snippet() {
+        let data = b"ver(k) = 0\nval(k) > \"v\"\nlease(k) = 31\n\nput k v --lease=1f\nget --prefix --max-mod-rev=9 a/\ndel k\n\n";
+        let txn = parse(data).expect("Failed to parse");
+        assert_eq!(
+            txn.to_rust_snippet().expect("Failed to render"),
+            r#"Txn::new()
+    .when(vec![
+        Compare::version(b"k", CompareOp::Equal, 0),
+        Compare::value(b"k", CompareOp::Greater, b"v"),
+        Compare::lease(b"k", CompareOp::Equal, 0x1f),
+    ])
+    .and_then(vec![
+        TxnOp::put(b"k", b"v", Some(PutOptions::new().with_lease(0x1f))),
+        TxnOp::get(b"a/", Some(GetOptions::new().with_prefix().with_max_mod_revision(9))),
+        TxnOp::delete(b"k", None),
+    ])
+    .or_else(vec![])
+"#
+        );
+    }
+
+    #[test]
+    fn test_to_rust_literal() {
+        let options = ParseOptions {
+            keep_unknown_lines: true,
+            allow_file_refs: true,
+            ..Default::default()
+        };
+        let data = b"lock(k)\n\nget --prefix --min-mod-rev=3 a/\nput k @v.bin\n\nput k \"v\" --lease=1f\ndel k\n";
+        let txn = parse_with_options(data, &options).expect("Failed to parse");
+        assert_eq!(
+            txn.to_rust_literal(),
+            r#"TxnData {
+    compares: vec![
+        Compare::Unknown(b"lock(k)"),
+    ],
+    success: vec![
+        Operation::Get(GetData {
+            key: Key(b"a/"),
+            key_kind: DataKind::Unquoted,
+            prefix: true,
+            from_key: false,
+            range_end: None,
+            range_end_kind: DataKind::Bytes,
+            min_create_rev: None,
+            max_create_rev: None,
+            min_mod_rev: Some(3),
+            max_mod_rev: None,
+            consistency: None,
+            unknown_flags: vec![],
+        }),
+        Operation::PutFile(PutFileData {
+            key: Key(b"k"),
+            key_kind: DataKind::Unquoted,
+            path: b"v.bin",
+        }),
+    ],
+    failure: vec![
+        Operation::Put(PutData {
+            key: Key(b"k"),
+            value: data::Value(b"v"),
+            lease: Some(0x1f),
+            key_kind: DataKind::Unquoted,
+            value_kind: DataKind::Quoted,
+            unknown_flags: vec![],
+        }),
+        Operation::Delete(DeleteData {
+            key: Key(b"k"),
+            key_kind: DataKind::Unquoted,
+        }),
+    ],
+}
+"#
+        );
+        assert_eq!(
+            parse(b"\n\n\n\n")
+                .expect("Failed to parse")
+                .to_rust_literal(),
+            "TxnData {\n    compares: vec![],\n    success: vec![],\n    failure: vec![],\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_to_go_snippet() {
+        let data = b"c(k) < 3\nval(k) = \"v\"\nlease(k) = 31\n\n\n\nput k v --lease=1f\nget --prefix --min-create-rev=2 a/\ndel k\n";
+        let txn = parse(data).expect("Failed to parse");
+        assert_eq!(
+            txn.to_go_snippet().expect("Failed to render"),
+            "resp, err := cli.Txn(ctx).If(
+\tclientv3.Compare(clientv3.CreateRevision(\"k\"), \"<\", 3),
+\tclientv3.Compare(clientv3.Value(\"k\"), \"=\", \"v\"),
+\tclientv3.Compare(clientv3.LeaseValue(\"k\"), \"=\", clientv3.LeaseID(0x1f)),
+).Then().Else(
+\tclientv3.OpPut(\"k\", \"v\", clientv3.WithLease(clientv3.LeaseID(0x1f))),
+\tclientv3.OpGet(\"a/\", clientv3.WithPrefix(), clientv3.WithMinCreateRev(2)),
+\tclientv3.OpDelete(\"k\"),
+).Commit()
+"
+        );
+    }
+
+    #[test]
+    fn test_snippet_escapes() {
+        // The bytes the keys and the values stand for are written
+        let data = br#"val("k\x31") = 'it\'s'
+
+put k "a\"b"
+get 'a' "\x63"
+
+"#;
+        let txn = parse(data).expect("Failed to parse");
+        assert_eq!(
+            txn.to_rust_snippet().expect("Failed to render"),
+            r#"Txn::new()
+    .when(vec![
+        Compare::value(b"k1", CompareOp::Equal, b"it\'s"),
+    ])
+    .and_then(vec![
+        TxnOp::put(b"k", b"a\"b", None),
+        TxnOp::get(b"a", Some(GetOptions::new().with_range(b"c"))),
+    ])
+    .or_else(vec![])
+"#
+        );
+        assert_eq!(
+            txn.to_go_snippet().expect("Failed to render"),
+            "resp, err := cli.Txn(ctx).If(
+\tclientv3.Compare(clientv3.Value(\"k1\"), \"=\", \"it's\"),
+).Then(
+\tclientv3.OpPut(\"k\", \"a\\\"b\"),
+\tclientv3.OpGet(\"a\", clientv3.WithRange(\"c\")),
+).Else().Commit()
+"
+        );
+
+        // The literal holds the decoded bytes, data holding an invalid escape
+        // sequence as written
+        let txn = parse(b"\n\nput \"\\x41\" \"\\q\"\n\n").expect("Failed to parse");
+        let literal = txn.to_rust_literal();
+        assert!(
+            literal.contains("key: Key(b\"A\"),\n            value: data::Value(b\"\\\\q\"),"),
+            "{literal}"
+        );
+        assert!(
+            literal
+                .contains("key_kind: DataKind::Bytes,\n            value_kind: DataKind::Quoted,"),
+            "{literal}"
+        );
+
+        let txn = parse(b"\n\nput k \"\\q\"\n\n").expect("Failed to parse");
+        assert!(matches!(txn.to_rust_snippet(), Err(Error::InvalidEscape)));
+        assert!(matches!(txn.to_go_snippet(), Err(Error::InvalidEscape)));
+    }
+
+    #[test]
+    fn test_snippet_unsupported() {
+        let options = ParseOptions {
+            keep_unknown_lines: true,
+            ..Default::default()
+        };
+        let txn = parse_with_options(b"lock(k) = 1\n\n\n\n", &options).expect("Failed to parse");
+        assert!(matches!(txn.to_rust_snippet(), Err(Error::UnknownNode)));
+        assert!(matches!(txn.to_go_snippet(), Err(Error::UnknownNode)));
+    }
+}
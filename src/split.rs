@@ -0,0 +1,210 @@
+//! Splitting of oversized transactions
+//!
+//! etcd rejects a transaction holding more compares, or more operations in a
+//! branch, than its `--max-txn-ops` limit, 128 by default. The operations can
+//! be spread over several transactions applied one after the other, which
+//! gives up the atomicity of the original transaction.
+
+use crate::operation::Operation;
+use crate::owned::TxnDataOwned;
+use crate::TxnData;
+use std::fmt::{Display, Formatter};
+
+/// An error refusing to split a transaction.
+#[derive(Debug, PartialEq)]
+pub enum SplitError {
+    /// The compares alone exceed the limit, every chunk holding all of them.
+    TooManyCompares {
+        /// The number of compares of the transaction.
+        compares: usize,
+        /// The maximum number of operations per transaction.
+        max_ops: usize,
+    },
+    /// The transaction is guarded by compares and writes, splitting it would
+    /// evaluate the compares again against the writes of the previous chunks.
+    NonAtomic,
+}
+
+impl Display for SplitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitError::TooManyCompares { compares, max_ops } => write!(
+                f,
+                "{compares} compares exceed the limit of {max_ops} operations"
+            ),
+            SplitError::NonAtomic => write!(
+                f,
+                "splitting a guarded transaction which writes isn't atomic"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+/// Spread operations over unconditional transactions of at most `max_ops`
+/// operations, in order. A `max_ops` of zero is taken as one.
+///
+/// Applying the transactions one after the other applies the operations in
+/// their original order.
+pub fn split_operations(operations: Vec<Operation<'_>>, max_ops: usize) -> Vec<TxnData<'_>> {
+    let mut transactions = vec![];
+    let mut operations = operations.into_iter().peekable();
+    while operations.peek().is_some() {
+        transactions.push(TxnData {
+            compares: vec![],
+            success: operations.by_ref().take(max_ops.max(1)).collect(),
+            failure: vec![],
+        });
+    }
+    transactions
+}
+
+/// Whether the operation leaves the keys untouched.
+fn is_read_only(operation: &Operation) -> bool {
+    matches!(operation, Operation::Get(_))
+}
+
+impl TxnData<'_> {
+    /// Spread the operations of the transaction over transactions of at most
+    /// `max_ops` compares and `max_ops` operations per branch, in order. A
+    /// `max_ops` of zero is taken as one.
+    ///
+    /// Every chunk holds all the compares, followed by the next operations of
+    /// each branch. A transaction within the limit is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// A transaction with more than `max_ops` compares can't be split. Unless
+    /// `allow_non_atomic` is set, neither can a transaction with compares and
+    /// a branch which writes: the compares of a chunk would see the writes of
+    /// the previous ones.
+    pub fn split(
+        &self,
+        max_ops: usize,
+        allow_non_atomic: bool,
+    ) -> Result<Vec<TxnDataOwned>, SplitError> {
+        let max_ops = max_ops.max(1);
+        if self.compares.len() > max_ops {
+            return Err(SplitError::TooManyCompares {
+                compares: self.compares.len(),
+                max_ops,
+            });
+        }
+        if self.success.len() <= max_ops && self.failure.len() <= max_ops {
+            return Ok(vec![self.to_owned()]);
+        }
+        let writes = !self.success.iter().chain(&self.failure).all(is_read_only);
+        if !self.compares.is_empty() && writes && !allow_non_atomic {
+            return Err(SplitError::NonAtomic);
+        }
+
+        let mut success = self.success.chunks(max_ops);
+        let mut failure = self.failure.chunks(max_ops);
+        let mut transactions = vec![];
+        loop {
+            let (success, failure) = match (success.next(), failure.next()) {
+                (None, None) => break,
+                (success, failure) => (success.unwrap_or_default(), failure.unwrap_or_default()),
+            };
+            transactions.push(TxnDataOwned {
+                compares: self.compares.iter().map(Into::into).collect(),
+                success: success.iter().map(Into::into).collect(),
+                failure: failure.iter().map(Into::into).collect(),
+            });
+        }
+        Ok(transactions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::owned::TxnDataOwned;
+    use crate::parse;
+    use crate::split::{split_operations, SplitError};
+
+    fn puts(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| format!("put key{index} v"))
+            .collect()
+    }
+
+    #[test]
+    fn test_split_operations() {
+        let input = format!("\n\n{}\n\n", puts(7).join("\n"));
+        let operations = || parse(input.as_bytes()).expect("Failed to parse").success;
+
+        let transactions = split_operations(operations(), 3);
+        let sizes: Vec<_> = transactions.iter().map(|txn| txn.success.len()).collect();
+        assert_eq!(sizes, vec![3, 3, 1]);
+        assert!(transactions
+            .iter()
+            .all(|txn| txn.compares.is_empty() && txn.failure.is_empty()));
+        let flattened: Vec<_> = transactions
+            .into_iter()
+            .flat_map(|txn| txn.success)
+            .collect();
+        assert_eq!(flattened, operations());
+
+        assert_eq!(split_operations(operations(), 7).len(), 1);
+        assert_eq!(split_operations(operations(), 0).len(), 7);
+        assert!(split_operations(vec![], 3).is_empty());
+    }
+
+    #[test]
+    fn test_split() {
+        let input = format!("\n\n{}\n\n{}\n", puts(6).join("\n"), puts(2).join("\n"));
+        let txn = parse(input.as_bytes()).expect("Failed to parse");
+        let chunks = txn.split(4, false).expect("Failed to split");
+        let sizes: Vec<_> = chunks
+            .iter()
+            .map(|txn| (txn.success.len(), txn.failure.len()))
+            .collect();
+        assert_eq!(sizes, vec![(4, 2), (2, 0)]);
+        assert_eq!(
+            chunks
+                .iter()
+                .flat_map(|chunk| chunk.success.clone())
+                .collect::<Vec<_>>(),
+            txn.to_owned().success
+        );
+
+        // Within the limit, the transaction is kept whole
+        assert_eq!(txn.split(6, false), Ok(vec![txn.to_owned()]));
+    }
+
+    #[test]
+    fn test_split_guarded() {
+        let input = format!("mod(key0) > 0\n\n{}\n\n\n", puts(3).join("\n"));
+        let txn = parse(input.as_bytes()).expect("Failed to parse");
+        assert_eq!(txn.split(2, false), Err(SplitError::NonAtomic));
+        let chunks = txn.split(2, true).expect("Failed to split");
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.compares == txn.to_owned().compares));
+
+        // Reads don't change the outcome of the compares
+        let txn = parse(b"mod(key0) > 0\n\nget a\nget b\nget c\n\n").expect("Failed to parse");
+        assert_eq!(
+            txn.split(2, false).map(|chunks| chunks.len()),
+            Ok::<_, SplitError>(2)
+        );
+
+        let txn = parse(b"mod(a) > 0\nmod(b) > 0\nmod(c) > 0\n\n\n\n").expect("Failed to parse");
+        assert_eq!(
+            txn.split(2, true),
+            Err(SplitError::TooManyCompares {
+                compares: 3,
+                max_ops: 2
+            })
+        );
+        assert_eq!(
+            TxnDataOwned::default()
+                .as_txn()
+                .split(2, false)
+                .map(|chunks| chunks.len()),
+            Ok(1)
+        );
+    }
+}
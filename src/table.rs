@@ -0,0 +1,262 @@
+//! Aligned table summary of a transaction
+//!
+//! One row per compare and per operation, for a quick look at a transaction
+//! in a terminal or a log.
+
+use crate::compare::{Compare, OpType};
+use crate::escape::decode_lossy;
+use crate::operation::{DataKind, Operation};
+use crate::writer::{consistency_flag, unknown_flag};
+use crate::TxnData;
+use std::fmt::Write;
+
+/// The maximum width of a cell, in characters, truncation marker included.
+const MAX_CELL_WIDTH: usize = 32;
+
+/// The column headers.
+const HEADERS: [&str; 5] = ["SECTION", "KIND", "KEY", "VALUE", "OPTIONS"];
+
+/// A cell as shown in the table: control characters are escaped and the text
+/// is truncated to [`MAX_CELL_WIDTH`] characters, an ellipsis marking the
+/// truncation.
+fn cell(text: &str) -> String {
+    let escaped: Vec<char> = text
+        .chars()
+        .flat_map(|char| match char {
+            char if char.is_control() => char.escape_default().collect::<Vec<_>>(),
+            char => vec![char],
+        })
+        .collect();
+    if escaped.len() <= MAX_CELL_WIDTH {
+        return escaped.into_iter().collect();
+    }
+    let mut shown: String = escaped.into_iter().take(MAX_CELL_WIDTH - 1).collect();
+    shown.push('…');
+    shown
+}
+
+fn lossy(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).into_owned()
+}
+
+/// A key or a value shown lossily, the bytes it stands for, see
+/// [`decode`](crate::escape::decode), or as written if it holds an invalid
+/// escape sequence.
+fn lossy_decoded(data: &[u8], kind: DataKind) -> String {
+    lossy(&decode_lossy(data, kind))
+}
+
+fn symbol(op: OpType) -> &'static str {
+    match op {
+        OpType::Equal => "=",
+        OpType::GreaterThan => ">",
+        OpType::LessThan => "<",
+        OpType::NotEqual => "!=",
+    }
+}
+
+/// The kind, key, value and options of a compare.
+fn compare_row(compare: &Compare) -> [String; 4] {
+    let (kind, (key, key_kind), op) = match compare {
+        Compare::CreateRevision(compare) => ("create", (compare.key, compare.key_kind), compare.op),
+        Compare::ModRevision(compare) => ("mod", (compare.key, compare.key_kind), compare.op),
+        Compare::Value(compare) => ("value", (compare.key, compare.key_kind), compare.op),
+        Compare::Version(compare) => ("version", (compare.key, compare.key_kind), compare.op),
+        Compare::Lease(compare) => ("lease", (compare.key, compare.key_kind), compare.op),
+        Compare::Custom(custom) => {
+            let args: Vec<_> = custom.args.iter().map(|arg| lossy(arg)).collect();
+            return [
+                lossy(custom.target),
+                String::new(),
+                args.join(" "),
+                String::new(),
+            ];
+        }
+        Compare::Unknown(line) => {
+            return ["unknown".into(), String::new(), lossy(line), String::new()];
+        }
+    };
+    let value = match compare {
+        Compare::Value(compare) => lossy_decoded(&compare.value, compare.value_kind),
+        _ => compare.number().unwrap_or_default().to_string(),
+    };
+    [
+        kind.into(),
+        lossy_decoded(&key, key_kind),
+        format!("{} {value}", symbol(op)),
+        String::new(),
+    ]
+}
+
+/// The kind, key, value and options of an operation.
+fn operation_row(operation: &Operation) -> [String; 4] {
+    match operation {
+        Operation::Put(put) => {
+            let options: Vec<_> = put
+                .lease
+                .map(|lease| format!("--lease={lease:x}"))
+                .into_iter()
+                .chain(put.unknown_flags.iter().map(unknown_flag))
+                .collect();
+            [
+                "put".into(),
+                lossy_decoded(&put.key, put.key_kind),
+                lossy_decoded(&put.value, put.value_kind),
+                options.join(" "),
+            ]
+        }
+        Operation::PutFile(put) => [
+            "put".into(),
+            lossy_decoded(&put.key, put.key_kind),
+            format!("@{}", lossy(put.path)),
+            String::new(),
+        ],
+        Operation::Delete(delete) => [
+            "del".into(),
+            lossy_decoded(&delete.key, delete.key_kind),
+            String::new(),
+            String::new(),
+        ],
+        Operation::Get(get) => {
+            let mut options = vec![];
+            if get.prefix {
+                options.push("--prefix".to_string());
+            }
+            if get.from_key {
+                options.push("--from-key".to_string());
+            }
+            if let Some(consistency) = get.consistency {
+                options.push(consistency_flag(consistency).to_string());
+            }
+            let filters = [
+                ("min-create-rev", get.min_create_rev),
+                ("max-create-rev", get.max_create_rev),
+                ("min-mod-rev", get.min_mod_rev),
+                ("max-mod-rev", get.max_mod_rev),
+            ];
+            for (name, revision) in filters {
+                if let Some(revision) = revision {
+                    options.push(format!("--{name}={revision}"));
+                }
+            }
+            options.extend(get.unknown_flags.iter().map(unknown_flag));
+            // The range end follows the key, as written
+            let mut key = lossy_decoded(&get.key, get.key_kind);
+            if let Some(range_end) = get.range_end {
+                key = format!("{key} {}", lossy_decoded(&range_end, get.range_end_kind));
+            }
+            ["get".into(), key, String::new(), options.join(" ")]
+        }
+        Operation::Noop => ["noop".into(), String::new(), String::new(), String::new()],
+        Operation::Custom(custom) => {
+            let args: Vec<_> = custom.args.iter().map(|arg| lossy(arg)).collect();
+            [
+                lossy(custom.command),
+                String::new(),
+                args.join(" "),
+                String::new(),
+            ]
+        }
+        Operation::Unknown(line) => ["unknown".into(), String::new(), lossy(line), String::new()],
+    }
+}
+
+impl TxnData<'_> {
+    /// Summarize the transaction as an aligned table, e.g.
+    ///
+    /// ```text
+    /// SECTION  KIND  KEY   VALUE           OPTIONS
+    /// compare  mod   key1  > 0
+    /// success  put   key1  overwrote-key1
+    /// ```
+    ///
+    /// One row per compare and per operation of each branch, under a header
+    /// row. The columns are as wide as their widest cell, cells being
+    /// truncated to 32 characters with an ellipsis. Keys and values are shown
+    /// lossily, their control characters escaped. Trailing spaces are
+    /// trimmed.
+    pub fn summary_table(&self) -> String {
+        let mut rows = vec![HEADERS.map(String::from)];
+        let compares = self
+            .compares
+            .iter()
+            .map(|compare| ("compare", compare_row(compare)));
+        let success = self
+            .success
+            .iter()
+            .map(|operation| ("success", operation_row(operation)));
+        let failure = self
+            .failure
+            .iter()
+            .map(|operation| ("failure", operation_row(operation)));
+        for (section, [kind, key, value, options]) in compares.chain(success).chain(failure) {
+            rows.push([section.into(), kind, key, value, options].map(|text| cell(&text)));
+        }
+
+        let mut widths = [0; HEADERS.len()];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut table = String::new();
+        for row in &rows {
+            let mut line = String::new();
+            for (cell, width) in row.iter().zip(widths) {
+                let _ = write!(line, "{cell:width$}  ");
+            }
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::ParseOptions;
+    use crate::parse_with_options;
+    use crate::table::{cell, MAX_CELL_WIDTH};
+
+    #[test]
+    fn test_cell() {
+        assert_eq!(cell("key1"), "key1");
+        assert_eq!(cell("a\nb\t"), "a\\nb\\t");
+        assert_eq!(
+            cell(&"a".repeat(MAX_CELL_WIDTH)),
+            "a".repeat(MAX_CELL_WIDTH)
+        );
+        assert_eq!(
+            cell(&"é".repeat(MAX_CELL_WIDTH + 1)),
+            format!("{}…", "é".repeat(MAX_CELL_WIDTH - 1))
+        );
+        // Truncated after escaping
+        assert_eq!(cell(&"\n".repeat(20)).chars().count(), MAX_CELL_WIDTH);
+    }
+
+    #[test]
+    fn test_summary_table_rows() {
+        let options = ParseOptions {
+            keep_unknown_lines: true,
+            allow_file_refs: true,
+            ..Default::default()
+        };
+        let txn = parse_with_options(
+            b"val(k) < \"a\\x20b\"\nlock(k)\n\nget --prefix --min-mod-rev=3 app/\nput k @v.bin\n\ndel \"\\xffk\"\nwatch k\n",
+            &options,
+        )
+        .expect("Failed to parse");
+        assert_eq!(
+            txn.summary_table(),
+            "SECTION  KIND     KEY   VALUE    OPTIONS
+compare  value    k     < a b
+compare  unknown        lock(k)
+success  get      app/           --prefix --min-mod-rev=3
+success  put      k     @v.bin
+failure  del      \u{fffd}k
+failure  unknown        watch k
+"
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Semantic validation of a parsed transaction
+
+use crate::operation::Operation;
+use crate::{Branch, Section, TxnData};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// A semantic error found in a syntactically valid transaction.
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// The same key is written more than once in a branch, etcd rejects
+    /// such a transaction with "duplicate key given in txn request".
+    DuplicateKey {
+        /// The duplicated key.
+        key: Vec<u8>,
+        /// The branch containing the duplicated key.
+        branch: Branch,
+    },
+    /// A get reads a key written earlier in the same branch, which is easily
+    /// mistaken for a read of the value the key held before the transaction,
+    /// see [`LintConfig::check_read_after_write`].
+    ReadAfterWrite {
+        /// The key written then read.
+        key: Vec<u8>,
+        /// The branch containing the operations.
+        branch: Branch,
+    },
+    /// A key doesn't start with the prefix required by
+    /// [`TxnData::validate_key_prefix`].
+    KeyOutsidePrefix {
+        /// The key outside the prefix.
+        key: Vec<u8>,
+        /// The section of the node holding the key, the compare section or
+        /// one of the branches.
+        section: Section,
+    },
+    /// A compare can never succeed, e.g. `version(k) < 0`, so the success
+    /// branch never runs, see [`LintConfig::check_unsatisfiable_compares`].
+    UnsatisfiableCompare {
+        /// The position of the compare.
+        index: usize,
+    },
+    /// A put value isn't valid JSON while its key requires it, see
+    /// [`LintConfig::require_json_values`].
+    #[cfg(feature = "json")]
+    InvalidJson {
+        /// The key of the put operation.
+        key: Vec<u8>,
+        /// The branch containing the put operation.
+        branch: Branch,
+        /// The position of the put operation in its branch.
+        index: usize,
+        /// The JSON parse error.
+        message: String,
+    },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::DuplicateKey { key, branch } => write!(
+                f,
+                "duplicate key {:?} in {branch} branch",
+                String::from_utf8_lossy(key)
+            ),
+            ValidationError::ReadAfterWrite { key, branch } => write!(
+                f,
+                "key {:?} read after being written in {branch} branch",
+                String::from_utf8_lossy(key)
+            ),
+            ValidationError::KeyOutsidePrefix { key, section } => write!(
+                f,
+                "key {:?} outside the allowed prefix in {section} section",
+                String::from_utf8_lossy(key)
+            ),
+            ValidationError::UnsatisfiableCompare { index } => {
+                write!(f, "compare {index} can never be satisfied")
+            }
+            #[cfg(feature = "json")]
+            ValidationError::InvalidJson {
+                key,
+                branch,
+                index,
+                message,
+            } => write!(
+                f,
+                "invalid JSON value for key {:?} in {branch} operation {index}: {message}",
+                String::from_utf8_lossy(key)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Opt-in rules checked by [`TxnData::lint`] on top of the validation rules.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    /// Whether to report the gets reading a key written before them.
+    read_after_write: bool,
+    /// Whether to report the compares which can never succeed.
+    unsatisfiable_compares: bool,
+    /// The key prefixes whose put values must be valid JSON.
+    #[cfg(feature = "json")]
+    json_prefixes: Vec<Vec<u8>>,
+}
+
+impl LintConfig {
+    /// Report the gets reading a key written earlier in their branch, by a
+    /// put or a delete.
+    pub fn check_read_after_write(mut self) -> Self {
+        self.read_after_write = true;
+        self
+    }
+
+    /// Report the compares which can never succeed, see
+    /// [`Compare::is_unsatisfiable`](crate::compare::Compare::is_unsatisfiable).
+    pub fn check_unsatisfiable_compares(mut self) -> Self {
+        self.unsatisfiable_compares = true;
+        self
+    }
+
+    /// Require the values put under the keys starting with `prefix`, e.g.
+    /// `config/`, to be valid JSON. A value which isn't UTF-8 is invalid.
+    #[cfg(feature = "json")]
+    pub fn require_json_values(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.json_prefixes.push(prefix.into());
+        self
+    }
+}
+
+impl TxnData<'_> {
+    /// Check the transaction against the rules enabled in `config`,
+    /// returning every violation found, in order.
+    pub fn lint(&self, config: &LintConfig) -> Vec<ValidationError> {
+        let mut violations = vec![];
+        if config.unsatisfiable_compares {
+            violations.extend(
+                self.compares
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, compare)| compare.is_unsatisfiable())
+                    .map(|(index, _)| ValidationError::UnsatisfiableCompare { index }),
+            );
+        }
+        for (branch, operations) in [
+            (Branch::Success, &self.success),
+            (Branch::Failure, &self.failure),
+        ] {
+            if config.read_after_write {
+                check_read_after_write(operations, branch, &mut violations);
+            }
+            #[cfg(feature = "json")]
+            check_json_values(operations, branch, &config.json_prefixes, &mut violations);
+        }
+        violations
+    }
+
+    /// Run the default validation rules against the transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first rule violation found.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check_duplicate_keys(&self.success, Branch::Success)?;
+        check_duplicate_keys(&self.failure, Branch::Failure)?;
+        Ok(())
+    }
+
+    /// Check that every key of the transaction starts with `prefix`, e.g. the
+    /// namespace of a tenant. The keys of the compares are checked along with
+    /// the ones of the operations, custom and unknown nodes having none.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first key outside the prefix, along with its section.
+    pub fn validate_key_prefix(&self, prefix: &[u8]) -> Result<(), ValidationError> {
+        let keys = self
+            .compares
+            .iter()
+            .map(|compare| (Section::Compare, compare.key()))
+            .chain(
+                self.success
+                    .iter()
+                    .map(|operation| (Section::Success, operation.key())),
+            )
+            .chain(
+                self.failure
+                    .iter()
+                    .map(|operation| (Section::Failure, operation.key())),
+            );
+        for (section, key) in keys {
+            match key {
+                Some(key) if !key.starts_with(prefix) => {
+                    return Err(ValidationError::KeyOutsidePrefix {
+                        key: key.to_vec(),
+                        section,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_duplicate_keys(operations: &[Operation], branch: Branch) -> Result<(), ValidationError> {
+    let mut written = HashSet::new();
+    for operation in operations {
+        let key = match operation {
+            Operation::Put(put) => put.key,
+            Operation::Delete(delete) => delete.key,
+            Operation::PutFile(put) => put.key,
+            Operation::Get(_) | Operation::Noop | Operation::Custom(_) | Operation::Unknown(_) => {
+                continue
+            }
+        };
+        if !written.insert(key) {
+            return Err(ValidationError::DuplicateKey {
+                key: key.to_vec(),
+                branch,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_read_after_write(
+    operations: &[Operation],
+    branch: Branch,
+    violations: &mut Vec<ValidationError>,
+) {
+    let mut written = vec![];
+    for operation in operations {
+        match operation {
+            // The ranges are decoded, so are the written keys. One holding an
+            // invalid escape sequence stands for no key.
+            Operation::Put(_) | Operation::PutFile(_) | Operation::Delete(_) => {
+                written.extend(operation.decoded_key().ok().flatten());
+            }
+            Operation::Get(get) => {
+                let Ok(range) = get.effective_range() else {
+                    continue;
+                };
+                if let Some(key) = written.iter().find(|key| range.contains(key)) {
+                    violations.push(ValidationError::ReadAfterWrite {
+                        key: key.to_vec(),
+                        branch,
+                    });
+                }
+            }
+            Operation::Noop | Operation::Custom(_) | Operation::Unknown(_) => {}
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn check_json_values(
+    operations: &[Operation],
+    branch: Branch,
+    prefixes: &[Vec<u8>],
+    violations: &mut Vec<ValidationError>,
+) {
+    for (index, operation) in operations.iter().enumerate() {
+        let Operation::Put(put) = operation else {
+            continue;
+        };
+        if !prefixes.iter().any(|prefix| put.key.starts_with(prefix)) {
+            continue;
+        }
+        if let Err(err) = serde_json::from_slice::<serde_json::Value>(&put.value) {
+            violations.push(ValidationError::InvalidJson {
+                key: put.key.to_vec(),
+                branch,
+                index,
+                message: err.to_string(),
+            });
+        }
+    }
+}
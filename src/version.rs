@@ -0,0 +1,138 @@
+//! Format version directive
+//!
+//! A file may declare the version of the format it is written in on its
+//! first line, after the shebang if any:
+//!
+//! ```text
+//! %txn-version 2
+//! ```
+//!
+//! Version 1 is the original format, without operation flags. Version 2 adds
+//! the `--lease` flag of puts, and the `--prefix`, `--from-key`, range end and
+//! revision flags of gets.
+
+use crate::error::Error;
+use crate::operation::Operation;
+use crate::{TxnData, FORMAT_VERSION};
+use elyze::errors::ParseError;
+
+const DIRECTIVE: &[u8] = b"%txn-version";
+
+/// Read the version directive starting `data`, returning the length of its
+/// line, line feed included, and the declared version. Without directive, the
+/// length is zero and the version `None`.
+///
+/// # Errors
+///
+/// A malformed directive is an `Error::Parse`, a version newer than
+/// [`FORMAT_VERSION`] an `Error::UnsupportedFormatVersion`.
+pub(crate) fn split_directive(data: &[u8]) -> Result<(usize, Option<u32>), Error> {
+    let Some(rest) = data.strip_prefix(DIRECTIVE) else {
+        return Ok((0, None));
+    };
+    let (line, len) = match rest.iter().position(|&byte| byte == b'\n') {
+        Some(end) => (
+            rest.get(..end).unwrap_or_default(),
+            DIRECTIVE.len() + end + 1,
+        ),
+        None => (rest, data.len()),
+    };
+    // At least one whitespace separates the directive from the version
+    if !line.first().is_some_and(u8::is_ascii_whitespace) {
+        return Err(ParseError::UnexpectedToken.into());
+    }
+    let version = std::str::from_utf8(line.trim_ascii())
+        .ok()
+        .filter(|version| version.bytes().all(|byte| byte.is_ascii_digit()))
+        .and_then(|version| version.parse::<u32>().ok())
+        .filter(|&version| version > 0)
+        .ok_or(ParseError::UnexpectedToken)?;
+    if version > FORMAT_VERSION {
+        return Err(Error::UnsupportedFormatVersion {
+            required: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    Ok((len, Some(version)))
+}
+
+/// The format version introducing the syntax of the operation.
+fn operation_version(operation: &Operation) -> u32 {
+    match operation {
+        Operation::Put(put) if put.lease.is_some() => 2,
+        Operation::Get(get)
+            if get.prefix
+                || get.from_key
+                || get.range_end.is_some()
+                || get.min_create_rev.is_some()
+                || get.max_create_rev.is_some()
+                || get.min_mod_rev.is_some()
+                || get.max_mod_rev.is_some()
+                || get.consistency.is_some() =>
+        {
+            2
+        }
+        _ => 1,
+    }
+}
+
+/// Check that the transaction only uses the syntax of the declared version.
+///
+/// # Errors
+///
+/// An operation written with a newer syntax is an `Error::Parse`.
+pub(crate) fn check_syntax(txn: &TxnData, version: u32) -> Result<(), Error> {
+    let newer = txn
+        .success
+        .iter()
+        .chain(&txn.failure)
+        .any(|operation| operation_version(operation) > version);
+    match newer {
+        true => Err(ParseError::UnexpectedToken.into()),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::version::split_directive;
+
+    #[test]
+    fn test_split_directive() {
+        for (data, expected) in [
+            (&b"mod(k) > 0\n"[..], (0, None)),
+            (b"%txn-version 1\n\n\n\n", (15, Some(1))),
+            (b"%txn-version\t2 \r\nput k v", (17, Some(2))),
+            (b"%txn-version 2", (14, Some(2))),
+        ] {
+            assert_eq!(
+                split_directive(data).expect("Failed to split"),
+                expected,
+                "{data:?}"
+            );
+        }
+
+        for data in [
+            &b"%txn-version\n"[..],
+            b"%txn-version2\n",
+            b"%txn-version 0\n",
+            b"%txn-version +1\n",
+            b"%txn-version 1 2\n",
+            b"%txn-version 99999999999\n",
+        ] {
+            assert!(
+                matches!(split_directive(data), Err(Error::Parse(_))),
+                "{data:?}"
+            );
+        }
+
+        assert!(matches!(
+            split_directive(b"%txn-version 3\n"),
+            Err(Error::UnsupportedFormatVersion {
+                required: 3,
+                supported: 2
+            })
+        ));
+    }
+}
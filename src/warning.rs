@@ -0,0 +1,44 @@
+//! Warnings emitted while parsing a transaction
+
+use crate::Section;
+use std::fmt::{Display, Formatter};
+
+/// A non fatal issue found while parsing a transaction.
+#[derive(Debug, PartialEq)]
+pub enum Warning<'a> {
+    /// A line which can't be parsed has been kept as an unknown node.
+    UnknownLine {
+        /// The section containing the line.
+        section: Section,
+        /// The position of the line in the input.
+        offset: usize,
+        /// The raw line.
+        line: &'a [u8],
+    },
+    /// The shebang line starting the input has been skipped.
+    Shebang {
+        /// The raw line, without its line feed.
+        line: &'a [u8],
+    },
+}
+
+impl Display for Warning<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::UnknownLine {
+                section,
+                offset,
+                line,
+            } => write!(
+                f,
+                "unknown line {:?} in {section} section at offset {offset}",
+                String::from_utf8_lossy(line)
+            ),
+            Warning::Shebang { line } => write!(
+                f,
+                "shebang line {:?} skipped",
+                String::from_utf8_lossy(line)
+            ),
+        }
+    }
+}
@@ -0,0 +1,911 @@
+//! Serialization of transactions in their canonical text format
+
+use crate::compare::{Compare, OpType};
+use crate::escape::decode;
+use crate::operation::{closing_quote, Consistency, DataKind, Operation};
+use crate::options::DEFAULT_QUOTE_CHARS;
+use crate::{QuoteTracker, TxnData};
+use std::fmt::Formatter;
+use std::io::{self, Write};
+
+fn operator(op: OpType) -> &'static [u8] {
+    match op {
+        OpType::Equal => b"=",
+        OpType::GreaterThan => b">",
+        OpType::LessThan => b"<",
+        OpType::NotEqual => b"!=",
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// What the writer does with a key or a value the syntax can't express.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Inexpressible {
+    /// Fail with an `io::ErrorKind::InvalidData` error.
+    Reject,
+    /// Write text, for `Display`: the keys and the values which aren't valid
+    /// UTF-8 are escaped, see [`write_escaped`], the ones which can't be
+    /// written are marked, see [`write_marked`].
+    Mark,
+}
+
+impl Inexpressible {
+    /// Whether `data` can be written as is: text must be valid UTF-8.
+    fn allows(self, data: &[u8]) -> bool {
+        self == Inexpressible::Reject || std::str::from_utf8(data).is_ok()
+    }
+}
+
+/// Where a key or a value is written.
+#[derive(Clone, Copy, PartialEq)]
+enum Place {
+    /// A key or a value of an operation, ending at the next space unquoted.
+    Word,
+    /// The key of a compare, delimited by counting parentheses, quotes aside.
+    CompareKey,
+    /// The value of a value compare, running until the end of the line
+    /// unquoted.
+    Line,
+}
+
+/// Whether the parentheses of `data` are balanced, none closing a group
+/// which isn't open.
+fn balanced(data: &[u8]) -> bool {
+    let mut depth = Some(0usize);
+    for &byte in data {
+        depth = match byte {
+            b'(' => depth.and_then(|depth| depth.checked_add(1)),
+            b')' => depth.and_then(|depth| depth.checked_sub(1)),
+            _ => depth,
+        };
+    }
+    depth == Some(0)
+}
+
+/// Whether the bytes, fed to a [`QuoteTracker`] from the start of a word,
+/// stay on their line: they hold no line feed outside of a string, which
+/// would end the line, and leave no string open, which would run over the
+/// following lines.
+fn stays_on_its_line<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> bool {
+    let mut quotes = QuoteTracker::new(DEFAULT_QUOTE_CHARS);
+    for &byte in bytes {
+        if !quotes.is_quoted(byte) && byte == b'\n' {
+            return false;
+        }
+    }
+    quotes.open().is_none()
+}
+
+/// Whether `data` is read back as is between `quote`s: only the closing
+/// quote ends the string, no trailing backslash escaping it.
+fn fits_quotes(data: &[u8], quote: u8) -> bool {
+    let backslashes = data.iter().rev().take_while(|&&byte| byte == b'\\').count();
+    let escapes_closing = match quote {
+        b'"' => backslashes % 2 == 1,
+        // Between single quotes, a backslash only escapes a single quote
+        _ => backslashes > 0,
+    };
+    let quote = [quote];
+    !escapes_closing
+        && closing_quote(data, quote[0]).is_none()
+        && stays_on_its_line(quote.iter().chain(data).chain(&quote))
+}
+
+/// Whether `data` is read back as is unquoted: it isn't read as a quoted
+/// string, a flag nor a file reference, has no whitespace around, and no
+/// separator of the compact format.
+fn fits_unquoted(data: &[u8], place: Place) -> bool {
+    let fits_end = match place {
+        Place::Word | Place::CompareKey => {
+            !data.contains(&b' ') && !data.starts_with(b"--") && !data.starts_with(b"@")
+        }
+        Place::Line => true,
+    };
+    fits_end
+        && !data.is_empty()
+        && data.trim_ascii() == data
+        && !data
+            .iter()
+            .any(|byte| matches!(byte, b'\n' | b'\r' | b',' | b';'))
+        && !data
+            .first()
+            .is_some_and(|first| DEFAULT_QUOTE_CHARS.contains(first))
+        && stays_on_its_line(data)
+}
+
+/// Write a key or a value so that it's read back as the same bytes.
+///
+/// Quoted data parsed from a transaction is written as is between its
+/// quotes, which it fits. Otherwise, the bytes it stands for are written, see
+/// [`write_bytes`], like those of unquoted data and of [`DataKind::Bytes`].
+/// Only quoted data which can't be written as is and holds an invalid escape
+/// sequence can't be written: parsing gives it when it isn't valid UTF-8, and
+/// text is asked for.
+fn write_data<W: Write>(
+    w: &mut W,
+    (data, kind): (&[u8], DataKind),
+    place: Place,
+    inexpressible: Inexpressible,
+) -> io::Result<()> {
+    let quote = match kind {
+        DataKind::Quoted => b'"',
+        DataKind::SingleQuoted => b'\'',
+        // Unquoted data stands for itself
+        DataKind::Unquoted | DataKind::Bytes => return write_bytes(w, data, place, inexpressible),
+    };
+    if (place != Place::CompareKey || balanced(data))
+        && fits_quotes(data, quote)
+        && inexpressible.allows(data)
+    {
+        w.write_all(&[quote])?;
+        w.write_all(data)?;
+        return w.write_all(&[quote]);
+    }
+    match (decode(data, kind), inexpressible) {
+        (Ok(bytes), _) => write_bytes(w, &bytes, place, inexpressible),
+        (Err(_), Inexpressible::Reject) => Err(invalid_data(
+            "a string the parser can't read back can't be written",
+        )),
+        (Err(_), Inexpressible::Mark) => write_marked(w, data, place),
+    }
+}
+
+/// Write bytes so that decoding the written key or value gives them back.
+///
+/// They're written between double quotes, or between single quotes if they
+/// hold a double quote, when they hold no escape sequence, else unquoted,
+/// else escaped between double quotes, see [`write_escaped`], as text if they
+/// aren't valid UTF-8. Any bytes can be written.
+fn write_bytes<W: Write>(
+    w: &mut W,
+    data: &[u8],
+    place: Place,
+    inexpressible: Inexpressible,
+) -> io::Result<()> {
+    let plain = |quote: u8| {
+        fits_quotes(data, quote)
+            && match quote {
+                b'"' => !data.contains(&b'\\'),
+                _ => memchr::memmem::find(data, b"\\'").is_none(),
+            }
+    };
+    let quotes = match data.contains(&b'"') {
+        true => [b'\'', b'"'],
+        false => [b'"', b'\''],
+    };
+    if (place != Place::CompareKey || balanced(data)) && inexpressible.allows(data) {
+        if let Some(quote) = quotes.into_iter().find(|&quote| plain(quote)) {
+            w.write_all(&[quote])?;
+            w.write_all(data)?;
+            return w.write_all(&[quote]);
+        }
+        if fits_unquoted(data, place) {
+            return w.write_all(data);
+        }
+    }
+    write_escaped(w, data, place)
+}
+
+/// Write a key or a value between double quotes, its backslashes, double
+/// quotes, line feeds and carriage returns escaped, along with the
+/// parentheses of a compare key and the bytes which aren't valid UTF-8, so
+/// that [`unescape`](crate::escape::unescape) gives it back.
+///
+/// The double quotes are written as `\x22`: a string is delimited by the
+/// quotes alone when splitting the transaction.
+fn write_escaped<W: Write>(w: &mut W, data: &[u8], place: Place) -> io::Result<()> {
+    w.write_all(b"\"")?;
+    for chunk in data.utf8_chunks() {
+        for &byte in chunk.valid().as_bytes() {
+            match byte {
+                b'\\' => w.write_all(b"\\\\")?,
+                b'"' => w.write_all(b"\\x22")?,
+                b'\n' => w.write_all(b"\\n")?,
+                b'\r' => w.write_all(b"\\r")?,
+                b'(' if place == Place::CompareKey => w.write_all(b"\\x28")?,
+                b')' if place == Place::CompareKey => w.write_all(b"\\x29")?,
+                _ => w.write_all(&[byte])?,
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(w, "\\x{byte:02x}")?;
+        }
+    }
+    w.write_all(b"\"")
+}
+
+/// Write quoted data which can't be written as is and holds an invalid
+/// escape sequence as `"..." <invalid escape>`, the bytes as written being
+/// escaped between the double quotes. It doesn't parse: the data has no
+/// meaning to give back, and no word follows a string, even one ending the
+/// line of a compare.
+fn write_marked<W: Write>(w: &mut W, data: &[u8], place: Place) -> io::Result<()> {
+    write_escaped(w, data, place)?;
+    w.write_all(b" <invalid escape>")
+}
+
+/// Format text written by the writer.
+///
+/// `write` is given [`Inexpressible::Mark`], so formatting never fails on the
+/// keys and the values, which are valid UTF-8. The lines kept as is, the
+/// arguments of the custom nodes and the paths of the file references are
+/// written as is, their bytes which aren't valid UTF-8 as `\xNN` escape
+/// sequences.
+pub(crate) fn display_with(
+    f: &mut Formatter<'_>,
+    write: impl FnOnce(&mut Vec<u8>, Inexpressible) -> io::Result<()>,
+) -> std::fmt::Result {
+    let mut text = vec![];
+    // Writing to a vector doesn't fail, the inexpressible data being marked
+    write(&mut text, Inexpressible::Mark).map_err(|_| std::fmt::Error)?;
+    display_text(f, &text)
+}
+
+/// Format text written by the writer, the bytes which aren't valid UTF-8 as
+/// `\xNN` escape sequences.
+fn display_text(f: &mut Formatter<'_>, text: &[u8]) -> std::fmt::Result {
+    for chunk in text.utf8_chunks() {
+        f.write_str(chunk.valid())?;
+        for byte in chunk.invalid() {
+            write!(f, "\\x{byte:02x}")?;
+        }
+    }
+    Ok(())
+}
+
+impl Compare<'_> {
+    /// Write the compare as a single line, without the line feed.
+    pub(crate) fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_with(w, Inexpressible::Reject)
+    }
+
+    /// Write the compare like [`Compare::write_to`], doing what `inexpressible`
+    /// tells with the key or the value the syntax can't express.
+    pub(crate) fn write_with<W: Write>(
+        &self,
+        w: &mut W,
+        inexpressible: Inexpressible,
+    ) -> io::Result<()> {
+        let (target, key, key_kind, op): (&[u8], _, _, _) = match self {
+            Compare::CreateRevision(compare) => {
+                (b"create", compare.key, compare.key_kind, compare.op)
+            }
+            Compare::ModRevision(compare) => (b"mod", compare.key, compare.key_kind, compare.op),
+            Compare::Value(compare) => (b"value", compare.key, compare.key_kind, compare.op),
+            Compare::Version(compare) => (b"version", compare.key, compare.key_kind, compare.op),
+            Compare::Lease(compare) => (b"lease", compare.key, compare.key_kind, compare.op),
+            Compare::Custom(custom) => {
+                w.write_all(custom.target)?;
+                for arg in &custom.args {
+                    w.write_all(b" ")?;
+                    w.write_all(arg)?;
+                }
+                return Ok(());
+            }
+            Compare::Unknown(line) => return w.write_all(line),
+        };
+
+        w.write_all(target)?;
+        w.write_all(b"(")?;
+        write_data(w, (&key, key_kind), Place::CompareKey, inexpressible)?;
+        w.write_all(b") ")?;
+        w.write_all(operator(op))?;
+        w.write_all(b" ")?;
+        match self {
+            Compare::Value(compare) => write_data(
+                w,
+                (&compare.value, compare.value_kind),
+                Place::Line,
+                inexpressible,
+            ),
+            _ => write!(w, "{}", self.number().unwrap_or_default()),
+        }
+    }
+
+    /// The compare as a line of the text format, without the line feed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if the key or the value
+    /// can't be expressed by the syntax, see [`TxnData::write_to`].
+    pub fn to_text(&self) -> io::Result<Vec<u8>> {
+        let mut text = vec![];
+        self.write_to(&mut text)?;
+        Ok(text)
+    }
+}
+
+/// The flag giving the consistency of a get, in its long form.
+pub(crate) fn consistency_flag(consistency: Consistency) -> &'static str {
+    match consistency {
+        Consistency::Linearizable => "--consistency=l",
+        Consistency::Serializable => "--consistency=s",
+    }
+}
+
+/// A flag the parser doesn't know, as written in the transaction.
+pub(crate) fn unknown_flag((name, value): &(String, Option<String>)) -> String {
+    match value {
+        Some(value) => format!("--{name}={value}"),
+        None => format!("--{name}"),
+    }
+}
+
+impl Operation<'_> {
+    /// Write the operation as a single line, without the line feed.
+    pub(crate) fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_with(w, Inexpressible::Reject)
+    }
+
+    /// Write the operation like [`Operation::write_to`], doing what
+    /// `inexpressible` tells with the key or the value the syntax can't
+    /// express.
+    pub(crate) fn write_with<W: Write>(
+        &self,
+        w: &mut W,
+        inexpressible: Inexpressible,
+    ) -> io::Result<()> {
+        match self {
+            Operation::Put(put) => {
+                w.write_all(b"put ")?;
+                write_data(w, (&put.key, put.key_kind), Place::Word, inexpressible)?;
+                w.write_all(b" ")?;
+                write_data(w, (&put.value, put.value_kind), Place::Word, inexpressible)?;
+                if let Some(lease) = put.lease {
+                    write!(w, " --lease={lease:x}")?;
+                }
+                for flag in &put.unknown_flags {
+                    write!(w, " {}", unknown_flag(flag))?;
+                }
+                Ok(())
+            }
+            Operation::Delete(delete) => {
+                w.write_all(b"del ")?;
+                write_data(
+                    w,
+                    (&delete.key, delete.key_kind),
+                    Place::Word,
+                    inexpressible,
+                )
+            }
+            Operation::Get(get) => {
+                w.write_all(b"get ")?;
+                write_data(w, (&get.key, get.key_kind), Place::Word, inexpressible)?;
+                if let Some(range_end) = get.range_end {
+                    w.write_all(b" ")?;
+                    write_data(
+                        w,
+                        (&range_end, get.range_end_kind),
+                        Place::Word,
+                        inexpressible,
+                    )?;
+                }
+                if get.prefix {
+                    w.write_all(b" --prefix")?;
+                }
+                if get.from_key {
+                    w.write_all(b" --from-key")?;
+                }
+                if let Some(consistency) = get.consistency {
+                    write!(w, " {}", consistency_flag(consistency))?;
+                }
+                let filters = [
+                    ("min-create-rev", get.min_create_rev),
+                    ("max-create-rev", get.max_create_rev),
+                    ("min-mod-rev", get.min_mod_rev),
+                    ("max-mod-rev", get.max_mod_rev),
+                ];
+                for (name, revision) in filters {
+                    if let Some(revision) = revision {
+                        write!(w, " --{name}={revision}")?;
+                    }
+                }
+                for flag in &get.unknown_flags {
+                    write!(w, " {}", unknown_flag(flag))?;
+                }
+                Ok(())
+            }
+            Operation::PutFile(put) => {
+                w.write_all(b"put ")?;
+                write_data(w, (&put.key, put.key_kind), Place::Word, inexpressible)?;
+                w.write_all(b" @")?;
+                w.write_all(put.path)
+            }
+            Operation::Noop => w.write_all(b"noop"),
+            Operation::Custom(custom) => {
+                w.write_all(custom.command)?;
+                for arg in &custom.args {
+                    w.write_all(b" ")?;
+                    w.write_all(arg)?;
+                }
+                Ok(())
+            }
+            Operation::Unknown(line) => w.write_all(line),
+        }
+    }
+
+    /// The operation as a line of the text format, without the line feed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if the key or the value
+    /// can't be expressed by the syntax, see [`TxnData::write_to`].
+    pub fn to_text(&self) -> io::Result<Vec<u8>> {
+        let mut text = vec![];
+        self.write_to(&mut text)?;
+        Ok(text)
+    }
+}
+
+impl TxnData<'_> {
+    /// Write the transaction in its canonical text format.
+    ///
+    /// Nodes are written one by one, so a large transaction is serialized
+    /// without building it in memory first. Quoted keys and values keep their
+    /// quotes and escape sequences. The others are double quoted, single
+    /// quoted if they hold a double quote, unquoted if they fit neither
+    /// quotes, escaped between double quotes as a last resort, so that they're
+    /// read back as the same bytes. Compare targets are written with their
+    /// long name.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the writer, or an `io::ErrorKind::InvalidData`
+    /// error if a quoted key or value doesn't fit its quotes and holds an
+    /// invalid escape sequence, which parsing never gives.
+    ///
+    /// The output is stable: parsing it gives back the transaction, and
+    /// writing that transaction again gives the same bytes.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_with(w, Inexpressible::Reject)
+    }
+
+    /// Write the transaction like [`TxnData::write_to`], doing what
+    /// `inexpressible` tells with the keys and the values the syntax can't
+    /// express.
+    pub(crate) fn write_with<W: Write>(
+        &self,
+        w: &mut W,
+        inexpressible: Inexpressible,
+    ) -> io::Result<()> {
+        for (index, compare) in self.compares.iter().enumerate() {
+            if index > 0 {
+                w.write_all(b"\n")?;
+            }
+            compare.write_with(w, inexpressible)?;
+        }
+        w.write_all(b"\n\n")?;
+
+        for (index, operation) in self.success.iter().enumerate() {
+            if index > 0 {
+                w.write_all(b"\n")?;
+            }
+            operation.write_with(w, inexpressible)?;
+        }
+        w.write_all(b"\n\n")?;
+
+        for operation in &self.failure {
+            operation.write_with(w, inexpressible)?;
+            w.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// The transaction in its canonical text format, as written by
+    /// [`TxnData::write_to`], e.g. to be fed back to `etcdctl txn`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if a key or a value can't
+    /// be expressed by the syntax, see [`TxnData::write_to`].
+    pub fn to_text(&self) -> io::Result<Vec<u8>> {
+        let mut text = vec![];
+        self.write_to(&mut text)?;
+        Ok(text)
+    }
+
+    /// Write the transaction on a single line, the form read by
+    /// [`parse_compact`](crate::parse_compact).
+    ///
+    /// Sections are separated by ` ; `, the nodes of a section by `, `. Nodes
+    /// are written like [`TxnData::write_to`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if a key or a value can't
+    /// be expressed by the syntax, see [`TxnData::write_to`], holds a line
+    /// feed or isn't valid UTF-8.
+    pub fn to_compact_string(&self) -> io::Result<String> {
+        fn write_nodes<T>(
+            w: &mut Vec<u8>,
+            nodes: &[T],
+            write: impl Fn(&T, &mut Vec<u8>) -> io::Result<()>,
+        ) -> io::Result<()> {
+            for (index, node) in nodes.iter().enumerate() {
+                if index > 0 {
+                    w.write_all(b", ")?;
+                }
+                write(node, w)?;
+            }
+            Ok(())
+        }
+
+        let mut buffer = vec![];
+        write_nodes(&mut buffer, &self.compares, Compare::write_to)?;
+        buffer.write_all(b" ; ")?;
+        write_nodes(&mut buffer, &self.success, Operation::write_to)?;
+        buffer.write_all(b" ; ")?;
+        write_nodes(&mut buffer, &self.failure, Operation::write_to)?;
+        if buffer.contains(&b'\n') {
+            return Err(invalid_data(
+                "a string holding a line feed can't be written on a single line",
+            ));
+        }
+        String::from_utf8(buffer).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::{Compare, OpType, Version};
+    use crate::data::{self, Key};
+    use crate::operation::{DataKind, DeleteData, Operation, PutData};
+    use crate::{parse, parse_compact, TxnData};
+    use std::io::ErrorKind;
+
+    /// A put of an empty value, given as bytes.
+    fn put_data(key: &[u8]) -> PutData<'_> {
+        match Operation::put(key, b"") {
+            Operation::Put(put) => put,
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(txn: &TxnData) -> Vec<u8> {
+        let mut buffer = vec![];
+        txn.write_to(&mut buffer).expect("Failed to write");
+        buffer
+    }
+
+    #[test]
+    fn test_write_to() {
+        let txn = parse(
+            b"mod(\"key1\") > 0\nval(key2) = \"some value\"\n\nput key1 \"overwrote-key1\"\nget key2\n\ndel key1\n",
+        )
+        .expect("Failed to parse");
+        let written = write(&txn);
+        assert_eq!(
+            written,
+            b"mod(\"key1\") > 0\nvalue(\"key2\") = \"some value\"\n\nput \"key1\" \"overwrote-key1\"\nget \"key2\"\n\ndel \"key1\"\n"
+        );
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+    }
+
+    #[test]
+    fn test_to_text() {
+        let txn = parse(b"mod(key1) != 0\n\nput \"key 1\" \"v w\"\n\ndel key1\n")
+            .expect("Failed to parse");
+        let text = txn.to_text().expect("Failed to write");
+        assert_eq!(text, write(&txn));
+        assert_eq!(parse(&text).expect("Failed to parse"), txn);
+
+        assert_eq!(
+            txn.compares[0].to_text().expect("Failed to write"),
+            b"mod(\"key1\") != 0"
+        );
+        assert_eq!(
+            txn.success[0].to_text().expect("Failed to write"),
+            b"put \"key 1\" \"v w\""
+        );
+        let put = Operation::Put(PutData {
+            value: data::Value(b"\"' \\q"),
+            value_kind: DataKind::Quoted,
+            ..put_data(b"key1")
+        });
+        let err = put.to_text().expect_err("Expected an error");
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_write_to_empty_sections() {
+        let txn = TxnData {
+            compares: vec![],
+            success: vec![Operation::get(b"key1")],
+            failure: vec![],
+        };
+        let written = write(&txn);
+        assert_eq!(written, b"\n\nget \"key1\"\n\n");
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+
+        let txn = TxnData {
+            compares: vec![Compare::version(b"key1", OpType::LessThan, 3)],
+            success: vec![],
+            failure: vec![Operation::put(b"key1", b"")],
+        };
+        let written = write(&txn);
+        assert_eq!(written, b"version(\"key1\") < 3\n\n\n\nput \"key1\" \"\"\n");
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+    }
+
+    #[test]
+    fn test_write_to_lease() {
+        let txn = TxnData {
+            compares: vec![],
+            success: vec![Operation::put_with_lease(b"key1", b"value1", 0x1f)],
+            failure: vec![],
+        };
+        let written = write(&txn);
+        assert_eq!(written, b"\n\nput \"key1\" \"value1\" --lease=1f\n\n");
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+    }
+
+    #[test]
+    fn test_write_to_get_flags() {
+        let txn = parse(b"\n\nget --max-mod-rev=9 --prefix app/ --min-create-rev=2\n\n")
+            .expect("Failed to parse");
+        let written = write(&txn);
+        assert_eq!(
+            written,
+            b"\n\nget \"app/\" --prefix --min-create-rev=2 --max-mod-rev=9\n\n"
+        );
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+    }
+
+    #[test]
+    fn test_to_compact_string() {
+        let txn = parse(
+            b"mod(key1) > 0\nval(key2) = \"a, b; c\"\n\nput key1 v --lease=1f\nget --prefix k\n\n",
+        )
+        .expect("Failed to parse");
+        let compact = txn.to_compact_string().expect("Failed to write");
+        assert_eq!(
+            compact,
+            "mod(\"key1\") > 0, value(\"key2\") = \"a, b; c\" ; put \"key1\" \"v\" --lease=1f, get \"k\" --prefix ; "
+        );
+        assert_eq!(
+            parse_compact(compact.as_bytes()).expect("Failed to parse"),
+            txn
+        );
+
+        let txn = parse(b"\n\n\n\n").expect("Failed to parse");
+        let compact = txn.to_compact_string().expect("Failed to write");
+        assert_eq!(compact, " ;  ; ");
+        assert_eq!(
+            parse_compact(compact.as_bytes()).expect("Failed to parse"),
+            txn
+        );
+
+        let txn = TxnData {
+            compares: vec![],
+            success: vec![Operation::put(b"key1", b"a\nb")],
+            failure: vec![],
+        };
+        let err = txn.to_compact_string().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_write_to_line_feeds() {
+        let txn = TxnData {
+            compares: vec![Compare::value(b"key\n1", OpType::Equal, b"\n\n")],
+            success: vec![Operation::put(b"key1", b"a\nb")],
+            failure: vec![Operation::delete(b"key\n")],
+        };
+        let written = write(&txn);
+        assert_eq!(
+            written,
+            b"value(\"key\n1\") = \"\n\n\"\n\nput \"key1\" \"a\nb\"\n\ndel \"key\n\"\n"
+        );
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+    }
+
+    #[test]
+    fn test_write_to_double_quotes() {
+        let txn = TxnData {
+            compares: vec![],
+            success: vec![Operation::put(b"key1", b"a \"b\"")],
+            failure: vec![],
+        };
+        let written = write(&txn);
+        assert_eq!(written, b"\n\nput \"key1\" 'a \"b\"'\n\n");
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+    }
+
+    #[test]
+    fn test_write_to_parsed_strings() {
+        // The strings fitting neither quotes are written unquoted
+        for (line, expected) in [
+            (&b"put k it's\"x\""[..], &b"put \"k\" it's\"x\""[..]),
+            (b"put k a\\", b"put \"k\" a\\"),
+            (b"del 'a\\' b'", b"del 'a\\' b'"),
+            (b"put k \"it's \\\"x\\\"\"", b"put \"k\" \"it's \\\"x\\\"\""),
+        ] {
+            let operation = Operation::parse(line).expect("Failed to parse");
+            let text = operation.to_text().expect("Failed to write");
+            assert_eq!(text, expected, "{:?}", String::from_utf8_lossy(line));
+            assert_eq!(Operation::parse(&text).expect("Failed to parse"), operation);
+        }
+
+        // The value of a value compare runs until the end of the line
+        let compare = Compare::parse(b"val(k) = it's \"x\" y").expect("Failed to parse");
+        let text = compare.to_text().expect("Failed to write");
+        assert_eq!(text, b"value(\"k\") = it's \"x\" y");
+        assert_eq!(Compare::parse(&text).expect("Failed to parse"), compare);
+    }
+
+    #[test]
+    fn test_write_to_escape_sequences() {
+        // Quoted data keeps its quotes and its escape sequences
+        for line in [
+            &br#"put "k" '\x41'"#[..],
+            br#"put "\x41" "it\'s \"x\"\n""#,
+            br"put 'it\'s' '\n'",
+            br#"del "\u{e9}""#,
+            br#"get "a\x00" "\x7f""#,
+        ] {
+            let operation = Operation::parse(line).expect("Failed to parse");
+            let text = operation.to_text().expect("Failed to write");
+            assert_eq!(text, line, "{:?}", String::from_utf8_lossy(line));
+            assert_eq!(Operation::parse(&text).expect("Failed to parse"), operation);
+        }
+        let compare = Compare::parse(br"val('(\x41)') = '\x41'").expect("Failed to parse");
+        let text = compare.to_text().expect("Failed to write");
+        assert_eq!(text, br"value('(\x41)') = '\x41'");
+        assert_eq!(Compare::parse(&text).expect("Failed to parse"), compare);
+
+        // The bytes quoted data stands for are written if it doesn't fit its
+        // quotes
+        let operation = Operation::Put(PutData {
+            value: data::Value(br#"say "hi" \x41"#),
+            value_kind: DataKind::Quoted,
+            ..put_data(b"k")
+        });
+        let text = operation.to_text().expect("Failed to write");
+        assert_eq!(text, br#"put "k" 'say "hi" A'"#);
+        let Operation::Put(put) = Operation::parse(&text).expect("Failed to parse") else {
+            panic!("expected a put");
+        };
+        assert_eq!(
+            put.decoded_value().expect("Failed to decode"),
+            &br#"say "hi" A"#[..]
+        );
+        let compare = Compare::Version(Version {
+            key: Key(br"a(\x41"),
+            key_kind: DataKind::Quoted,
+            value: 1,
+            op: OpType::Equal,
+        });
+        let text = compare.to_text().expect("Failed to write");
+        assert_eq!(text, br#"version("a\x28A") = 1"#);
+        assert_eq!(Compare::parse(&text).expect("Failed to parse"), compare);
+    }
+
+    #[test]
+    fn test_write_to_inexpressible() {
+        // Parsing never gives quoted data which doesn't fit its quotes and
+        // can't be decoded
+        let inexpressible = [
+            Operation::Put(PutData {
+                value: data::Value(b"it's \"quoted\" \\q"),
+                value_kind: DataKind::Quoted,
+                ..put_data(b"key1")
+            }),
+            Operation::Delete(DeleteData {
+                key: Key(b"\"\\q"),
+                key_kind: DataKind::Quoted,
+            }),
+        ];
+        for operation in inexpressible {
+            let err = operation.write_to(&mut vec![]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+        for key in [&b"key1(\\q"[..], b")key1(\\q"] {
+            let compare = Compare::Version(Version {
+                key: Key(key),
+                key_kind: DataKind::Quoted,
+                value: 1,
+                op: OpType::Equal,
+            });
+            let err = compare.write_to(&mut vec![]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+
+        // The bytes given to the constructors are always written, escaped
+        // when they fit no other form
+        let txn = TxnData {
+            compares: vec![
+                Compare::version(b"key1(", OpType::Equal, 1),
+                Compare::version(b")key1(", OpType::Equal, 1),
+            ],
+            success: vec![Operation::put(b"key1", b"it's \"quoted\"")],
+            failure: vec![Operation::delete(b"\"key1\"\\\\")],
+        };
+        let written = write(&txn);
+        assert_eq!(
+            written,
+            b"version(\"key1\\x28\") = 1\nversion(\"\\x29key1\\x28\") = 1\n\n\
+              put \"key1\" \"it's \\x22quoted\\x22\"\n\n\
+              del \"\\x22key1\\x22\\\\\\\\\"\n"
+        );
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+
+        let txn = TxnData {
+            compares: vec![Compare::version(b"(key1)", OpType::Equal, 1)],
+            success: vec![Operation::put(b"key1\\\\", b"a\\b")],
+            failure: vec![],
+        };
+        let written = write(&txn);
+        assert_eq!(
+            written,
+            b"version(\"(key1)\") = 1\n\nput key1\\\\ 'a\\b'\n\n"
+        );
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+
+        let txn = TxnData {
+            compares: vec![Compare::value(b"\"key1\"", OpType::Equal, b"it's")],
+            success: vec![],
+            failure: vec![],
+        };
+        let written = write(&txn);
+        assert_eq!(written, b"value('\"key1\"') = \"it's\"\n\n\n\n");
+        assert_eq!(parse(&written).expect("Failed to parse"), txn);
+    }
+
+    #[test]
+    fn test_display_inexpressible() {
+        // The bytes the syntax can't express as is are displayed escaped
+        // between double quotes
+        let txn = TxnData {
+            compares: vec![Compare::value(b"key1(", OpType::Equal, b"a")],
+            success: vec![Operation::put(b"key1", b"it's \"quoted\"\\")],
+            failure: vec![Operation::delete(b"a\n\"b 'c")],
+        };
+        let displayed = txn.to_string();
+        assert_eq!(
+            displayed,
+            "value(\"key1\\x28\") = \"a\"\n\n\
+             put \"key1\" \"it's \\x22quoted\\x22\\\\\"\n\n\
+             del \"a\\n\\x22b 'c\"\n"
+        );
+
+        // The displayed transaction parses back to the same bytes
+        assert_eq!(parse(displayed.as_bytes()).expect("Failed to parse"), txn);
+        let txn = TxnData {
+            compares: vec![Compare::value(b"k\xff)", OpType::Equal, b"it's \"q\" \xfe")],
+            success: vec![Operation::put(b"k", b"it's \"q\" x")],
+            failure: vec![Operation::put(b"'\xff'", b"\\\xff")],
+        };
+        let displayed = txn.to_string();
+        assert_eq!(
+            displayed,
+            "value(\"k\\xff\\x29\") = \"it's \\x22q\\x22 \\xfe\"\n\n\
+             put \"k\" \"it's \\x22q\\x22 x\"\n\n\
+             put \"'\\xff'\" \"\\\\\\xff\"\n"
+        );
+        assert_eq!(parse(displayed.as_bytes()).expect("Failed to parse"), txn);
+
+        // Quoted data which isn't valid UTF-8 is displayed as the bytes it
+        // stands for
+        let txn = parse(b"val('\xff') = 'it\\'s \xfe'\n\nput \"\\x41\xff\" 'a'\n\n")
+            .expect("Failed to parse");
+        let displayed = txn.to_string();
+        assert_eq!(
+            displayed,
+            "value(\"\\xff\") = \"it's \\xfe\"\n\nput \"A\\xff\" 'a'\n\n"
+        );
+        assert_eq!(parse(displayed.as_bytes()).expect("Failed to parse"), txn);
+
+        // Unless it holds an invalid escape sequence: it's marked, and
+        // doesn't parse
+        let txn = parse(b"\n\nput k \"\\q\xff\"\n\n").expect("Failed to parse");
+        let displayed = txn.to_string();
+        assert_eq!(
+            displayed,
+            "\n\nput \"k\" \"\\\\q\\xff\" <invalid escape>\n\n"
+        );
+        assert!(parse(displayed.as_bytes()).is_err());
+    }
+}
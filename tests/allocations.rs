@@ -0,0 +1,79 @@
+//! Parsing must only allocate the vectors holding the nodes of each section.
+
+use etcd_txn_parser::parse;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts the allocations made by the current thread, reallocations included.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Count the allocations made by `f`.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let baseline = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - baseline)
+}
+
+#[test]
+fn test_allocations_simple() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let (result, allocations) = count_allocations(|| parse(transaction));
+    let result = result.expect("Failed to parse");
+    assert_eq!(
+        (
+            result.compares.len(),
+            result.success.len(),
+            result.failure.len()
+        ),
+        (1, 1, 2)
+    );
+    // One allocation per section vector, none of them grows past its first
+    // capacity
+    assert!(
+        allocations <= 3,
+        "parsing the simple fixture made {allocations} allocations"
+    );
+}
+
+#[test]
+fn test_allocations_per_line() {
+    // Every kind of node, with flags, whitespaces and quoted strings
+    let line = "put --lease=1f \"some key\" value\nget --prefix --min-mod-rev=5 app/\ndel  key\n";
+    let mut transaction = "mod(\"key1\") > 0\nval(key2) = \"v\"\nlease(key3) = 31\n\n".to_string();
+    transaction.push_str(&line.repeat(1000));
+    transaction.push('\n');
+
+    let (result, allocations) = count_allocations(|| parse(transaction.as_bytes()));
+    let result = result.expect("Failed to parse");
+    assert_eq!(result.success.len(), 3000);
+    // Only the growths of the section vectors, logarithmic in their length:
+    // 11 for the success vector doubling from 4 to 4096 operations, 1 for
+    // the compares vector
+    assert!(
+        allocations <= 12,
+        "parsing 3000 lines made {allocations} allocations"
+    );
+}
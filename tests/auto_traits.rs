@@ -0,0 +1,116 @@
+//! The public types must be usable across threads and await points: these
+//! checks fail to compile if one of them loses an auto trait.
+
+use etcd_txn_parser::compare::{
+    Compare, CompareKind, CreateRevision, CustomCompare, Lease, ModRevision, OpType, Value, Version,
+};
+use etcd_txn_parser::convert::{Construct, ConvertError, Converted, Lossy};
+use etcd_txn_parser::data::{self, Key};
+use etcd_txn_parser::error::Error;
+use etcd_txn_parser::explain::ExplainOptions;
+use etcd_txn_parser::intern::{
+    CompareInterned, InternedValue, Interner, OperationInterned, Symbol, TxnDataInterned,
+};
+use etcd_txn_parser::keyrange::KeyRange;
+use etcd_txn_parser::operation::{
+    CustomData, Data, DataKind, DeleteData, GetData, Operation, OperationKind, PutData, PutFileData,
+};
+use etcd_txn_parser::options::{ChecksumMode, ParseOptions, UnknownFlagPolicy};
+use etcd_txn_parser::output::{KeyValue, OpResponse, TxnOutput};
+use etcd_txn_parser::owned::{CompareOwned, OperationOwned, TxnDataOwned};
+use etcd_txn_parser::split::SplitError;
+use etcd_txn_parser::validate::ValidationError;
+use etcd_txn_parser::warning::Warning;
+use etcd_txn_parser::{parse, Branch, LineError, Metadata, Parsed, Section, TxnData, Write};
+
+/// Assert at compile time that the types are `Send + Sync + Unpin`.
+macro_rules! assert_auto_traits {
+    ($($ty:ty),+ $(,)?) => {
+        const _: fn() = || {
+            fn check<T: Send + Sync + Unpin>() {}
+            $(check::<$ty>();)+
+        };
+    };
+}
+
+/// Assert at compile time that the types are `Send + Sync + Unpin` and own
+/// their data.
+macro_rules! assert_owned {
+    ($($ty:ty),+ $(,)?) => {
+        const _: fn() = || {
+            fn check<T: Send + Sync + Unpin + 'static>() {}
+            $(check::<$ty>();)+
+        };
+    };
+}
+
+assert_auto_traits!(
+    TxnData<'_>,
+    Parsed<'_>,
+    Compare<'_>,
+    CreateRevision<'_>,
+    ModRevision<'_>,
+    Value<'_>,
+    Version<'_>,
+    Lease<'_>,
+    CustomCompare<'_>,
+    Key<'_>,
+    data::Value<'_>,
+    Operation<'_>,
+    Data<'_>,
+    PutData<'_>,
+    PutFileData<'_>,
+    CustomData<'_>,
+    DeleteData<'_>,
+    GetData<'_>,
+    KeyRange<'_>,
+    Warning<'_>,
+    Write<'_>,
+    TxnOutput<'_>,
+    OpResponse<'_>,
+    KeyValue<'_>,
+);
+
+assert_owned!(
+    TxnDataOwned,
+    CompareOwned,
+    OperationOwned,
+    TxnDataInterned,
+    CompareInterned,
+    OperationInterned,
+    InternedValue,
+    Interner,
+    Symbol,
+    OpType,
+    CompareKind,
+    OperationKind,
+    DataKind,
+    Branch,
+    Section,
+    ParseOptions,
+    ChecksumMode,
+    UnknownFlagPolicy,
+    Metadata,
+    ExplainOptions,
+    Error,
+    ValidationError,
+    SplitError,
+    LineError,
+    Lossy,
+    Construct,
+    ConvertError,
+    Converted<String>,
+);
+
+#[cfg(feature = "proto")]
+assert_owned!(etcd_txn_parser::decode::DecodeError);
+
+#[test]
+fn test_error_crosses_threads() {
+    let err = parse(b"mod(key1) >> 0\n\n\n\n").expect_err("Parsed an invalid transaction");
+    let boxed: Box<dyn std::error::Error + Send + Sync + 'static> = Box::new(err);
+    let message = std::thread::spawn(move || boxed.to_string())
+        .join()
+        .expect("The thread panicked");
+    assert!(message.starts_with("parse error: "));
+}
@@ -0,0 +1,376 @@
+//! Conformance suite of the parser backends
+//!
+//! Runs against whichever backend is enabled, `elyze` by default or `nom`
+//! with the `backend-nom` feature, so that both parse the fixtures, report
+//! errors at the same positions and round trip the same transactions.
+
+use etcd_txn_parser::error::Error;
+use etcd_txn_parser::options::ParseOptions;
+use etcd_txn_parser::{
+    parse, parse_collect_errors, parse_flat, parse_labeled, parse_prefix, parse_with_options,
+    Section, TxnData,
+};
+
+type Entry = fn(&[u8]) -> Result<TxnData<'_>, Error>;
+
+fn keep_unknown_lines(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    parse_with_options(data, &options)
+}
+
+fn numeric_prefixes(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    let options = ParseOptions {
+        numeric_prefixes: true,
+        ..Default::default()
+    };
+    parse_with_options(data, &options)
+}
+
+fn allow_file_refs(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    let options = ParseOptions {
+        allow_file_refs: true,
+        ..Default::default()
+    };
+    parse_with_options(data, &options)
+}
+
+fn embedded(data: &[u8]) -> Result<TxnData<'_>, Error> {
+    let start = b"[before]\n".len();
+    parse_prefix(data.get(start..).unwrap_or_default()).map(|(txn, _)| txn)
+}
+
+/// The fixtures, along with the entry point parsing them.
+const FIXTURES: &[(&str, Entry)] = &[
+    ("blank_line_compares", parse),
+    ("checksum", parse),
+    ("checksum_mismatch", parse),
+    ("duplicate_key", parse),
+    ("embedded", embedded),
+    ("file_ref", parse),
+    ("file_ref", allow_file_refs),
+    ("flat", parse_flat),
+    ("format_version", parse),
+    ("fourth_section", parse),
+    ("get_prefix", parse),
+    ("just_success", parse),
+    ("labeled", parse_labeled),
+    ("leases", parse),
+    ("long_value", parse),
+    ("markdown_escaping", parse),
+    ("mod_equal_0", parse),
+    ("mod_less_than_0", parse),
+    ("no_compare", parse),
+    ("no_failure", parse),
+    ("no_success", parse),
+    ("numeric_prefixes", parse),
+    ("numeric_prefixes", numeric_prefixes),
+    ("padded", parse),
+    ("shebang", parse),
+    ("simple", parse),
+    ("trailing_junk", parse),
+    ("trailing_whitespace", parse),
+    ("two_malformed", parse),
+    ("two_malformed", keep_unknown_lines),
+    ("unknown_lines", parse),
+    ("unknown_lines", keep_unknown_lines),
+    ("val_key", parse),
+];
+
+/// The canonical form of a transaction, or the error parsing it.
+fn canonical(result: Result<TxnData, Error>) -> String {
+    let written = result.and_then(|txn| {
+        let mut written = vec![];
+        txn.write_to(&mut written)?;
+        Ok(written)
+    });
+    match written {
+        Ok(written) => String::from_utf8_lossy(&written).into_owned(),
+        Err(err) => format!("error: {err}\n"),
+    }
+}
+
+/// Each fixture parses to the transaction, or fails with the error, in its
+/// `.parsed` file. The fixtures parsed by several entry points have a line
+/// per entry point, separated by `---`.
+#[test]
+fn test_fixtures() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut names: Vec<&str> = FIXTURES.iter().map(|(name, _)| *name).collect();
+    names.dedup();
+    for name in names {
+        let transaction = std::fs::read(dir.join(format!("{name}.txt"))).expect("Missing fixture");
+        let parsed = FIXTURES
+            .iter()
+            .filter(|(fixture, _)| *fixture == name)
+            .map(|(_, entry)| canonical(entry(&transaction)))
+            .collect::<Vec<_>>()
+            .join("\n---\n");
+        let expected =
+            std::fs::read_to_string(dir.join(format!("{name}.parsed"))).expect("Missing output");
+        assert_eq!(parsed, expected, "{name}");
+    }
+}
+
+/// Malformed lines are reported at the offset of their line, in their
+/// section, or at the content following their compare if there is one.
+#[test]
+fn test_error_positions() {
+    type Case<'a> = (&'a [u8], &'a [(Section, usize)]);
+    let cases: &[Case] = &[
+        (
+            include_bytes!("fixtures/two_malformed.txt"),
+            &[(Section::Compare, 16), (Section::Success, 50)],
+        ),
+        (
+            b"mod(key1) >> 0\n\nput key1 value1\n\nget key1",
+            &[(Section::Compare, 0)],
+        ),
+        (
+            b"mod(key1) > 0\nval(key1 = 1\n\nput key1\n\ndel",
+            &[(Section::Compare, 14)],
+        ),
+        (
+            b"mod(key1) > 0x\n\nput key1 value1\n\n",
+            &[(Section::Compare, 13)],
+        ),
+        (
+            b"version(key1) = x\n\nput key1 value1 --lease=zz\n\nget key1 --prefix=1",
+            &[
+                (Section::Compare, 0),
+                (Section::Success, 19),
+                (Section::Failure, 47),
+            ],
+        ),
+        (
+            b"lease(key1) = 1\n\nget key1\nfrob key1\n\n",
+            &[(Section::Success, 26)],
+        ),
+        (
+            b"\n\nfrobnicate\n\nput\tkey1 value1",
+            &[(Section::Success, 2)],
+        ),
+    ];
+    for (transaction, expected) in cases {
+        let (_, errors) = parse_collect_errors(transaction);
+        let positions: Vec<_> = errors
+            .iter()
+            .map(|(section, offset, _)| (*section, *offset))
+            .collect();
+        assert_eq!(
+            positions,
+            *expected,
+            "{:?}",
+            String::from_utf8_lossy(transaction)
+        );
+    }
+}
+
+/// A xorshift generator, seeded so that a failure reproduces.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+
+    fn word(&mut self) -> String {
+        const ALPHABET: &[u8] = b"abcxyz019_./:";
+        (0..1 + self.below(8))
+            .map(|_| char::from(ALPHABET[self.below(ALPHABET.len())]))
+            .collect()
+    }
+
+    /// A key or a value, unquoted or quoted with whitespaces around, quotes
+    /// or backslashes inside.
+    fn data(&mut self) -> String {
+        match self.below(11) {
+            0 => format!("\" {} \"", self.word()),
+            1 => format!("\"\t{}\"", self.word()),
+            2 => format!("\"{} {}  \"", self.word(), self.word()),
+            3 => "\"\"".to_string(),
+            4 => format!("'{}\" {}'", self.word(), self.word()),
+            5 => format!("\"{}\\\" {}'\"", self.word(), self.word()),
+            6 => format!("{}'\"{}\"", self.word(), self.word()),
+            7 => format!("{}\\", self.word()),
+            8 => format!("\"{}\\\\\"", self.word()),
+            _ => self.word(),
+        }
+    }
+
+    fn compare(&mut self) -> String {
+        let target = ["mod", "create", "version", "lease", "value"][self.below(5)];
+        let op = ["=", ">", "<"][self.below(3)];
+        let value = match target {
+            "value" => self.data(),
+            _ => self.below(1_000_000).to_string(),
+        };
+        format!("{target}(\"{}\") {op} {value}", self.word())
+    }
+
+    fn operation(&mut self) -> String {
+        match self.below(6) {
+            0 => format!("put {} {}", self.data(), self.data()),
+            1 => format!(
+                "put {} {} --lease={:x}",
+                self.word(),
+                self.word(),
+                self.below(1 << 20)
+            ),
+            2 => format!("del {}", self.data()),
+            3 => format!("get {} --prefix", self.word()),
+            4 => format!("get --from-key {}", self.data()),
+            _ => format!("get {} {}", self.data(), self.data()),
+        }
+    }
+
+    fn lines(&mut self, line: fn(&mut Self) -> String) -> Vec<String> {
+        (0..self.below(4)).map(|_| line(self)).collect()
+    }
+}
+
+/// Generated transactions parse back to themselves once written.
+#[test]
+fn test_round_trip() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..2_000 {
+        let transaction = format!(
+            "{}\n\n{}\n\n{}\n",
+            rng.lines(Rng::compare).join("\n"),
+            rng.lines(Rng::operation).join("\n"),
+            rng.lines(Rng::operation).join("\n"),
+        );
+        let txn = parse(transaction.as_bytes())
+            .unwrap_or_else(|err| panic!("Failed to parse {transaction:?}: {err}"));
+        let mut written = vec![];
+        txn.write_to(&mut written).expect("Failed to write");
+        assert_eq!(
+            parse(&written).expect("Failed to parse again"),
+            txn,
+            "{transaction:?}"
+        );
+    }
+}
+
+fn format(txn: &TxnData) -> Vec<u8> {
+    txn.to_text().expect("Failed to format")
+}
+
+/// Formatting the transaction parsed from `input` keeps the transaction,
+/// and formatting it again gives the same bytes.
+fn assert_stable(input: &[u8], options: &ParseOptions) {
+    let txn = parse_with_options(input, options).unwrap_or_else(|err| {
+        panic!(
+            "Failed to parse {:?}: {err}",
+            String::from_utf8_lossy(input)
+        )
+    });
+    let formatted = format(&txn);
+    let reparsed = parse_with_options(&formatted, options)
+        .unwrap_or_else(|err| panic!("Failed to parse {formatted:?}: {err}"));
+    assert_eq!(reparsed, txn, "{:?}", String::from_utf8_lossy(input));
+    assert_eq!(
+        String::from_utf8_lossy(&format(&reparsed)),
+        String::from_utf8_lossy(&formatted),
+        "{:?}",
+        String::from_utf8_lossy(input)
+    );
+}
+
+/// The fixtures which `parse` rejects, malformed or meant for another entry
+/// point, along with whether keeping the unknown lines parses them.
+const UNPARSED: &[(&str, bool)] = &[
+    ("blank_line_compares", false),
+    ("checksum", true),
+    ("checksum_mismatch", true),
+    ("embedded", false),
+    ("flat", false),
+    ("fourth_section", false),
+    ("labeled", false),
+    ("numeric_prefixes", true),
+    ("trailing_junk", false),
+    ("two_malformed", true),
+    ("unknown_lines", true),
+];
+
+/// Formatting is stable over the fixtures and the generated transactions,
+/// which have quoted data with whitespaces around, quotes and backslashes
+/// inside, empty sections and lines ending with whitespaces.
+#[test]
+fn test_format_stable() {
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for (name, _) in FIXTURES {
+        let transaction = std::fs::read(dir.join(format!("{name}.txt"))).expect("Missing fixture");
+        let unparsed = UNPARSED.iter().find(|(unparsed, _)| unparsed == name);
+        for (options, parses) in [
+            (&ParseOptions::default(), unparsed.is_none()),
+            (&options, unparsed.is_none_or(|(_, kept)| *kept)),
+        ] {
+            match parses {
+                true => assert_stable(&transaction, options),
+                false => assert!(parse_with_options(&transaction, options).is_err(), "{name}"),
+            }
+        }
+
+        // Displaying is formatting
+        if unparsed.is_none() {
+            let txn = parse(&transaction).expect("Failed to parse");
+            assert_eq!(txn.to_string().as_bytes(), format(&txn), "{name}");
+        }
+    }
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..5_000 {
+        let transaction = format!(
+            "{}\n\n{}\n\n{}{}",
+            rng.lines(Rng::compare).join("\n"),
+            rng.lines(Rng::operation).join("\n"),
+            rng.lines(Rng::operation).join(["\n", " \n"][rng.below(2)]),
+            ["", "\n", "\n\n", " "][rng.below(4)],
+        );
+        assert_stable(transaction.as_bytes(), &ParseOptions::default());
+        assert_stable(transaction.as_bytes(), &options);
+    }
+}
+
+/// The transactions generated for the fuzz targets are kept by formatting,
+/// and formatted once and for all.
+#[cfg(feature = "arbitrary")]
+#[test]
+fn test_format_stable_arbitrary() {
+    use arbitrary::{Arbitrary, Unstructured};
+    use etcd_txn_parser::owned::TxnDataOwned;
+
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        allow_file_refs: true,
+        allow_extensions: true,
+        ..Default::default()
+    };
+    let mut rng = Rng(0xd1b5_4a32_d192_ed03);
+    for _ in 0..5_000 {
+        let bytes: Vec<u8> = (0..rng.below(4096)).map(|_| rng.below(256) as u8).collect();
+        let Ok(txn) = TxnDataOwned::arbitrary(&mut Unstructured::new(&bytes)) else {
+            continue;
+        };
+        let txn = txn.as_txn();
+        let formatted = format(&txn);
+        let reparsed = parse_with_options(&formatted, &options).unwrap_or_else(|err| {
+            panic!(
+                "Failed to parse {:?}: {err}",
+                String::from_utf8_lossy(&formatted)
+            )
+        });
+        let formatted = String::from_utf8_lossy(&formatted);
+        assert_eq!(reparsed, txn, "{formatted:?}");
+        assert_eq!(String::from_utf8_lossy(&format(&reparsed)), formatted);
+    }
+}
@@ -0,0 +1,257 @@
+//! Differential tests against the real `etcdctl`.
+//!
+//! Generated transactions are run through `etcdctl txn` as written, and as
+//! written back by this crate once parsed, against throwaway keys of a scratch
+//! etcd. The crate and `etcdctl` must accept or reject them alike, and both
+//! writings must leave the keys in the same state.
+//!
+//! Skipped unless `ETCDCTL_BIN` and `ETCD_ENDPOINT` are set, e.g.
+//! `ETCDCTL_BIN=etcdctl ETCD_ENDPOINT=http://127.0.0.1:2379 cargo test --test etcdctl`.
+
+use etcd_txn_parser::parse;
+use std::ffi::OsString;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// The number of generated transactions.
+const CASES: usize = 200;
+
+/// The seed of the generator, fixed so that a failing case can be replayed.
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// ----------------------------------------------------------------------------
+// Corpus generation
+// ----------------------------------------------------------------------------
+
+/// A xorshift generator, good enough to pick the nodes of a transaction.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+/// Lines etcdctl rejects whatever the state of the keys, `{key}` being an
+/// unquoted key.
+const MALFORMED: &[&str] = &[
+    "frobnicate {key}",
+    "size({key}) = 1",
+    "mod({key}) >",
+    "ver({key}) = one",
+];
+
+/// Pick one of the throwaway keys, quoted or not.
+fn key(rng: &mut Rng, prefix: &str) -> String {
+    let key = format!("{prefix}k{}", rng.below(3));
+    if rng.below(2) == 0 {
+        format!("\"{key}\"")
+    } else {
+        key
+    }
+}
+
+/// Generate a value, quoted when it holds a space.
+fn value(rng: &mut Rng) -> String {
+    match rng.pick(&["v0", "v1", "x", "new value"]) {
+        value if value.contains(' ') || rng.below(2) == 0 => format!("\"{value}\""),
+        value => value.to_string(),
+    }
+}
+
+fn compare(rng: &mut Rng, prefix: &str) -> String {
+    let key = key(rng, prefix);
+    let op = rng.pick(&["=", ">", "<"]);
+    match rng.below(4) {
+        // Revisions differ from one run to the other, only their existence
+        // check is stable
+        0 => format!(
+            "{}({key}) {} 0",
+            rng.pick(&["create", "c", "mod", "m"]),
+            rng.pick(&["=", ">"])
+        ),
+        1 => format!(
+            "{}({key}) {op} {}",
+            rng.pick(&["version", "ver"]),
+            rng.below(3)
+        ),
+        2 => format!("{}({key}) {op} {}", rng.pick(&["value", "val"]), value(rng)),
+        _ => format!("lease({key}) = 0"),
+    }
+}
+
+fn operation(rng: &mut Rng, prefix: &str) -> String {
+    let key = key(rng, prefix);
+    match rng.below(3) {
+        0 => format!("put {key} {}", value(rng)),
+        1 => format!("del {key}"),
+        _ => format!("get {key}"),
+    }
+}
+
+/// Generate a transaction over the keys of `prefix`, one line in eight being
+/// malformed.
+fn transaction(rng: &mut Rng, prefix: &str) -> String {
+    let section = |rng: &mut Rng, node: fn(&mut Rng, &str) -> String| {
+        (0..rng.below(4))
+            .map(|_| {
+                if rng.below(8) == 0 {
+                    let key = format!("{prefix}k{}", rng.below(3));
+                    rng.pick(MALFORMED).replace("{key}", &key)
+                } else {
+                    node(rng, prefix)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let compares = section(rng, compare);
+    let success = section(rng, operation);
+    let failure = section(rng, operation);
+    format!("{compares}\n\n{success}\n\n{failure}\n")
+}
+
+// ----------------------------------------------------------------------------
+// etcdctl
+// ----------------------------------------------------------------------------
+
+struct Etcdctl {
+    bin: OsString,
+    endpoint: OsString,
+    /// The prefix of the throwaway keys.
+    prefix: String,
+}
+
+impl Etcdctl {
+    fn command(&self, args: &[&str]) -> Command {
+        let mut endpoints = OsString::from("--endpoints=");
+        endpoints.push(&self.endpoint);
+        let mut command = Command::new(&self.bin);
+        command
+            .env("ETCDCTL_API", "3")
+            .arg(endpoints)
+            .arg("--dial-timeout=5s")
+            .args(args);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = self.command(args).output().expect("Failed to run etcdctl");
+        assert!(
+            output.status.success(),
+            "etcdctl {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Run `etcdctl txn` with the transaction on its standard input.
+    fn txn(&self, transaction: &[u8]) -> Output {
+        let mut child = self
+            .command(&["txn"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to run etcdctl");
+        child
+            .stdin
+            .take()
+            .expect("Missing standard input")
+            .write_all(transaction)
+            .expect("Failed to write the transaction");
+        child.wait_with_output().expect("Failed to run etcdctl")
+    }
+
+    /// Remove the throwaway keys and write the initial ones.
+    fn reset(&self) {
+        self.run(&["del", "--prefix", &self.prefix]);
+        self.run(&["put", &format!("{}k0", self.prefix), "v0"]);
+        self.run(&["put", &format!("{}k1", self.prefix), "v1"]);
+    }
+
+    /// The throwaway keys along with their values, in key order.
+    fn snapshot(&self) -> Vec<(String, String)> {
+        let output = self.run(&["get", "--prefix", &self.prefix]);
+        let output = String::from_utf8(output.stdout).expect("Invalid UTF-8 output");
+        let lines: Vec<_> = output.lines().collect();
+        lines
+            .chunks(2)
+            .map(|pair| (pair[0].to_string(), pair.get(1).unwrap_or(&"").to_string()))
+            .collect()
+    }
+}
+
+impl Drop for Etcdctl {
+    fn drop(&mut self) {
+        let _ = self.command(&["del", "--prefix", &self.prefix]).output();
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[test]
+fn test_differential_etcdctl() {
+    let (Some(bin), Some(endpoint)) = (
+        std::env::var_os("ETCDCTL_BIN"),
+        std::env::var_os("ETCD_ENDPOINT"),
+    ) else {
+        eprintln!("skipped: ETCDCTL_BIN and ETCD_ENDPOINT aren't set");
+        return;
+    };
+    let etcdctl = Etcdctl {
+        bin,
+        endpoint,
+        prefix: format!("etcd-txn-parser-differential/{}/", std::process::id()),
+    };
+
+    let mut rng = Rng(SEED);
+    for case in 0..CASES {
+        let transaction = transaction(&mut rng, &etcdctl.prefix);
+
+        etcdctl.reset();
+        let output = etcdctl.txn(transaction.as_bytes());
+        let parsed = parse(transaction.as_bytes());
+        assert_eq!(
+            parsed.is_ok(),
+            output.status.success(),
+            "case {case} accepted by one side only: {transaction:?}\n{parsed:?}\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let Ok(parsed) = parsed else {
+            continue;
+        };
+        let expected = etcdctl.snapshot();
+
+        let mut written = vec![];
+        parsed
+            .write_to(&mut written)
+            .expect("Failed to write the transaction");
+        etcdctl.reset();
+        let output = etcdctl.txn(&written);
+        assert!(
+            output.status.success(),
+            "case {case} rejected once written back: {:?}\n{}",
+            String::from_utf8_lossy(&written),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            etcdctl.snapshot(),
+            expected,
+            "case {case} diverges once written back: {transaction:?}"
+        );
+    }
+}
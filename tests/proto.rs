@@ -0,0 +1,196 @@
+//! The protobuf encoding decoded by `prost` as the etcd v3 messages.
+//!
+//! The messages mirror the fields of `etcdserverpb` in etcd's `rpc.proto`
+//! which the encoding writes.
+
+#![cfg(feature = "proto")]
+
+use etcd_txn_parser::parse;
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+struct TxnRequest {
+    #[prost(message, repeated, tag = "1")]
+    compare: Vec<Compare>,
+    #[prost(message, repeated, tag = "2")]
+    success: Vec<RequestOp>,
+    #[prost(message, repeated, tag = "3")]
+    failure: Vec<RequestOp>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Compare {
+    #[prost(int32, tag = "1")]
+    result: i32,
+    #[prost(int32, tag = "2")]
+    target: i32,
+    #[prost(bytes = "vec", tag = "3")]
+    key: Vec<u8>,
+    #[prost(oneof = "TargetUnion", tags = "4, 5, 6, 7, 8")]
+    target_union: Option<TargetUnion>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+enum TargetUnion {
+    #[prost(int64, tag = "4")]
+    Version(i64),
+    #[prost(int64, tag = "5")]
+    CreateRevision(i64),
+    #[prost(int64, tag = "6")]
+    ModRevision(i64),
+    #[prost(bytes = "vec", tag = "7")]
+    Value(Vec<u8>),
+    #[prost(int64, tag = "8")]
+    Lease(i64),
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RequestOp {
+    #[prost(oneof = "Request", tags = "1, 2, 3")]
+    request: Option<Request>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+enum Request {
+    #[prost(message, tag = "1")]
+    Range(RangeRequest),
+    #[prost(message, tag = "2")]
+    Put(PutRequest),
+    #[prost(message, tag = "3")]
+    DeleteRange(DeleteRangeRequest),
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RangeRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    range_end: Vec<u8>,
+    #[prost(bool, tag = "7")]
+    serializable: bool,
+    #[prost(int64, tag = "10")]
+    min_mod_revision: i64,
+    #[prost(int64, tag = "11")]
+    max_mod_revision: i64,
+    #[prost(int64, tag = "12")]
+    min_create_revision: i64,
+    #[prost(int64, tag = "13")]
+    max_create_revision: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct PutRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    value: Vec<u8>,
+    #[prost(int64, tag = "3")]
+    lease: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DeleteRangeRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    range_end: Vec<u8>,
+}
+
+/// Parse and encode a transaction, then decode it with `prost`.
+fn decode(transaction: &[u8]) -> TxnRequest {
+    let txn = parse(transaction).expect("Failed to parse");
+    let encoded = txn.to_protobuf().expect("Failed to encode");
+    TxnRequest::decode(encoded.as_slice()).expect("Failed to decode")
+}
+
+fn compare(result: i32, target: i32, key: &[u8], target_union: TargetUnion) -> Compare {
+    Compare {
+        result,
+        target,
+        key: key.to_vec(),
+        target_union: Some(target_union),
+    }
+}
+
+fn op(request: Request) -> RequestOp {
+    RequestOp {
+        request: Some(request),
+    }
+}
+
+#[test]
+fn test_decode_compares() {
+    let request =
+        decode(b"ver(k1) = 0\nc(k2) > 3\nmod(k3) < 4\nval(k4) != v\nlease(k5) = 31\n\n\n\n");
+    assert_eq!(
+        request.compare,
+        [
+            compare(0, 0, b"k1", TargetUnion::Version(0)),
+            compare(1, 1, b"k2", TargetUnion::CreateRevision(3)),
+            compare(2, 2, b"k3", TargetUnion::ModRevision(4)),
+            compare(3, 3, b"k4", TargetUnion::Value(b"v".to_vec())),
+            compare(0, 4, b"k5", TargetUnion::Lease(31)),
+        ]
+    );
+    assert!(request.success.is_empty());
+    assert!(request.failure.is_empty());
+}
+
+#[test]
+fn test_decode_operations() {
+    let request = decode(
+        b"\n\nput k1 v --lease=1f\ndel k2\n\nget --consistency=s --min-mod-rev=5 --max-create-rev=9 k3 k4\n",
+    );
+    assert_eq!(
+        request.success,
+        [
+            op(Request::Put(PutRequest {
+                key: b"k1".to_vec(),
+                value: b"v".to_vec(),
+                lease: 0x1f,
+            })),
+            op(Request::DeleteRange(DeleteRangeRequest {
+                key: b"k2".to_vec(),
+                range_end: vec![],
+            })),
+        ]
+    );
+    assert_eq!(
+        request.failure,
+        [op(Request::Range(RangeRequest {
+            key: b"k3".to_vec(),
+            range_end: b"k4".to_vec(),
+            serializable: true,
+            min_mod_revision: 5,
+            max_create_revision: 9,
+            ..RangeRequest::default()
+        }))]
+    );
+}
+
+#[test]
+fn test_decode_escapes() {
+    // The bytes the keys and the values stand for are encoded
+    let request =
+        decode(b"val(\"k\\x31\") = 'it\\'s'\n\nput k \"a\\\"b\"\n\nget --prefix \"a\\x2f\"\n");
+    assert_eq!(
+        request.compare,
+        [compare(0, 3, b"k1", TargetUnion::Value(b"it's".to_vec()))]
+    );
+    assert_eq!(
+        request.success,
+        [op(Request::Put(PutRequest {
+            key: b"k".to_vec(),
+            value: b"a\"b".to_vec(),
+            lease: 0,
+        }))]
+    );
+    assert_eq!(
+        request.failure,
+        [op(Request::Range(RangeRequest {
+            key: b"a/".to_vec(),
+            range_end: b"a0".to_vec(),
+            ..RangeRequest::default()
+        }))]
+    );
+}
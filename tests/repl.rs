@@ -0,0 +1,87 @@
+//! Drive the `txn-repl` binary through its standard input.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_txn-repl"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn txn-repl");
+    child
+        .stdin
+        .take()
+        .expect("Missing stdin")
+        .write_all(input.as_bytes())
+        .expect("Failed to write stdin");
+    child.wait_with_output().expect("Failed to run txn-repl")
+}
+
+#[test]
+fn test_repl() {
+    let output = run("mod(key1) > 0
+put key1 v
+val(key2) = x
+:undo
+
+put key1 overwrote
+frobnicate key1
+del key1
+
+get key1
+");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "compares:
+success requests (get, put, del):
+failure requests (get, put, del):
+
+mod(\"key1\") > 0
+
+put \"key1\" \"overwrote\"
+del \"key1\"
+
+get \"key1\"
+"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: unexpected token in compare section
+ --> 2:1
+  |
+2 | put key1 v
+  | ^
+  = help: operations go in the success and failure sections, end the compares with a blank line
+undone: val(key2) = x
+error: unexpected token in success section
+ --> 4:1
+  |
+4 | frobnicate key1
+  | ^
+"
+    );
+}
+
+#[test]
+fn test_repl_end_of_input() {
+    // The sections left are empty
+    let output = run("\nput key1 v\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "compares:
+success requests (get, put, del):
+
+\n\nput \"key1\" \"v\"\n\n"
+    );
+
+    let output = run(":undo\n\n\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nothing to undo in the compare section\n"
+    );
+}
@@ -0,0 +1,128 @@
+//! Pathological inputs must be handled in linear time without large
+//! transient allocations.
+
+use etcd_txn_parser::options::ParseOptions;
+use etcd_txn_parser::{parse, parse_with_options};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Counts the bytes allocated by the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| {
+            allocated.set(allocated.get() + layout.size());
+            let _ = PEAK.try_with(|peak| peak.set(peak.get().max(allocated.get())));
+        });
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = ALLOCATED
+            .try_with(|allocated| allocated.set(allocated.get().saturating_sub(layout.size())));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The size of the pathological inputs, smaller in debug builds which are
+/// much slower.
+const SIZE: usize = if cfg!(debug_assertions) {
+    8 << 20
+} else {
+    50 << 20
+};
+
+/// The time allowed to parse an input of `SIZE` bytes, only checked in
+/// release builds: the timings of debug builds vary too much with the machine
+/// for a bound not to be flaky. The bound leaves a wide margin over a linear
+/// parse, a quadratic one being orders of magnitude slower.
+const TIME_BOUND: Duration = Duration::from_secs(5);
+
+/// The memory a parse may allocate on top of the input.
+const ALLOCATION_BOUND: usize = 1 << 20;
+
+/// Run `parse` over `input`, checking it stays within the time and
+/// allocation bounds, see [`TIME_BOUND`].
+fn check_bounds<'a, T>(input: &'a [u8], parse: impl FnOnce(&'a [u8]) -> T) -> T {
+    let baseline = ALLOCATED.with(Cell::get);
+    PEAK.with(|peak| peak.set(baseline));
+    let start = Instant::now();
+
+    let result = parse(input);
+
+    let elapsed = start.elapsed();
+    let allocated = PEAK.with(Cell::get) - baseline;
+    if !cfg!(debug_assertions) {
+        assert!(elapsed < TIME_BOUND, "parsing took {elapsed:?}");
+    }
+    assert!(
+        allocated < ALLOCATION_BOUND,
+        "parsing allocated {allocated} bytes"
+    );
+    result
+}
+
+#[test]
+fn test_huge_single_line() {
+    let mut input = b"put key ".to_vec();
+    input.resize(SIZE, b'a');
+    assert!(check_bounds(&input, parse).is_err());
+
+    let mut input = vec![b'x'; SIZE];
+    input[SIZE / 2] = b'(';
+    assert!(check_bounds(&input, parse).is_err());
+}
+
+#[test]
+fn test_huge_single_line_in_failure_section() {
+    let mut input = b"\n\n\n\nput key ".to_vec();
+    input.resize(SIZE, b'a');
+    let txn = check_bounds(&input, parse).expect("Failed to parse");
+    assert_eq!(txn.failure[0].value().map(<[u8]>::len), Some(SIZE - 12));
+}
+
+#[test]
+fn test_huge_run_of_line_feeds() {
+    let input = vec![b'\n'; SIZE];
+    let txn = check_bounds(&input, parse).expect("Failed to parse");
+    assert!(txn.compares.is_empty() && txn.success.is_empty() && txn.failure.is_empty());
+}
+
+#[test]
+fn test_huge_run_of_double_quotes() {
+    let input = vec![b'"'; SIZE];
+    assert!(check_bounds(&input, parse).is_err());
+
+    let mut input = b"\n\n\n\n".to_vec();
+    input.resize(SIZE, b'"');
+    assert!(check_bounds(&input, parse).is_err());
+}
+
+#[test]
+fn test_unterminated_quote() {
+    let mut input = b"mod(\"key) > 0\n\nput key value\n\n".to_vec();
+    input.resize(SIZE, b'a');
+    assert!(check_bounds(&input, parse).is_err());
+
+    let mut input = b"\n\n\n\nput key \"value\nput key value\n".to_vec();
+    input.resize(SIZE, b'a');
+    assert!(check_bounds(&input, parse).is_err());
+
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    let txn =
+        check_bounds(&input, |input| parse_with_options(input, &options)).expect("Failed to parse");
+    assert!(txn.has_unknown());
+}
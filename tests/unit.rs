@@ -1,6 +1,25 @@
-use etcd_txn_parser::compare::{Compare, ModRevision, OpType, Value};
-use etcd_txn_parser::operation::{DeleteData, GetData, Operation, PutData};
-use etcd_txn_parser::{parse, TxnData};
+use elyze::errors::{ParseError, ParseResult};
+use etcd_txn_parser::compare::{
+    Compare, CompareKind, CompareParser, CreateRevision, CustomCompare, Lease, ModRevision, OpType,
+    Value, Version,
+};
+use etcd_txn_parser::convert::{Construct, ConvertError, Lossy};
+use etcd_txn_parser::data::{self, Key};
+use etcd_txn_parser::error::{render_error, Error};
+use etcd_txn_parser::explain::ExplainOptions;
+use etcd_txn_parser::operation::{
+    CustomData, DataKind, GetData, Operation, OperationKind, OperationParser, PutData, PutFileData,
+};
+use etcd_txn_parser::options::{ChecksumMode, ParseOptions, UnknownFlagPolicy};
+use etcd_txn_parser::output::{parse_txn_output, KeyValue, OpResponse};
+use etcd_txn_parser::validate::ValidationError;
+use etcd_txn_parser::warning::Warning;
+use etcd_txn_parser::{
+    parse, parse_checked, parse_collect_errors, parse_compact, parse_flat, parse_flat_with_options,
+    parse_labeled, parse_prefix, parse_with_options, parse_with_warnings, Branch, Section, TxnData,
+    WalEntry, Write, FORMAT_VERSION,
+};
+use std::sync::Arc;
 
 #[test]
 fn test_transaction() {
@@ -10,23 +29,15 @@ fn test_transaction() {
         result,
         TxnData {
             compares: vec![Compare::ModRevision(ModRevision {
-                key: b"key1",
+                key: Key(b"key1"),
+                key_kind: DataKind::Quoted,
                 value: 0,
                 op: OpType::GreaterThan
             })],
-            success: vec![Operation::Put(PutData {
-                key: b"key1",
-                value: b"overwrote-key1"
-            })],
+            success: vec![Operation::put(b"key1", b"overwrote-key1")],
             failure: vec![
-                Operation::Put(PutData {
-                    key: b"key1",
-                    value: b"created-key1"
-                }),
-                Operation::Put(PutData {
-                    key: b"key2",
-                    value: b"some extra key"
-                })
+                Operation::put(b"key1", b"created-key1"),
+                Operation::put(b"key2", b"some extra key")
             ]
         }
     )
@@ -40,19 +51,10 @@ fn test_transaction_no_compare() {
         result,
         TxnData {
             compares: vec![],
-            success: vec![Operation::Put(PutData {
-                key: b"key1",
-                value: b"overwrote-key1"
-            })],
+            success: vec![Operation::put(b"key1", b"overwrote-key1")],
             failure: vec![
-                Operation::Put(PutData {
-                    key: b"key1",
-                    value: b"created-key1"
-                }),
-                Operation::Put(PutData {
-                    key: b"key2",
-                    value: b"some extra key"
-                })
+                Operation::put(b"key1", b"created-key1"),
+                Operation::put(b"key2", b"some extra key")
             ]
         }
     )
@@ -66,20 +68,15 @@ fn test_transaction_no_success() {
         result,
         TxnData {
             compares: vec![Compare::ModRevision(ModRevision {
-                key: b"key1",
+                key: Key(b"key1"),
+                key_kind: DataKind::Quoted,
                 value: 0,
                 op: OpType::GreaterThan
             })],
             success: vec![],
             failure: vec![
-                Operation::Put(PutData {
-                    key: b"key1",
-                    value: b"created-key1"
-                }),
-                Operation::Put(PutData {
-                    key: b"key2",
-                    value: b"some extra key"
-                })
+                Operation::put(b"key1", b"created-key1"),
+                Operation::put(b"key2", b"some extra key")
             ]
         }
     )
@@ -93,14 +90,12 @@ fn test_transaction_no_failure() {
         result,
         TxnData {
             compares: vec![Compare::ModRevision(ModRevision {
-                key: b"key1",
+                key: Key(b"key1"),
+                key_kind: DataKind::Quoted,
                 value: 0,
                 op: OpType::GreaterThan
             })],
-            success: vec![Operation::Put(PutData {
-                key: b"key1",
-                value: b"overwrote-key1"
-            })],
+            success: vec![Operation::put(b"key1", b"overwrote-key1")],
             failure: vec![]
         }
     )
@@ -114,15 +109,14 @@ fn test_transaction_val_key() {
         result,
         TxnData {
             compares: vec![Compare::Value(Value {
-                key: b"key",
-                value: b"toto",
+                key: Key(b"key"),
+                key_kind: DataKind::Quoted,
+                value: data::Value(b"toto"),
+                value_kind: DataKind::Quoted,
                 op: OpType::Equal
             })],
             success: vec![],
-            failure: vec![Operation::Put(PutData {
-                key: b"key",
-                value: b"toto"
-            })]
+            failure: vec![Operation::put(b"key", b"toto")]
         }
     )
 }
@@ -136,10 +130,10 @@ fn test_transaction_just_success() {
         TxnData {
             compares: vec![],
             success: vec![
-                Operation::Get(GetData { key: b"key1" }),
-                Operation::Get(GetData { key: b"key2" }),
-                Operation::Get(GetData { key: b"key3" }),
-                Operation::Delete(DeleteData { key: b"key4" })
+                Operation::get(b"key1"),
+                Operation::get(b"key2"),
+                Operation::get(b"key3"),
+                Operation::delete(b"key4")
             ],
             failure: vec![]
         }
@@ -154,15 +148,1946 @@ fn test_transaction_mod_equal_0() {
         result,
         TxnData {
             compares: vec![Compare::ModRevision(ModRevision {
-                key: b"/bootstrap/10aee79d3",
+                key: Key(b"/bootstrap/10aee79d3"),
+                key_kind: DataKind::Quoted,
                 value: 0,
                 op: OpType::Equal
             })],
-            success: vec![Operation::Put(PutData {
-                key: b"/bootstrap/10aee79d3",
-                value: b""
-            })],
+            success: vec![Operation::put(b"/bootstrap/10aee79d3", b"")],
             failure: vec![]
         }
     )
 }
+
+#[test]
+fn test_transaction_strict_numbers() {
+    let transaction = include_bytes!("fixtures/mod_less_than_0.txt");
+    let result =
+        parse_with_options(transaction, &ParseOptions::default()).expect("Failed to parse");
+    assert_eq!(
+        result,
+        TxnData {
+            compares: vec![Compare::ModRevision(ModRevision {
+                key: Key(b"key1"),
+                key_kind: DataKind::Quoted,
+                value: 0,
+                op: OpType::LessThan
+            })],
+            success: vec![Operation::put(b"key1", b"overwrote-key1")],
+            failure: vec![]
+        }
+    );
+
+    let options = ParseOptions {
+        strict_numbers: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        parse_with_options(transaction, &options),
+        Err(Error::UnsatisfiableCompare { offset: 0 })
+    ));
+    assert!(matches!(
+        parse_with_options(b"mod(k) > 0\nversion(k) < 0\n\nput k v\n\n", &options),
+        Err(Error::UnsatisfiableCompare { offset: 11 })
+    ));
+    // A value compare isn't numeric
+    assert!(parse_with_options(b"val(k) < \"\"\n\nput k v\n\n", &options).is_ok());
+
+    let transaction = include_bytes!("fixtures/simple.txt");
+    assert!(parse_with_options(transaction, &options).is_ok());
+}
+
+#[test]
+fn test_transaction_checked() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    assert_eq!(
+        parse_checked(transaction).expect("Failed to parse"),
+        parse(transaction).expect("Failed to parse")
+    );
+
+    let transaction = include_bytes!("fixtures/duplicate_key.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(result.success.len(), 2);
+
+    let result = parse_checked(transaction);
+    assert!(matches!(
+        result,
+        Err(Error::Validation(ValidationError::DuplicateKey {
+            branch: Branch::Success,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_transaction_keep_unknown_lines() {
+    let transaction = include_bytes!("fixtures/unknown_lines.txt");
+    assert!(parse(transaction).is_err());
+
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    let result = parse_with_warnings(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.txn,
+        TxnData {
+            compares: vec![
+                Compare::ModRevision(ModRevision {
+                    key: Key(b"key1"),
+                    key_kind: DataKind::Quoted,
+                    value: 0,
+                    op: OpType::GreaterThan
+                }),
+                Compare::Unknown(b"lock(\"key1\") = 1"),
+                Compare::Value(Value {
+                    key: Key(b"key2"),
+                    key_kind: DataKind::Quoted,
+                    value: data::Value(b"toto"),
+                    value_kind: DataKind::Quoted,
+                    op: OpType::Equal
+                })
+            ],
+            success: vec![
+                Operation::put(b"key1", b"overwrote-key1"),
+                Operation::Unknown(b"watch key1"),
+                Operation::delete(b"key2")
+            ],
+            failure: vec![
+                Operation::get(b"key1"),
+                Operation::Unknown(b"lease grant 60"),
+                Operation::get(b"key2")
+            ]
+        }
+    );
+    assert!(result.txn.has_unknown());
+    assert_eq!(
+        result.warnings,
+        vec![
+            Warning::UnknownLine {
+                section: Section::Compare,
+                offset: 16,
+                line: b"lock(\"key1\") = 1"
+            },
+            Warning::UnknownLine {
+                section: Section::Success,
+                offset: 81,
+                line: b"watch key1"
+            },
+            Warning::UnknownLine {
+                section: Section::Failure,
+                offset: 111,
+                line: b"lease grant 60"
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_transaction_explain_compares() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.explain_compares().collect::<Vec<_>>(),
+        vec!["key1's mod revision must be greater than 0"]
+    );
+
+    let transaction = include_bytes!("fixtures/val_key.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.explain_compares().collect::<Vec<_>>(),
+        vec!["key's value must be equal to \"toto\""]
+    );
+}
+
+/// `watch <key>`
+#[derive(Debug)]
+struct Watch;
+
+impl OperationParser for Watch {
+    fn command(&self) -> &[u8] {
+        b"watch"
+    }
+
+    fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+        match args.trim_ascii_end() {
+            b"" => Err(ParseError::UnexpectedToken),
+            key => Ok(vec![key]),
+        }
+    }
+}
+
+#[test]
+fn test_transaction_custom_operations() {
+    let transaction = b"\n\nwatch key1\nput key1 value1\n\nwatch\n";
+    assert!(parse(transaction).is_err());
+
+    let options = ParseOptions {
+        operation_parsers: vec![Arc::new(Watch)],
+        ..Default::default()
+    };
+    // The arguments are checked by the parser
+    assert!(parse_with_options(transaction, &options).is_err());
+
+    let transaction = b"\n\nwatch key1\nput key1 value1\n\n  watch   key2  \n";
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.success,
+        vec![
+            Operation::Custom(CustomData {
+                command: b"watch",
+                args: vec![b"key1"],
+            }),
+            Operation::put(b"key1", b"value1"),
+        ]
+    );
+    assert_eq!(result.failure[0].kind(), OperationKind::Custom);
+
+    let mut written = vec![];
+    result.write_to(&mut written).expect("Failed to write");
+    let reparsed = parse_with_options(&written, &options).expect("Failed to parse");
+    assert_eq!(reparsed, result);
+    assert_eq!(result.to_owned().as_txn(), result);
+}
+
+/// A lock name, the only argument of `lock <name>` and `locked <name>`.
+fn lock_name(args: &[u8]) -> ParseResult<Vec<&[u8]>> {
+    match args.trim_ascii_end() {
+        name if name.is_empty() || name.contains(&b' ') => Err(ParseError::UnexpectedToken),
+        name => Ok(vec![name]),
+    }
+}
+
+/// `lock <name>`
+#[derive(Debug)]
+struct Lock;
+
+impl OperationParser for Lock {
+    fn command(&self) -> &[u8] {
+        b"lock"
+    }
+
+    fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+        lock_name(args)
+    }
+}
+
+/// `locked <name>`
+#[derive(Debug)]
+struct Locked;
+
+impl CompareParser for Locked {
+    fn target(&self) -> &[u8] {
+        b"locked"
+    }
+
+    fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+        lock_name(args)
+    }
+}
+
+#[test]
+fn test_transaction_extensions() {
+    let transaction = b"locked deploy\nmod(app/version) > 0\n\nlock deploy\nput app/version 2\n\nget app/version\n";
+    assert!(parse(transaction).is_err());
+
+    let options = ParseOptions {
+        operation_parsers: vec![Arc::new(Lock)],
+        compare_parsers: vec![Arc::new(Locked)],
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.compares,
+        vec![
+            Compare::Custom(CustomCompare {
+                target: b"locked",
+                args: vec![b"deploy"],
+            }),
+            Compare::mod_revision(b"app/version", OpType::GreaterThan, 0),
+        ]
+    );
+    assert_eq!(result.compares[0].kind(), CompareKind::Custom);
+    assert_eq!(
+        result.success,
+        vec![
+            Operation::Custom(CustomData {
+                command: b"lock",
+                args: vec![b"deploy"],
+            }),
+            Operation::put(b"app/version", b"2"),
+        ]
+    );
+    assert!(matches!(
+        result.to_rust_snippet(),
+        Err(Error::CustomOperation)
+    ));
+
+    let mut written = vec![];
+    result.write_to(&mut written).expect("Failed to write");
+    let reparsed = parse_with_options(&written, &options).expect("Failed to parse");
+    assert_eq!(reparsed, result);
+    assert_eq!(result.to_owned().as_txn(), result);
+
+    // The errors of the parsers are reported at their line
+    let transaction = b"locked\n\n\n\n";
+    assert!(parse_with_options(transaction, &options).is_err());
+}
+
+/// `put <anything>`, clashing with the put operations
+#[derive(Debug)]
+struct PutCompare;
+
+impl CompareParser for PutCompare {
+    fn target(&self) -> &[u8] {
+        b"put"
+    }
+
+    fn parse_args<'a>(&self, args: &'a [u8]) -> ParseResult<Vec<&'a [u8]>> {
+        Ok(vec![args])
+    }
+}
+
+#[test]
+fn test_transaction_parse_flat() {
+    let transaction = include_bytes!("fixtures/flat.txt");
+    let result = parse_flat(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.compares,
+        vec![
+            Compare::mod_revision(b"key1", OpType::GreaterThan, 0),
+            Compare::value(b"key2", OpType::Equal, b"some extra key"),
+        ]
+    );
+    assert_eq!(
+        result.success,
+        vec![
+            Operation::put(b"key1", b"overwrote-key1"),
+            Operation::delete(b"key2"),
+            Operation::Get(GetData {
+                prefix: true,
+                ..GetData::new(b"key")
+            }),
+        ]
+    );
+    assert!(result.failure.is_empty());
+
+    assert!(parse_flat(b"put key1 value1\nmod(key1) >> 0\n").is_err());
+
+    // A line both a compare and an operation is ambiguous
+    let options = ParseOptions {
+        compare_parsers: vec![Arc::new(PutCompare)],
+        ..Default::default()
+    };
+    assert!(matches!(
+        parse_flat_with_options(b"mod(key1) > 0\nput key1 value1\n", &options),
+        Err(Error::Parse(_))
+    ));
+    let result =
+        parse_flat_with_options(b"mod(key1) > 0\ndel key1\n", &options).expect("Failed to parse");
+    assert_eq!(result.success, vec![Operation::delete(b"key1")]);
+}
+
+#[test]
+fn test_transaction_trailing_checksum() {
+    let transaction = include_bytes!("fixtures/checksum.txt");
+    assert!(parse(transaction).is_err());
+
+    let options = ParseOptions {
+        trailing_checksum: ChecksumMode::Verify,
+        ..Default::default()
+    };
+    let parsed = parse_with_warnings(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        parsed.txn,
+        parse(include_bytes!("fixtures/simple.txt")).expect("Failed to parse")
+    );
+    let checksum = parsed.metadata.checksum.expect("Missing checksum");
+    assert_eq!(checksum[..4], [0xe4, 0x17, 0xc9, 0x53]);
+
+    let transaction = include_bytes!("fixtures/checksum_mismatch.txt");
+    assert!(matches!(
+        parse_with_options(transaction, &options),
+        Err(Error::ChecksumMismatch { expected, actual }) if expected == checksum && actual != checksum
+    ));
+    assert!(matches!(
+        parse_with_options(include_bytes!("fixtures/simple.txt"), &options),
+        Err(Error::MissingChecksum)
+    ));
+
+    // Stripped without being checked
+    let options = ParseOptions {
+        trailing_checksum: ChecksumMode::Strip,
+        ..Default::default()
+    };
+    let parsed = parse_with_warnings(transaction, &options).expect("Failed to parse");
+    assert_eq!(parsed.metadata.checksum, Some(checksum));
+    let parsed = parse_with_warnings(include_bytes!("fixtures/simple.txt"), &options)
+        .expect("Failed to parse");
+    assert_eq!(parsed.metadata.checksum, None);
+}
+
+#[test]
+fn test_transaction_unknown_flag() {
+    let transaction = b"\n\nput key1 value1 --weird\nget --weird=1 key2\n\n";
+    assert!(parse(transaction).is_err());
+
+    let parse_with = |unknown_flag| {
+        let options = ParseOptions {
+            unknown_flag,
+            ..Default::default()
+        };
+        parse_with_options(transaction, &options).expect("Failed to parse")
+    };
+    let ignored = parse_with(UnknownFlagPolicy::Ignore);
+    assert_eq!(
+        ignored.success,
+        vec![
+            Operation::put(b"key1", b"value1"),
+            Operation::Get(GetData::new(b"key2"))
+        ]
+    );
+
+    let captured = parse_with(UnknownFlagPolicy::Capture);
+    let flags: Vec<_> = captured
+        .success
+        .iter()
+        .map(Operation::unknown_flags)
+        .collect();
+    assert_eq!(
+        flags,
+        [
+            &[("weird".to_string(), None)][..],
+            &[("weird".to_string(), Some("1".to_string()))]
+        ]
+    );
+    let mut written = vec![];
+    captured.write_to(&mut written).expect("Failed to write");
+    assert_eq!(
+        written,
+        b"\n\nput \"key1\" \"value1\" --weird\nget \"key2\" --weird=1\n\n"
+    );
+    assert_eq!(captured.to_owned().as_txn(), captured);
+
+    // etcd has no equivalent for the flags
+    assert!(matches!(
+        captured.to_rust_snippet(),
+        Err(Error::UnknownFlag)
+    ));
+}
+
+#[test]
+fn test_transaction_file_refs() {
+    let transaction = include_bytes!("fixtures/file_ref.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.success,
+        vec![
+            Operation::put(b"key", b"@data.bin"),
+            Operation::put(b"key2", b"@literal")
+        ]
+    );
+
+    let options = ParseOptions {
+        allow_file_refs: true,
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.success,
+        vec![
+            Operation::PutFile(PutFileData {
+                key: Key(b"key"),
+                key_kind: DataKind::Unquoted,
+                path: b"data.bin"
+            }),
+            Operation::put(b"key2", b"@literal")
+        ]
+    );
+
+    let mut owned = result.to_owned();
+    owned
+        .resolve_file_refs(|path| {
+            assert_eq!(path, b"data.bin");
+            Ok::<_, ()>(b"file content".to_vec())
+        })
+        .expect("Failed to resolve");
+    assert_eq!(
+        owned.as_txn().success[0],
+        Operation::put(b"key", b"file content")
+    );
+}
+
+#[test]
+fn test_transaction_is_unconditional() {
+    let transaction = include_bytes!("fixtures/no_compare.txt");
+    assert!(parse(transaction)
+        .expect("Failed to parse")
+        .is_unconditional());
+
+    let transaction = include_bytes!("fixtures/simple.txt");
+    assert!(!parse(transaction)
+        .expect("Failed to parse")
+        .is_unconditional());
+}
+
+#[test]
+fn test_transaction_branches_identical() {
+    assert!(
+        parse(b"mod(key1) > 0\n\nput key1 v\ndel key2\n\nput key1 v\ndel key2\n")
+            .expect("Failed to parse")
+            .branches_identical()
+    );
+
+    let transaction = include_bytes!("fixtures/simple.txt");
+    assert!(!parse(transaction)
+        .expect("Failed to parse")
+        .branches_identical());
+}
+
+#[test]
+fn test_compare_fields() {
+    // The fields of every target are public, e.g. to diff two transactions
+    let transaction = b"create(a) = 1\nmod(b) > 2\nval(c) = v\nver(d) < 3\nlease(e) != 4\n\n\n\n";
+    let result = parse(transaction).expect("Failed to parse");
+    let fields: Vec<(&[u8], OpType, String)> = result
+        .compares
+        .iter()
+        .map(|compare| match compare {
+            Compare::CreateRevision(CreateRevision { key, value, op, .. })
+            | Compare::ModRevision(ModRevision { key, value, op, .. })
+            | Compare::Version(Version { key, value, op, .. })
+            | Compare::Lease(Lease { key, value, op, .. }) => {
+                (key.as_bytes(), *op, value.to_string())
+            }
+            Compare::Value(Value { key, value, op, .. }) => {
+                (key.as_bytes(), *op, value.to_string())
+            }
+            other => panic!("Unexpected compare {other:?}"),
+        })
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            (&b"a"[..], OpType::Equal, "1".to_string()),
+            (b"b", OpType::GreaterThan, "2".to_string()),
+            (b"c", OpType::Equal, "v".to_string()),
+            (b"d", OpType::LessThan, "3".to_string()),
+            (b"e", OpType::NotEqual, "4".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_transaction_non_exhaustive_matching() {
+    fn describe_compare(compare: &Compare) -> String {
+        match compare {
+            Compare::ModRevision(ModRevision { key, value, .. }) => {
+                format!("mod {} {value}", String::from_utf8_lossy(key))
+            }
+            // New compare targets don't break downstream matches
+            other => format!(
+                "{:?} {}",
+                other.kind(),
+                String::from_utf8_lossy(other.key().unwrap_or_default().as_bytes())
+            ),
+        }
+    }
+
+    fn describe_operation(operation: &Operation) -> String {
+        match operation {
+            Operation::Put(PutData { key, .. }) => format!("put {}", String::from_utf8_lossy(key)),
+            other => format!(
+                "{:?} {}",
+                other.kind(),
+                String::from_utf8_lossy(other.key().unwrap_or_default().as_bytes())
+            ),
+        }
+    }
+
+    let transaction = include_bytes!("fixtures/just_success.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result
+            .success
+            .iter()
+            .map(describe_operation)
+            .collect::<Vec<_>>(),
+        vec!["Get key1", "Get key2", "Get key3", "Delete key4"]
+    );
+    assert!(result.success.iter().take(3).all(Operation::is_get));
+    assert_eq!(result.success[3], Operation::delete(b"key4"));
+
+    let transaction = include_bytes!("fixtures/val_key.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(describe_compare(&result.compares[0]), "Value key");
+    assert_eq!(
+        result.compares[0],
+        Compare::value(b"key", OpType::Equal, b"toto")
+    );
+    assert_eq!(result.compares[0].kind(), CompareKind::Value);
+    assert_eq!(result.compares[0].op(), Some(OpType::Equal));
+    assert_eq!(
+        result.compares[0].expected_value(),
+        Some(data::Value(b"toto"))
+    );
+    assert_eq!(result.compares[0].number(), None);
+    assert_eq!(result.failure[0].kind(), OperationKind::Put);
+    assert_eq!(result.failure[0].value(), Some(&b"toto"[..]));
+
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(describe_compare(&result.compares[0]), "mod key1 0");
+    assert_eq!(
+        result.compares[0],
+        Compare::mod_revision(b"key1", OpType::GreaterThan, 0)
+    );
+    assert_eq!(result.compares[0].number(), Some(0));
+    assert!(result.compares[0].is_mod_revision());
+}
+
+#[test]
+fn test_transaction_value_expectations() {
+    let transaction = include_bytes!("fixtures/val_key.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    let expectations = result.value_expectations();
+    assert_eq!(expectations.len(), 1);
+    assert_eq!(
+        expectations.get(&b"key"[..]),
+        Some(&(OpType::Equal, &b"toto"[..]))
+    );
+
+    // Only the value compares contribute
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert!(result.value_expectations().is_empty());
+}
+
+#[test]
+fn test_transaction_operations_for_key() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.operations_for_key(b"key1"),
+        vec![
+            (Branch::Success, &Operation::put(b"key1", b"overwrote-key1")),
+            (Branch::Failure, &Operation::put(b"key1", b"created-key1")),
+        ]
+    );
+    assert_eq!(
+        result.operations_for_key(b"key2"),
+        vec![(Branch::Failure, &Operation::put(b"key2", b"some extra key"))]
+    );
+    // Exact match only
+    assert!(result.operations_for_key(b"key").is_empty());
+}
+
+#[test]
+fn test_transaction_writes() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.writes(true),
+        vec![Write::Put {
+            key: b"key1",
+            value: b"overwrote-key1",
+        }]
+    );
+    assert_eq!(
+        result.writes(false),
+        vec![
+            Write::Put {
+                key: b"key1",
+                value: b"created-key1",
+            },
+            Write::Put {
+                key: b"key2",
+                value: b"some extra key",
+            },
+        ]
+    );
+
+    // Gets are left out
+    let result = parse(b"\n\nget key1\ndel key1\nput key2 v --lease=1f\n\nget key2\n")
+        .expect("Failed to parse");
+    assert_eq!(
+        result.writes(true),
+        vec![
+            Write::Delete { key: b"key1" },
+            Write::Put {
+                key: b"key2",
+                value: b"v",
+            },
+        ]
+    );
+    assert!(result.writes(false).is_empty());
+}
+
+#[test]
+fn test_transaction_wal_entries() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.to_wal_entries(false),
+        vec![
+            WalEntry {
+                index: 1,
+                write: Write::Put {
+                    key: b"key1",
+                    value: b"created-key1",
+                },
+            },
+            WalEntry {
+                index: 2,
+                write: Write::Put {
+                    key: b"key2",
+                    value: b"some extra key",
+                },
+            },
+        ]
+    );
+
+    // Gets are left out without taking an index
+    let result = parse(b"\n\nget key1\ndel key1\nput key2 v\n\n").expect("Failed to parse");
+    let indexes: Vec<u64> = result
+        .to_wal_entries(true)
+        .iter()
+        .map(|entry| entry.index)
+        .collect();
+    assert_eq!(indexes, [1, 2]);
+    assert!(result.to_wal_entries(false).is_empty());
+}
+
+#[test]
+fn test_transaction_lenient_blank_lines() {
+    let transaction = include_bytes!("fixtures/blank_line_compares.txt");
+    assert!(matches!(parse(transaction), Err(Error::Parse(_))));
+
+    let options = ParseOptions {
+        lenient_blank_lines: true,
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(result.compares.len(), 3);
+    assert_eq!(result.success, vec![Operation::put(b"key1", b"value1")]);
+    assert_eq!(result.failure, vec![Operation::put(b"key1", b"value2")]);
+
+    // A blank line between operations still ends the section
+    let transaction = include_bytes!("fixtures/fourth_section.txt");
+    assert!(matches!(
+        parse_with_options(transaction, &options),
+        Err(Error::TrailingContent { offset: 51, .. })
+    ));
+}
+
+#[test]
+fn test_transaction_numeric_prefixes() {
+    let transaction = include_bytes!("fixtures/numeric_prefixes.txt");
+    assert!(parse(transaction).is_err());
+
+    let options = ParseOptions {
+        numeric_prefixes: true,
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.compares,
+        vec![
+            Compare::mod_revision(b"key1", OpType::GreaterThan, 31),
+            Compare::create_revision(b"key2", OpType::Equal, 15),
+            Compare::version(b"key3", OpType::LessThan, 10),
+            Compare::lease(b"key4", OpType::Equal, 42),
+        ]
+    );
+}
+
+#[test]
+fn test_transaction_quote_chars() {
+    let transaction = b"val(`a b`) = `x y`\n\nput `key with space` value\n\nput \"k\" v\n";
+    assert!(parse(transaction).is_err());
+
+    let options = ParseOptions {
+        quote_chars: b"`\"",
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.compares,
+        vec![Compare::value(b"a b", OpType::Equal, b"x y")]
+    );
+    assert!(matches!(
+        result.success.as_slice(),
+        [Operation::Put(PutData {
+            key: Key(b"key with space"),
+            value: data::Value(b"value"),
+            key_kind: DataKind::Quoted,
+            value_kind: DataKind::Unquoted,
+            ..
+        })]
+    ));
+    assert_eq!(result.failure, vec![Operation::put(b"k", b"v")]);
+
+    // A string is closed by the character opening it
+    let result =
+        parse_with_options(b"\n\nput `a\"b` `c\n\"d`\n\n", &options).expect("Failed to parse");
+    assert_eq!(result.success, vec![Operation::put(b"a\"b", b"c\n\"d")]);
+
+    // Only the configured characters quote
+    let options = ParseOptions {
+        quote_chars: b"`",
+        ..Default::default()
+    };
+    assert!(parse_with_options(b"\n\nput \"a b\" c\n\n", &options).is_err());
+}
+
+#[test]
+fn test_transaction_noop() {
+    let transaction = b"mod(\"key1\") > 0\n\nnoop\n\ndel \"key1\"\n";
+    // An extension, unknown to etcdctl
+    assert!(parse(transaction).is_err());
+
+    let options = ParseOptions {
+        allow_extensions: true,
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(result.success, vec![Operation::Noop]);
+    assert_eq!(result.success[0].kind(), OperationKind::Noop);
+    assert_eq!(result.success[0].key(), None);
+    assert_eq!(result.failure, vec![Operation::delete(b"key1")]);
+    let mut written = vec![];
+    result.write_to(&mut written).expect("Failed to write");
+    assert_eq!(written, transaction);
+
+    // A placeholder to be filled in before being sent
+    let err = result
+        .to_rust_snippet_with(Lossy::Error)
+        .expect_err("Expected an error");
+    assert_eq!(err.construct, Construct::Noop);
+}
+
+#[test]
+fn test_transaction_assignments() {
+    let transaction = br#"
+
+put key1=value1
+put "a=b"=c --lease=1f
+put k=a=b
+put 'k'="v w"
+put a=b c
+put key2 value2
+
+"#;
+    // A single `key=value` argument is the key by default
+    let result = parse(b"\n\nput key1=value1\n\n").expect("Failed to parse");
+    assert_eq!(result.success, vec![Operation::put(b"key1=value1", b"")]);
+
+    let options = ParseOptions {
+        allow_assignments: true,
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.success,
+        vec![
+            Operation::put(b"key1", b"value1"),
+            Operation::put_with_lease(b"a=b", b"c", 0x1f),
+            Operation::put(b"k", b"a=b"),
+            Operation::put(b"k", b"v w"),
+            // Two arguments are the key and the value
+            Operation::put(b"a=b", b"c"),
+            Operation::put(b"key2", b"value2"),
+        ]
+    );
+    let Some(Operation::Put(put)) = result.success.get(3) else {
+        panic!("Expected a put, got {:?}", result.success);
+    };
+    assert_eq!(put.key_kind, DataKind::SingleQuoted);
+    assert_eq!(put.value_kind, DataKind::Quoted);
+
+    // The key can't be empty
+    let result = parse_with_options(b"\n\nput =v\n\n", &options).expect("Failed to parse");
+    assert_eq!(result.success, vec![Operation::put(b"=v", b"")]);
+}
+
+#[test]
+fn test_transaction_max_line_len() {
+    let options = ParseOptions {
+        max_line_len: Some(16),
+        ..Default::default()
+    };
+    let transaction = b"mod(key1) > 0\n\nput key1 value1\nput key2 \"a very long value\"\n\n";
+    assert!(matches!(
+        parse_with_options(transaction, &options),
+        Err(Error::LineTooLong { offset: 31 })
+    ));
+    assert!(matches!(
+        parse_flat_with_options(b"put key1 value1\nput key2 value22 \n", &options),
+        Err(Error::LineTooLong { offset: 16 })
+    ));
+
+    // The limit is inclusive, and blank lines don't count
+    let transaction =
+        b"mod(key1) > 0\n\nput key1 value1\nput key2 value22\n\n\n                    \n";
+    assert!(parse_with_options(transaction, &options).is_ok());
+    assert!(parse(b"mod(key1) > 0\n\nput key2 \"a very long value\"\n\n").is_ok());
+}
+
+#[test]
+fn test_transaction_quoted_line_feeds() {
+    // A line feed inside a string doesn't end the operation
+    let result = parse(b"\n\nput k \"a\nb\"\nput k2 v\n\ndel k\n").expect("Failed to parse");
+    assert_eq!(
+        result.success,
+        vec![Operation::put(b"k", b"a\nb"), Operation::put(b"k2", b"v")]
+    );
+    assert_eq!(result.failure, vec![Operation::delete(b"k")]);
+
+    // Nor does a blank line end the section
+    let result = parse(b"val(k) = \"x\n\ny\"\n\nput k \"a\n\nb\"\n\nput 'k' '\n\n'\n")
+        .expect("Failed to parse");
+    assert_eq!(
+        result.compares,
+        vec![Compare::value(b"k", OpType::Equal, b"x\n\ny")]
+    );
+    assert_eq!(result.success, vec![Operation::put(b"k", b"a\n\nb")]);
+    assert_eq!(result.failure, vec![Operation::put(b"k", b"\n\n")]);
+
+    // An unclosed string runs until the end of the input
+    assert!(parse(b"\n\nput k \"a\n\ndel k\n").is_err());
+}
+
+#[test]
+fn test_transaction_single_quotes() {
+    let transaction = br#"value('say "hi"') = 'it\'s "quoted"'
+
+put "it's" 'a "b" \n'
+
+put it's v
+"#;
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.compares,
+        vec![Compare::value(
+            br#"say "hi""#,
+            OpType::Equal,
+            br#"it's "quoted""#
+        )]
+    );
+    let [Operation::Put(put)] = result.success.as_slice() else {
+        panic!("Expected a put, got {:?}", result.success);
+    };
+    assert_eq!(put.key, b"it's");
+    assert_eq!(put.key_kind, DataKind::Quoted);
+    assert_eq!(put.value_kind, DataKind::SingleQuoted);
+    // Only `\'` is an escape sequence between single quotes
+    assert_eq!(
+        put.decoded_value().expect("Failed to decode"),
+        &br#"a "b" \n"#[..]
+    );
+    // An apostrophe inside a word doesn't quote
+    assert_eq!(result.failure, vec![Operation::put(b"it's", b"v")]);
+
+    // A single-quoted string may span lines
+    let result = parse(b"\n\nput k 'a\nb'\n\ndel k\n").expect("Failed to parse");
+    assert_eq!(result.success, vec![Operation::put(b"k", b"a\nb")]);
+
+    // etcdctl doesn't quote with single quotes
+    let options = ParseOptions {
+        strict_etcdctl: true,
+        ..Default::default()
+    };
+    assert!(parse_with_options(transaction, &options).is_err());
+    assert!(parse_with_options(b"\n\nput 'k' v\n\n", &options).is_err());
+    let result = parse_with_options(b"val(k) = \"it's\"\n\nput \"k'\" it's\n\n", &options)
+        .expect("Failed to parse");
+    assert_eq!(result.success, vec![Operation::put(b"k'", b"it's")]);
+}
+
+#[test]
+fn test_transaction_control_bytes() {
+    let transaction =
+        b"mod(\"key\x00\x01\") > 0\n\nput \"key\x00\" \"value\x00\x01\"\n\nput key\x00 \x01\n";
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result,
+        TxnData {
+            compares: vec![Compare::mod_revision(
+                b"key\x00\x01",
+                OpType::GreaterThan,
+                0
+            )],
+            success: vec![Operation::put(b"key\x00", b"value\x00\x01")],
+            failure: vec![Operation::put(b"key\x00", b"\x01")],
+        }
+    );
+
+    let mut written = vec![];
+    result.write_to(&mut written).expect("Failed to write");
+    assert_eq!(parse(&written).expect("Failed to parse"), result);
+}
+
+#[test]
+fn test_transaction_referenced_leases() {
+    let transaction = include_bytes!("fixtures/leases.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.success,
+        vec![
+            Operation::put_with_lease(b"key1", b"value1", 0x694d77aa9e38260f),
+            Operation::put(b"key2", b"value2"),
+        ]
+    );
+    // The lease 0x1f of the compare and of the failure put is yielded once
+    assert_eq!(result.referenced_leases(), vec![0x1f, 0x694d77aa9e38260f]);
+}
+
+#[test]
+fn test_transaction_trailing_content() {
+    let transaction = include_bytes!("fixtures/fourth_section.txt");
+    let result = parse(transaction);
+    assert!(matches!(
+        result,
+        Err(Error::TrailingContent { offset: 51, ref line }) if line == b"put key2 forgotten"
+    ));
+
+    let transaction = include_bytes!("fixtures/trailing_junk.txt");
+    let result = parse(transaction);
+    assert!(matches!(
+        result,
+        Err(Error::TrailingContent { offset: 56, ref line }) if line == b"stray text"
+    ));
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "unexpected content after the failure section at offset 56: \"stray text\""
+    );
+
+    let transaction = include_bytes!("fixtures/trailing_whitespace.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(result.failure, vec![Operation::put(b"key1", b"value2")]);
+}
+
+#[test]
+fn test_transaction_padded() {
+    let transaction = include_bytes!("fixtures/padded.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    let expected = parse(include_bytes!("fixtures/simple.txt")).expect("Failed to parse");
+    assert_eq!(result, expected);
+
+    // Leading blank lines before an operation end empty sections
+    let result = parse(b"\n\n\n\nput key1 value1\n\n\n").expect("Failed to parse");
+    assert!(result.compares.is_empty());
+    assert!(result.success.is_empty());
+    assert_eq!(result.failure, vec![Operation::put(b"key1", b"value1")]);
+}
+
+#[test]
+fn test_transaction_collect_errors() {
+    let transaction = include_bytes!("fixtures/two_malformed.txt");
+    let (result, errors) = parse_collect_errors(transaction);
+    let offsets: Vec<_> = errors
+        .iter()
+        .map(|(section, offset, _)| (*section, *offset))
+        .collect();
+    assert_eq!(
+        offsets,
+        vec![(Section::Compare, 16), (Section::Success, 50)]
+    );
+    let result = result.expect("Failed to parse");
+    assert_eq!(result.compares[1], Compare::Unknown(b"mod(\"key2\") >> 0"));
+    assert_eq!(result.success[1], Operation::Unknown(b"frobnicate key1"));
+    assert_eq!(result.failure, vec![Operation::put(b"key1", b"value2")]);
+
+    // A missing section can't be recovered from
+    let (result, errors) = parse_collect_errors(b"mod(\"key1\") >> 0\nput key1 value1");
+    assert!(result.is_none());
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], (Section::Compare, 0, _)));
+}
+
+#[test]
+fn test_transaction_trailing_junk() {
+    let parse_compare = |line: &[u8]| parse(&[line, b"\n\nput k v\n\n"].concat()).map(|_| ());
+    assert!(matches!(
+        parse_compare(b"mod(key) > 5x"),
+        Err(Error::TrailingJunk { offset: 12 })
+    ));
+    // A mangled `>=`
+    assert!(matches!(
+        parse_compare(b"mod(key) > 5="),
+        Err(Error::TrailingJunk { offset: 12 })
+    ));
+    assert!(matches!(
+        parse_compare(b"ver(key) = 12 extra"),
+        Err(Error::TrailingJunk { offset: 14 })
+    ));
+    assert!(matches!(
+        parse_compare(b"val(key) = \"v\" junk"),
+        Err(Error::TrailingJunk { offset: 15 })
+    ));
+    assert!(matches!(
+        parse_compare(b"val(key) = \"v\"junk"),
+        Err(Error::TrailingJunk { offset: 14 })
+    ));
+    assert!(parse_compare(b"mod(key) > 5 \t ").is_ok());
+    // An unquoted value runs until the end of the line
+    assert!(parse_compare(b"val(key) = v w").is_ok());
+    // A line which isn't a compare isn't junk
+    assert!(matches!(
+        parse_compare(b"mod(key) >> 5"),
+        Err(Error::Parse(_))
+    ));
+
+    let transaction = b"mod(a) > 0\nmod(key) > 5x\n\nput k v\n\n";
+    assert_eq!(
+        parse(transaction).map_err(|err| err.to_string()).err(),
+        Some("unexpected content after the compare at offset 23".to_string())
+    );
+    let (_, errors) = parse_collect_errors(transaction);
+    assert!(matches!(errors.as_slice(), [(Section::Compare, 23, _)]));
+}
+
+#[test]
+fn test_transaction_etcd_version() {
+    let parse_compare = |line: &[u8], etcd_version| {
+        let options = ParseOptions {
+            etcd_version,
+            ..Default::default()
+        };
+        let data = [line, b"\n\nput k v\n\n"].concat();
+        parse_with_options(&data, &options).map(|txn| txn.compares[0].op())
+    };
+    for etcd_version in [None, Some((3, 3)), Some((3, 5))] {
+        assert!(matches!(
+            parse_compare(b"val(k) != v", etcd_version),
+            Ok(Some(OpType::NotEqual))
+        ));
+        assert!(matches!(
+            parse_compare(b"mod(k)!=3", etcd_version),
+            Ok(Some(OpType::NotEqual))
+        ));
+    }
+    assert!(matches!(
+        parse_compare(b"val(k) != v", Some((3, 2))),
+        Err(Error::UnsupportedOperator { offset: 7, operator }) if operator == "!="
+    ));
+    assert!(matches!(
+        parse_compare(b"mod(k) > 3", Some((3, 2))),
+        Ok(Some(OpType::GreaterThan))
+    ));
+    // Without a targeted version, `>=` on a value compare is `>` followed by
+    // a value
+    assert!(parse_compare(b"val(k) >= 5", None).is_ok());
+    for etcd_version in [Some((3, 2)), Some((3, 5))] {
+        assert!(matches!(
+            parse_compare(b"val(k) >= 5", etcd_version),
+            Err(Error::UnsupportedOperator { offset: 7, operator }) if operator == ">="
+        ));
+        assert!(matches!(
+            parse_compare(b"mod(\"k\") <= 5", etcd_version),
+            Err(Error::UnsupportedOperator { offset: 9, operator }) if operator == "<="
+        ));
+    }
+
+    let options = ParseOptions {
+        etcd_version: Some((3, 2)),
+        ..Default::default()
+    };
+    let transaction = b"mod(a) > 0\nmod(key) != 5\n\nput k v\n\n";
+    assert_eq!(
+        parse_with_options(transaction, &options)
+            .map_err(|err| err.to_string())
+            .err(),
+        Some(
+            "the operator != at offset 20 isn't supported by the targeted etcd version".to_string()
+        )
+    );
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..options
+    };
+    let txn = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(txn.compares[1], Compare::Unknown(b"mod(key) != 5"));
+
+    let transaction = b"value(\"k\") != \"v\"\n\nput \"k\" \"v\"\n\n";
+    let mut written = vec![];
+    parse(transaction)
+        .expect("Failed to parse")
+        .write_to(&mut written)
+        .expect("Failed to write");
+    assert_eq!(written, transaction);
+}
+
+#[test]
+fn test_transaction_data_kind() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    let Operation::Put(put) = &result.success[0] else {
+        panic!("Expected a put operation");
+    };
+    assert_eq!(put.key_kind, DataKind::Unquoted);
+    assert_eq!(put.value_kind, DataKind::Quoted);
+
+    // The way a key is written doesn't change the operation
+    assert_eq!(
+        result.success[0],
+        Operation::put(b"key1", b"overwrote-key1")
+    );
+}
+
+#[test]
+fn test_transaction_parse_prefix() {
+    let document = include_bytes!("fixtures/embedded.txt");
+    let start = b"[before]\n".len();
+    let (result, consumed) = parse_prefix(&document[start..]).expect("Failed to parse");
+    assert_eq!(result.compares.len(), 1);
+    assert_eq!(result.success, vec![Operation::put(b"key1", b"value1")]);
+    assert_eq!(result.failure, vec![Operation::get(b"key1")]);
+    assert_eq!(
+        &document[start + consumed..],
+        b"[after]\nname = \"some text\"\n"
+    );
+
+    // Without a blank line the transaction runs until the end of the input
+    let transaction = include_bytes!("fixtures/no_failure.txt");
+    let (_, consumed) = parse_prefix(transaction).expect("Failed to parse");
+    assert_eq!(consumed, transaction.len());
+
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let (_, consumed) = parse_prefix(transaction).expect("Failed to parse");
+    assert_eq!(consumed, transaction.len());
+}
+
+#[test]
+fn test_transaction_meaning_eq() {
+    let quoted =
+        parse(b"mod(\"key1\") > 0\n\nput k \"v\"\n\nget \"key1\"\n").expect("Failed to parse");
+    let unquoted = parse(b"m(key1) > 0\n\nput k v\n\nget key1\n").expect("Failed to parse");
+    assert!(quoted.meaning_eq(&unquoted));
+
+    // Keys and values are compared by the bytes they stand for
+    let escaped = parse(b"val(\"k\\x31\") = 'it\\'s'\n\nput k \"\\x41\"\n\nget 'a' \"\\x63\"\n")
+        .expect("Failed to parse");
+    let plain = parse(b"val(k1) = \"it's\"\n\nput k A\n\nget a c\n").expect("Failed to parse");
+    assert!(escaped.meaning_eq(&plain));
+    // An escape sequence isn't one between single quotes
+    let single_quoted = parse(b"\n\nput k '\\x41'\n\n").expect("Failed to parse");
+    let double_quoted = parse(b"\n\nput k \"\\x41\"\n\n").expect("Failed to parse");
+    assert!(!single_quoted.meaning_eq(&double_quoted));
+    assert!(single_quoted.meaning_eq(&parse(b"\n\nput k \\x41\n\n").expect("Failed to parse")));
+
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    let spaced = parse_with_options(b"\n\nwatch  key1\n\n", &options).expect("Failed to parse");
+    let tight = parse_with_options(b"\n\nwatch\tkey1\n\n", &options).expect("Failed to parse");
+    assert_ne!(spaced, tight);
+    assert!(spaced.meaning_eq(&tight));
+
+    // The order of the nodes matters
+    let swapped = parse(b"m(key1) > 0\n\nput k v\nget key1\n\n").expect("Failed to parse");
+    let ordered = parse(b"m(key1) > 0\n\nget key1\nput k v\n\n").expect("Failed to parse");
+    assert!(!swapped.meaning_eq(&ordered));
+}
+
+#[test]
+fn test_transaction_lint_read_after_write() {
+    use etcd_txn_parser::validate::LintConfig;
+
+    let config = LintConfig::default().check_read_after_write();
+    let transaction =
+        b"\n\nget key1\nput key1 v\nget key1\nget --prefix app/\n\ndel app/db\nget --prefix app/\n";
+    let result = parse(transaction).expect("Failed to parse");
+    let violations = result.lint(&config);
+    assert_eq!(
+        violations,
+        vec![
+            ValidationError::ReadAfterWrite {
+                key: b"key1".to_vec(),
+                branch: Branch::Success,
+            },
+            ValidationError::ReadAfterWrite {
+                key: b"app/db".to_vec(),
+                branch: Branch::Failure,
+            },
+        ]
+    );
+    assert_eq!(
+        violations[0].to_string(),
+        "key \"key1\" read after being written in success branch"
+    );
+    assert!(result.lint(&LintConfig::default()).is_empty());
+}
+
+#[test]
+fn test_transaction_lint_unsatisfiable_compares() {
+    use etcd_txn_parser::validate::LintConfig;
+
+    let config = LintConfig::default().check_unsatisfiable_compares();
+    let transaction = b"version(key1) < 0\nmod(key1) > 0\ncreate(key2) < 0\n\nput key1 v\n\n";
+    let result = parse(transaction).expect("Failed to parse");
+    assert!(result.has_unsatisfiable_compare());
+    let violations = result.lint(&config);
+    assert_eq!(
+        violations,
+        vec![
+            ValidationError::UnsatisfiableCompare { index: 0 },
+            ValidationError::UnsatisfiableCompare { index: 2 },
+        ]
+    );
+    assert_eq!(
+        violations[0].to_string(),
+        "compare 0 can never be satisfied"
+    );
+    assert!(result.lint(&LintConfig::default()).is_empty());
+
+    let result = parse(b"version(key1) < 1\n\nput key1 v\n\n").expect("Failed to parse");
+    assert!(!result.has_unsatisfiable_compare());
+    assert!(result.lint(&config).is_empty());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_transaction_lint_json_values() {
+    use etcd_txn_parser::validate::LintConfig;
+
+    let config = LintConfig::default().require_json_values("config/");
+    let transaction = b"\n\nput config/app {\"port\":8080}\nput config/flag true\nput other/raw not-json\n\nput config/broken {\"port\":\nput config/bin \"\xff\"\n";
+    let result = parse(transaction).expect("Failed to parse");
+    let violations = result.lint(&config);
+    assert_eq!(violations.len(), 2, "{violations:?}");
+    assert!(matches!(
+        &violations[0],
+        ValidationError::InvalidJson { key, branch: Branch::Failure, index: 0, .. } if key == b"config/broken"
+    ));
+    // A binary value is never valid JSON
+    assert!(matches!(
+        &violations[1],
+        ValidationError::InvalidJson { key, branch: Branch::Failure, index: 1, .. } if key == b"config/bin"
+    ));
+    assert!(result.lint(&LintConfig::default()).is_empty());
+}
+
+#[test]
+fn test_transaction_validate_key_prefix() {
+    let transaction = b"mod(tenant1/a) > 0\n\nput tenant1/a v\nget --prefix tenant1/\n\nput tenant2/a v\ndel tenant1/a\n";
+    let result = parse(transaction).expect("Failed to parse");
+    let err = result.validate_key_prefix(b"tenant1/").unwrap_err();
+    assert_eq!(
+        err,
+        ValidationError::KeyOutsidePrefix {
+            key: b"tenant2/a".to_vec(),
+            section: Section::Failure,
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "key \"tenant2/a\" outside the allowed prefix in failure section"
+    );
+
+    // The compares are checked too
+    let result = parse(b"mod(tenant2/a) > 0\n\nput tenant1/a v\n\n").expect("Failed to parse");
+    assert!(matches!(
+        result.validate_key_prefix(b"tenant1/"),
+        Err(ValidationError::KeyOutsidePrefix {
+            section: Section::Compare,
+            ..
+        })
+    ));
+    assert_eq!(result.validate_key_prefix(b"tenant"), Ok(()));
+}
+
+#[cfg(feature = "proto")]
+#[test]
+fn test_transaction_decode_txn_request() {
+    use etcd_txn_parser::decode::decode_txn_request;
+
+    let request = include_bytes!("fixtures/leases.bin");
+    let transaction = include_bytes!("fixtures/leases.txt");
+    let expected = parse(transaction).expect("Failed to parse");
+    let result = decode_txn_request(request).expect("Failed to decode");
+    assert_eq!(result, expected.to_owned());
+    assert_eq!(expected.to_protobuf().expect("Failed to encode"), request);
+}
+
+#[test]
+fn test_transaction_render_error() {
+    let transaction = include_bytes!("fixtures/two_malformed.txt");
+    let (_, errors) = parse_collect_errors(transaction);
+    assert_eq!(
+        render_error(transaction, &errors[0]),
+        "error: unexpected token in compare section\n --> 2:1\n  |\n2 | mod(\"key2\") >> 0\n  | ^\n"
+    );
+}
+
+#[test]
+fn test_transaction_format_version() {
+    let transaction = include_bytes!("fixtures/format_version.txt");
+    let parsed =
+        parse_with_warnings(transaction, &ParseOptions::default()).expect("Failed to parse");
+    assert_eq!(parsed.metadata.format_version, Some(FORMAT_VERSION));
+    assert_eq!(
+        parsed.txn.success,
+        vec![Operation::put_with_lease(b"key1", b"value1", 0x1f)]
+    );
+    assert!(parsed.warnings.is_empty());
+
+    // After the shebang
+    let script = b"#!/usr/bin/env etcdctl-txn\n%txn-version 1\n\n\nput key1 value1\n\n";
+    let parsed = parse_with_warnings(script, &ParseOptions::default()).expect("Failed to parse");
+    assert_eq!(parsed.metadata.format_version, Some(1));
+    assert_eq!(parsed.txn.success, vec![Operation::put(b"key1", b"value1")]);
+
+    // Version 1 has no operation flags
+    for body in [
+        &b"\n\nput key1 value1 --lease=1f\n\n"[..],
+        b"\n\n\n\nget --min-mod-rev=3 key1\n",
+    ] {
+        let v1 = [&b"%txn-version 1\n"[..], body].concat();
+        let v2 = [&b"%txn-version 2\n"[..], body].concat();
+        assert!(matches!(parse(&v1), Err(Error::Parse(_))), "{v1:?}");
+        assert!(parse(&v2).is_ok(), "{v2:?}");
+    }
+
+    let transaction = b"%txn-version 3\nmod(key1) > 0\n\n\n\n";
+    let err = parse(transaction).expect_err("Parsed an unsupported version");
+    assert!(matches!(
+        err,
+        Error::UnsupportedFormatVersion {
+            required: 3,
+            supported: FORMAT_VERSION
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "this file requires format version 3, the parser supports up to 2"
+    );
+
+    // Without directive, nothing changes
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let parsed =
+        parse_with_warnings(transaction, &ParseOptions::default()).expect("Failed to parse");
+    assert_eq!(parsed.metadata.format_version, None);
+}
+
+#[test]
+fn test_transaction_shebang() {
+    let script = include_bytes!("fixtures/shebang.txt");
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(script).expect("Failed to parse");
+    assert_eq!(result, parse(transaction).expect("Failed to parse"));
+
+    // The positions are the ones in the script
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    let script = b"#!/usr/bin/env etcdctl-txn\nlock(key1) = 1\n\n\n\n";
+    let result = parse_with_warnings(script, &options).expect("Failed to parse");
+    assert_eq!(
+        result.warnings,
+        vec![
+            Warning::Shebang {
+                line: b"#!/usr/bin/env etcdctl-txn"
+            },
+            Warning::UnknownLine {
+                section: Section::Compare,
+                offset: 27,
+                line: b"lock(key1) = 1"
+            }
+        ]
+    );
+
+    // The shebang must be the very first line
+    assert!(parse(b"\n#!/usr/bin/env etcdctl-txn\n\nget key1\n\n").is_err());
+}
+
+#[test]
+fn test_transaction_display() {
+    let result = parse(include_bytes!("fixtures/simple.txt")).expect("Failed to parse");
+    assert_eq!(
+        result.to_string(),
+        "mod(\"key1\") > 0\n\nput \"key1\" \"overwrote-key1\"\n\nput \"key1\" \"created-key1\"\nput \"key2\" \"some extra key\"\n"
+    );
+
+    // Empty sections are kept
+    let result = parse(b"\n\n\n\nput \"k 1\" \"a\nb\"\n").expect("Failed to parse");
+    let displayed = result.to_string();
+    assert_eq!(displayed, "\n\n\n\nput \"k 1\" \"a\nb\"\n");
+    assert_eq!(
+        parse(displayed.as_bytes()).expect("Failed to parse"),
+        result
+    );
+}
+
+#[test]
+fn test_transaction_display_edited() {
+    let mut result = parse(include_bytes!("fixtures/simple.txt")).expect("Failed to parse");
+    let Operation::Put(put) = &mut result.failure[1] else {
+        panic!("expected a put, got {:?}", result.failure[1]);
+    };
+    put.key = Key(b"key 2");
+    put.value = data::Value(b"edited (value)");
+    let displayed = result.to_string();
+    assert_eq!(
+        displayed,
+        "mod(\"key1\") > 0\n\nput \"key1\" \"overwrote-key1\"\n\nput \"key1\" \"created-key1\"\nput \"key 2\" \"edited (value)\"\n"
+    );
+    assert_eq!(
+        parse(displayed.as_bytes()).expect("Failed to parse"),
+        result
+    );
+}
+
+#[test]
+fn test_transaction_summary() {
+    let result = parse(include_bytes!("fixtures/simple.txt")).expect("Failed to parse");
+    assert_eq!(
+        result.summary(),
+        "txn: 1 compare, 1 success op, 2 failure ops"
+    );
+    let result = parse(b"\n\n\n\n").expect("Failed to parse");
+    assert_eq!(
+        result.summary(),
+        "txn: 0 compares, 0 success ops, 0 failure ops"
+    );
+}
+
+#[test]
+fn test_transaction_into_parts() {
+    let result = parse(include_bytes!("fixtures/simple.txt")).expect("Failed to parse");
+    let (compares, success, failure) = result.into_parts();
+    assert_eq!(
+        compares,
+        vec![Compare::mod_revision(b"key1", OpType::GreaterThan, 0)]
+    );
+    assert_eq!(success, vec![Operation::put(b"key1", b"overwrote-key1")]);
+    assert_eq!(
+        failure,
+        vec![
+            Operation::put(b"key1", b"created-key1"),
+            Operation::put(b"key2", b"some extra key")
+        ]
+    );
+}
+
+#[test]
+fn test_transaction_partition_compares() {
+    let transaction = b"create(key1) = 0\nmod(key2) > 4\nver(key3) > 0\nval(key4) = \"v\"\nversion(key5) < 1\n\nput key1 v\n\n";
+    let result = parse(transaction).expect("Failed to parse");
+    let (existence, checks) = result.partition_compares();
+    assert_eq!(
+        existence
+            .iter()
+            .map(|compare| compare.key())
+            .collect::<Vec<_>>(),
+        vec![Some(Key(b"key1")), Some(Key(b"key3")), Some(Key(b"key5"))]
+    );
+    assert_eq!(
+        checks
+            .iter()
+            .map(|compare| compare.key())
+            .collect::<Vec<_>>(),
+        vec![Some(Key(b"key2")), Some(Key(b"key4"))]
+    );
+}
+
+#[test]
+fn test_transaction_rust_snippet() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.to_rust_snippet().expect("Failed to render"),
+        r#"Txn::new()
+    .when(vec![
+        Compare::mod_revision(b"key1", CompareOp::Greater, 0),
+    ])
+    .and_then(vec![
+        TxnOp::put(b"key1", b"overwrote-key1", None),
+    ])
+    .or_else(vec![
+        TxnOp::put(b"key1", b"created-key1", None),
+        TxnOp::put(b"key2", b"some extra key", None),
+    ])
+"#
+    );
+}
+
+#[test]
+fn test_transaction_parse_labeled() {
+    let transaction = include_bytes!("fixtures/labeled.txt");
+    let result = parse_labeled(transaction).expect("Failed to parse");
+    assert_eq!(
+        result,
+        TxnData {
+            compares: vec![Compare::ModRevision(ModRevision {
+                key: Key(b"key1"),
+                key_kind: DataKind::Quoted,
+                value: 0,
+                op: OpType::GreaterThan
+            })],
+            success: vec![
+                Operation::put(b"key1", b"overwrote-key1"),
+                Operation::put(b"key3", b"value3"),
+                Operation::get(b"key1")
+            ],
+            failure: vec![
+                Operation::put(b"key1", b"created-key1"),
+                Operation::delete(b"key2")
+            ]
+        }
+    );
+
+    assert!(parse_labeled(b"success: watch key1").is_err());
+    assert!(parse_labeled(b"failure: mod(key1) > 0").is_err());
+}
+
+#[test]
+fn test_transaction_parse_compact() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let expected = parse(transaction).expect("Failed to parse");
+    let result = parse_compact(
+        b"mod(\"key1\") > 0 ; put key1 \"overwrote-key1\" ; put \"key1\" \"created-key1\", put key2 \"some extra key\"",
+    )
+    .expect("Failed to parse");
+    assert_eq!(result, expected);
+    assert_eq!(
+        parse_compact(
+            expected
+                .to_compact_string()
+                .expect("Failed to write")
+                .as_bytes()
+        )
+        .expect("Failed to parse"),
+        expected
+    );
+
+    assert!(parse_compact(b"mod(key1) > 0 ; put key1 v").is_err());
+    assert!(parse_compact(b" ; ; ; ").is_err());
+    assert!(parse_compact(b" ; put key1 v, watch key1 ; ").is_err());
+}
+
+#[test]
+fn test_transaction_unicode_escape() {
+    let result = parse(b"\n\nput k \"\\u{00e9}\"\n\n").expect("Failed to parse");
+    let Operation::Put(put) = &result.success[0] else {
+        panic!("Expected a put operation");
+    };
+    assert_eq!(put.value, br"\u{00e9}");
+    assert_eq!(put.decoded_key().expect("Failed to decode"), &b"k"[..]);
+    assert_eq!(
+        put.decoded_value().expect("Failed to decode"),
+        &[0xc3, 0xa9][..]
+    );
+
+    let result = parse(b"\n\nput k \"\\u{d800}\"\n\n").expect("Failed to parse");
+    let Operation::Put(put) = &result.success[0] else {
+        panic!("Expected a put operation");
+    };
+    assert!(put.decoded_value().is_err());
+}
+
+#[test]
+fn test_transaction_to_dot() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(result.to_dot(), include_str!("fixtures/simple.dot"));
+
+    let transaction = include_bytes!("fixtures/leases.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(result.to_dot(), include_str!("fixtures/leases.dot"));
+}
+
+#[test]
+fn test_transaction_output() {
+    let transaction = include_bytes!("fixtures/get_prefix.txt");
+    let txn = parse(transaction).expect("Failed to parse");
+    let kv = |key, value| KeyValue { key, value };
+
+    let output = include_bytes!("fixtures/get_prefix.success.out");
+    let output = parse_txn_output(output).expect("Failed to parse the output");
+    assert!(output.succeeded);
+    let expected = [
+        OpResponse::Put { prev_kv: None },
+        OpResponse::Get {
+            kvs: vec![
+                kv(b"app/a", b"1"),
+                kv(b"app/b", b"2"),
+                kv(b"app/lock", b"me"),
+            ],
+        },
+        OpResponse::Delete {
+            deleted: 1,
+            prev_kvs: vec![],
+        },
+    ];
+    assert_eq!(output.responses, expected);
+    let zipped = output.zip(&txn).expect("Mismatched responses");
+    assert!(zipped
+        .iter()
+        .zip(&txn.success)
+        .all(|((operation, _), expected)| std::ptr::eq(*operation, expected)));
+
+    let output = include_bytes!("fixtures/get_prefix.failure.out");
+    let output = parse_txn_output(output).expect("Failed to parse the output");
+    assert!(!output.succeeded);
+    assert_eq!(
+        output.responses,
+        vec![OpResponse::Get {
+            kvs: vec![kv(b"app/lock", b"someone-else")],
+        }]
+    );
+    assert_eq!(
+        output.zip(&txn),
+        Some(vec![(&txn.failure[0], &output.responses[0])])
+    );
+
+    // Responses of another transaction don't line up
+    let output = include_bytes!("fixtures/simple.failure.out");
+    let output = parse_txn_output(output).expect("Failed to parse the output");
+    assert_eq!(output.responses.len(), 2);
+    assert_eq!(output.zip(&txn), None);
+    let simple = parse(include_bytes!("fixtures/simple.txt")).expect("Failed to parse");
+    assert_eq!(output.zip(&simple).map(|zipped| zipped.len()), Some(2));
+    let success = parse_txn_output(b"SUCCESS\n\n1\n").expect("Failed to parse the output");
+    assert_eq!(success.zip(&simple), None);
+}
+
+#[test]
+fn test_transaction_to_rust_literal() {
+    let transaction = include_bytes!("fixtures/val_key.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.to_rust_literal(),
+        r#"TxnData {
+    compares: vec![
+        Compare::Value(Value {
+            key: Key(b"key"),
+            key_kind: DataKind::Quoted,
+            value: data::Value(b"toto"),
+            value_kind: DataKind::Quoted,
+            op: OpType::Equal,
+        }),
+    ],
+    success: vec![],
+    failure: vec![
+        Operation::Put(PutData {
+            key: Key(b"key"),
+            value: data::Value(b"toto"),
+            lease: None,
+            key_kind: DataKind::Quoted,
+            value_kind: DataKind::Quoted,
+            unknown_flags: vec![],
+        }),
+    ],
+}
+"#
+    );
+
+    // The generated code builds the parsed transaction
+    #[rustfmt::skip]
+    let literal = TxnData {
+        compares: vec![
+            Compare::Value(Value {
+                key: Key(b"key"),
+                key_kind: DataKind::Quoted,
+                value: data::Value(b"toto"),
+                value_kind: DataKind::Quoted,
+                op: OpType::Equal,
+            }),
+        ],
+        success: vec![],
+        failure: vec![
+            Operation::Put(PutData {
+                key: Key(b"key"),
+                value: data::Value(b"toto"),
+                lease: None,
+                key_kind: DataKind::Quoted,
+                value_kind: DataKind::Quoted,
+                unknown_flags: vec![],
+            }),
+        ],
+    };
+    assert_eq!(result, literal);
+}
+
+#[test]
+fn test_transaction_summary_table() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.summary_table(),
+        include_str!("fixtures/simple.table")
+    );
+
+    // Cells are truncated to 32 characters
+    let transaction = include_bytes!("fixtures/long_value.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.summary_table(),
+        include_str!("fixtures/long_value.table")
+    );
+}
+
+#[test]
+fn test_transaction_to_markdown() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(result.to_markdown(), include_str!("fixtures/simple.md"));
+
+    // Pipes and backticks in keys and values
+    let transaction = include_bytes!("fixtures/markdown_escaping.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(
+        result.to_markdown(),
+        include_str!("fixtures/markdown_escaping.md")
+    );
+}
+
+#[test]
+fn test_transaction_explain() {
+    let transaction = include_bytes!("fixtures/simple.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(result.explain(), include_str!("fixtures/simple.explain"));
+    let terse = ExplainOptions {
+        verbose: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        result.explain_with(&terse),
+        include_str!("fixtures/simple.terse.explain")
+    );
+
+    let transaction = include_bytes!("fixtures/leases.txt");
+    let result = parse(transaction).expect("Failed to parse");
+    assert_eq!(result.explain(), include_str!("fixtures/leases.explain"));
+
+    let transaction = include_bytes!("fixtures/unknown_lines.txt");
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    let result = parse_with_options(transaction, &options).expect("Failed to parse");
+    assert_eq!(
+        result.explain(),
+        include_str!("fixtures/unknown_lines.explain")
+    );
+}
+
+/// A transaction holding an unknown operation, at position 1 of the success
+/// section.
+const UNKNOWN_OPERATION: &[u8] = b"\n\nput key1 value1\nwatch key1\n\n";
+
+fn unknown_operation() -> TxnData<'static> {
+    let options = ParseOptions {
+        keep_unknown_lines: true,
+        ..Default::default()
+    };
+    parse_with_options(UNKNOWN_OPERATION, &options).expect("Failed to parse")
+}
+
+const UNKNOWN_OPERATION_ERROR: ConvertError = ConvertError {
+    section: Section::Success,
+    index: 1,
+    construct: Construct::UnknownNode,
+};
+
+#[test]
+fn test_transaction_convert_snippet() {
+    let txn = unknown_operation();
+    assert_eq!(
+        txn.to_rust_snippet_with(Lossy::Error),
+        Err(UNKNOWN_OPERATION_ERROR)
+    );
+    assert!(matches!(txn.to_rust_snippet(), Err(Error::UnknownNode)));
+
+    let converted = txn
+        .to_rust_snippet_with(Lossy::Skip)
+        .expect("Failed to render");
+    assert_eq!(converted.report, vec![UNKNOWN_OPERATION_ERROR]);
+    assert_eq!(
+        converted.value,
+        r#"Txn::new()
+    .when(vec![])
+    .and_then(vec![
+        TxnOp::put(b"key1", b"value1", None),
+    ])
+    .or_else(vec![])
+"#
+    );
+
+    let converted = txn
+        .to_go_snippet_with(Lossy::BestEffort)
+        .expect("Failed to render");
+    assert_eq!(converted.report, vec![UNKNOWN_OPERATION_ERROR]);
+    assert_eq!(
+        converted.value,
+        "resp, err := cli.Txn(ctx).If().Then(
+\tclientv3.OpPut(\"key1\", \"value1\"),
+\t// unknown line: \"watch key1\",
+).Else().Commit()
+"
+    );
+}
+
+#[cfg(feature = "proto")]
+#[test]
+fn test_transaction_convert_protobuf() {
+    let txn = unknown_operation();
+    assert_eq!(
+        txn.to_protobuf_with(Lossy::Error),
+        Err(UNKNOWN_OPERATION_ERROR)
+    );
+
+    // Nothing to approximate the operation with
+    let expected = parse(b"\n\nput key1 value1\n\n")
+        .expect("Failed to parse")
+        .to_protobuf()
+        .expect("Failed to encode");
+    for lossy in [Lossy::Skip, Lossy::BestEffort] {
+        let converted = txn.to_protobuf_with(lossy).expect("Failed to encode");
+        assert_eq!(converted.value, expected);
+        assert_eq!(converted.report, vec![UNKNOWN_OPERATION_ERROR]);
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_transaction_convert_gateway_json() {
+    let txn = unknown_operation();
+    assert_eq!(
+        txn.to_gateway_json_with(Lossy::Error),
+        Err(UNKNOWN_OPERATION_ERROR)
+    );
+
+    let expected = parse(b"\n\nput key1 value1\n\n")
+        .expect("Failed to parse")
+        .to_gateway_json()
+        .expect("Failed to render");
+    for lossy in [Lossy::Skip, Lossy::BestEffort] {
+        let converted = txn.to_gateway_json_with(lossy).expect("Failed to render");
+        assert_eq!(converted.value, expected);
+        assert_eq!(converted.report, vec![UNKNOWN_OPERATION_ERROR]);
+    }
+}
+
+#[test]
+fn test_parse_single_line() {
+    assert_eq!(
+        Compare::parse(b"mod(key1) > 0\n").expect("Failed to parse"),
+        Compare::mod_revision(b"key1", OpType::GreaterThan, 0)
+    );
+    assert_eq!(
+        Operation::parse(b"put key1 value1").expect("Failed to parse"),
+        Operation::put(b"key1", b"value1")
+    );
+
+    // A whole line of the right kind
+    for line in [&b"put key1 value1"[..], b"mod(key1) > 0 mod(key2) > 0"] {
+        assert!(Compare::parse(line).is_err(), "{line:?}");
+    }
+    for line in [&b"mod(key1) > 0"[..], b"put key1 value1\nput key2 value2"] {
+        assert!(Operation::parse(line).is_err(), "{line:?}");
+    }
+}